@@ -0,0 +1,95 @@
+// Embeds precision tables for the sample rates a downstream crate asks for, when the
+// build-tables feature is on. See src/embedded_tables.rs.
+
+use std::{env, fs, path::Path};
+
+#[path = "src/splitmix.rs"]
+#[allow(dead_code)]
+mod splitmix;
+#[path = "src/tablegen.rs"]
+#[allow(dead_code)]
+mod tablegen;
+
+const CONFIG_VAR: &str = "PITCH_PIPE_TABLES";
+
+// Optional noise seed for generated tables, decimal or 0x prefixed hex. Defaults to
+// tablegen::DEFAULT_SEED.
+const SEED_VAR: &str = "PITCH_PIPE_TABLE_SEED";
+
+// One sample rate in hz per line, # starts a comment.
+fn parse_rates(config: &str) -> Vec<u32> {
+    let mut rates: Vec<u32> = config
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.parse()
+                .unwrap_or_else(|_| panic!("{CONFIG_VAR}: '{line}' isn't a sample rate in hz"))
+        })
+        .collect();
+    rates.sort_unstable();
+    rates.dedup();
+    rates
+}
+
+fn parse_seed(seed: &str) -> Option<u64> {
+    let seed = seed.trim();
+    match seed.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(&hex.replace('_', ""), 16).ok(),
+        None => seed.replace('_', "").parse().ok(),
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/tablegen.rs");
+    println!("cargo:rerun-if-changed=src/splitmix.rs");
+    println!("cargo:rerun-if-env-changed={CONFIG_VAR}");
+    println!("cargo:rerun-if-env-changed={SEED_VAR}");
+
+    if env::var_os("CARGO_FEATURE_BUILD_TABLES").is_none() {
+        return;
+    }
+
+    let rates = match env::var(CONFIG_VAR) {
+        Ok(path) => {
+            println!("cargo:rerun-if-changed={path}");
+            let config = fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("{CONFIG_VAR}: couldn't read {path}: {e}"));
+            parse_rates(&config)
+        }
+        Err(_) => Vec::new(),
+    };
+
+    let seed = match env::var(SEED_VAR) {
+        Ok(seed) => {
+            parse_seed(&seed).unwrap_or_else(|| panic!("{SEED_VAR}: '{seed}' isn't a 64 bit seed"))
+        }
+        Err(_) => tablegen::DEFAULT_SEED,
+    };
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let mut out = String::new();
+    for &rate in rates.iter() {
+        let table =
+            tablegen::generate_seeded(rate as f64, tablegen::DEFAULT_MEASURED_SAMPLES, seed);
+        let packed = Path::new(&out_dir).join(format!("table_{rate}hz.bin"));
+        fs::write(packed, tablegen::to_packed(&table, rate as f64)).unwrap();
+    }
+
+    out.push_str("// The embedded table for a sample rate packed, see Grid::from_packed.\n");
+    out.push_str("pub fn packed_for(sample_rate: u32) -> Option<&'static [u8]> {\n");
+    out.push_str("    match sample_rate {\n");
+    for rate in rates.iter() {
+        out.push_str(&format!(
+            "        {rate} => Some(include_bytes!(concat!(env!(\"OUT_DIR\"), \"/table_{rate}hz.bin\"))),\n"
+        ));
+    }
+    out.push_str("        _ => None,\n    }\n}\n");
+
+    out.push_str("\n// Every sample rate with an embedded table.\n");
+    out.push_str(&format!("pub const SAMPLE_RATES: &[u32] = &{rates:?};\n"));
+
+    let dest = Path::new(&out_dir).join("tables.rs");
+    fs::write(dest, out).unwrap();
+}
@@ -0,0 +1,237 @@
+use std::collections::VecDeque;
+
+use crate::{
+    calibrator::{AmplitudeCalibrator, CalibrationStage},
+    units::{Seconds, StdDev},
+};
+
+// The calibrator runs at 60 hz, see wizard.rs.
+const SAMPLE_HZ: f64 = 60.0;
+
+// How far back classification looks.
+const WINDOW_SECS: f64 = 0.25;
+
+// Sample to sample change past this many of noise's own (differenced) standard deviations is
+// movement.
+const MOVING_DEVIATIONS: f64 = 3.0;
+
+// Movement whose change in velocity is at least this fraction of its velocity is jerky - smooth
+// movement changes velocity slowly from one sample to the next.
+const JERK_RATIO: f64 = 0.5;
+
+const DEFAULT_SUSTAIN_SECS: f64 = 1.0;
+
+/// What the user is doing with the device, judged against its noise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Activity {
+    Idle,
+    Moving,
+    Jerky,
+}
+
+/// Classifies three axis input as idle, moving or jerky from exponentially weighted mean squares
+/// of its first and second differences, scaled by the calibrated noise.
+#[derive(Debug, Clone)]
+pub struct ActivityClassifier {
+    alpha: f64,
+    // Mean square of first differences that white noise alone produces.
+    idle_limit: f64,
+    previous: Option<[f64; 3]>,
+    previous_diff: Option<[f64; 3]>,
+    diff_power: f64,
+    second_diff_power: f64,
+}
+
+impl ActivityClassifier {
+    pub fn new(sample_rate: f64, noise_std_dev: StdDev) -> Self {
+        let diff_std_dev = MOVING_DEVIATIONS * noise_std_dev.0 * 2f64.sqrt();
+        Self {
+            alpha: 1.0 / (WINDOW_SECS * sample_rate).max(1.0),
+            idle_limit: diff_std_dev * diff_std_dev,
+            previous: None,
+            previous_diff: None,
+            diff_power: 0.0,
+            second_diff_power: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, sample: [f64; 3]) -> Activity {
+        if let Some(previous) = self.previous.replace(sample) {
+            let diff: [f64; 3] = core::array::from_fn(|i| sample[i] - previous[i]);
+            self.diff_power += self.alpha * (mean_square(&diff) - self.diff_power);
+            if let Some(previous_diff) = self.previous_diff.replace(diff) {
+                let second: [f64; 3] = core::array::from_fn(|i| diff[i] - previous_diff[i]);
+                self.second_diff_power +=
+                    self.alpha * (mean_square(&second) - self.second_diff_power);
+            }
+        }
+        self.activity()
+    }
+
+    pub fn activity(&self) -> Activity {
+        if self.diff_power <= self.idle_limit {
+            Activity::Idle
+        } else if self.second_diff_power > JERK_RATIO * JERK_RATIO * self.diff_power {
+            Activity::Jerky
+        } else {
+            Activity::Moving
+        }
+    }
+}
+
+fn mean_square(values: &[f64; 3]) -> f64 {
+    values.iter().map(|v| v * v).sum::<f64>() / 3.0
+}
+
+/// CalibrationStage that decides for itself when to move from noise to amplitude calibration.
+/// Once noise calibration completes, input is classified against the noise it found, and the
+/// first stretch of sustained movement (moving or jerky) advances to the amplitude stage - that
+/// stretch is replayed into amplitude calibration, so no movement is lost. Samples after noise
+/// completes don't go into the noise estimate, so the start of movement can't skew it.
+pub struct AutoCalibrator {
+    // Only None mid-advance.
+    stage: Option<CalibrationStage>,
+    classifier: Option<ActivityClassifier>,
+    activity: Activity,
+    sustain_samples: usize,
+    // Consecutive moving samples, held until movement is sustained.
+    pending: VecDeque<[f64; 3]>,
+}
+
+impl Default for AutoCalibrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AutoCalibrator {
+    pub fn new() -> Self {
+        Self::with_sustain(Seconds(DEFAULT_SUSTAIN_SECS))
+    }
+
+    // How long movement has to last before it counts, so a bump isn't taken as the user
+    // starting to move. Classification lingers on moving for a few hundred milliseconds after a
+    // bump, so much under a second lets bumps through.
+    pub fn with_sustain(sustain: Seconds) -> Self {
+        Self {
+            stage: Some(CalibrationStage::new()),
+            classifier: None,
+            activity: Activity::Idle,
+            sustain_samples: ((sustain.0 * SAMPLE_HZ).ceil() as usize).max(1),
+            pending: VecDeque::new(),
+        }
+    }
+
+    // Returns the activity the sample was classified as. Always idle until noise calibration
+    // completes, as there's nothing to judge movement against before then.
+    pub fn process(&mut self, x: f64, y: f64, z: f64) -> Activity {
+        let sample = [x, y, z];
+        let stage = self
+            .stage
+            .as_mut()
+            .expect("stage is only taken while advancing");
+
+        let noise = match stage {
+            CalibrationStage::Amplitude(amplitude) => {
+                amplitude.process_amplitude(x, y, z);
+                if let Some(classifier) = self.classifier.as_mut() {
+                    self.activity = classifier.update(sample);
+                }
+                return self.activity;
+            }
+            CalibrationStage::Noise(noise) => noise,
+        };
+
+        let Some(classifier) = self.classifier.as_mut() else {
+            if noise.process_noise(x, y, z) {
+                self.classifier = Some(ActivityClassifier::new(
+                    SAMPLE_HZ,
+                    noise.report().noise_std_dev,
+                ));
+            }
+            return self.activity;
+        };
+
+        self.activity = classifier.update(sample);
+        if self.activity == Activity::Idle {
+            self.pending.clear();
+            return self.activity;
+        }
+
+        self.pending.push_back(sample);
+        if self.pending.len() >= self.sustain_samples {
+            let mut stage = self.stage.take().map(CalibrationStage::advance);
+            if let Some(CalibrationStage::Amplitude(amplitude)) = stage.as_mut() {
+                amplitude.process_amplitude_batch(self.pending.drain(..));
+            }
+            self.stage = stage;
+        }
+        self.activity
+    }
+
+    pub fn activity(&self) -> Activity {
+        self.activity
+    }
+
+    pub fn is_noise(&self) -> bool {
+        self.stage.as_ref().is_some_and(CalibrationStage::is_noise)
+    }
+
+    // Whether noise calibration has completed, and movement is being watched for.
+    pub fn noise_complete(&self) -> bool {
+        self.classifier.is_some()
+    }
+
+    pub fn into_stage(self) -> CalibrationStage {
+        self.stage.expect("stage is only taken while advancing")
+    }
+
+    // The amplitude stage, ready for tuning - None if movement hasn't been seen yet.
+    pub fn into_amplitude(self) -> Option<AmplitudeCalibrator> {
+        self.into_stage().into_amplitude()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+    use std::f64::consts::PI;
+
+    #[test]
+    pub fn test_advances_on_sustained_motion() {
+        let mut calibrator = AutoCalibrator::new();
+        let mut noise = GaussianNoise::new(0.5, 17);
+
+        let mut samples = 0;
+        while !calibrator.noise_complete() {
+            calibrator.process(noise.sample(), noise.sample(), noise.sample());
+            samples += 1;
+            assert!(samples < 100_000, "noise stage never completed");
+        }
+
+        // Staying idle, or a short bump, doesn't advance.
+        for _ in 0..120 {
+            let activity = calibrator.process(noise.sample(), noise.sample(), noise.sample());
+            assert_eq!(activity, Activity::Idle);
+        }
+        for i in 0..5 {
+            calibrator.process(20.0 * i as f64, 0.0, 0.0);
+        }
+        for _ in 0..60 {
+            calibrator.process(noise.sample(), noise.sample(), noise.sample());
+        }
+        assert!(calibrator.is_noise());
+
+        // Smooth sweeping movement does.
+        let mut moving = false;
+        for i in 0..120 {
+            let x = 100.0 * (2.0 * PI * i as f64 / SAMPLE_HZ).sin();
+            moving |= calibrator.process(x + noise.sample(), noise.sample(), noise.sample())
+                == Activity::Moving;
+        }
+        assert!(moving);
+        assert!(!calibrator.is_noise());
+        assert!(calibrator.into_amplitude().is_some());
+    }
+}
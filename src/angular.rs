@@ -0,0 +1,171 @@
+use std::f64::consts::{PI, TAU};
+
+use crate::{
+    calibrator::{CalibrationStage, TuningSettings},
+    math,
+    tuner::{FinalTuningSettings, Tuner},
+    units::{Seconds, StdDev},
+};
+
+/// What angles are given in. Calibration and tuning run in radians whichever is used, so the
+/// results match QuaternionOneEuroFilter, which measures angular speed in radians per second.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AngleUnit {
+    #[default]
+    Radians,
+    Degrees,
+}
+
+impl AngleUnit {
+    pub fn to_radians(self, angle: f64) -> f64 {
+        match self {
+            AngleUnit::Radians => angle,
+            AngleUnit::Degrees => angle.to_radians(),
+        }
+    }
+
+    pub fn from_radians(self, radians: f64) -> f64 {
+        match self {
+            AngleUnit::Radians => radians,
+            AngleUnit::Degrees => radians.to_degrees(),
+        }
+    }
+}
+
+// Wraps an angle in radians into [-pi, pi).
+pub fn wrap_angle(radians: f64) -> f64 {
+    (radians + PI).rem_euclid(TAU) - PI
+}
+
+/// Calibration for orientation data - either three angles (i.e. euler angles or a gimbal's
+/// joint angles) or unit quaternions. Angles are unwrapped as they arrive, so a device sitting
+/// near +-180 degrees reads as the small jitter it is rather than a full turn every time it
+/// crosses over.
+///
+/// Quaternions are turned into a rotation vector from the first one seen, so all three axis are
+/// angles in radians.
+pub struct AngularCalibrator {
+    unit: AngleUnit,
+    stage: CalibrationStage,
+    // Last raw angles, and where unwrapping has got them to.
+    last: Option<([f64; 3], [f64; 3])>,
+    reference: Option<[f64; 4]>,
+}
+
+impl AngularCalibrator {
+    pub fn new(unit: AngleUnit) -> Self {
+        Self {
+            unit,
+            stage: CalibrationStage::new(),
+            last: None,
+            reference: None,
+        }
+    }
+
+    // Returns true once the current stage has enough data.
+    pub fn process_angles(&mut self, angles: [f64; 3]) -> bool {
+        let radians = angles.map(|angle| self.unit.to_radians(angle));
+        let unwrapped = match self.last {
+            Some((last, unwrapped)) => {
+                core::array::from_fn(|i| unwrapped[i] + wrap_angle(radians[i] - last[i]))
+            }
+            None => radians,
+        };
+        self.last = Some((radians, unwrapped));
+        let [x, y, z] = unwrapped;
+        self.stage.process(x, y, z)
+    }
+
+    // A unit quaternion in [x, y, z, w] order. Returns true once the current stage has enough
+    // data.
+    pub fn process_quaternion(&mut self, q: [f64; 4]) -> bool {
+        let reference = *self.reference.get_or_insert(q);
+        let [x, y, z] = rotation_vector(relative(reference, q));
+        self.stage.process(x, y, z)
+    }
+
+    // Moves from noise to amplitude calibration, see CalibrationStage::advance.
+    pub fn next(self) -> Self {
+        Self {
+            stage: self.stage.advance(),
+            ..self
+        }
+    }
+
+    // Angular noise in radians, once noise calibration has been advanced past.
+    pub fn noise_std_dev(&self) -> Option<StdDev> {
+        match &self.stage {
+            CalibrationStage::Amplitude(amplitude) => Some(amplitude.noise_std_dev()),
+            CalibrationStage::Noise(_) => None,
+        }
+    }
+
+    // Tuning settings in radians, with least_precision in the calibrator's unit.
+    pub fn tuning_settings(
+        self,
+        least_precision: f64,
+        worst_lag: Seconds,
+    ) -> Option<TuningSettings> {
+        let least_precision = self.unit.to_radians(least_precision);
+        Some(
+            self.stage
+                .into_amplitude()?
+                .tuning_settings(least_precision, worst_lag),
+        )
+    }
+
+    // Build the filter with FinalTuningSettings::to_quaternion_filter.
+    pub fn tune(self, least_precision: f64, worst_lag: Seconds) -> Option<FinalTuningSettings> {
+        Tuner::new(self.tuning_settings(least_precision, worst_lag)?).tune()
+    }
+}
+
+// The rotation taking a to b, both [x, y, z, w].
+fn relative(a: [f64; 4], b: [f64; 4]) -> [f64; 4] {
+    let [ax, ay, az, aw] = [-a[0], -a[1], -a[2], a[3]];
+    let [bx, by, bz, bw] = b;
+    [
+        aw * bx + ax * bw + ay * bz - az * by,
+        aw * by - ax * bz + ay * bw + az * bx,
+        aw * bz + ax * by - ay * bx + az * bw,
+        aw * bw - ax * bx - ay * by - az * bz,
+    ]
+}
+
+// Axis times angle in radians, taking the short way around.
+fn rotation_vector(q: [f64; 4]) -> [f64; 3] {
+    let q = if q[3] < 0.0 { q.map(|c| -c) } else { q };
+    let sin_half = (q[0] * q[0] + q[1] * q[1] + q[2] * q[2]).sqrt();
+    if sin_half < f64::EPSILON {
+        // Small angles - the angle is twice the vector part.
+        return [2.0 * q[0], 2.0 * q[1], 2.0 * q[2]];
+    }
+    let angle = 2.0 * math::atan2(sin_half, q[3]);
+    [q[0], q[1], q[2]].map(|c| c / sin_half * angle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+
+    #[test]
+    pub fn test_wraps_at_pi() {
+        assert!((wrap_angle(3.0 * PI / 2.0) + PI / 2.0).abs() < 1e-12);
+        let rotation = rotation_vector([0.0, 0.0, (0.25_f64).sin(), (0.25_f64).cos()]);
+        assert!((rotation[2] - 0.5).abs() < 1e-12);
+
+        // Heading right on the wrap, jittering by half a degree.
+        let mut noise = GaussianNoise::new(0.5, 47);
+        let mut calibrator = AngularCalibrator::new(AngleUnit::Degrees);
+        let wrap = |angle: f64| AngleUnit::Degrees.from_radians(wrap_angle(angle.to_radians()));
+        while !calibrator.process_angles([
+            wrap(180.0 + noise.sample()),
+            noise.sample(),
+            noise.sample(),
+        ]) {}
+        let calibrator = calibrator.next();
+        let noise = calibrator.noise_std_dev().unwrap().0.to_degrees();
+        assert!(noise > 0.25 && noise < 0.75);
+    }
+}
@@ -0,0 +1,183 @@
+//! Smoothing for slow one dimensional signals - barometric altitude and the like, sampled at 1
+//! to 25 hz. The usual pipeline assumes tens of hz of bandwidth: the noise estimators read
+//! bins 10 hz and more above DC, and the precision table and cutoff search are for 60 hz. Here
+//! noise comes from second differences instead, and precision is simulated at the device's own
+//! rate over cutoffs scaled down to its bandwidth.
+
+use crate::{
+    calibrator::TuningSettings,
+    estimators::DifferenceNoiseEstimator,
+    tuner::{CutoffSearch, FinalTuningSettings, Tuner},
+    units::{Hertz, Seconds, StdDev},
+    velocity::{NoiseColor, VelocityPrecision},
+};
+
+// Slow devices calibrate for long enough as it is, so settle for a slightly wider CI than the
+// default.
+const NOISE_THRESHOLD: f64 = 0.15;
+
+// The highest cutoff searched, as a fraction of the sample rate - 4 hz at 60 hz, as for the
+// table.
+const MAX_CUTOFF_FRACTION: f64 = 1.0 / 15.0;
+
+// Cutoffs tried per pass, about as many as the default search.
+const CUTOFF_STEPS: f64 = 390.0;
+
+// Precision nodes, see VelocityPrecision::with_cutoff_step.
+const CUTOFF_NODES: f64 = 40.0;
+
+enum Stage {
+    Noise(DifferenceNoiseEstimator<1>),
+    Amplitude {
+        noise_std_dev: StdDev,
+        // Lowest and highest values seen.
+        range: Option<(f64, f64)>,
+    },
+}
+
+/// Calibration and tuning for one slow signal. Same flow as the three axis calibrators - keep
+/// the device still until process returns true, call next, then move it through the biggest
+/// change it has to follow, i.e. up or down a floor.
+///
+/// At these rates a change rarely moves further in one sample than the noise does, so rather
+/// than the largest per sample move, amplitude is the whole range seen while moving.
+pub struct BarometerCalibrator {
+    sample_rate: f64,
+    stage: Stage,
+}
+
+impl BarometerCalibrator {
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            sample_rate,
+            stage: Stage::Noise(DifferenceNoiseEstimator::new(NOISE_THRESHOLD)),
+        }
+    }
+
+    // During the noise stage this returns true once noise calibration has completed. Always true
+    // in the amplitude stage.
+    pub fn process(&mut self, x: f64) -> bool {
+        match &mut self.stage {
+            Stage::Noise(noise) => noise.update([x]),
+            Stage::Amplitude { range, .. } => {
+                *range = Some(match *range {
+                    Some((low, high)) => (low.min(x), high.max(x)),
+                    None => (x, x),
+                });
+                true
+            }
+        }
+    }
+
+    // Moves from noise to amplitude calibration. Does nothing if already there.
+    pub fn next(self) -> Self {
+        let stage = match self.stage {
+            Stage::Noise(noise) => Stage::Amplitude {
+                noise_std_dev: noise.mean_variance().std_dev(),
+                range: None,
+            },
+            stage => stage,
+        };
+        Self { stage, ..self }
+    }
+
+    pub fn noise_std_dev(&self) -> StdDev {
+        match &self.stage {
+            Stage::Noise(noise) => noise.mean_variance().std_dev(),
+            Stage::Amplitude { noise_std_dev, .. } => *noise_std_dev,
+        }
+    }
+
+    // None before amplitude calibration.
+    pub fn tuning_settings(
+        &self,
+        least_precision: f64,
+        worst_lag: Seconds,
+    ) -> Option<TuningSettings> {
+        let Stage::Amplitude {
+            noise_std_dev,
+            range,
+        } = &self.stage
+        else {
+            return None;
+        };
+        let (low, high) = range.unwrap_or_default();
+
+        Some(TuningSettings {
+            max_target_precision: least_precision / 3.0,
+            max_lag_secs: worst_lag,
+            noise_variance: noise_std_dev.variance(),
+            max_amplitude: high - low,
+            sample_rate: Hertz(self.sample_rate),
+        })
+    }
+
+    // See slow_rate_tuner. Building it simulates a couple thousand short runs.
+    pub fn tuner(&self, least_precision: f64, worst_lag: Seconds) -> Option<Tuner> {
+        Some(slow_rate_tuner(
+            self.tuning_settings(least_precision, worst_lag)?,
+        ))
+    }
+
+    pub fn tune(&self, least_precision: f64, worst_lag: Seconds) -> Option<FinalTuningSettings> {
+        self.tuner(least_precision, worst_lag)?.tune()
+    }
+}
+
+// A tuner simulating at settings.sample_rate rather than 60 hz, searching cutoffs up to a
+// fifteenth of it.
+pub fn slow_rate_tuner(settings: TuningSettings) -> Tuner {
+    let sample_rate = settings.sample_rate.0;
+    let max_cutoff_hz = sample_rate * MAX_CUTOFF_FRACTION;
+    let precision = VelocityPrecision::with_cutoff_step(
+        settings.noise_variance.std_dev().0,
+        sample_rate,
+        NoiseColor::White,
+        max_cutoff_hz / CUTOFF_NODES,
+    );
+    let filter = FinalTuningSettings {
+        min_cutoff_hz: 1.0,
+        beta: 1.0,
+    }
+    .to_one_euro_rs(sample_rate);
+
+    Tuner::with_filter(settings, filter)
+        .with_precision_model(precision)
+        .with_cutoff_search(CutoffSearch::new(
+            max_cutoff_hz / CUTOFF_NODES,
+            max_cutoff_hz,
+            CUTOFF_STEPS / max_cutoff_hz,
+        ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+    use std::f64::consts::PI;
+
+    #[test]
+    pub fn test_barometer() {
+        // 10 hz, with 0.3 m of noise on a slowly rising altitude.
+        let rate = 10.0;
+        let mut noise = GaussianNoise::new(0.3, 61);
+        let mut calibrator = BarometerCalibrator::new(rate);
+        let mut i = 0;
+        while !calibrator.process(100.0 + 0.01 * i as f64 + noise.sample()) {
+            i += 1;
+        }
+        let noise_std_dev = calibrator.noise_std_dev().0;
+        assert!((noise_std_dev - 0.3).abs() < 0.05, "{noise_std_dev}");
+
+        let mut calibrator = calibrator.next();
+        // Up a floor and back down.
+        for i in 0..600 {
+            calibrator.process(100.0 + 3.0 * (1.0 - (i as f64 / 300.0 * PI).cos()));
+        }
+        let settings = calibrator.tuning_settings(1.0, Seconds(1.0)).unwrap();
+        assert!((settings.max_amplitude - 6.0).abs() < 0.01);
+
+        let tuned = calibrator.tune(1.0, Seconds(1.0)).unwrap();
+        assert!(tuned.min_cutoff_hz < rate * MAX_CUTOFF_FRACTION);
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    calibrator::TuningSettings,
+    math,
+    tuner::{FinalTuningSettings, Tuner},
+};
+
+// Calibrations within 5% of each other tune to near enough the same parameters.
+const DEFAULT_RESOLUTION: f64 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct CacheKey {
+    noise_std_dev: i64,
+    max_amplitude: i64,
+    sample_rate: i64,
+    max_target_precision: i64,
+    max_lag_secs: i64,
+}
+
+/// Remembers tuning results for recent calibrations, so applications that recalibrate often
+/// under near identical conditions skip the search. Inputs are quantized on a log scale with
+/// steps of the given resolution, and calibrations whose inputs all land on the same steps share
+/// an entry.
+///
+/// Once full, the oldest entry is evicted first.
+#[derive(Debug, Clone)]
+pub struct TuningCache {
+    capacity: usize,
+    // Relative step size of the quantization, i.e. 0.05 for 5%.
+    resolution: f64,
+    entries: HashMap<CacheKey, FinalTuningSettings>,
+    order: VecDeque<CacheKey>,
+}
+
+impl TuningCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            resolution: DEFAULT_RESOLUTION,
+            entries: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn with_resolution(mut self, resolution: f64) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    fn quantize(&self, value: f64) -> i64 {
+        (math::ln(value.max(f64::MIN_POSITIVE)) / math::ln_1p(self.resolution)).round() as i64
+    }
+
+    fn key(&self, settings: &TuningSettings) -> CacheKey {
+        CacheKey {
+            noise_std_dev: self.quantize(settings.noise_variance.std_dev().0),
+            max_amplitude: self.quantize(settings.max_amplitude),
+            sample_rate: self.quantize(settings.sample_rate.0),
+            max_target_precision: self.quantize(settings.max_target_precision),
+            max_lag_secs: self.quantize(settings.max_lag_secs.0),
+        }
+    }
+
+    pub fn get(&self, settings: &TuningSettings) -> Option<FinalTuningSettings> {
+        self.entries.get(&self.key(settings)).cloned()
+    }
+
+    pub fn insert(&mut self, settings: &TuningSettings, tuning: FinalTuningSettings) {
+        self.insert_key(self.key(settings), tuning);
+    }
+
+    fn insert_key(&mut self, key: CacheKey, tuning: FinalTuningSettings) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.insert(key, tuning).is_some() {
+            return;
+        }
+
+        self.order.push_back(key);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    // Returns the cached tuning for these settings, or runs the full search and caches it.
+    pub fn tune(&mut self, settings: TuningSettings) -> Option<FinalTuningSettings> {
+        let key = self.key(&settings);
+        if let Some(tuning) = self.entries.get(&key) {
+            return Some(tuning.clone());
+        }
+
+        let tuning = Tuner::new(settings).tune()?;
+        self.insert_key(key, tuning.clone());
+        Some(tuning)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::units::{Hertz, Seconds, Variance};
+
+    fn settings(noise_variance: f64) -> TuningSettings {
+        TuningSettings {
+            max_target_precision: 1.0,
+            max_lag_secs: Seconds(0.08),
+            noise_variance: Variance(noise_variance),
+            max_amplitude: 500.0,
+            sample_rate: Hertz(60.0),
+        }
+    }
+
+    #[test]
+    pub fn test_tuning_cache() {
+        let mut cache = TuningCache::new(1);
+        let tuning = cache.tune(settings(4.0)).unwrap();
+
+        // 4.0 -> 4.04 variance is a 0.5% change in std dev, well within resolution.
+        assert_eq!(cache.get(&settings(4.04)), Some(tuning));
+        assert_eq!(cache.get(&settings(9.0)), None);
+
+        cache.insert(
+            &settings(9.0),
+            FinalTuningSettings {
+                min_cutoff_hz: 1.0,
+                beta: 0.1,
+            },
+        );
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&settings(4.0)), None);
+    }
+}
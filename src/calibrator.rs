@@ -1,8 +1,14 @@
-use num::pow::Pow;
-
 use crate::{
-    estimators::{SixtyHzThreeAxisNoiseEstimator, ThreeAxisMaxDistanceEstimator},
+    estimators::{
+        AmplitudeEstimation, BinExclusion, MemoryFootprint, NoiseEstimation,
+        SixtyHzThreeAxisNoiseEstimator, ThreeAxisMaxDistanceEstimator, ThreeAxisNoiseSnapshot,
+    },
+    events,
+    interference::InterferenceRejector,
+    math,
+    quality::{QualityScore, SignalMonitor},
     tuner::Tuner,
+    units::{DeviceScale, Hertz, PrecisionTarget, Seconds, StdDev, Variance},
 };
 
 // The smallest target in our Fitt's law test.
@@ -12,23 +18,159 @@ const MINIMUM_TARGET_SIZE: f64 = 14.0;
 // is less than a quarter of the target size, the impact
 // on misses is negligible.
 fn least_precision() -> f64 {
-    (MINIMUM_TARGET_SIZE * 0.25).floor()
+    least_precision_for_target_size(MINIMUM_TARGET_SIZE)
+}
+
+// The least precision needed to reliably hit targets of the given size, in the same units as the
+// target size.
+pub fn least_precision_for_target_size(target_size: f64) -> f64 {
+    (target_size * 0.25).floor()
 }
 
 // Similarly, lag doesn't become much of a problem
 // until it reaches above 80ms.
-const MAX_LAG_SECONDS: f64 = 0.080;
+const MAX_LAG: Seconds = Seconds(0.080);
+
+// The rate the precision table is for, assumed unless a calibrator is told otherwise.
+const DEFAULT_SAMPLE_RATE: Hertz = Hertz(60.0);
+
+/// Canned precision/lag trade-offs for when you don't want to reason about jitter and lag
+/// yourself. Balanced matches the defaults derived from the Fitt's law results above.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TuningPreset {
+    // Tolerates more jitter in exchange for half the lag - i.e. for aiming or drawing.
+    LowLatency,
+    #[default]
+    Balanced,
+    // Tolerates more lag in exchange for a much steadier signal - i.e. for text selection or
+    // small targets.
+    MaxPrecision,
+}
+
+impl TuningPreset {
+    pub fn least_precision(&self) -> f64 {
+        match self {
+            TuningPreset::LowLatency => least_precision() * 1.5,
+            TuningPreset::Balanced => least_precision(),
+            TuningPreset::MaxPrecision => least_precision() * 0.5,
+        }
+    }
+
+    pub fn max_lag(&self) -> Seconds {
+        match self {
+            TuningPreset::LowLatency => Seconds(MAX_LAG.0 * 0.5),
+            TuningPreset::Balanced => MAX_LAG,
+            TuningPreset::MaxPrecision => Seconds(MAX_LAG.0 * 2.0),
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct StartCalibration;
 
-pub struct NoiseCalibrator {
-    noise_estimator: SixtyHzThreeAxisNoiseEstimator,
+// Generic so a different noise estimator can be swapped in, see
+// StartCalibration::first_stage_with. The table is for 60 hz, so that's the default.
+pub struct NoiseCalibrator<E: NoiseEstimation = SixtyHzThreeAxisNoiseEstimator> {
+    noise_estimator: E,
+    // Optional notching of narrowband interference ahead of the estimator, see
+    // with_interference_rejection.
+    interference: Option<InterferenceRejector<3>>,
+    // Set the first time the estimator reports done, so convergence is only announced once.
+    converged: bool,
+    sample_rate: Hertz,
+}
+
+// Generic over the amplitude estimator the same way NoiseCalibrator is over noise, see
+// NoiseCalibrator::next_with.
+pub struct AmplitudeCalibrator<A: AmplitudeEstimation = ThreeAxisMaxDistanceEstimator> {
+    noise_std_dev: StdDev,
+    amplitude_estimator: A,
+    // Carried through to the tuning settings.
+    sample_rate: Hertz,
+}
+
+/// Either stage of calibration, for callers that need to hold on to calibration across stages
+/// (i.e. in a struct field) rather than moving from one stage type to the next.
+pub enum CalibrationStage {
+    // Boxed, as the noise estimators are much larger than anything in the amplitude stage.
+    Noise(Box<NoiseCalibrator>),
+    Amplitude(AmplitudeCalibrator),
+}
+
+impl CalibrationStage {
+    pub fn new() -> Self {
+        CalibrationStage::Noise(Box::new(StartCalibration::new().first_stage()))
+    }
+
+    // Processes a sample in whichever stage we're in. Returns true once the current stage has
+    // enough data - the amplitude stage is never complete by itself, so it always returns true.
+    pub fn process(&mut self, x: f64, y: f64, z: f64) -> bool {
+        match self {
+            CalibrationStage::Noise(noise) => noise.process_noise(x, y, z),
+            CalibrationStage::Amplitude(amplitude) => {
+                amplitude.process_amplitude(x, y, z);
+                true
+            }
+        }
+    }
+
+    // For devices with fewer than three axis, see NoiseCalibrator::with_axes.
+    pub fn with_axes(axes: usize) -> Self {
+        CalibrationStage::Noise(Box::new(
+            StartCalibration::new().first_stage().with_axes(axes),
+        ))
+    }
+
+    // For input at other than 60 hz, see NoiseCalibrator::with_sample_rate.
+    pub fn with_sample_rate(self, sample_rate: Hertz) -> Self {
+        match self {
+            CalibrationStage::Noise(noise) => {
+                CalibrationStage::Noise(Box::new(noise.with_sample_rate(sample_rate)))
+            }
+            CalibrationStage::Amplitude(amplitude) => {
+                CalibrationStage::Amplitude(amplitude.with_sample_rate(sample_rate))
+            }
+        }
+    }
+
+    // Moves from noise to amplitude calibration. Does nothing if already in the amplitude stage.
+    pub fn advance(self) -> Self {
+        match self {
+            CalibrationStage::Noise(noise) => CalibrationStage::Amplitude(noise.next()),
+            stage => stage,
+        }
+    }
+
+    // Memory held right now. The noise stage lives in a Box, so it counts as heap.
+    pub fn memory_footprint(&self) -> MemoryFootprint {
+        let heap_bytes = match self {
+            CalibrationStage::Noise(_) => <NoiseCalibrator>::memory_footprint().total_bytes(),
+            CalibrationStage::Amplitude(_) => 0,
+        };
+        MemoryFootprint {
+            inline_bytes: core::mem::size_of::<Self>(),
+            heap_bytes,
+        }
+    }
+
+    pub fn is_noise(&self) -> bool {
+        matches!(self, CalibrationStage::Noise(_))
+    }
+
+    // The amplitude stage, ready for tuning - or None if noise calibration hasn't been advanced
+    // past yet.
+    pub fn into_amplitude(self) -> Option<AmplitudeCalibrator> {
+        match self {
+            CalibrationStage::Amplitude(amplitude) => Some(amplitude),
+            CalibrationStage::Noise(_) => None,
+        }
+    }
 }
 
-pub struct AmplitudeCalibrator {
-    noise_std_dev: f64,
-    amplitude_estimator: ThreeAxisMaxDistanceEstimator,
+impl Default for CalibrationStage {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl StartCalibration {
@@ -40,59 +182,439 @@ impl StartCalibration {
     pub fn first_stage(self) -> NoiseCalibrator {
         NoiseCalibrator {
             noise_estimator: SixtyHzThreeAxisNoiseEstimator::new(0.1),
+            interference: None,
+            converged: false,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+        }
+    }
+
+    // Noise calibration that finishes in around two seconds, for when users can't be asked to
+    // stay still for long. Check the report for how much was given up.
+    pub fn quick_first_stage(self) -> NoiseCalibrator {
+        NoiseCalibrator {
+            noise_estimator: SixtyHzThreeAxisNoiseEstimator::quick(),
+            interference: None,
+            converged: false,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+        }
+    }
+
+    // Noise calibration with any estimator.
+    pub fn first_stage_with<E: NoiseEstimation>(self, noise_estimator: E) -> NoiseCalibrator<E> {
+        NoiseCalibrator {
+            noise_estimator,
+            interference: None,
+            converged: false,
+            sample_rate: DEFAULT_SAMPLE_RATE,
         }
     }
 }
 
+/// How noise calibration went, for logging or showing alongside the result. The relative CI
+/// width is the honest measure of quality - a quick calibration stops at a wider one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationReport {
+    pub noise_std_dev: StdDev,
+    // Samples per axis it took.
+    pub samples: u64,
+    pub duration: Seconds,
+    // 95% CI width of the variance estimate, as a fraction of the estimate.
+    pub relative_ci_width: f64,
+    // The width calibration was allowed to stop at.
+    pub threshold: f64,
+    pub monitored_bins: usize,
+    pub quick: bool,
+    // Narrowband interference that was notched out ahead of estimation, if rejection was on.
+    pub interference_hz: Vec<f64>,
+}
+
 impl NoiseCalibrator {
+    pub fn report(&self) -> CalibrationReport {
+        let estimator = &self.noise_estimator;
+        CalibrationReport {
+            noise_std_dev: estimator.mean_variance().std_dev(),
+            samples: estimator.samples(),
+            duration: Seconds(estimator.samples() as f64 / self.sample_rate.0),
+            relative_ci_width: estimator.relative_ci_width(),
+            threshold: estimator.threshold(),
+            monitored_bins: estimator.monitored_bins(),
+            quick: estimator.is_quick(),
+            interference_hz: self.interference_hz().unwrap_or_default().to_vec(),
+        }
+    }
+
+    pub(crate) fn estimator(&self) -> &SixtyHzThreeAxisNoiseEstimator {
+        &self.noise_estimator
+    }
+
+    // Captures noise calibration in progress, see SixtyHzThreeAxisNoiseEstimator::snapshot.
+    pub fn checkpoint(&self) -> ThreeAxisNoiseSnapshot {
+        self.noise_estimator.snapshot()
+    }
+
+    // Picks calibration back up from a checkpoint. None if the checkpoint isn't from a 60 hz
+    // calibration.
+    pub fn restore(checkpoint: &ThreeAxisNoiseSnapshot) -> Option<Self> {
+        Some(Self {
+            noise_estimator: SixtyHzThreeAxisNoiseEstimator::restore(checkpoint)?,
+            interference: None,
+            converged: false,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+        })
+    }
+
+    // Warm starts from a previous calibration of the same device, see
+    // SixtyHzThreeAxisNoiseEstimator::with_prior.
+    pub fn with_prior(self, prior: Variance, weight: u64) -> Self {
+        Self {
+            noise_estimator: self.noise_estimator.with_prior(prior, weight),
+            ..self
+        }
+    }
+
+    // Opt in to high pass detrending ahead of noise estimation, for users who can't keep
+    // perfectly still. See SixtyHzThreeAxisNoiseEstimator::with_detrending.
+    pub fn with_detrending(self, cutoff_hz: f64) -> Self {
+        Self {
+            noise_estimator: self.noise_estimator.with_detrending(cutoff_hz),
+            ..self
+        }
+    }
+
+    // Estimates noise from only the first `axes` of x, y and z, for devices with fewer than
+    // three - padding the rest with a constant would average in zero noise and pull the
+    // estimate down. See SixtyHzThreeAxisNoiseEstimator::with_axes.
+    pub fn with_axes(self, axes: usize) -> Self {
+        Self {
+            noise_estimator: self.noise_estimator.with_axes(axes),
+            ..self
+        }
+    }
+}
+
+impl<E: NoiseEstimation> NoiseCalibrator<E> {
+    pub const fn memory_footprint() -> MemoryFootprint {
+        MemoryFootprint::inline::<Self>()
+    }
+
+    // The rate samples arrive at, when it isn't 60 hz. Reaches the report's duration and the
+    // tuning settings, so the tuner simulates at the real rate.
+    pub fn with_sample_rate(mut self, sample_rate: Hertz) -> Self {
+        self.sample_rate = sample_rate;
+        if self.interference.is_some() {
+            self.interference = Some(InterferenceRejector::new(sample_rate.0));
+        }
+        self
+    }
+
+    pub fn sample_rate(&self) -> Hertz {
+        self.sample_rate
+    }
+
     // Processes the noise - returns true when completed.
     pub fn process_noise(&mut self, x: f64, y: f64, z: f64) -> bool {
-        self.noise_estimator.update(x, y, z)
+        let [x, y, z] = match self.interference.as_mut() {
+            Some(interference) => match interference.process([x, y, z]) {
+                Some(sample) => sample,
+                None => {
+                    // Once detection is done, as bins near the notches would still read what's
+                    // left of the interference.
+                    if let Some(found) = interference.detected() {
+                        let exclusion = BinExclusion {
+                            dc_guard_hz: 0.0,
+                            interference_hz: found.to_vec(),
+                            ..Default::default()
+                        };
+                        self.noise_estimator
+                            .exclude_bins(&exclusion, self.sample_rate.0);
+                    }
+                    return false;
+                }
+            },
+            None => [x, y, z],
+        };
+        let done = self.noise_estimator.update(x, y, z);
+        if done && !self.converged {
+            self.converged = true;
+            events::milestone!(
+                "noise calibration converged",
+                noise_variance = self.noise_estimator.mean_variance().0
+            );
+        }
+        done
+    }
+
+    // Looks for narrowband interference (i.e. mains hum) in the first couple of seconds of idle
+    // input, and notches whatever it finds out of everything after, so it isn't mistaken for
+    // noise. Estimation starts once detection is done, without the bins near what was found.
+    // Whatever was found is in the report, and should be notched out ahead of the filter too -
+    // see interference::NotchBank.
+    pub fn with_interference_rejection(mut self) -> Self {
+        self.interference = Some(InterferenceRejector::new(self.sample_rate.0));
+        self
+    }
+
+    // Interference found ahead of estimation. None if rejection is off or still detecting.
+    pub fn interference_hz(&self) -> Option<&[f64]> {
+        self.interference.as_ref()?.detected()
+    }
+
+    // Processes a whole recording of idle samples at once, i.e. from io::read_csv. Returns true
+    // if noise estimation completed somewhere in the batch - remaining samples are still used.
+    pub fn process_noise_batch(&mut self, samples: impl IntoIterator<Item = [f64; 3]>) -> bool {
+        let mut done = false;
+        for [x, y, z] in samples {
+            done = self.process_noise(x, y, z) || done;
+        }
+        done
+    }
+
+    // From 0 to 1, how close noise estimation is to completing.
+    pub fn progress(&self) -> f64 {
+        self.noise_estimator.progress()
     }
 
     // Should be called when process_noise returns true (complete to a satisfactory statstical
     // level) -> transforms into the next calibration stage of amplitude calibration.
     pub fn next(self) -> AmplitudeCalibrator {
-        let noise_std_dev = self.noise_estimator.mean_variance();
+        self.next_with()
+    }
+
+    // Same as next, but with any amplitude estimator, i.e.
+    // `noise.next_with::<MyEstimator>()`.
+    pub fn next_with<A: AmplitudeEstimation>(self) -> AmplitudeCalibrator<A> {
+        let noise_std_dev = self.noise_estimator.mean_variance().std_dev();
+        events::milestone!(
+            "calibration stage: noise -> amplitude",
+            noise_std_dev = noise_std_dev.0
+        );
         AmplitudeCalibrator {
             noise_std_dev,
-            amplitude_estimator: ThreeAxisMaxDistanceEstimator::new(noise_std_dev),
+            amplitude_estimator: A::new(noise_std_dev),
+            sample_rate: self.sample_rate,
         }
     }
 }
 
-impl AmplitudeCalibrator {
+impl<A: AmplitudeEstimation> AmplitudeCalibrator<A> {
+    pub const fn memory_footprint() -> MemoryFootprint {
+        MemoryFootprint::inline::<Self>()
+    }
+
+    // Skips noise calibration, for when noise is already known - i.e. from a joint calibration
+    // or a datasheet.
+    pub fn from_noise(noise_std_dev: StdDev) -> Self {
+        Self {
+            noise_std_dev,
+            amplitude_estimator: A::new(noise_std_dev),
+            sample_rate: DEFAULT_SAMPLE_RATE,
+        }
+    }
+
+    // See NoiseCalibrator::with_sample_rate.
+    pub fn with_sample_rate(mut self, sample_rate: Hertz) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    pub fn sample_rate(&self) -> Hertz {
+        self.sample_rate
+    }
+
+    // Starts a fresh amplitude calibration sharing this one's noise estimate - i.e. for several
+    // joints or devices that share the same sensor characteristics.
+    pub fn fork(&self) -> Self {
+        Self {
+            noise_std_dev: self.noise_std_dev,
+            amplitude_estimator: A::new(self.noise_std_dev),
+            sample_rate: self.sample_rate,
+        }
+    }
+
+    // The noise measured by the noise stage, or given to from_noise.
+    pub fn noise_std_dev(&self) -> StdDev {
+        self.noise_std_dev
+    }
+
     // Processes motion data for highest amplitude.
     pub fn process_amplitude(&mut self, x: f64, y: f64, z: f64) {
         self.amplitude_estimator.update(x, y, z);
     }
 
+    // Processes a whole recording of motion samples at once.
+    pub fn process_amplitude_batch(&mut self, samples: impl IntoIterator<Item = [f64; 3]>) {
+        for [x, y, z] in samples {
+            self.process_amplitude(x, y, z);
+        }
+    }
+
+    // Rates the signal so far against the precision that will be asked of it (same
+    // least_precision as tuning_settings). Pass the SignalMonitor fed alongside calibration to
+    // include dropouts and timing.
+    pub fn quality(&self, least_precision: f64, monitor: Option<&SignalMonitor>) -> QualityScore {
+        QualityScore::new(
+            Some(QualityScore::noise_score(
+                self.noise_std_dev,
+                least_precision / 3.0,
+            )),
+            monitor.and_then(SignalMonitor::dropout_score),
+            monitor.and_then(SignalMonitor::timing_score),
+            Some(QualityScore::headroom_score(
+                self.amplitude_estimator.max_amplitude(),
+                self.noise_std_dev,
+            )),
+        )
+    }
+
     // When amplitude calibration is done, this can be called to generate all required tuning
     // settings for tuning a one euro filter.
-    pub fn tuning_settings(self, least_precision: f64, worst_lag_secs: f64) -> TuningSettings {
+    pub fn tuning_settings(self, least_precision: f64, worst_lag: Seconds) -> TuningSettings {
+        let max_amplitude = self.amplitude_estimator.max_amplitude();
+        events::milestone!(
+            "calibration stage: amplitude -> tuning",
+            max_amplitude = max_amplitude
+        );
         TuningSettings {
             max_target_precision: least_precision / 3.0,
-            max_lag_secs: worst_lag_secs,
-            noise_variance: self.noise_std_dev.pow(2),
-            max_amplitude: self.amplitude_estimator.max_within_reason(),
-            sample_rate: 60.0,
+            max_lag_secs: worst_lag,
+            noise_variance: self.noise_std_dev.variance(),
+            max_amplitude,
+            sample_rate: self.sample_rate,
         }
     }
 
-    pub fn tuner(self, least_precision: f64, worst_lag_secs: f64) -> Tuner {
-        Tuner::new(self.tuning_settings(least_precision, worst_lag_secs))
+    // tuning_settings with the precision target in pixels, millimeters or degrees. None if the
+    // scale can't convert the target to device units.
+    pub fn tuning_settings_for_target(
+        self,
+        target: PrecisionTarget,
+        scale: &DeviceScale,
+        worst_lag: Seconds,
+    ) -> Option<TuningSettings> {
+        let least_precision = target.to_units(scale)?;
+        Some(self.tuning_settings(least_precision, worst_lag))
+    }
+
+    // Tuning settings with a precision target per axis, i.e. tight in screen x and y but loose
+    // in depth. Everything else is shared, as noise is assumed homogeneous across axis. Tune with
+    // tuner::tune_per_axis.
+    pub fn tuning_settings_per_axis<const D: usize>(
+        self,
+        least_precision: [f64; D],
+        worst_lag: Seconds,
+    ) -> [TuningSettings; D] {
+        let noise_variance = self.noise_std_dev.variance();
+        let max_amplitude = self.amplitude_estimator.max_amplitude();
+        let sample_rate = self.sample_rate;
+        events::milestone!(
+            "calibration stage: amplitude -> tuning",
+            max_amplitude = max_amplitude,
+            axes = D
+        );
+        core::array::from_fn(|i| TuningSettings {
+            max_target_precision: least_precision[i] / 3.0,
+            max_lag_secs: worst_lag,
+            noise_variance,
+            max_amplitude,
+            sample_rate,
+        })
+    }
+
+    pub fn tuner(self, least_precision: f64, worst_lag: Seconds) -> Tuner {
+        Tuner::new(self.tuning_settings(least_precision, worst_lag))
     }
 
     pub fn tuner_with_defaults(self) -> Tuner {
-        Tuner::new(self.tuning_settings(least_precision(), MAX_LAG_SECONDS))
+        self.tuner_with_preset(TuningPreset::default())
+    }
+
+    pub fn tuning_settings_with_preset(self, preset: TuningPreset) -> TuningSettings {
+        self.tuning_settings(preset.least_precision(), preset.max_lag())
+    }
+
+    pub fn tuner_with_preset(self, preset: TuningPreset) -> Tuner {
+        Tuner::new(self.tuning_settings_with_preset(preset))
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TuningSettings {
     pub max_target_precision: f64,
-    pub max_lag_secs: f64,
-    pub noise_variance: f64,
+    pub max_lag_secs: Seconds,
+    pub noise_variance: Variance,
     pub max_amplitude: f64,
-    pub sample_rate: f64,
+    pub sample_rate: Hertz,
+}
+
+// How far the preference slider can scale the precision and lag targets either way.
+const PREFERENCE_RANGE: f64 = 2.0;
+
+impl TuningSettings {
+    // Adjusts the targets along a single responsiveness to smoothness slider, for end users who
+    // shouldn't have to know what beta is. 0.0 is most responsive - half the lag budget, with
+    // twice the precision target to make room for it. 1.0 is the reverse, and 0.5 leaves the
+    // targets as calibrated. Retune afterwards, i.e. with Tuner::tune_fast while dragging.
+    pub fn with_preference(mut self, preference: f64) -> Self {
+        let smoothing = math::powf(PREFERENCE_RANGE, 2.0 * preference.clamp(0.0, 1.0) - 1.0);
+        self.max_target_precision /= smoothing;
+        self.max_lag_secs = Seconds(self.max_lag_secs.0 * smoothing);
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+    use std::f64::consts::PI;
+
+    #[test]
+    pub fn test_interference_rejection() {
+        // 50 hz mains sampled at 120 hz, on top of white noise.
+        let sample_rate = 120.0;
+        let mut noise = GaussianNoise::new(1.0, 13);
+        let mut calibrator = StartCalibration::new()
+            .first_stage()
+            .with_sample_rate(Hertz(sample_rate))
+            .with_interference_rejection();
+        let mut i = 0;
+        while !calibrator.process_noise(
+            5.0 * (2.0 * PI * 50.0 * i as f64 / sample_rate).sin() + noise.sample(),
+            noise.sample(),
+            noise.sample(),
+        ) {
+            i += 1;
+        }
+        for _ in 0..1200 {
+            calibrator.process_noise(
+                5.0 * (2.0 * PI * 50.0 * i as f64 / sample_rate).sin() + noise.sample(),
+                noise.sample(),
+                noise.sample(),
+            );
+            i += 1;
+        }
+
+        // Found at the calibrator's rate, and the bins around it (25 hz in a 60 sample window)
+        // left out so what's left of the hum doesn't read as noise.
+        let report = calibrator.report();
+        assert_eq!(report.interference_hz.len(), 1);
+        assert!((report.interference_hz[0] - 50.0).abs() <= 0.5);
+        assert_eq!(report.monitored_bins, 17);
+        assert!((report.noise_std_dev.0 - 1.0).abs() < 0.15);
+    }
+
+    #[test]
+    pub fn test_noise_std_dev() {
+        // Noise other than 1, where a variance passed off as a std dev (0.25 here) shows.
+        let mut noise = GaussianNoise::new(0.5, 7);
+        let mut calibrator = StartCalibration::new().first_stage();
+        while !calibrator.process_noise(noise.sample(), noise.sample(), noise.sample()) {}
+
+        let amplitude = calibrator.next();
+        let std_dev = amplitude.noise_std_dev().0;
+        assert!((std_dev - 0.5).abs() < 0.075, "{std_dev}");
+
+        let settings = amplitude.tuning_settings(1.0, Seconds(0.08));
+        assert!((settings.noise_variance.0 - std_dev * std_dev).abs() < 1e-12);
+    }
 }
@@ -0,0 +1,149 @@
+use crate::{filter::MultiAxisFilter, tuner::FinalTuningSettings};
+
+/// Reconciles a device's sample timestamps with the host's arrival times, by fitting
+/// host = offset + skew * device over a sliding exponential window.
+///
+/// Device clocks drift against the host by tens to hundreds of ppm, which over a long session
+/// slowly skews both dt (and with it the filter's derivative and adaptive cutoff) and anything
+/// comparing device time to host time, like lag measurement. Arrival times are jittered by
+/// transport latency, so the offset includes the mean latency - skew isn't affected by it.
+#[derive(Debug, Clone)]
+pub struct ClockSync {
+    // Weight kept by older samples on each update.
+    decay: f64,
+    // First timestamps seen - everything else is relative to these, to keep the sums small.
+    origin: Option<(f64, f64)>,
+    sw: f64,
+    sx: f64,
+    sy: f64,
+    sxx: f64,
+    sxy: f64,
+}
+
+impl ClockSync {
+    // window_samples is roughly how many recent samples the fit considers. More gives a steadier
+    // skew estimate but follows changes in drift (i.e. temperature) more slowly.
+    pub fn new(window_samples: f64) -> Self {
+        Self {
+            decay: 1.0 - 1.0 / window_samples.max(1.0),
+            origin: None,
+            sw: 0.0,
+            sx: 0.0,
+            sy: 0.0,
+            sxx: 0.0,
+            sxy: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, device_secs: f64, host_secs: f64) {
+        let (device_origin, host_origin) = *self.origin.get_or_insert((device_secs, host_secs));
+        let x = device_secs - device_origin;
+        let y = host_secs - host_origin;
+
+        self.sw = self.sw * self.decay + 1.0;
+        self.sx = self.sx * self.decay + x;
+        self.sy = self.sy * self.decay + y;
+        self.sxx = self.sxx * self.decay + x * x;
+        self.sxy = self.sxy * self.decay + x * y;
+    }
+
+    // Host seconds per device second. 1.0 until there's enough data to say otherwise.
+    pub fn skew(&self) -> f64 {
+        let denominator = self.sw * self.sxx - self.sx * self.sx;
+        if denominator <= f64::EPSILON * self.sw * self.sxx {
+            return 1.0;
+        }
+        (self.sw * self.sxy - self.sx * self.sy) / denominator
+    }
+
+    // Maps a device timestamp to host time.
+    pub fn to_host(&self, device_secs: f64) -> Option<f64> {
+        let (device_origin, host_origin) = self.origin?;
+        let skew = self.skew();
+        let intercept = (self.sy - skew * self.sx) / self.sw;
+        Some(host_origin + intercept + skew * (device_secs - device_origin))
+    }
+
+    // A device side dt in host seconds.
+    pub fn corrected_dt(&self, device_dt: f64) -> f64 {
+        device_dt * self.skew()
+    }
+}
+
+/// A filter driven by device timestamps, with dt corrected for clock drift against the host.
+pub struct SyncedFilter<const D: usize> {
+    clock: ClockSync,
+    filter: MultiAxisFilter<D>,
+    last_device_secs: Option<f64>,
+}
+
+impl<const D: usize> SyncedFilter<D> {
+    pub fn new(sample_rate: f64, settings: &FinalTuningSettings, window_samples: f64) -> Self {
+        Self {
+            clock: ClockSync::new(window_samples),
+            filter: MultiAxisFilter::new(sample_rate, settings),
+            last_device_secs: None,
+        }
+    }
+
+    // Samples with repeated or out of order timestamps are filtered at the nominal rate, as
+    // gps::GpsFilter does.
+    pub fn filter(&mut self, sample: [f64; D], device_secs: f64, host_secs: f64) -> [f64; D] {
+        self.clock.update(device_secs, host_secs);
+
+        let device_dt = match self.last_device_secs {
+            Some(last) => device_secs - last,
+            None => 1.0 / self.filter.sample_rate(),
+        };
+        self.last_device_secs = Some(device_secs);
+
+        let dt = self.clock.corrected_dt(device_dt);
+        if dt > 0.0 {
+            self.filter.filter_with_dt(sample, dt)
+        } else {
+            self.filter.filter(sample)
+        }
+    }
+
+    pub fn clock(&self) -> &ClockSync {
+        &self.clock
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+
+    #[test]
+    pub fn test_clock_skew() {
+        // Device runs 200 ppm slow, host sees it 30 ms later give or take a few ms.
+        let mut clock = ClockSync::new(5000.0);
+        let mut latency = GaussianNoise::new(0.003, 11);
+        for i in 0..20_000 {
+            let host = i as f64 / 60.0;
+            let device = 1000.0 + host * (1.0 - 200e-6);
+            clock.update(device, host + 0.030 + latency.sample().abs());
+        }
+
+        let skew_ppm = (clock.skew() - 1.0) * 1e6;
+        assert!((skew_ppm - 200.0).abs() < 20.0, "{skew_ppm}");
+    }
+
+    #[test]
+    pub fn test_repeated_timestamps() {
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 1.0,
+            beta: 0.01,
+        };
+        let mut synced = SyncedFilter::<1>::new(60.0, &settings, 100.0);
+        // A repeated timestamp, then one that goes backwards, then back on track.
+        for (i, device_secs) in [0.0, 1.0 / 60.0, 1.0 / 60.0, 0.0, 3.0 / 60.0]
+            .into_iter()
+            .enumerate()
+        {
+            let out = synced.filter([i as f64 * 10.0], device_secs, device_secs + 0.03);
+            assert!((0.0..=40.0).contains(&out[0]), "{out:?}");
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::cmp::Ordering;
+
+use crate::response::StepResponse;
+
+/// One of the things tune trades off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Constraint {
+    // Filtered noise within the precision target.
+    Precision,
+    // A step reaching the target precision within the lag budget.
+    Lag,
+    // A step overshooting by no more than the overshoot budget.
+    Ringing,
+}
+
+/// Tunes against precision, lag and ringing together. Each candidate is scored by how far it
+/// misses each budget, and candidates are compared on those misses in priority order - so a
+/// candidate that misses a higher priority constraint by less always wins, whatever it does to
+/// the rest. Among candidates that meet everything, the most precise wins.
+///
+/// The default priority follows plain tune - precision, then lag - with ringing last.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JointConstraints {
+    pub priority: [Constraint; 3],
+    // How far a step may go past its target, as a percentage of the step.
+    pub max_overshoot_percent: f64,
+}
+
+impl Default for JointConstraints {
+    fn default() -> Self {
+        Self {
+            priority: [Constraint::Precision, Constraint::Lag, Constraint::Ringing],
+            max_overshoot_percent: 1.0,
+        }
+    }
+}
+
+/// How one candidate measured up, see JointConstraints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct CandidateScore {
+    // Relative miss per constraint, in priority order. Zero when within budget.
+    misses: [f64; 3],
+    precision: f64,
+}
+
+impl JointConstraints {
+    pub(crate) fn score(
+        &self,
+        precision: f64,
+        target_precision: f64,
+        response: &StepResponse,
+        max_lag_secs: f64,
+    ) -> CandidateScore {
+        let miss = |value: f64, budget: f64| ((value - budget) / budget.max(1e-9)).max(0.0);
+        let misses = self.priority.map(|constraint| match constraint {
+            Constraint::Precision => miss(precision, target_precision),
+            Constraint::Lag => miss(response.lag_secs, max_lag_secs),
+            Constraint::Ringing => miss(response.overshoot_percent, self.max_overshoot_percent),
+        });
+        CandidateScore { misses, precision }
+    }
+}
+
+impl CandidateScore {
+    pub(crate) fn beats(&self, other: &CandidateScore) -> bool {
+        for (ours, theirs) in self.misses.iter().zip(other.misses.iter()) {
+            match ours.partial_cmp(theirs) {
+                Some(Ordering::Less) => return true,
+                Some(Ordering::Greater) => return false,
+                _ => {}
+            }
+        }
+        self.precision < other.precision
+    }
+}
@@ -0,0 +1,134 @@
+use num::ToPrimitive;
+
+use crate::{
+    calibrator::CalibrationStage,
+    units::{StdDev, Variance},
+};
+
+/// Converts integer device counts (mouse counts, raw ADC values and the like) into physical or
+/// screen units, so calibration, precision targets and tuning all agree on what a unit is.
+/// Scale samples on the way in, and keep everything downstream - targets, amplitudes and the
+/// filter itself - in units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CountScale {
+    counts_per_unit: f64,
+    // Device value that corresponds to zero units, i.e. mid scale on an unsigned ADC.
+    zero_count: f64,
+}
+
+impl CountScale {
+    // i.e. 800 for an 800 DPI mouse reporting in inches, or 32768 / 16 for a +-16 g
+    // accelerometer with a 16 bit ADC reporting in g.
+    pub fn new(counts_per_unit: f64) -> Self {
+        Self {
+            counts_per_unit,
+            zero_count: 0.0,
+        }
+    }
+
+    pub fn with_zero_count(mut self, zero_count: f64) -> Self {
+        self.zero_count = zero_count;
+        self
+    }
+
+    pub fn counts_per_unit(&self) -> f64 {
+        self.counts_per_unit
+    }
+
+    // Counts that don't fit an f64 (only possible for u128 and the like) come through as NaN.
+    pub fn to_units<T: ToPrimitive>(&self, count: T) -> f64 {
+        (count.to_f64().unwrap_or(f64::NAN) - self.zero_count) / self.counts_per_unit
+    }
+
+    pub fn scale<T: ToPrimitive + Copy, const D: usize>(&self, counts: [T; D]) -> [f64; D] {
+        counts.map(|count| self.to_units(count))
+    }
+
+    // The other way, for showing results in counts.
+    pub fn to_counts(&self, units: f64) -> f64 {
+        units * self.counts_per_unit + self.zero_count
+    }
+
+    // Rounding to whole counts adds uniform noise of 1/12 of a count squared. If calibration
+    // reports noise near this, the device is quieter than its resolution and what's being
+    // measured is the quantization.
+    pub fn quantization_variance(&self) -> Variance {
+        Variance(StdDev(1.0 / self.counts_per_unit).variance().0 / 12.0)
+    }
+}
+
+/// CalibrationStage for devices reporting integer counts on D axis - i.e. two for a mouse.
+/// Noise is only estimated from the axis the device has, see CalibrationStage::with_axes, so D
+/// can be anything from 1 to 3.
+pub struct CountCalibrator<const D: usize = 3> {
+    scale: CountScale,
+    stage: CalibrationStage,
+}
+
+impl<const D: usize> CountCalibrator<D> {
+    // Checked at compile time.
+    const AXES: usize = {
+        assert!(D >= 1 && D <= 3, "CountCalibrator takes 1 to 3 axis");
+        D
+    };
+
+    pub fn new(scale: CountScale) -> Self {
+        Self {
+            scale,
+            stage: CalibrationStage::with_axes(Self::AXES),
+        }
+    }
+
+    // Returns true once the current stage has enough data.
+    pub fn process<T: ToPrimitive + Copy>(&mut self, counts: [T; D]) -> bool {
+        let units = self.scale.scale(counts);
+        let axis = |i: usize| units.get(i).copied().unwrap_or_default();
+        self.stage.process(axis(0), axis(1), axis(2))
+    }
+
+    pub fn next(self) -> Self {
+        Self {
+            stage: self.stage.advance(),
+            ..self
+        }
+    }
+
+    pub fn scale(&self) -> &CountScale {
+        &self.scale
+    }
+
+    // The stage, for tuning once done. Anything produced from it is in units.
+    pub fn into_stage(self) -> CalibrationStage {
+        self.stage
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_count_scale() {
+        let mouse = CountScale::new(800.0);
+        assert_eq!(mouse.scale([800i32, -400]), [1.0, -0.5]);
+        assert_eq!(mouse.to_counts(0.5), 400.0);
+
+        let adc = CountScale::new(2048.0).with_zero_count(32768.0);
+        assert_eq!(adc.to_units(34816u16), 1.0);
+
+        let quantization = mouse.quantization_variance().0;
+        assert!((quantization - 1.0 / (12.0 * 800.0 * 800.0)).abs() < 1e-15);
+
+        // A mouse jittering by 8 counts, calibrated on its two axis.
+        let mut noise = crate::synth::GaussianNoise::new(8.0, 103);
+        let mut count = || (1000.0 + noise.sample()).round() as i32;
+        let mut calibrator = CountCalibrator::<2>::new(mouse);
+        while !calibrator.process([count(), count()]) {}
+        for _ in 0..1200 {
+            calibrator.process([count(), count()]);
+        }
+        let stage = calibrator.next().into_stage().into_amplitude().unwrap();
+        let noise_units = stage.noise_std_dev().0;
+        assert!((noise_units - 0.01).abs() < 0.001, "{noise_units}");
+    }
+}
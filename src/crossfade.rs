@@ -0,0 +1,81 @@
+use crate::{tuner::FinalTuningSettings, units::Seconds};
+
+/// Moves min_cutoff and beta from one set of parameters to another over a fixed duration, so
+/// installing new tuning mid session changes the feel gradually rather than all at once. See
+/// LiveFilter::with_crossfade.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crossfade {
+    from: FinalTuningSettings,
+    to: FinalTuningSettings,
+    duration_secs: f64,
+    elapsed_secs: f64,
+}
+
+impl Crossfade {
+    pub fn new(from: &FinalTuningSettings, to: &FinalTuningSettings, duration: Seconds) -> Self {
+        Self {
+            from: from.clone(),
+            to: to.clone(),
+            duration_secs: duration.0,
+            elapsed_secs: 0.0,
+        }
+    }
+
+    // Moves dt seconds along the fade, returning the parameters to use from here.
+    pub fn advance(&mut self, dt: f64) -> FinalTuningSettings {
+        self.elapsed_secs += dt;
+        self.current()
+    }
+
+    // The parameters at the current point of the fade - linear in both, so beta can fade to or
+    // from zero.
+    pub fn current(&self) -> FinalTuningSettings {
+        if self.is_done() {
+            return self.to.clone();
+        }
+        let t = self.elapsed_secs / self.duration_secs;
+        FinalTuningSettings {
+            min_cutoff_hz: self.from.min_cutoff_hz
+                + t * (self.to.min_cutoff_hz - self.from.min_cutoff_hz),
+            beta: self.from.beta + t * (self.to.beta - self.from.beta),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.elapsed_secs >= self.duration_secs
+    }
+
+    pub fn target(&self) -> &FinalTuningSettings {
+        &self.to
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_crossfade() {
+        let from = FinalTuningSettings {
+            min_cutoff_hz: 1.0,
+            beta: 0.0,
+        };
+        let to = FinalTuningSettings {
+            min_cutoff_hz: 3.0,
+            beta: 0.5,
+        };
+        let mut fade = Crossfade::new(&from, &to, Seconds(1.0));
+        assert_eq!(fade.current(), from);
+
+        let halfway = fade.advance(0.5);
+        assert!((halfway.min_cutoff_hz - 2.0).abs() < 1e-12);
+        assert!((halfway.beta - 0.25).abs() < 1e-12);
+        assert!(!fade.is_done());
+
+        assert_eq!(fade.advance(0.6), to);
+        assert!(fade.is_done());
+
+        // Zero duration is an instant swap.
+        assert!(Crossfade::new(&from, &to, Seconds(0.0)).is_done());
+    }
+}
@@ -0,0 +1,347 @@
+use std::f64::consts::PI;
+
+use crate::{
+    calibrator::CalibrationStage,
+    dsp::Butterworth4,
+    estimators::RunningStatistics,
+    filter::{MultiAxisFilter, DERIVATIVE_CUTOFF_HZ},
+    rate,
+    tuner::{FinalTuningSettings, Tuner},
+    units::{Hertz, Seconds},
+};
+
+// Where the anti-alias filter cuts off, as a fraction of the decimated rate's Nyquist frequency.
+// Leaves room for the filter's roll off so little folds back into the monitored bins.
+const ANTI_ALIAS_FRACTION: f64 = 0.8;
+
+/// Reduces the sample rate of very high rate devices (1-8 kHz mice, 500 Hz trackers) by an
+/// integer factor, low passing first so noise above the new Nyquist frequency doesn't alias
+/// back into the band the noise estimators look at.
+#[derive(Debug, Clone)]
+pub struct Decimator<const D: usize> {
+    native_rate: f64,
+    factor: usize,
+    anti_alias: [Butterworth4; D],
+    phase: usize,
+}
+
+impl<const D: usize> Decimator<D> {
+    pub fn new(native_rate: f64, factor: usize) -> Self {
+        let factor = factor.max(1);
+        let internal_rate = native_rate / factor as f64;
+        let cutoff = ANTI_ALIAS_FRACTION * internal_rate / 2.0;
+
+        Self {
+            native_rate,
+            factor,
+            anti_alias: [Butterworth4::new(native_rate, cutoff); D],
+            phase: 0,
+        }
+    }
+
+    // Picks the smallest factor that brings the native rate down to at most target_rate.
+    pub fn for_target_rate(native_rate: f64, target_rate: f64) -> Self {
+        let factor = (native_rate / target_rate).ceil() as usize;
+        Self::new(native_rate, factor)
+    }
+
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    pub fn native_rate(&self) -> f64 {
+        self.native_rate
+    }
+
+    pub fn internal_rate(&self) -> f64 {
+        self.native_rate / self.factor as f64
+    }
+
+    // Pushes a native rate sample, returning a sample at the internal rate every factor calls.
+    pub fn push(&mut self, sample: [f64; D]) -> Option<[f64; D]> {
+        let filtered: [f64; D] = core::array::from_fn(|i| self.anti_alias[i].process(sample[i]));
+
+        self.phase += 1;
+        if self.phase < self.factor {
+            return None;
+        }
+        self.phase = 0;
+        Some(filtered)
+    }
+
+    pub fn reset(&mut self) {
+        self.phase = 0;
+        self.anti_alias.iter_mut().for_each(Butterworth4::reset);
+    }
+}
+
+/// Runs the one euro filter at the decimated rate while accepting and producing samples at the
+/// native rate. Between decimated samples the latest filtered value is held.
+///
+/// Settings should come from calibrating the decimated stream, i.e. by feeding the calibrator
+/// what Decimator::push returns.
+pub struct DecimatingFilter<const D: usize> {
+    decimator: Decimator<D>,
+    filter: MultiAxisFilter<D>,
+    last: Option<[f64; D]>,
+}
+
+impl<const D: usize> DecimatingFilter<D> {
+    pub fn new(decimator: Decimator<D>, settings: &FinalTuningSettings) -> Self {
+        Self {
+            filter: MultiAxisFilter::new(decimator.internal_rate(), settings),
+            decimator,
+            last: None,
+        }
+    }
+
+    // Filters a native rate sample. Until the first decimated sample comes through the input is
+    // passed through as is.
+    pub fn filter(&mut self, sample: [f64; D]) -> [f64; D] {
+        if let Some(decimated) = self.decimator.push(sample) {
+            self.last = Some(self.filter.filter(decimated));
+        }
+        self.last.unwrap_or(sample)
+    }
+
+    pub fn reset(&mut self) {
+        self.decimator.reset();
+        self.filter.reset();
+        self.last = None;
+    }
+}
+
+// The rate the tuner's tables are for.
+const TUNING_RATE: f64 = 60.0;
+
+/// Calibration for devices sampling in the kHz range - pen digitizers, 8 kHz mice. The
+/// estimators only ever see the stream decimated to 60 hz, so memory and CPU stay what they are
+/// for a 60 hz device, while tuning gives parameters for filtering every sample at the native
+/// rate.
+///
+/// min_cutoff is moved to the native rate the same way rate::translate moves it. Beta isn't:
+/// translate assumes the same white noise per sample at both rates, but decimating low passes the
+/// noise first, so the native stream is several times noisier per sample than the one that was
+/// tuned on - and its derivative with it, which would open the cutoff on jitter alone. So during
+/// the noise stage the filter's smoothed derivative is measured at both rates, and beta is scaled
+/// down by how much noisier it is natively.
+pub struct HighRateCalibrator {
+    decimator: Decimator<3>,
+    stage: CalibrationStage,
+    complete: bool,
+    native: DerivativeNoise,
+    decimated: DerivativeNoise,
+}
+
+impl HighRateCalibrator {
+    pub fn new(native_rate: f64) -> Self {
+        let decimator = Decimator::for_target_rate(native_rate, TUNING_RATE);
+        Self {
+            native: DerivativeNoise::new(native_rate),
+            decimated: DerivativeNoise::new(decimator.internal_rate()),
+            stage: CalibrationStage::new().with_sample_rate(Hertz(decimator.internal_rate())),
+            decimator,
+            complete: false,
+        }
+    }
+
+    // Processes a sample at the native rate. Returns true once the current stage has enough
+    // data.
+    pub fn process(&mut self, x: f64, y: f64, z: f64) -> bool {
+        let noise = self.stage.is_noise();
+        if noise {
+            self.native.update([x, y, z]);
+        }
+        if let Some(decimated) = self.decimator.push([x, y, z]) {
+            if noise {
+                self.decimated.update(decimated);
+            }
+            let [x, y, z] = decimated;
+            self.complete = self.stage.process(x, y, z);
+        }
+        self.complete
+    }
+
+    pub fn next(self) -> Self {
+        Self {
+            stage: self.stage.advance(),
+            complete: false,
+            ..self
+        }
+    }
+
+    pub fn native_rate(&self) -> f64 {
+        self.decimator.native_rate()
+    }
+
+    pub fn internal_rate(&self) -> f64 {
+        self.decimator.internal_rate()
+    }
+
+    // What beta is scaled by for filtering at the native rate. 1 until noise has been seen at
+    // both rates.
+    pub fn rate_correction(&self) -> f64 {
+        match (self.native.power(), self.decimated.power()) {
+            (Some(native), Some(decimated)) if native > 0.0 => (decimated / native).sqrt(),
+            _ => 1.0,
+        }
+    }
+
+    // Turns settings tuned on the decimated stream into settings for the native rate.
+    pub fn to_native(&self, settings: &FinalTuningSettings) -> FinalTuningSettings {
+        to_native(
+            settings,
+            Hertz(self.internal_rate()),
+            Hertz(self.native_rate()),
+            self.rate_correction(),
+        )
+    }
+
+    // Settings for filtering at the native rate, i.e. with spawn_filter(native_rate). None
+    // before amplitude calibration.
+    pub fn tune(self, least_precision: f64, worst_lag: Seconds) -> Option<FinalTuningSettings> {
+        let internal_rate = Hertz(self.internal_rate());
+        let native_rate = Hertz(self.native_rate());
+        let correction = self.rate_correction();
+        let amplitude = self.stage.into_amplitude()?;
+        let settings = Tuner::new(amplitude.tuning_settings(least_precision, worst_lag)).tune()?;
+        Some(to_native(&settings, internal_rate, native_rate, correction))
+    }
+}
+
+// See HighRateCalibrator: the cutoff as rate::translate moves it, beta by the measured correction.
+fn to_native(
+    settings: &FinalTuningSettings,
+    internal_rate: Hertz,
+    native_rate: Hertz,
+    beta_correction: f64,
+) -> FinalTuningSettings {
+    FinalTuningSettings {
+        min_cutoff_hz: rate::translate(settings, internal_rate, native_rate).min_cutoff_hz,
+        beta: settings.beta * beta_correction,
+    }
+}
+
+// The one euro filter's smoothed derivative on noise, at one rate.
+struct DerivativeNoise {
+    sample_rate: f64,
+    alpha: f64,
+    last: Option<[f64; 3]>,
+    smoothed: [f64; 3],
+    power: RunningStatistics,
+}
+
+impl DerivativeNoise {
+    fn new(sample_rate: f64) -> Self {
+        let tau = 1.0 / (2.0 * PI * DERIVATIVE_CUTOFF_HZ);
+        Self {
+            sample_rate,
+            alpha: 1.0 / (1.0 + tau * sample_rate),
+            last: None,
+            smoothed: [0.0; 3],
+            power: RunningStatistics::new(),
+        }
+    }
+
+    fn update(&mut self, sample: [f64; 3]) {
+        if let Some(last) = self.last.replace(sample) {
+            for i in 0..3 {
+                let dx = (sample[i] - last[i]) * self.sample_rate;
+                self.smoothed[i] += self.alpha * (dx - self.smoothed[i]);
+                self.power.update(self.smoothed[i] * self.smoothed[i]);
+            }
+        }
+    }
+
+    fn power(&self) -> Option<f64> {
+        (self.power.count() > 0).then(|| self.power.mean())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_decimates_without_transient() {
+        let mut decimator = Decimator::<1>::for_target_rate(1000.0, 60.0);
+        assert_eq!(decimator.factor(), 17);
+
+        let out: Vec<f64> = (0..170)
+            .filter_map(|_| decimator.push([5.0]))
+            .map(|s| s[0])
+            .collect();
+
+        assert_eq!(out.len(), 10);
+        assert!(out.iter().all(|x| (x - 5.0).abs() < 1e-9));
+    }
+
+    #[test]
+    pub fn test_high_rate_calibration() {
+        use crate::synth::GaussianNoise;
+
+        let rate = 1000.0;
+        let mut noise = GaussianNoise::new(0.5, 73);
+        let mut calibrator = HighRateCalibrator::new(rate);
+        assert_eq!(calibrator.internal_rate(), rate / 17.0);
+        while !calibrator.process(noise.sample(), noise.sample(), noise.sample()) {}
+
+        // The derivative of white noise gets noisier with rate, so beta has to come down a lot.
+        let correction = calibrator.rate_correction();
+        assert!(correction > 0.02 && correction < 0.5, "{correction}");
+
+        // Filtering every sample with corrected settings jitters about as much as filtering
+        // the decimated stream, where uncorrected ones open up on noise.
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 0.5,
+            beta: 0.5,
+        };
+        let jitter = |settings: &FinalTuningSettings, noise: &mut GaussianNoise| {
+            let mut filter = settings.spawn_filter::<1>(rate);
+            let mut jitter = RunningStatistics::new();
+            for i in 0..(20.0 * rate) as usize {
+                let x = filter.filter([noise.sample()])[0];
+                // Past the filter settling on the first sample.
+                if i as f64 > 5.0 * rate {
+                    jitter.update(x);
+                }
+            }
+            jitter.population_variance().unwrap().sqrt()
+        };
+        // The cutoff moves with rate::translate, but beta by far more than translate would
+        // scale it, as the native stream is noisier per sample than the decimated one.
+        let native_settings = calibrator.to_native(&settings);
+        let translated = rate::translate(&settings, Hertz(rate / 17.0), Hertz(rate));
+        assert_eq!(native_settings.min_cutoff_hz, translated.min_cutoff_hz);
+        assert!(native_settings.beta < 0.5 * translated.beta);
+        let native = jitter(&native_settings, &mut noise);
+        let uncorrected = jitter(&settings, &mut noise);
+        let mut decimator = Decimator::<1>::for_target_rate(rate, TUNING_RATE);
+        let mut filter = settings.spawn_filter::<1>(decimator.internal_rate());
+        let mut decimated = RunningStatistics::new();
+        for i in 0..20 * rate as usize {
+            if let Some(sample) = decimator.push([noise.sample()]) {
+                let x = filter.filter(sample)[0];
+                if i as f64 > 5.0 * rate {
+                    decimated.update(x);
+                }
+            }
+        }
+        let decimated = decimated.population_variance().unwrap().sqrt();
+        assert!(native < 1.5 * decimated && uncorrected > 1.5 * native);
+
+        // Tuned for the stream the estimators actually saw.
+        let mut calibrator = calibrator.next();
+        match &calibrator.stage {
+            CalibrationStage::Amplitude(amplitude) => {
+                assert_eq!(amplitude.sample_rate(), Hertz(rate / 17.0));
+            }
+            CalibrationStage::Noise(_) => panic!("still in the noise stage"),
+        }
+        for i in 0..5000 {
+            let x = 20.0 * (i as f64 / rate * 2.0 * PI).sin();
+            calibrator.process(x, 0.0, 0.0);
+        }
+        assert!(calibrator.tune(2.0, Seconds(0.1)).is_some());
+    }
+}
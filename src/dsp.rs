@@ -0,0 +1,226 @@
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+use crate::math;
+
+/// A second order IIR section (transposed direct form II), with coefficients from the RBJ audio
+/// EQ cookbook.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    s1: f64,
+    s2: f64,
+}
+
+impl Biquad {
+    fn normalized(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b0: b[0] / a[0],
+            b1: b[1] / a[0],
+            b2: b[2] / a[0],
+            a1: a[1] / a[0],
+            a2: a[2] / a[0],
+            s1: 0.0,
+            s2: 0.0,
+        }
+    }
+
+    pub fn low_pass(sample_rate: f64, cutoff_hz: f64, q: f64) -> Self {
+        let w0 = 2.0 * PI * cutoff_hz / sample_rate;
+        let alpha = math::sin(w0) / (2.0 * q);
+        let cos = math::cos(w0);
+
+        Self::normalized(
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    pub fn high_pass(sample_rate: f64, cutoff_hz: f64, q: f64) -> Self {
+        let w0 = 2.0 * PI * cutoff_hz / sample_rate;
+        let alpha = math::sin(w0) / (2.0 * q);
+        let cos = math::cos(w0);
+
+        Self::normalized(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    // Rejects a narrow band around center_hz, passing everything else. Bandwidth is roughly
+    // center_hz / q.
+    pub fn notch(sample_rate: f64, center_hz: f64, q: f64) -> Self {
+        let w0 = 2.0 * PI * center_hz / sample_rate;
+        let alpha = math::sin(w0) / (2.0 * q);
+        let cos = math::cos(w0);
+
+        Self::normalized(
+            [1.0, -2.0 * cos, 1.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.s1;
+        self.s1 = self.b1 * x - self.a1 * y + self.s2;
+        self.s2 = self.b2 * x - self.a2 * y;
+        y
+    }
+
+    // Settles the filter as if it had seen x forever, so starting on a non-zero signal doesn't
+    // produce a transient.
+    pub fn prime(&mut self, x: f64) {
+        let dc_gain = (self.b0 + self.b1 + self.b2) / (1.0 + self.a1 + self.a2);
+        let y = x * dc_gain;
+        self.s1 = y - self.b0 * x;
+        self.s2 = self.b2 * x - self.a2 * y;
+    }
+
+    pub fn reset(&mut self) {
+        self.s1 = 0.0;
+        self.s2 = 0.0;
+    }
+}
+
+// Q factors of the two sections making up a 4th order Butterworth low pass.
+const BUTTERWORTH_4_Q: [f64; 2] = [0.541_196_1, 1.306_563];
+
+/// A 4th order Butterworth low pass, built from two biquads.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Butterworth4 {
+    sections: [Biquad; 2],
+    primed: bool,
+}
+
+impl Butterworth4 {
+    pub fn new(sample_rate: f64, cutoff_hz: f64) -> Self {
+        Self {
+            sections: BUTTERWORTH_4_Q.map(|q| Biquad::low_pass(sample_rate, cutoff_hz, q)),
+            primed: false,
+        }
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        if !self.primed {
+            self.primed = true;
+            self.sections.iter_mut().for_each(|s| s.prime(x));
+        }
+        self.sections.iter_mut().fold(x, |x, s| s.process(x))
+    }
+
+    pub fn reset(&mut self) {
+        self.primed = false;
+        self.sections.iter_mut().for_each(Biquad::reset);
+    }
+}
+
+/// How a `DcRemover` separates the slow offset from the signal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DcRemoval {
+    /// A 2nd order Butterworth high pass. Sharper, so it keeps more of the signal just above the
+    /// cutoff.
+    HighPass { cutoff_hz: f64 },
+    /// Tracks the offset with a slow exponential average and subtracts it, the way gravity is
+    /// usually pulled out of accelerometer data.
+    Complementary { time_constant_secs: f64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DcState<const D: usize> {
+    HighPass([Biquad; D]),
+    Complementary { alpha: f64 },
+}
+
+/// Strips the DC offset (gravity, sensor bias, slow drift) from each axis so it can't
+/// contaminate noise statistics downstream. Works in front of any estimator or filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DcRemover<const D: usize> {
+    state: DcState<D>,
+    offset: Option<[f64; D]>,
+}
+
+impl<const D: usize> DcRemover<D> {
+    pub fn new(sample_rate: f64, removal: DcRemoval) -> Self {
+        let state = match removal {
+            DcRemoval::HighPass { cutoff_hz } => DcState::HighPass(
+                [(); D].map(|_| Biquad::high_pass(sample_rate, cutoff_hz, FRAC_1_SQRT_2)),
+            ),
+            DcRemoval::Complementary { time_constant_secs } => {
+                let dt = 1.0 / sample_rate;
+                DcState::Complementary {
+                    alpha: dt / (time_constant_secs + dt),
+                }
+            }
+        };
+
+        Self {
+            state,
+            offset: None,
+        }
+    }
+
+    // Returns the sample with its offset removed. The first sample is taken as the offset
+    // outright, so there's no long settling period from zero.
+    pub fn process(&mut self, sample: [f64; D]) -> [f64; D] {
+        let offset = match (&mut self.state, self.offset) {
+            (DcState::HighPass(sections), previous) => {
+                if previous.is_none() {
+                    sections
+                        .iter_mut()
+                        .zip(sample)
+                        .for_each(|(s, x)| s.prime(x));
+                }
+                core::array::from_fn(|i| sample[i] - sections[i].process(sample[i]))
+            }
+            (DcState::Complementary { .. }, None) => sample,
+            (DcState::Complementary { alpha }, Some(o)) => {
+                core::array::from_fn(|i| o[i] + *alpha * (sample[i] - o[i]))
+            }
+        };
+        self.offset = Some(offset);
+        core::array::from_fn(|i| sample[i] - offset[i])
+    }
+
+    // The offset removed from the last sample, e.g. the gravity vector.
+    pub fn offset(&self) -> Option<[f64; D]> {
+        self.offset
+    }
+
+    pub fn reset(&mut self) {
+        self.offset = None;
+        if let DcState::HighPass(sections) = &mut self.state {
+            sections.iter_mut().for_each(Biquad::reset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_dc_remover() {
+        for removal in [
+            DcRemoval::HighPass { cutoff_hz: 0.5 },
+            DcRemoval::Complementary {
+                time_constant_secs: 2.0,
+            },
+        ] {
+            let mut remover = DcRemover::<3>::new(60.0, removal);
+            let mut out = [0.0; 3];
+            for i in 0..600 {
+                let wobble = if i % 2 == 0 { 0.01 } else { -0.01 };
+                out = remover.process([wobble, 9.81 + wobble, -3.0 + wobble]);
+            }
+
+            for x in out {
+                assert!(x.abs() < 0.02, "{removal:?}: {out:?}");
+            }
+            let offset = remover.offset().unwrap();
+            assert!((offset[1] - 9.81).abs() < 0.05, "{removal:?}: {offset:?}");
+        }
+    }
+}
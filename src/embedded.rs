@@ -0,0 +1,391 @@
+//! Allocation free calibration for firmware doing on-device recalibration. Everything here is
+//! sized at compile time through const generics and lives on the stack (or in a static), so no
+//! allocator is needed.
+//!
+//! Tuning itself still needs the precision table, so the usual flow is to recalibrate on-device
+//! and send the resulting EmbeddedCalibration to a host for tuning (or compare it against the
+//! calibration the current tuning was made for). The runtime MultiAxisFilter is already
+//! allocation free.
+//!
+//! Noise estimation splits into NoiseBins, cheap enough for an interrupt handler, and
+//! NoiseAggregator for the main loop, with a VarianceQueue between them.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::{
+    estimators::{MaxDistanceEstimator, MemoryFootprint, NoiseEstimator, RunningStatistics},
+    units::{StdDev, Variance},
+    wcet::{BoundedWork, WorkBound},
+};
+
+/// The per sample half of SingleAxisNoiseEstimator - BINS frequency bins counting down from the
+/// Nyquist frequency, over a circular buffer of N samples (usually one second's worth). Cheap
+/// and fixed cost enough for an interrupt handler, with each bin's variance handed to a sink -
+/// i.e. a VarianceQueue drained into a NoiseAggregator from the main loop.
+pub struct NoiseBins<const N: usize, const BINS: usize> {
+    bins: [NoiseEstimator<N>; BINS],
+}
+
+impl<const N: usize, const BINS: usize> NoiseBins<N, BINS> {
+    pub fn new() -> Self {
+        Self {
+            bins: core::array::from_fn(NoiseEstimator::new),
+        }
+    }
+
+    // For placing the bins directly in a static. Per bin setup happens on the first update
+    // instead, or with the bounded-time feature on prepare, see NoiseEstimator::new_const.
+    pub const fn new_const() -> Self {
+        let mut bins = [const { NoiseEstimator::new_const(0) }; BINS];
+        let mut i = 0;
+        while i < BINS {
+            bins[i].monitor_hz = i;
+            i += 1;
+        }
+        Self { bins }
+    }
+
+    // Sets up every bin, see NoiseEstimator::prepare.
+    pub fn prepare(&mut self) {
+        self.bins.iter_mut().for_each(NoiseEstimator::prepare);
+    }
+
+    // Updates every bin, passing on each variance estimate. Nothing comes out for the first N
+    // samples.
+    pub fn update(&mut self, sample: f64, mut sink: impl FnMut(f64)) {
+        for bin in self.bins.iter_mut() {
+            bin.update(sample);
+
+            if let Some(variance) = bin.variance() {
+                sink(variance);
+            }
+        }
+    }
+}
+
+impl<const N: usize, const BINS: usize> Default for NoiseBins<N, BINS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, const BINS: usize> BoundedWork for NoiseBins<N, BINS> {
+    fn work_bound(&self) -> WorkBound {
+        WorkBound::bins(BINS)
+    }
+}
+
+/// The aggregating half of SingleAxisNoiseEstimator - the running mean and confidence interval
+/// over bin variances, and the check against the threshold. Belongs in the main loop.
+#[derive(Debug, Clone)]
+pub struct NoiseAggregator {
+    stats: RunningStatistics,
+
+    // Used to determine when the 95% confidence interval determines that we are within the given
+    // threshold of the mean.
+    //
+    // 0.1 is the typical default value.
+    threshold: f64,
+}
+
+impl NoiseAggregator {
+    pub const fn new(threshold: f64) -> Self {
+        Self {
+            stats: RunningStatistics::new(),
+            threshold,
+        }
+    }
+
+    pub fn add(&mut self, variance: f64) {
+        self.stats.update(variance);
+    }
+
+    // Adds everything queued so far. Returns true once the 95% CI width is within the threshold
+    // of the mean.
+    pub fn drain<const CAP: usize>(&mut self, queue: &VarianceQueue<CAP>) -> bool {
+        while let Some(variance) = queue.pop() {
+            self.add(variance);
+        }
+        self.converged()
+    }
+
+    pub fn converged(&self) -> bool {
+        self.stats.converged(self.threshold)
+    }
+
+    pub fn mean_variance(&self) -> Variance {
+        Variance(self.stats.mean)
+    }
+}
+
+/// A lock free single producer, single consumer queue of variances, from NoiseBins in an
+/// interrupt handler to a NoiseAggregator in the main loop. Both sides only load and store, so
+/// it works on targets without compare and swap. Pushing to a full queue drops the variance -
+/// the estimate just takes a little longer - and counts it in dropped.
+///
+/// Only one context may push and one pop. Anything else won't corrupt memory, but can lose or
+/// repeat variances.
+pub struct VarianceQueue<const CAP: usize> {
+    slots: [AtomicU64; CAP],
+    // Next to pop and next to push, counting up forever.
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+impl<const CAP: usize> VarianceQueue<CAP> {
+    // Const, so the queue can live in a static shared by the handler and the main loop.
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { AtomicU64::new(0) }; CAP],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    // Producer side. Returns false if the queue was full.
+    pub fn push(&self, variance: f64) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail.wrapping_sub(self.head.load(Ordering::Acquire)) >= CAP {
+            let dropped = self.dropped.load(Ordering::Relaxed);
+            self.dropped
+                .store(dropped.saturating_add(1), Ordering::Relaxed);
+            return false;
+        }
+        self.slots[tail % CAP].store(variance.to_bits(), Ordering::Relaxed);
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    // Consumer side.
+    pub fn pop(&self) -> Option<f64> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        let variance = f64::from_bits(self.slots[head % CAP].load(Ordering::Relaxed));
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(variance)
+    }
+
+    // Variances pushed to a full queue so far. If this keeps growing, drain more often or
+    // make the queue bigger - it needs to hold BINS variances per sample between drains.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<const CAP: usize> Default for VarianceQueue<CAP> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Noise estimator for a single axis monitoring BINS frequency bins, counting down from the
+/// Nyquist frequency. N is the circular buffer size - usually one second of samples.
+///
+/// Fewer bins means less RAM and CPU per sample at the cost of a noisier estimate. To run the
+/// per sample part in an interrupt handler, use NoiseBins and NoiseAggregator separately.
+pub struct SingleAxisNoiseEstimator<const N: usize, const BINS: usize> {
+    bins: NoiseBins<N, BINS>,
+    aggregator: NoiseAggregator,
+}
+
+impl<const N: usize, const BINS: usize> SingleAxisNoiseEstimator<N, BINS> {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            bins: NoiseBins::new(),
+            aggregator: NoiseAggregator::new(threshold),
+        }
+    }
+
+    // For placing the estimator directly in a static, see NoiseBins::new_const.
+    pub const fn new_const(threshold: f64) -> Self {
+        Self {
+            bins: NoiseBins::new_const(),
+            aggregator: NoiseAggregator::new(threshold),
+        }
+    }
+
+    pub const fn memory_footprint() -> MemoryFootprint {
+        MemoryFootprint::inline::<Self>()
+    }
+
+    // Sets up every bin, see NoiseEstimator::prepare.
+    pub fn prepare(&mut self) {
+        self.bins.prepare();
+    }
+
+    // Returns true once the 95% CI width is within a given threshold of the mean.
+    pub fn update(&mut self, sample: f64) -> bool {
+        let aggregator = &mut self.aggregator;
+        self.bins
+            .update(sample, |variance| aggregator.add(variance));
+        aggregator.converged()
+    }
+
+    pub fn mean_variance(&self) -> Variance {
+        self.aggregator.mean_variance()
+    }
+}
+
+impl<const N: usize, const BINS: usize> BoundedWork for SingleAxisNoiseEstimator<N, BINS> {
+    fn work_bound(&self) -> WorkBound {
+        WorkBound::bins(BINS)
+    }
+}
+
+/// Result of an on-device calibration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmbeddedCalibration {
+    pub noise_std_dev: StdDev,
+    pub max_amplitude: f64,
+}
+
+/// Single axis noise then amplitude calibration without any allocation.
+pub struct EmbeddedCalibrator<const N: usize, const BINS: usize> {
+    noise: SingleAxisNoiseEstimator<N, BINS>,
+    amplitude: MaxDistanceEstimator,
+    noise_done: bool,
+}
+
+impl<const N: usize, const BINS: usize> EmbeddedCalibrator<N, BINS> {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            noise: SingleAxisNoiseEstimator::new(threshold),
+            amplitude: MaxDistanceEstimator::new(),
+            noise_done: false,
+        }
+    }
+
+    // i.e. `static mut CALIBRATOR: EmbeddedCalibrator<100, 20> = EmbeddedCalibrator::new_const(0.1);`
+    pub const fn new_const(threshold: f64) -> Self {
+        Self {
+            noise: SingleAxisNoiseEstimator::new_const(threshold),
+            amplitude: MaxDistanceEstimator::new(),
+            noise_done: false,
+        }
+    }
+
+    // Everything is inline, so this is exactly what needs reserving in static memory.
+    pub const fn memory_footprint() -> MemoryFootprint {
+        MemoryFootprint::inline::<Self>()
+    }
+
+    // Sets up the noise bins, see NoiseEstimator::prepare.
+    pub fn prepare(&mut self) {
+        self.noise.prepare();
+    }
+
+    // Processes a sample of idle noise. Returns true once noise estimation has converged, after
+    // which samples should go to process_amplitude instead.
+    pub fn process_noise(&mut self, sample: f64) -> bool {
+        if !self.noise_done {
+            self.noise_done = self.noise.update(sample);
+        }
+        self.noise_done
+    }
+
+    pub fn process_amplitude(&mut self, sample: f64) {
+        self.amplitude.update(sample, self.noise_std_dev().0);
+    }
+
+    pub fn noise_std_dev(&self) -> StdDev {
+        self.noise.mean_variance().std_dev()
+    }
+
+    pub fn calibration(&self) -> EmbeddedCalibration {
+        EmbeddedCalibration {
+            noise_std_dev: self.noise_std_dev(),
+            max_amplitude: self.amplitude.max_within_reason(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+
+    static mut CALIBRATOR: EmbeddedCalibrator<60, 20> = EmbeddedCalibrator::new_const(0.1);
+
+    #[test]
+    pub fn test_const_matches_runtime() {
+        let mut runtime = EmbeddedCalibrator::<60, 20>::new(0.1);
+        // Only this test touches the static.
+        #[allow(static_mut_refs)]
+        let constant = unsafe { &mut CALIBRATOR };
+        constant.prepare();
+
+        let mut noise = GaussianNoise::new(0.5, 7);
+        for _ in 0..300 {
+            let sample = noise.sample();
+            assert_eq!(
+                runtime.process_noise(sample),
+                constant.process_noise(sample)
+            );
+        }
+        assert_eq!(runtime.noise_std_dev(), constant.noise_std_dev());
+    }
+
+    #[test]
+    pub fn test_split_matches_monolithic() {
+        static QUEUE: VarianceQueue<64> = VarianceQueue::new();
+        let mut whole = SingleAxisNoiseEstimator::<60, 20>::new(0.1);
+        let mut bins = NoiseBins::<60, 20>::new();
+        let mut aggregator = NoiseAggregator::new(0.1);
+
+        let mut noise = GaussianNoise::new(0.5, 83);
+        for _ in 0..300 {
+            let sample = noise.sample();
+            // The handler's side...
+            bins.update(sample, |variance| {
+                QUEUE.push(variance);
+            });
+            // ...and the main loop's.
+            assert_eq!(whole.update(sample), aggregator.drain(&QUEUE));
+        }
+        assert_eq!(whole.mean_variance(), aggregator.mean_variance());
+        assert_eq!(QUEUE.dropped(), 0);
+
+        // Falling behind drops variances rather than blocking the handler.
+        for _ in 0..4 {
+            bins.update(noise.sample(), |variance| {
+                QUEUE.push(variance);
+            });
+        }
+        assert_eq!(QUEUE.dropped(), 16);
+        aggregator.drain(&QUEUE);
+        assert_eq!(QUEUE.pop(), None);
+    }
+
+    #[test]
+    pub fn test_calibration() {
+        let mut calibrator = EmbeddedCalibrator::<60, 20>::new(0.1);
+        calibrator.prepare();
+        let mut noise = GaussianNoise::new(0.5, 29);
+        let mut samples = 0;
+        while !calibrator.process_noise(noise.sample()) {
+            samples += 1;
+            assert!(samples < 10_000, "noise never converged");
+        }
+        // Once converged, more noise doesn't move the estimate.
+        let std_dev = calibrator.noise_std_dev();
+        assert!(calibrator.process_noise(100.0));
+        assert_eq!(calibrator.noise_std_dev(), std_dev);
+        assert!((std_dev.0 - 0.5).abs() < 0.075, "{std_dev:?}");
+
+        // Moves within 3 std devs read as noise, so only the jumps of 5 count.
+        for sample in [0.0, 1.0, 0.0, 5.0, 0.0, 5.0, 0.0, 5.0, 0.0, 5.0, 0.0] {
+            calibrator.process_amplitude(sample);
+        }
+        assert_eq!(
+            calibrator.calibration(),
+            EmbeddedCalibration {
+                noise_std_dev: std_dev,
+                max_amplitude: 5.0,
+            }
+        );
+    }
+}
@@ -0,0 +1,27 @@
+//! Precision tables generated and embedded at build time, for exactly the sample rates a
+//! downstream crate needs - no runtime generation cost and no unused tables in the binary.
+//!
+//! List the sample rates (in hz, one per line) in a file and point the PITCH_PIPE_TABLES
+//! environment variable at it, i.e. in .cargo/config.toml:
+//!
+//! ```toml
+//! [env]
+//! PITCH_PIPE_TABLES = { value = "pitch-pipe-tables.txt", relative = true }
+//! ```
+//!
+//! Generating a table is around 150k short simulations. Build scripts are unoptimized by
+//! default, so also set `[profile.dev.build-override] opt-level = 3` or debug builds will take a
+//! while.
+
+// With no rates configured, the generated packed_for is a match with only a wildcard arm.
+#![allow(clippy::match_single_binding)]
+
+use crate::tuner::Grid;
+
+include!(concat!(env!("OUT_DIR"), "/tables.rs"));
+
+// A Grid for the sample rate, if its table was embedded. Reads from the packed copy, so nothing
+// is copied out of the binary.
+pub fn grid_for(sample_rate: u32) -> Option<Grid> {
+    Grid::from_packed(packed_for(sample_rate)?).ok()
+}
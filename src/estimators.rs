@@ -1,21 +1,87 @@
+use std::{error::Error, fmt};
+
 use circular_buffer::CircularBuffer;
-use num::{complex::ComplexFloat, pow::Pow, Complex};
+use num::Complex;
+
+use crate::{
+    dsp::{DcRemoval, DcRemover},
+    math,
+    units::{StdDev, Variance},
+    wcet::{BoundedWork, WorkBound},
+};
+
+/// How much memory a calibration type takes for a given set of const parameters. Inline bytes
+/// live wherever the value itself does (stack, static or inside a Box), heap bytes are owned
+/// allocations on top of that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryFootprint {
+    pub inline_bytes: usize,
+    pub heap_bytes: usize,
+}
+
+impl MemoryFootprint {
+    // Everything stored inline, nothing on the heap.
+    pub const fn inline<T>() -> Self {
+        Self {
+            inline_bytes: core::mem::size_of::<T>(),
+            heap_bytes: 0,
+        }
+    }
+
+    pub const fn total_bytes(&self) -> usize {
+        self.inline_bytes + self.heap_bytes
+    }
+}
+
+/// Which divisor a variance is computed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarianceDivisor {
+    // n - 1, the unbiased estimate when the values are a sample of something larger.
+    Sample,
+    // n, when the values are the whole population.
+    Population,
+}
+
+/// A 95% confidence interval around a mean.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConfidenceInterval {
+    pub lower: f64,
+    pub upper: f64,
+    pub half_width: f64,
+}
+
+/// Everything RunningStatistics needs to pick up where it left off.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RunningStatisticsState {
+    pub count: u64,
+    pub mean: f64,
+    pub m2: f64,
+    pub max: f64,
+}
 
 /// Can be used to aggregate variance data, using the Welford algorithm:
 /// https://en.wikipedia.org/wiki/Algorithms_for_calculating_variance
 ///
 /// It also stores an active ci95 value, otherwise known as the 95% confidence interval.
+#[derive(Debug, Clone)]
 pub struct RunningStatistics {
     count: u64,
-    mean: f64,
+    pub(crate) mean: f64,
     m2: f64,
     sample_variance: f64,
     max: f64,
-    ci95: f64,
+    pub(crate) ci95: f64,
 }
 
 impl Default for RunningStatistics {
     fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RunningStatistics {
+    pub const fn new() -> Self {
         Self {
             count: 0,
             mean: 0.0,
@@ -25,9 +91,83 @@ impl Default for RunningStatistics {
             max: f64::MIN,
         }
     }
-}
 
-impl RunningStatistics {
+    // Starts from count values that averaged mean with the given standard deviation, as if they
+    // had already been seen. Later values are weighed against them like any other.
+    pub fn with_prior(mean: f64, std_dev: f64, count: u64) -> Self {
+        if count == 0 {
+            return Self::new();
+        }
+        let m2 = std_dev * std_dev * count.saturating_sub(1) as f64;
+        let sample_variance = if count > 1 {
+            m2 / (count - 1) as f64
+        } else {
+            0.0
+        };
+        Self {
+            count,
+            mean,
+            m2,
+            sample_variance,
+            ci95: 1.96 * (sample_variance / count as f64).sqrt(),
+            max: mean,
+        }
+    }
+
+    // Everything needed to rebuild these statistics exactly, see from_state.
+    pub fn state(&self) -> RunningStatisticsState {
+        RunningStatisticsState {
+            count: self.count,
+            mean: self.mean,
+            m2: self.m2,
+            max: self.max,
+        }
+    }
+
+    pub fn from_state(state: &RunningStatisticsState) -> Self {
+        let sample_variance = if state.count > 1 {
+            state.m2 / (state.count - 1) as f64
+        } else {
+            0.0
+        };
+        Self {
+            count: state.count,
+            mean: state.mean,
+            m2: state.m2,
+            sample_variance,
+            ci95: if state.count > 0 {
+                1.96 * (sample_variance / state.count as f64).sqrt()
+            } else {
+                0.0
+            },
+            max: state.max,
+        }
+    }
+
+    // Combines with statistics gathered separately, as if every value had gone through one
+    // instance. Uses Chan's parallel form of Welford's algorithm.
+    pub fn merge(&mut self, other: &RunningStatistics) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = other.clone();
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        let mean = self.mean + delta * other.count as f64 / count as f64;
+        let m2 = self.m2
+            + other.m2
+            + delta * delta * (self.count as f64 * other.count as f64) / count as f64;
+        *self = Self::from_state(&RunningStatisticsState {
+            count,
+            mean,
+            m2,
+            max: self.max.max(other.max),
+        });
+    }
+
     pub fn update(&mut self, val: f64) {
         self.count += 1;
         let delta = val - self.mean;
@@ -40,6 +180,68 @@ impl RunningStatistics {
         self.sample_variance = self.m2 / (self.count - 1) as f64;
         self.ci95 = 1.96 * (self.sample_variance / self.count as f64).sqrt();
     }
+
+    // Whether the 95% CI width is within the given fraction of the mean.
+    pub(crate) fn converged(&self, threshold: f64) -> bool {
+        (2.0 * self.ci95) / self.mean < threshold
+    }
+
+    // How close the CI width is to the given fraction of the mean, from 0 to 1. Reaches 1 when
+    // converged returns true.
+    pub(crate) fn convergence(&self, threshold: f64) -> f64 {
+        let ratio = (2.0 * self.ci95) / self.mean;
+        if self.count < 2 || !ratio.is_finite() || ratio <= 0.0 {
+            return 0.0;
+        }
+        (threshold / ratio).min(1.0)
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    // The largest value seen, if any.
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    // None until there are enough values for the divisor to be non-zero. Sample variance needs
+    // two.
+    pub fn variance(&self, divisor: VarianceDivisor) -> Option<f64> {
+        let n = match divisor {
+            VarianceDivisor::Sample => self.count.checked_sub(1)?,
+            VarianceDivisor::Population => self.count,
+        };
+        (n > 0).then(|| self.m2 / n as f64)
+    }
+
+    pub fn sample_variance(&self) -> Option<f64> {
+        self.variance(VarianceDivisor::Sample)
+    }
+
+    pub fn population_variance(&self) -> Option<f64> {
+        self.variance(VarianceDivisor::Population)
+    }
+
+    // Standard error of the mean.
+    pub fn standard_error(&self, divisor: VarianceDivisor) -> Option<f64> {
+        Some((self.variance(divisor)? / self.count as f64).sqrt())
+    }
+
+    // The 95% confidence interval of the mean, using the normal approximation. The calibrators
+    // stop on the sample divisor version of this.
+    pub fn confidence_interval(&self, divisor: VarianceDivisor) -> Option<ConfidenceInterval> {
+        let half_width = 1.96 * self.standard_error(divisor)?;
+        Some(ConfidenceInterval {
+            lower: self.mean - half_width,
+            upper: self.mean + half_width,
+            half_width,
+        })
+    }
 }
 
 #[derive(Default)]
@@ -55,26 +257,24 @@ pub struct MaxDistanceEstimator {
 }
 
 impl MaxDistanceEstimator {
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         Self {
             previous: None,
             speeds: [0.0; 5],
         }
     }
 
+    pub const fn memory_footprint() -> MemoryFootprint {
+        MemoryFootprint::inline::<Self>()
+    }
+
     pub fn update(&mut self, sample: f64, stddev: f64) {
         if let Some(previous) = self.previous {
             let delta = (previous - sample).abs();
 
             if delta > (3.0 * stddev) {
-                // Unwrap is safe - the array will never be empty.
-                let min = self
-                    .speeds
-                    .iter_mut()
-                    .min_by(|a, b| a.total_cmp(b))
-                    .unwrap();
-
-                if delta > *min {
+                let min = self.speeds.iter_mut().min_by(|a, b| a.total_cmp(b));
+                if let Some(min) = min.filter(|min| delta > **min) {
                     *min = delta;
                 }
             }
@@ -88,7 +288,11 @@ impl MaxDistanceEstimator {
     /// At any rate, this is the lowest of the 5 maximum values - so we should just clearly call it
     /// that.
     pub fn max_within_reason(&self) -> f64 {
-        *self.speeds.iter().min_by(|a, b| a.total_cmp(b)).unwrap()
+        self.speeds
+            .iter()
+            .copied()
+            .min_by(f64::total_cmp)
+            .unwrap_or_default()
     }
 }
 
@@ -109,6 +313,10 @@ impl ThreeAxisMaxDistanceEstimator {
         }
     }
 
+    pub const fn memory_footprint() -> MemoryFootprint {
+        MemoryFootprint::inline::<Self>()
+    }
+
     pub fn update(&mut self, x: f64, y: f64, z: f64) {
         self.x.update(x, self.noise_std_dev);
         self.y.update(y, self.noise_std_dev);
@@ -123,6 +331,19 @@ impl ThreeAxisMaxDistanceEstimator {
     }
 }
 
+/// The internals of a NoiseEstimator, for checkpointing a calibration in progress.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NoiseEstimatorSnapshot {
+    pub monitor_hz: usize,
+    // The last N samples, oldest first. Empty if the estimator was never updated.
+    pub samples: Vec<f64>,
+    pub power: f64,
+    pub count: u64,
+    // Running DFT terms for the bin and its two neighbours, as (re, im).
+    pub terms: [(f64, f64); 3],
+}
+
 /// Estimates power spectral density on the monitor_hz frequency
 /// in order to estimate Gaussian white noise variance in
 /// an input device signal. When using, ensure the user is
@@ -140,6 +361,10 @@ impl ThreeAxisMaxDistanceEstimator {
 pub struct NoiseEstimator<const N: usize> {
     // Sample frequency as an integer. Should be an integer and ideally an even number.
     sample_hz: u64,
+    // Countdown offset from the Nyquist frequency, see note 1.
+    pub(crate) monitor_hz: usize,
+    // False until the buffer is filled and the twiddle factors computed, see new_const.
+    ready: bool,
     // To efficiently allocate an internal circular buffer on the stack
     // we make the construction of the NoiseEstimator take a generic
     // of the circular buffer size. This is usually the number of samples in one second.
@@ -160,48 +385,82 @@ pub struct NoiseEstimator<const N: usize> {
 
 impl<const N: usize> NoiseEstimator<N> {
     pub fn new(monitor_hz: usize) -> Self {
+        let mut estimator = Self::new_const(monitor_hz);
+        estimator.prepare();
+        estimator
+    }
+
+    // Usable in const and static initializers. The buffer fill and twiddle factors can't be
+    // computed at compile time, so they're deferred to the first update - or, with the
+    // bounded-time feature, left for prepare.
+    pub const fn new_const(monitor_hz: usize) -> Self {
+        let zero = Complex::new(0.0, 0.0);
+        Self {
+            sample_hz: N as u64,
+            monitor_hz,
+            ready: false,
+            samples: CircularBuffer::new(),
+            power: 0.0,
+            count: 0,
+            x0: zero,
+            x1: zero,
+            x2: zero,
+            w0: zero,
+            w1: zero,
+            w2: zero,
+            w: 0.0,
+        }
+    }
+
+    // Fills the buffer and computes the twiddle factors, which takes O(N). new does this
+    // already - call it on estimators from new_const before they reach a real time thread.
+    // Does nothing once prepared.
+    pub fn prepare(&mut self) {
         use std::f64::consts::PI;
 
-        let monitor_hz = (N / 2) - monitor_hz;
+        if self.ready {
+            return;
+        }
+
+        let monitor_hz = (N / 2) - self.monitor_hz;
 
         // A buffer to store one seconds worth of samples
-        let mut samples = CircularBuffer::<N, Complex<f64>>::new();
-        samples.fill(Complex::new(0.0, 0.0));
+        self.samples.fill(Complex::new(0.0, 0.0));
 
         // x1 represents the frequency we want to monitor, but
         // for a Hanning window, we need its neighbors as well.
-        let x0 = Complex::new(0.0, 0.0);
-        let x1 = Complex::new(0.0, 0.0);
-        let x2 = Complex::new(0.0, 0.0);
-
-        let w0 = Complex::new(0.0, -2.0 * PI * (monitor_hz as f64 - 1.0) / N as f64).exp();
-        let w1 = Complex::new(0.0, -2.0 * PI * monitor_hz as f64 / N as f64).exp();
-        let w2 = Complex::new(0.0, -2.0 * PI * (monitor_hz as f64 + 1.0) / N as f64).exp();
+        let twiddle = |hz: f64| {
+            let (re, im) = math::cis(-2.0 * PI * hz / N as f64);
+            Complex::new(re, im)
+        };
+        self.w0 = twiddle(monitor_hz as f64 - 1.0);
+        self.w1 = twiddle(monitor_hz as f64);
+        self.w2 = twiddle(monitor_hz as f64 + 1.0);
 
         let mut w = 0.0;
 
         for hz in 0..N {
             let tmp = 2.0 * PI * hz as f64 / (N as f64 - 1.0);
-            let win = 0.5 - 0.5 * tmp.cos();
-            w += win.pow(2);
+            let win = 0.5 - 0.5 * math::cos(tmp);
+            w += win * win;
         }
+        self.w = w;
+        self.ready = true;
+    }
 
-        Self {
-            sample_hz: N as u64,
-            samples,
-            power: 0.0,
-            count: 0,
-            x0,
-            x1,
-            x2,
-            w0,
-            w1,
-            w2,
-            w,
-        }
+    pub fn is_ready(&self) -> bool {
+        self.ready
     }
 
+    // With the bounded-time feature, samples reaching an unprepared estimator are dropped rather
+    // than preparing it mid call, see wcet.
     pub fn update(&mut self, sample: f64) {
+        if !self.ready {
+            if cfg!(feature = "bounded-time") {
+                return;
+            }
+            self.prepare();
+        }
         let sample = Complex::new(sample, 0.0);
 
         self.x0 = self.w0 * (self.x0 + sample - unsafe { self.samples.get(0).unwrap_unchecked() });
@@ -216,10 +475,50 @@ impl<const N: usize> NoiseEstimator<N> {
                 - (Complex::new(0.25, 0.0) * self.x0)
                 - (Complex::new(0.25, 0.0) * self.x2);
 
-            self.power += tmp.abs().pow(2);
+            // Squared magnitude, without the hypot and square root in between.
+            self.power += tmp.norm_sqr();
+        }
+    }
+
+    pub const fn memory_footprint() -> MemoryFootprint {
+        MemoryFootprint::inline::<Self>()
+    }
+
+    pub fn snapshot(&self) -> NoiseEstimatorSnapshot {
+        NoiseEstimatorSnapshot {
+            monitor_hz: self.monitor_hz,
+            samples: if self.ready {
+                self.samples.iter().map(|sample| sample.re).collect()
+            } else {
+                vec![]
+            },
+            power: self.power,
+            count: self.count,
+            terms: [self.x0, self.x1, self.x2].map(|term| (term.re, term.im)),
         }
     }
 
+    // None if the snapshot was taken from an estimator with a different window size.
+    pub fn restore(snapshot: &NoiseEstimatorSnapshot) -> Option<Self> {
+        let mut estimator = Self::new(snapshot.monitor_hz);
+        match snapshot.samples.len() {
+            0 => {}
+            len if len == N => {
+                for &sample in snapshot.samples.iter() {
+                    estimator.samples.push_back(Complex::new(sample, 0.0));
+                }
+            }
+            _ => return None,
+        }
+        estimator.power = snapshot.power;
+        estimator.count = snapshot.count;
+        let [x0, x1, x2] = snapshot.terms.map(|(re, im)| Complex::new(re, im));
+        estimator.x0 = x0;
+        estimator.x1 = x1;
+        estimator.x2 = x2;
+        Some(estimator)
+    }
+
     pub fn variance(&self) -> Option<f64> {
         // If we haven't gone through one round of the circular buffer, then we can't determine
         // variance yet.
@@ -233,21 +532,209 @@ impl<const N: usize> NoiseEstimator<N> {
     }
 }
 
+/// A three axis noise estimate in progress, see the estimators' snapshot methods. Detrending and
+/// interference rejection aren't included - reapply them after restoring, and they'll settle
+/// again within a second or two.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThreeAxisNoiseSnapshot {
+    pub x: Vec<NoiseEstimatorSnapshot>,
+    pub y: Vec<NoiseEstimatorSnapshot>,
+    pub z: Vec<NoiseEstimatorSnapshot>,
+    pub stats: RunningStatisticsState,
+    pub threshold: f64,
+    // Axis carrying data, see with_axes. Snapshots from before it existed are all three.
+    #[cfg_attr(feature = "serde", serde(default = "all_axes"))]
+    pub axes: usize,
+}
+
+#[cfg(feature = "serde")]
+fn all_axes() -> usize {
+    3
+}
+
+impl ThreeAxisNoiseSnapshot {
+    fn take<const N: usize>(
+        bins: [&[NoiseEstimator<N>]; 3],
+        axes: usize,
+        stats: &RunningStatistics,
+        threshold: f64,
+    ) -> Self {
+        let [x, y, z] = bins.map(|axis| axis.iter().map(NoiseEstimator::snapshot).collect());
+        Self {
+            x,
+            y,
+            z,
+            stats: stats.state(),
+            threshold,
+            axes,
+        }
+    }
+
+    fn offsets(&self) -> impl Iterator<Item = usize> + '_ {
+        self.x.iter().map(|bin| bin.monitor_hz)
+    }
+
+    // Overwrites the bins an estimator was built with.
+    fn restore_bins<const N: usize>(&self, bins: [&mut [NoiseEstimator<N>]; 3]) -> Option<()> {
+        let [x, y, z] = bins;
+        for (bins, snapshots) in [(x, &self.x), (y, &self.y), (z, &self.z)] {
+            if bins.len() != snapshots.len() {
+                return None;
+            }
+            for (bin, snapshot) in bins.iter_mut().zip(snapshots.iter()) {
+                *bin = NoiseEstimator::restore(snapshot)?;
+            }
+        }
+        Some(())
+    }
+}
+
+/// Anything that can estimate white noise variance from idle three axis samples, so the
+/// calibrator isn't tied to one estimator.
+pub trait NoiseEstimation {
+    // Update estimate with new samples. Returns true once the estimate is good enough to stop.
+    fn update(&mut self, x: f64, y: f64, z: f64) -> bool;
+
+    fn mean_variance(&self) -> Variance;
+
+    // How far along the estimate is, from 0 to 1, for showing progress. Estimators that can't
+    // tell just report 0 until done.
+    fn progress(&self) -> f64 {
+        0.0
+    }
+
+    // Stops monitoring whatever bins the exclusion rules out - i.e. near interference found after
+    // the estimator was built - for input at sample_rate. Meant for before the first update.
+    // Estimators without bins ignore it.
+    fn exclude_bins(&mut self, _exclusion: &BinExclusion, _sample_rate: f64) {}
+}
+
+/// Anything that can estimate the largest motion a user makes in one sample, once noise is known.
+pub trait AmplitudeEstimation {
+    // Called once noise calibration is done, with its result.
+    fn new(noise_std_dev: StdDev) -> Self
+    where
+        Self: Sized;
+
+    fn update(&mut self, x: f64, y: f64, z: f64);
+
+    // The amplitude to tune for.
+    fn max_amplitude(&self) -> f64;
+}
+
+// Shared by both three axis estimators - feeds a sample to every bin on the first `axes` axis
+// and aggregates the resulting variances. The rest are left alone, as a constant axis would
+// pool in a variance of zero for every bin.
+fn update_bins<const N: usize>(
+    mut bins: [&mut [NoiseEstimator<N>]; 3],
+    axes: usize,
+    stats: &mut RunningStatistics,
+    detrend: &mut Option<DcRemover<3>>,
+    sample: [f64; 3],
+) {
+    let sample = match detrend {
+        Some(detrend) => detrend.process(sample),
+        None => sample,
+    };
+
+    for i in 0..bins[0].len() {
+        let mut variances = [None; 3];
+        for (axis, bins) in bins.iter_mut().enumerate().take(axes) {
+            bins[i].update(sample[axis]);
+            variances[axis] = bins[i].variance();
+        }
+
+        let live = &variances[..axes];
+        if live.iter().all(Option::is_some) {
+            live.iter()
+                .flatten()
+                .for_each(|&variance| stats.update(variance));
+        }
+    }
+}
+
+// Between one and three axis.
+fn live_axes(axes: usize) -> usize {
+    axes.clamp(1, 3)
+}
+
+/// Why a bin selection was turned down, see ThreeAxisNoiseEstimator::with_bins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinSelectionError {
+    // No offset in the selection was a bin the estimator has, so it would never converge.
+    Empty,
+    // More bins than the estimator has room for.
+    TooMany { max: usize },
+}
+
+impl fmt::Display for BinSelectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinSelectionError::Empty => write!(f, "no usable noise bins selected"),
+            BinSelectionError::TooMany { max } => {
+                write!(f, "too many noise bins selected, at most {max} fit")
+            }
+        }
+    }
+}
+
+impl Error for BinSelectionError {}
+
+// The most bins a ThreeAxisNoiseEstimator keeps with the heapless feature, nearest Nyquist first
+// - as many as SixtyHzThreeAxisNoiseEstimator monitors. Every bin is inline, so this is what
+// sets the estimator's size.
+#[cfg(feature = "heapless")]
+pub const HEAPLESS_MAX_BINS: usize = 20;
+
+#[cfg(not(feature = "heapless"))]
+const MAX_BINS: usize = usize::MAX;
+#[cfg(feature = "heapless")]
+const MAX_BINS: usize = HEAPLESS_MAX_BINS;
+
+// One axis worth of bins - on the heap, or inline with the heapless feature.
+#[cfg(not(feature = "heapless"))]
+type Bins<const N: usize> = Vec<NoiseEstimator<N>>;
+#[cfg(feature = "heapless")]
+type Bins<const N: usize> = heapless::Vec<NoiseEstimator<N>, HEAPLESS_MAX_BINS>;
+
+// Returns false if there was no room.
+#[cfg(not(feature = "heapless"))]
+fn push_bin<const N: usize>(bins: &mut Bins<N>, bin: NoiseEstimator<N>) -> bool {
+    bins.push(bin);
+    true
+}
+
+#[cfg(feature = "heapless")]
+fn push_bin<const N: usize>(bins: &mut Bins<N>, bin: NoiseEstimator<N>) -> bool {
+    bins.push(bin).is_ok()
+}
+
+// Gives back whatever the Vec grew past its bins. Inline bins are a fixed size anyway.
+#[cfg(not(feature = "heapless"))]
+fn shrink_bins<const N: usize>(bins: &mut Bins<N>) {
+    bins.shrink_to_fit();
+}
+
+#[cfg(feature = "heapless")]
+fn shrink_bins<const N: usize>(_: &mut Bins<N>) {}
+
 /// Estimates noise in signal across three axis. N in this case should be the frequency and
 /// allocates a circular ring buffer at compile time so we can stack allocate the ring buffer.
 ///
 /// It maps to frequency because each ring buffer has 1 seconds worth of samples.
+///
+/// The bins themselves are heap allocated, as how many there are depends on N - unless the
+/// heapless feature is on, which keeps up to HEAPLESS_MAX_BINS of them inline.
 pub struct ThreeAxisNoiseEstimator<const N: usize> {
-    // TODO: See if we can have these not be in Vecs. Right now they are heap allocated which kind
-    // of defeats the point of the circular buffers being stack allocated.
-    //
-    // Consider turning on generic_const_exprs and depending on nightly.
-    // We could also require it as one more generic and leverage the caller passing the value in,
-    // but this seems really clunky.
-    x: Vec<NoiseEstimator<N>>,
-    y: Vec<NoiseEstimator<N>>,
-    z: Vec<NoiseEstimator<N>>,
+    x: Bins<N>,
+    y: Bins<N>,
+    z: Bins<N>,
     stats: RunningStatistics,
+    // Optional high pass run ahead of the PSD estimate, see with_detrending.
+    detrend: Option<DcRemover<3>>,
+    // How many of x, y and z carry data, see with_axes.
+    axes: usize,
 
     // Used to determine wen the 95% confidence interval determines that we are within the given
     // threshold of the mean.
@@ -257,63 +744,265 @@ pub struct ThreeAxisNoiseEstimator<const N: usize> {
 }
 
 impl<const N: usize> ThreeAxisNoiseEstimator<N> {
+    // With the heapless feature, only the HEAPLESS_MAX_BINS bins nearest Nyquist.
     pub fn new(threshold: f64) -> Self {
-        let mut x = vec![];
-        let mut y = vec![];
-        let mut z = vec![];
+        Self::from_offsets(threshold, (0..Self::bin_count()).take(MAX_BINS)).0
+    }
+
+    // Monitors only the given bins, as offsets counting down from Nyquist. By default that's
+    // every bin down to 10 hz above DC. Fewer bins is cheaper per sample but each sample says
+    // less about the noise, and bins further from Nyquist are more likely to pick up slow motion.
+    // Offsets at or past N / 2 are ignored, and an error if that leaves none - or, with the
+    // heapless feature, more than HEAPLESS_MAX_BINS.
+    pub fn with_bins(
+        threshold: f64,
+        offsets: impl IntoIterator<Item = usize>,
+    ) -> Result<Self, BinSelectionError> {
+        match Self::from_offsets(threshold, offsets) {
+            (_, true) => Err(BinSelectionError::TooMany { max: MAX_BINS }),
+            (estimator, _) if estimator.x.is_empty() => Err(BinSelectionError::Empty),
+            (estimator, _) => Ok(estimator),
+        }
+    }
 
-        let freq_cnt = N / 2 - 10;
+    // with_bins, leaving it to the caller to check the selection. Also returns whether any bins
+    // didn't fit.
+    fn from_offsets(threshold: f64, offsets: impl IntoIterator<Item = usize>) -> (Self, bool) {
+        let mut x = Bins::new();
+        let mut y = Bins::new();
+        let mut z = Bins::new();
+        let mut overflowed = false;
 
-        for monitor_hz in 0..freq_cnt {
-            x.push(NoiseEstimator::new(monitor_hz));
-            y.push(NoiseEstimator::new(monitor_hz));
-            z.push(NoiseEstimator::new(monitor_hz));
+        for monitor_hz in offsets {
+            if monitor_hz >= N / 2 || x.iter().any(|bin| bin.monitor_hz == monitor_hz) {
+                continue;
+            }
+            // All three axis fill up together.
+            if !push_bin(&mut x, NoiseEstimator::new(monitor_hz)) {
+                overflowed = true;
+                break;
+            }
+            push_bin(&mut y, NoiseEstimator::new(monitor_hz));
+            push_bin(&mut z, NoiseEstimator::new(monitor_hz));
+        }
+        for bins in [&mut x, &mut y, &mut z] {
+            shrink_bins(bins);
         }
 
-        Self {
+        let estimator = Self {
             x,
             y,
             z,
             stats: RunningStatistics::default(),
+            detrend: None,
+            axes: 3,
 
             threshold,
+        };
+        (estimator, overflowed)
+    }
+
+    // Every bin the exclusion policy allows - with the heapless feature, the first
+    // HEAPLESS_MAX_BINS of them nearest Nyquist. An error if it allows none.
+    pub fn with_exclusion(
+        threshold: f64,
+        exclusion: &BinExclusion,
+    ) -> Result<Self, BinSelectionError> {
+        Self::with_bins(threshold, exclusion.offsets(N).take(MAX_BINS))
+    }
+
+    // Drops the monitored bins the exclusion rules out, for input at sample_rate, keeping the
+    // rest. Left as is if that would drop every bin.
+    pub fn exclude_bins(&mut self, exclusion: &BinExclusion, sample_rate: f64) {
+        let exclusion = exclusion.for_window(N, sample_rate);
+        let kept: Vec<usize> = self
+            .x
+            .iter()
+            .map(|bin| bin.monitor_hz)
+            .filter(|&offset| !exclusion.excludes(N, offset))
+            .collect();
+        if kept.is_empty() {
+            return;
+        }
+        let (Self { x, y, z, .. }, _) = Self::from_offsets(self.threshold, kept);
+        (self.x, self.y, self.z) = (x, y, z);
+    }
+
+    // For devices with fewer than three axis (i.e. 2D pointers), so only x, or x and y, are
+    // estimated from - whatever is passed for the rest is ignored. Clamped to 1..=3.
+    pub fn with_axes(mut self, axes: usize) -> Self {
+        self.axes = live_axes(axes);
+        self
+    }
+
+    pub fn axes(&self) -> usize {
+        self.axes
+    }
+
+    pub fn snapshot(&self) -> ThreeAxisNoiseSnapshot {
+        ThreeAxisNoiseSnapshot::take(
+            [&self.x, &self.y, &self.z],
+            self.axes,
+            &self.stats,
+            self.threshold,
+        )
+    }
+
+    // None if the snapshot came from an estimator with a different window size.
+    pub fn restore(snapshot: &ThreeAxisNoiseSnapshot) -> Option<Self> {
+        let mut estimator = Self::with_bins(snapshot.threshold, snapshot.offsets())
+            .ok()?
+            .with_axes(snapshot.axes);
+        snapshot.restore_bins([&mut estimator.x, &mut estimator.y, &mut estimator.z])?;
+        estimator.stats = RunningStatistics::from_state(&snapshot.stats);
+        Some(estimator)
+    }
+
+    fn bin_count() -> usize {
+        N / 2 - 10
+    }
+
+    // The bins actually selected, three axis worth of them - on the heap, unless the heapless
+    // feature keeps them inline.
+    pub fn memory_footprint(&self) -> MemoryFootprint {
+        let bin_bytes = [&self.x, &self.y, &self.z]
+            .iter()
+            .map(|bins| core::mem::size_of_val(bins.as_slice()))
+            .sum();
+        MemoryFootprint {
+            inline_bytes: core::mem::size_of::<Self>(),
+            heap_bytes: if cfg!(feature = "heapless") {
+                0
+            } else {
+                bin_bytes
+            },
         }
     }
 
+    // High passes samples before they reach the PSD estimators, so slow purposeful drift during
+    // "idle" calibration can't leak into the monitored bins near Nyquist. A cutoff of around
+    // 0.5 hz leaves those bins untouched.
+    pub fn with_detrending(mut self, cutoff_hz: f64) -> Self {
+        self.detrend = Some(DcRemover::new(N as f64, DcRemoval::HighPass { cutoff_hz }));
+        self
+    }
+
     // Update estimate with new samples. Note - we assume noise is homogeneous across all axis.
     //
     // Returns true once the 95% CI width is within a given threshold of the mean.
     pub fn update(&mut self, x: f64, y: f64, z: f64) -> bool {
-        for i in 0..self.x.len() {
-            self.x[i].update(x);
-            self.y[i].update(y);
-            self.z[i].update(z);
-
-            let var_x = self.x[i].variance();
-            let var_y = self.y[i].variance();
-            let var_z = self.z[i].variance();
-
-            match (var_x, var_y, var_z) {
-                (Some(var_x), Some(var_y), Some(var_z)) => {
-                    self.stats.update(var_x);
-                    self.stats.update(var_y);
-                    self.stats.update(var_z);
-                }
-                _ => continue,
-            }
-        }
-
-        let ratio = (2.0 * self.stats.ci95) / self.stats.mean;
-        ratio < self.threshold
+        update_bins(
+            [&mut self.x, &mut self.y, &mut self.z],
+            self.axes,
+            &mut self.stats,
+            &mut self.detrend,
+            [x, y, z],
+        );
+        self.stats.converged(self.threshold)
     }
 
     // Returns white noise variance estimates which is the mean of our
     // PSD estimates.
-    pub fn mean_variance(&self) -> f64 {
-        self.stats.mean
+    pub fn mean_variance(&self) -> Variance {
+        Variance(self.stats.mean)
+    }
+}
+
+impl<const N: usize> BoundedWork for NoiseEstimator<N> {
+    fn work_bound(&self) -> WorkBound {
+        WorkBound::bins(1)
+    }
+}
+
+// Every bin on every live axis, behind detrending's three sections.
+fn three_axis_bound(bins: usize, axes: usize, detrend: &Option<DcRemover<3>>) -> WorkBound {
+    WorkBound {
+        biquad_steps: if detrend.is_some() { 3 } else { 0 },
+        ..WorkBound::bins(axes * bins)
+    }
+}
+
+impl<const N: usize> BoundedWork for ThreeAxisNoiseEstimator<N> {
+    fn work_bound(&self) -> WorkBound {
+        three_axis_bound(self.x.len(), self.axes, &self.detrend)
+    }
+}
+
+/// Which bins to leave out of the variance average. Bins near DC pick up slow motion and bins
+/// near interference (i.e. a display refresh beating against the sample rate, or mains hum)
+/// pick up the interference, and either biases the white noise estimate upwards.
+///
+/// Interference above Nyquist is folded back to where it aliases to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinExclusion {
+    // Bins at or below this are excluded.
+    pub dc_guard_hz: f64,
+    pub interference_hz: Vec<f64>,
+    // How close to an interference frequency a bin can be. Each bin also reads its neighbours,
+    // so this is on top of the bin's own 1 hz either side.
+    pub interference_guard_hz: f64,
+}
+
+impl Default for BinExclusion {
+    fn default() -> Self {
+        Self {
+            dc_guard_hz: 10.0,
+            interference_hz: vec![],
+            interference_guard_hz: 1.0,
+        }
+    }
+}
+
+impl BinExclusion {
+    pub fn with_interference(mut self, hz: f64) -> Self {
+        self.interference_hz.push(hz);
+        self
+    }
+
+    // Whether the bin at the given offset from Nyquist is left out, for a one second window at
+    // sample_hz.
+    pub fn excludes(&self, sample_hz: usize, offset: usize) -> bool {
+        let nyquist = sample_hz as f64 / 2.0;
+        let bin_hz = nyquist - offset as f64;
+        if bin_hz <= self.dc_guard_hz {
+            return true;
+        }
+
+        let reach = 1.0 + self.interference_guard_hz;
+        self.interference_hz.iter().any(|&hz| {
+            // Fold into 0..=nyquist.
+            let folded = hz.rem_euclid(sample_hz as f64);
+            let aliased = if folded > nyquist {
+                sample_hz as f64 - folded
+            } else {
+                folded
+            };
+            (bin_hz - aliased).abs() <= reach
+        })
+    }
+
+    // The same exclusion for a window of `window` samples at sample_rate rather than one second's
+    // worth, so bins are sample_rate / window hz apart - in the window's terms, where bins are
+    // always 1 hz apart.
+    pub fn for_window(&self, window: usize, sample_rate: f64) -> Self {
+        let scale = window as f64 / sample_rate;
+        Self {
+            dc_guard_hz: self.dc_guard_hz * scale,
+            interference_hz: self.interference_hz.iter().map(|hz| hz * scale).collect(),
+            interference_guard_hz: self.interference_guard_hz * scale,
+        }
+    }
+
+    // The offsets from Nyquist that survive, nearest Nyquist first.
+    pub fn offsets(&self, sample_hz: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..sample_hz / 2).filter(move |&offset| !self.excludes(sample_hz, offset))
     }
 }
 
+// Bins and CI threshold for SixtyHzThreeAxisNoiseEstimator::quick.
+pub const QUICK_BINS: usize = 5;
+pub const QUICK_THRESHOLD: f64 = 0.2;
+
 // Similar to the noise estimator above for now, we need to use a multidimensional table from the
 // original JS database - I have no idea where this table came from or how to create one for
 // different frequencies, but it's a 60 hz table - so we might as well hard code for 60 hz anyways
@@ -322,7 +1011,14 @@ pub struct SixtyHzThreeAxisNoiseEstimator {
     x: [NoiseEstimator<60>; 20],
     y: [NoiseEstimator<60>; 20],
     z: [NoiseEstimator<60>; 20],
+    // How many of the bins above are actually updated. All 20 unless fewer were asked for, see
+    // with_bins.
+    bins: usize,
     stats: RunningStatistics,
+    // Optional high pass run ahead of the PSD estimate, see with_detrending.
+    detrend: Option<DcRemover<3>>,
+    // How many of x, y and z carry data, see with_axes.
+    axes: usize,
 
     // Used to determine when the 95% confidence interval determines that we are within the given
     // threshold of the mean.
@@ -332,73 +1028,491 @@ pub struct SixtyHzThreeAxisNoiseEstimator {
 }
 
 impl SixtyHzThreeAxisNoiseEstimator {
-    // TODO: There *must* be a better way to do this.
-    fn noise_estimators() -> [NoiseEstimator<60>; 20] {
-        [
-            NoiseEstimator::new(0),
-            NoiseEstimator::new(1),
-            NoiseEstimator::new(2),
-            NoiseEstimator::new(3),
-            NoiseEstimator::new(4),
-            NoiseEstimator::new(5),
-            NoiseEstimator::new(6),
-            NoiseEstimator::new(7),
-            NoiseEstimator::new(8),
-            NoiseEstimator::new(9),
-            NoiseEstimator::new(10),
-            NoiseEstimator::new(11),
-            NoiseEstimator::new(12),
-            NoiseEstimator::new(13),
-            NoiseEstimator::new(14),
-            NoiseEstimator::new(15),
-            NoiseEstimator::new(16),
-            NoiseEstimator::new(17),
-            NoiseEstimator::new(18),
-            NoiseEstimator::new(19),
-        ]
+    // Bins for the given offsets from Nyquist first, then the rest in order. Only the first
+    // `bins` are ever updated.
+    fn noise_estimators(offsets: &[usize]) -> [NoiseEstimator<60>; 20] {
+        core::array::from_fn(|i| NoiseEstimator::new(offsets.get(i).copied().unwrap_or(i)))
     }
 
     pub fn new(threshold: f64) -> Self {
-        Self {
-            x: Self::noise_estimators(),
-            y: Self::noise_estimators(),
-            z: Self::noise_estimators(),
+        Self::from_offsets(threshold, 0..20).0
+    }
+
+    // Monitors only the given bins, as offsets counting down from Nyquist (0 is 30 hz, 1 is 29
+    // hz and so on). Fewer bins is cheaper per sample but each sample says less about the
+    // noise, and bins further from Nyquist are more likely to pick up slow motion. Offsets of 30
+    // or more are ignored, and an error if that leaves none or more than 20.
+    pub fn with_bins(
+        threshold: f64,
+        offsets: impl IntoIterator<Item = usize>,
+    ) -> Result<Self, BinSelectionError> {
+        match Self::from_offsets(threshold, offsets) {
+            (_, true) => Err(BinSelectionError::TooMany { max: 20 }),
+            (estimator, _) if estimator.bins == 0 => Err(BinSelectionError::Empty),
+            (estimator, _) => Ok(estimator),
+        }
+    }
+
+    // with_bins, leaving it to the caller to check the selection. Also returns whether any bins
+    // didn't fit.
+    fn from_offsets(threshold: f64, offsets: impl IntoIterator<Item = usize>) -> (Self, bool) {
+        let mut selected = [0; 20];
+        let mut bins = 0;
+        let mut overflowed = false;
+        for offset in offsets {
+            if offset >= 30 || selected[..bins].contains(&offset) {
+                continue;
+            }
+            if bins == 20 {
+                overflowed = true;
+                break;
+            }
+            selected[bins] = offset;
+            bins += 1;
+        }
+        let selected = &selected[..bins];
+
+        let estimator = Self {
+            x: Self::noise_estimators(selected),
+            y: Self::noise_estimators(selected),
+            z: Self::noise_estimators(selected),
+            bins,
             stats: RunningStatistics::default(),
+            detrend: None,
+            axes: 3,
 
             threshold,
+        };
+        (estimator, overflowed)
+    }
+
+    // Trades accuracy for time when users can't be asked to sit still for long - only the
+    // QUICK_BINS bins nearest Nyquist are monitored, and the estimate is accepted at a looser
+    // QUICK_THRESHOLD. Finishes within about two seconds of idle input, at the cost of a
+    // noticeably noisier estimate - see calibrator::CalibrationReport.
+    pub fn quick() -> Self {
+        Self::from_offsets(QUICK_THRESHOLD, 0..QUICK_BINS).0
+    }
+
+    // The first 20 bins that the exclusion policy allows, nearest Nyquist first. An error if it
+    // allows none.
+    pub fn with_exclusion(
+        threshold: f64,
+        exclusion: &BinExclusion,
+    ) -> Result<Self, BinSelectionError> {
+        Self::with_bins(threshold, exclusion.offsets(60).take(20))
+    }
+
+    // Drops the monitored bins the exclusion rules out, for input at sample_rate, keeping the
+    // rest. Left as is if that would drop every bin.
+    pub fn exclude_bins(&mut self, exclusion: &BinExclusion, sample_rate: f64) {
+        let exclusion = exclusion.for_window(60, sample_rate);
+        let kept: Vec<usize> = self.x[..self.bins]
+            .iter()
+            .map(|bin| bin.monitor_hz)
+            .filter(|&offset| !exclusion.excludes(60, offset))
+            .collect();
+        if kept.is_empty() {
+            return;
         }
+        let (Self { x, y, z, bins, .. }, _) = Self::from_offsets(self.threshold, kept);
+        (self.x, self.y, self.z, self.bins) = (x, y, z, bins);
+    }
+
+    // Captures a calibration in progress, to survive an app restart or move to another
+    // process.
+    pub fn snapshot(&self) -> ThreeAxisNoiseSnapshot {
+        let bins = self.bins;
+        ThreeAxisNoiseSnapshot::take(
+            [&self.x[..bins], &self.y[..bins], &self.z[..bins]],
+            self.axes,
+            &self.stats,
+            self.threshold,
+        )
+    }
+
+    // Resumes from a snapshot. None if it came from an estimator with a different window size
+    // or more than 20 bins.
+    pub fn restore(snapshot: &ThreeAxisNoiseSnapshot) -> Option<Self> {
+        let mut estimator = Self::with_bins(snapshot.threshold, snapshot.offsets())
+            .ok()?
+            .with_axes(snapshot.axes);
+        let bins = estimator.bins;
+        snapshot.restore_bins([
+            &mut estimator.x[..bins],
+            &mut estimator.y[..bins],
+            &mut estimator.z[..bins],
+        ])?;
+        estimator.stats = RunningStatistics::from_state(&snapshot.stats);
+        Some(estimator)
+    }
+
+    // Seeds the estimate with a variance from an earlier calibration of the same device, so it
+    // converges sooner. Weight is how many bin estimates the prior is worth - calibration
+    // usually stops after around 1500, so a few hundred gives a head start without drowning out
+    // a real change in noise.
+    pub fn with_prior(mut self, prior: Variance, weight: u64) -> Self {
+        // Individual bin estimates spread about as wide as the variance itself.
+        self.stats = RunningStatistics::with_prior(prior.0, prior.0, weight);
+        self
+    }
+
+    // For devices with fewer than three axis (i.e. 2D pointers), so only x, or x and y, are
+    // estimated from - whatever is passed for the rest is ignored. Clamped to 1..=3.
+    pub fn with_axes(mut self, axes: usize) -> Self {
+        self.axes = live_axes(axes);
+        self
+    }
+
+    pub fn axes(&self) -> usize {
+        self.axes
+    }
+
+    // Whether fewer than the full 20 bins are monitored, as with quick.
+    pub fn is_quick(&self) -> bool {
+        self.bins < 20
+    }
+
+    pub fn monitored_bins(&self) -> usize {
+        self.bins
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    // Samples seen so far, per axis.
+    pub fn samples(&self) -> u64 {
+        self.x[0].count
+    }
+
+    // The 95% CI width as a fraction of the estimate - what threshold is compared against.
+    pub fn relative_ci_width(&self) -> f64 {
+        (2.0 * self.stats.ci95) / self.stats.mean
+    }
+
+    // The per bin variances averaged so far, i.e. for pooling across devices.
+    pub fn statistics(&self) -> &RunningStatistics {
+        &self.stats
+    }
+
+    pub const fn memory_footprint() -> MemoryFootprint {
+        MemoryFootprint::inline::<Self>()
+    }
+
+    // High passes samples before they reach the PSD estimators, so slow purposeful drift during
+    // "idle" calibration can't leak into the monitored bins near Nyquist. A cutoff of around
+    // 0.5 hz leaves those bins untouched.
+    pub fn with_detrending(mut self, cutoff_hz: f64) -> Self {
+        self.detrend = Some(DcRemover::new(60.0, DcRemoval::HighPass { cutoff_hz }));
+        self
     }
 
     // Update estimate with new samples. Note - we assume noise is homogeneous across all axis.
     //
     // Returns true once the 95% CI width is within a given threshold of the mean.
     pub fn update(&mut self, x: f64, y: f64, z: f64) -> bool {
-        for i in 0..20 {
-            self.x[i].update(x);
-            self.y[i].update(y);
-            self.z[i].update(z);
-
-            let var_x = self.x[i].variance();
-            let var_y = self.y[i].variance();
-            let var_z = self.z[i].variance();
-
-            match (var_x, var_y, var_z) {
-                (Some(var_x), Some(var_y), Some(var_z)) => {
-                    self.stats.update(var_x);
-                    self.stats.update(var_y);
-                    self.stats.update(var_z);
-                }
-                _ => continue,
+        let bins = self.bins;
+        update_bins(
+            [
+                &mut self.x[..bins],
+                &mut self.y[..bins],
+                &mut self.z[..bins],
+            ],
+            self.axes,
+            &mut self.stats,
+            &mut self.detrend,
+            [x, y, z],
+        );
+        self.stats.converged(self.threshold)
+    }
+
+    // Returns white noise variance estimates which is the mean of our
+    // PSD estimates.
+    pub fn mean_variance(&self) -> Variance {
+        Variance(self.stats.mean)
+    }
+}
+
+impl BoundedWork for SixtyHzThreeAxisNoiseEstimator {
+    fn work_bound(&self) -> WorkBound {
+        three_axis_bound(self.bins, self.axes, &self.detrend)
+    }
+}
+
+// Fewer second differences than this can look converged by chance.
+const MIN_DIFFERENCES: u64 = 50;
+
+/// Estimates white noise from second differences - x[n] - 2 x[n-1] + x[n-2] carries 6 times the
+/// noise variance, and cancels anything changing linearly over the three samples. Unlike the PSD
+/// estimators there's no one second window of bins, so it works at any sample rate, down to a 1
+/// hz barometer. The price is that any curvature in the signal counts as noise, so the device
+/// has to be properly still.
+///
+/// Consecutive differences share samples, which makes the confidence interval optimistic - keep
+/// the threshold on the tight side.
+#[derive(Debug, Clone)]
+pub struct DifferenceNoiseEstimator<const D: usize> {
+    // The previous sample and the one before it, per axis.
+    history: [[f64; 2]; D],
+    seen: usize,
+    stats: RunningStatistics,
+    threshold: f64,
+}
+
+impl<const D: usize> DifferenceNoiseEstimator<D> {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            history: [[0.0; 2]; D],
+            seen: 0,
+            stats: RunningStatistics::new(),
+            threshold,
+        }
+    }
+
+    // Returns true once the 95% CI width is within the threshold of the mean.
+    pub fn update(&mut self, sample: [f64; D]) -> bool {
+        for (history, x) in self.history.iter_mut().zip(sample) {
+            if self.seen >= 2 {
+                let difference = x - 2.0 * history[0] + history[1];
+                self.stats.update(difference * difference / 6.0);
             }
+            *history = [x, history[0]];
         }
+        self.seen = (self.seen + 1).min(2);
+        self.stats.count() >= MIN_DIFFERENCES && self.stats.converged(self.threshold)
+    }
 
-        let ratio = (2.0 * self.stats.ci95) / self.stats.mean;
-        ratio < self.threshold
+    pub fn mean_variance(&self) -> Variance {
+        Variance(self.stats.mean)
     }
 
-    // Returns white noise variance estimates which is the mean of our
-    // PSD estimates.
-    pub fn mean_variance(&self) -> f64 {
-        self.stats.mean
+    pub fn progress(&self) -> f64 {
+        self.stats.convergence(self.threshold)
+    }
+}
+
+impl NoiseEstimation for DifferenceNoiseEstimator<3> {
+    fn update(&mut self, x: f64, y: f64, z: f64) -> bool {
+        DifferenceNoiseEstimator::update(self, [x, y, z])
+    }
+
+    fn mean_variance(&self) -> Variance {
+        DifferenceNoiseEstimator::mean_variance(self)
+    }
+
+    fn progress(&self) -> f64 {
+        DifferenceNoiseEstimator::progress(self)
+    }
+}
+
+impl<const N: usize> NoiseEstimation for ThreeAxisNoiseEstimator<N> {
+    fn update(&mut self, x: f64, y: f64, z: f64) -> bool {
+        ThreeAxisNoiseEstimator::update(self, x, y, z)
+    }
+
+    fn mean_variance(&self) -> Variance {
+        ThreeAxisNoiseEstimator::mean_variance(self)
+    }
+
+    fn progress(&self) -> f64 {
+        self.stats.convergence(self.threshold)
+    }
+
+    fn exclude_bins(&mut self, exclusion: &BinExclusion, sample_rate: f64) {
+        ThreeAxisNoiseEstimator::exclude_bins(self, exclusion, sample_rate)
+    }
+}
+
+impl NoiseEstimation for SixtyHzThreeAxisNoiseEstimator {
+    fn update(&mut self, x: f64, y: f64, z: f64) -> bool {
+        SixtyHzThreeAxisNoiseEstimator::update(self, x, y, z)
+    }
+
+    fn mean_variance(&self) -> Variance {
+        SixtyHzThreeAxisNoiseEstimator::mean_variance(self)
+    }
+
+    fn progress(&self) -> f64 {
+        self.stats.convergence(self.threshold)
+    }
+
+    fn exclude_bins(&mut self, exclusion: &BinExclusion, sample_rate: f64) {
+        SixtyHzThreeAxisNoiseEstimator::exclude_bins(self, exclusion, sample_rate)
+    }
+}
+
+impl AmplitudeEstimation for ThreeAxisMaxDistanceEstimator {
+    fn new(noise_std_dev: StdDev) -> Self {
+        ThreeAxisMaxDistanceEstimator::new(noise_std_dev.0)
+    }
+
+    fn update(&mut self, x: f64, y: f64, z: f64) {
+        ThreeAxisMaxDistanceEstimator::update(self, x, y, z)
+    }
+
+    fn max_amplitude(&self) -> f64 {
+        self.max_within_reason()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_running_statistics_divisors() {
+        let mut stats = RunningStatistics::new();
+        assert_eq!(stats.population_variance(), None);
+
+        stats.update(2.0);
+        assert_eq!(stats.population_variance(), Some(0.0));
+        assert_eq!(stats.sample_variance(), None);
+
+        for val in [4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            stats.update(val);
+        }
+        assert_eq!(stats.mean(), 5.0);
+        assert_eq!(stats.population_variance(), Some(4.0));
+        assert_eq!(stats.sample_variance(), Some(32.0 / 7.0));
+
+        let ci = stats.confidence_interval(VarianceDivisor::Sample).unwrap();
+        assert!((ci.half_width - stats.ci95).abs() < 1e-12);
+        assert!((ci.upper - ci.lower - 2.0 * ci.half_width).abs() < 1e-12);
+
+        let restored = RunningStatistics::from_state(&stats.state());
+        assert_eq!(restored.state(), stats.state());
+        assert_eq!(restored.sample_variance(), stats.sample_variance());
+
+        let mut first = RunningStatistics::new();
+        let mut second = RunningStatistics::new();
+        for val in [2.0, 4.0, 4.0] {
+            first.update(val);
+        }
+        for val in [4.0, 5.0, 5.0, 7.0, 9.0] {
+            second.update(val);
+        }
+        first.merge(&second);
+        assert_eq!(first.count(), 8);
+        assert_eq!(first.mean(), 5.0);
+        assert_eq!(first.max(), Some(9.0));
+        assert!((first.population_variance().unwrap() - 4.0).abs() < 1e-12);
+    }
+
+    #[test]
+    pub fn test_quick_estimator() {
+        let mut quick = SixtyHzThreeAxisNoiseEstimator::quick();
+        let mut noise = crate::synth::GaussianNoise::new(2.0, 1);
+        while !quick.update(noise.sample(), noise.sample(), noise.sample()) {}
+
+        assert!(quick.is_quick());
+        assert!(quick.samples() <= 120);
+        assert!(quick.relative_ci_width() < QUICK_THRESHOLD);
+        assert!((quick.mean_variance().0 - 4.0).abs() < 4.0 * 0.5);
+    }
+
+    #[test]
+    pub fn test_prior_speeds_convergence() {
+        let samples_to_converge = |mut estimator: SixtyHzThreeAxisNoiseEstimator| {
+            let mut noise = crate::synth::GaussianNoise::new(2.0, 3);
+            let mut samples = 1;
+            while !estimator.update(noise.sample(), noise.sample(), noise.sample()) {
+                samples += 1;
+            }
+            (samples, estimator.mean_variance().0)
+        };
+
+        let (cold, _) = samples_to_converge(SixtyHzThreeAxisNoiseEstimator::new(0.1));
+        let (warm, variance) = samples_to_converge(
+            SixtyHzThreeAxisNoiseEstimator::new(0.1).with_prior(Variance(4.0), 600),
+        );
+        assert!(warm < cold);
+        assert!((variance - 4.0).abs() < 1.0);
+    }
+
+    #[test]
+    pub fn test_bin_selection() {
+        let estimator = SixtyHzThreeAxisNoiseEstimator::with_bins(0.1, [4, 2, 2, 30, 0]).unwrap();
+        assert_eq!(estimator.monitored_bins(), 3);
+        let offsets: Vec<usize> = estimator.x[..3].iter().map(|bin| bin.monitor_hz).collect();
+        assert_eq!(offsets, [4, 2, 0]);
+
+        let selection = ThreeAxisNoiseEstimator::<120>::with_bins(0.1, (0..60).step_by(2));
+        // Only the bins selected take up room.
+        #[cfg(not(feature = "heapless"))]
+        {
+            let estimator = selection.unwrap();
+            assert_eq!(estimator.x.len(), 30);
+            let bin_bytes = core::mem::size_of::<NoiseEstimator<120>>();
+            assert_eq!(estimator.memory_footprint().heap_bytes, 3 * 30 * bin_bytes);
+            let full = ThreeAxisNoiseEstimator::<120>::new(0.1).memory_footprint();
+            assert_eq!(full.heap_bytes, 3 * 50 * bin_bytes);
+        }
+        // Turned down rather than cut short, and nothing on the heap.
+        #[cfg(feature = "heapless")]
+        {
+            assert_eq!(
+                selection.err(),
+                Some(BinSelectionError::TooMany {
+                    max: HEAPLESS_MAX_BINS
+                })
+            );
+            assert_eq!(
+                ThreeAxisNoiseEstimator::<120>::new(0.1).x.len(),
+                HEAPLESS_MAX_BINS
+            );
+            let footprint = ThreeAxisNoiseEstimator::<120>::new(0.1).memory_footprint();
+            assert_eq!(footprint.heap_bytes, 0);
+        }
+        assert_eq!(
+            SixtyHzThreeAxisNoiseEstimator::with_bins(0.1, 0..21).err(),
+            Some(BinSelectionError::TooMany { max: 20 })
+        );
+
+        // Nothing usable would never converge, so it's turned down up front.
+        assert_eq!(
+            SixtyHzThreeAxisNoiseEstimator::with_bins(0.1, [30, 45]).err(),
+            Some(BinSelectionError::Empty)
+        );
+        assert_eq!(
+            ThreeAxisNoiseEstimator::<60>::with_bins(0.1, []).err(),
+            Some(BinSelectionError::Empty)
+        );
+        let everything = BinExclusion {
+            dc_guard_hz: 30.0,
+            ..Default::default()
+        };
+        assert!(ThreeAxisNoiseEstimator::<60>::with_exclusion(0.1, &everything).is_err());
+    }
+
+    #[test]
+    pub fn test_bin_exclusion() {
+        // 72 hz refresh aliases to 12 hz at 60 hz, taking out 11 to 14 hz.
+        let exclusion = BinExclusion::default().with_interference(72.0);
+        let offsets: Vec<usize> = exclusion.offsets(60).collect();
+        assert_eq!(offsets.len(), 20 - 4);
+        assert!(!offsets.contains(&18));
+        assert!(offsets.contains(&14));
+
+        let estimator = ThreeAxisNoiseEstimator::<60>::with_exclusion(0.1, &exclusion).unwrap();
+        assert_eq!(estimator.x.len(), 16);
+    }
+
+    #[test]
+    pub fn test_snapshot_resumes() {
+        let mut noise = crate::synth::GaussianNoise::new(2.0, 9);
+        let mut samples = (0..200).map(|_| [noise.sample(), noise.sample(), noise.sample()]);
+
+        let mut original = SixtyHzThreeAxisNoiseEstimator::new(0.01);
+        for [x, y, z] in samples.by_ref().take(100) {
+            original.update(x, y, z);
+        }
+        let mut restored = SixtyHzThreeAxisNoiseEstimator::restore(&original.snapshot()).unwrap();
+
+        for [x, y, z] in samples {
+            assert_eq!(original.update(x, y, z), restored.update(x, y, z));
+        }
+        assert_eq!(original.mean_variance(), restored.mean_variance());
+        assert_eq!(original.snapshot(), restored.snapshot());
     }
 }
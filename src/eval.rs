@@ -0,0 +1,268 @@
+use crate::{filter::MultiAxisFilter, math, tuner::FinalTuningSettings};
+
+// We don't look for lag beyond this - anything slower than a second isn't a smoothing filter
+// anymore.
+const MAX_LAG_SEARCH_SECS: f64 = 1.0;
+
+/// How a filter configuration performed against a known ground truth trace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    // Standard deviation of the residual (filtered - ground truth).
+    pub precision: f64,
+    // Delay of the filtered trace relative to the ground truth, found by cross-correlation.
+    pub lag_secs: f64,
+    // How far the filtered trace travelled past the range covered by the ground truth, in the
+    // same units as the input.
+    pub overshoot: f64,
+}
+
+// Runs the tuned filter over the noisy trace and compares the output against the ground truth.
+// Both traces must be sampled at sample_rate and be the same length, otherwise None is returned.
+pub fn evaluate(
+    ground_truth: &[f64],
+    noisy: &[f64],
+    sample_rate: f64,
+    settings: &FinalTuningSettings,
+) -> Option<Evaluation> {
+    if ground_truth.is_empty() || ground_truth.len() != noisy.len() {
+        return None;
+    }
+
+    let filtered = run_filter(noisy, sample_rate, settings);
+
+    Some(evaluate_filtered(ground_truth, &filtered, sample_rate))
+}
+
+fn run_filter(input: &[f64], sample_rate: f64, settings: &FinalTuningSettings) -> Vec<f64> {
+    let mut filter = MultiAxisFilter::<1>::new(sample_rate, settings);
+    input.iter().map(|&x| filter.filter([x])[0]).collect()
+}
+
+fn evaluate_filtered(ground_truth: &[f64], filtered: &[f64], sample_rate: f64) -> Evaluation {
+    Evaluation {
+        precision: residual_std_dev(ground_truth, filtered),
+        lag_secs: lag_samples(ground_truth, filtered, sample_rate) as f64 / sample_rate,
+        overshoot: overshoot(ground_truth, filtered),
+    }
+}
+
+/// Side by side metrics for one candidate in an A/B comparison.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub settings: FinalTuningSettings,
+    pub evaluation: Evaluation,
+    // Number of separate times the filtered trace left the range covered by the reference.
+    pub ringing: usize,
+    // Total distance travelled by the filtered trace. Jittery output travels further, so lower
+    // is smoother for the same underlying motion.
+    pub path_length: f64,
+}
+
+// Runs every candidate over the same recorded input so tuning changes can be justified with
+// numbers. The reference should be ground truth if you have it - otherwise passing the raw
+// recording still gives meaningful lag and path length, while precision then reads as how far
+// each filter pulled the signal away from the raw input.
+//
+// Results are returned in the same order as the candidates, or None if the traces are empty or
+// differ in length.
+pub fn compare(
+    reference: &[f64],
+    input: &[f64],
+    sample_rate: f64,
+    candidates: &[FinalTuningSettings],
+) -> Option<Vec<Comparison>> {
+    if reference.is_empty() || reference.len() != input.len() {
+        return None;
+    }
+
+    let comparisons = candidates
+        .iter()
+        .map(|settings| {
+            let filtered = run_filter(input, sample_rate, settings);
+
+            Comparison {
+                settings: settings.clone(),
+                evaluation: evaluate_filtered(reference, &filtered, sample_rate),
+                ringing: excursions(reference, &filtered),
+                path_length: path_length(&filtered),
+            }
+        })
+        .collect();
+
+    Some(comparisons)
+}
+
+pub(crate) fn residual_std_dev(truth: &[f64], filtered: &[f64]) -> f64 {
+    let n = truth.len() as f64;
+    let residuals = truth.iter().zip(filtered).map(|(t, f)| f - t);
+    let mean = residuals.clone().sum::<f64>() / n;
+    let var = residuals.map(|r| math::powi(r - mean, 2)).sum::<f64>() / n;
+    var.sqrt()
+}
+
+// Finds the shift (in samples) of the filtered trace that best lines it up with the truth.
+//
+// Each shift is scored on the normalized correlation of just the overlapping parts. A plain
+// covariance against whole-trace means drifts with how much of the trace each shift drops, which
+// can favour a shift even when the traces are identical.
+pub(crate) fn lag_samples(truth: &[f64], filtered: &[f64], sample_rate: f64) -> usize {
+    let max_shift = ((MAX_LAG_SEARCH_SECS * sample_rate) as usize).min(truth.len() / 2);
+
+    let mut best_shift = 0;
+    let mut best_corr = f64::MIN;
+    for shift in 0..=max_shift {
+        let corr = correlation(&truth[..truth.len() - shift], &filtered[shift..]);
+
+        if corr > best_corr {
+            best_corr = corr;
+            best_shift = shift;
+        }
+    }
+
+    best_shift
+}
+
+// Pearson correlation of two equal length traces, or 0 if either is flat.
+fn correlation(a: &[f64], b: &[f64]) -> f64 {
+    let n = a.len() as f64;
+    let a_mean = a.iter().sum::<f64>() / n;
+    let b_mean = b.iter().sum::<f64>() / n;
+
+    let (mut cov, mut a_var, mut b_var) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        let (dx, dy) = (x - a_mean, y - b_mean);
+        cov += dx * dy;
+        a_var += dx * dx;
+        b_var += dy * dy;
+    }
+
+    if a_var > 0.0 && b_var > 0.0 {
+        cov / (a_var * b_var).sqrt()
+    } else {
+        0.0
+    }
+}
+
+pub(crate) fn overshoot(truth: &[f64], filtered: &[f64]) -> f64 {
+    let max = truth.iter().copied().fold(f64::MIN, f64::max);
+    let min = truth.iter().copied().fold(f64::MAX, f64::min);
+
+    filtered
+        .iter()
+        .map(|&f| (f - max).max(min - f))
+        .fold(0.0, f64::max)
+}
+
+pub(crate) fn excursions(truth: &[f64], filtered: &[f64]) -> usize {
+    let max = truth.iter().copied().fold(f64::MIN, f64::max);
+    let min = truth.iter().copied().fold(f64::MAX, f64::min);
+
+    let mut count = 0;
+    let mut outside = false;
+    for &f in filtered {
+        let now_outside = f > max || f < min;
+        if now_outside && !outside {
+            count += 1;
+        }
+        outside = now_outside;
+    }
+
+    count
+}
+
+pub(crate) fn path_length(filtered: &[f64]) -> f64 {
+    filtered.windows(2).map(|w| (w[1] - w[0]).abs()).sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::{Shape, SignalGenerator};
+
+    #[test]
+    pub fn test_evaluate() {
+        let signal = SignalGenerator::new(60.0).with_noise(1.0).generate(
+            Shape::Sine {
+                amplitude: 10.0,
+                frequency_hz: 0.1,
+            },
+            20.0,
+        );
+        let settings = |min_cutoff_hz| FinalTuningSettings {
+            min_cutoff_hz,
+            beta: 0.0,
+        };
+        let eval = |min_cutoff_hz| {
+            evaluate(
+                &signal.ground_truth,
+                &signal.noisy,
+                60.0,
+                &settings(min_cutoff_hz),
+            )
+            .unwrap()
+        };
+
+        // Smoothing takes out much of the noise, and lowering the cutoff trades more lag for it.
+        let (light, heavy) = (eval(2.0), eval(0.5));
+        assert!(light.precision < 0.75, "{light:?}");
+        assert!(
+            light.lag_secs > 0.0 && heavy.lag_secs > light.lag_secs,
+            "{light:?} {heavy:?}"
+        );
+        // A plain low pass can't overshoot a sine.
+        assert!(heavy.overshoot < 0.5, "{heavy:?}");
+
+        // Unfiltered, the precision is just the noise.
+        let raw = evaluate_filtered(&signal.ground_truth, &signal.noisy, 60.0);
+        assert!((raw.precision - 1.0).abs() < 0.05, "{raw:?}");
+        let exact = evaluate_filtered(&signal.ground_truth, &signal.ground_truth, 60.0);
+        assert_eq!(
+            (exact.precision, exact.lag_secs, exact.overshoot),
+            (0.0, 0.0, 0.0)
+        );
+
+        assert!(evaluate(&[], &[], 60.0, &settings(1.0)).is_none());
+        assert!(evaluate(&[0.0; 2], &[0.0; 3], 60.0, &settings(1.0)).is_none());
+    }
+
+    #[test]
+    pub fn test_compare() {
+        let signal = SignalGenerator::new(60.0).with_noise(0.5).generate(
+            Shape::Jerk {
+                amplitude: 10.0,
+                start_secs: 1.0,
+                duration_secs: 0.5,
+            },
+            4.0,
+        );
+        let candidates = [
+            FinalTuningSettings {
+                min_cutoff_hz: 5.0,
+                beta: 0.0,
+            },
+            FinalTuningSettings {
+                min_cutoff_hz: 0.5,
+                beta: 0.0,
+            },
+        ];
+
+        let results = compare(&signal.ground_truth, &signal.noisy, 60.0, &candidates).unwrap();
+        assert_eq!(results.len(), 2);
+        for (result, settings) in results.iter().zip(&candidates) {
+            assert_eq!(&result.settings, settings);
+            let evaluation = evaluate(&signal.ground_truth, &signal.noisy, 60.0, settings);
+            assert_eq!(Some(&result.evaluation), evaluation.as_ref());
+        }
+
+        // The heavier filter travels less and lags more, and being slower leaves the range of the
+        // move fewer times.
+        let (light, heavy) = (&results[0], &results[1]);
+        assert!(heavy.path_length < light.path_length, "{light:?} {heavy:?}");
+        assert!(heavy.evaluation.lag_secs > light.evaluation.lag_secs);
+        assert!(heavy.ringing < light.ringing, "{light:?} {heavy:?}");
+        // Without jitter, a move's path length is just how far it went.
+        assert!((path_length(&signal.ground_truth) - 10.0).abs() < 1e-9);
+
+        assert!(compare(&[0.0; 2], &[0.0; 3], 60.0, &candidates).is_none());
+    }
+}
@@ -0,0 +1,49 @@
+//! Milestone log records for apps already using the log crate, behind the `log` feature.
+//! Everything goes out under the `pitch_pipe` target, with the interesting numbers as key
+//! values, so records can be filtered and picked apart without parsing messages. Nothing is
+//! logged per sample - only when calibration or tuning moves along, or parameters change.
+//!
+//! Without the feature the macros expand to nothing that runs, and their values are never
+//! evaluated.
+
+pub const TARGET: &str = "pitch_pipe";
+
+// Info level, for calibration stages, convergence and tuning. Used as
+// `milestone!("message", key = value, ...)`.
+macro_rules! milestone {
+    ($msg:literal $(, $key:ident = $value:expr)* $(,)?) => {
+        $crate::events::record!(log::Level::Info, $msg $(, $key = $value)*)
+    };
+}
+
+// Debug level, for parameter changes, which can come much more often than a tune.
+macro_rules! detail {
+    ($msg:literal $(, $key:ident = $value:expr)* $(,)?) => {
+        $crate::events::record!(log::Level::Debug, $msg $(, $key = $value)*)
+    };
+}
+
+#[cfg(feature = "log")]
+macro_rules! record {
+    ($level:expr, $msg:literal) => {
+        log::log!(target: $crate::events::TARGET, $level, $msg)
+    };
+    ($level:expr, $msg:literal $(, $key:ident = $value:expr)+) => {
+        log::log!(target: $crate::events::TARGET, $level, $($key = $value),+; $msg)
+    };
+}
+
+// Refers to the values from a closure that's never called, so variables only used for logging
+// don't warn.
+#[cfg(not(feature = "log"))]
+macro_rules! record {
+    ($level:expr, $msg:literal $(, $key:ident = $value:expr)*) => {
+        let _ = || {
+            $(let _ = &$value;)*
+        };
+    };
+}
+
+pub(crate) use detail;
+pub(crate) use milestone;
+pub(crate) use record;
@@ -0,0 +1,395 @@
+use crate::{
+    events,
+    one_euro::OneEuroFilter,
+    tuner::FinalTuningSettings,
+    wcet::{BoundedWork, WorkBound},
+};
+
+// The derivative cutoff used by the tuner when simulating lag. Filters built from tuning results
+// need to match it, otherwise the lag promised by the tuner doesn't hold.
+pub(crate) const DERIVATIVE_CUTOFF_HZ: f64 = 1.0;
+
+/// The interface the tuner needs from a filter - filter one sample, take new parameters, and
+/// start over. Implement it to tune smoothers other than the one euro filter (i.e. DESP or a
+/// Kalman filter) with Tuner::with_filter, mapping min_cutoff_hz and beta onto whatever the
+/// smoother's two knobs are.
+pub trait SmoothingFilter {
+    fn filter(&mut self, x: f64) -> f64;
+
+    // Takes effect from the next sample, without dropping state.
+    fn set_parameters(&mut self, settings: &FinalTuningSettings);
+
+    fn reset(&mut self);
+}
+
+impl SmoothingFilter for OneEuroFilter {
+    fn filter(&mut self, x: f64) -> f64 {
+        OneEuroFilter::filter(self, x)
+    }
+
+    fn set_parameters(&mut self, settings: &FinalTuningSettings) {
+        self.min_cutoff_hz = settings.min_cutoff_hz;
+        self.beta = settings.beta;
+    }
+
+    fn reset(&mut self) {
+        OneEuroFilter::reset(self)
+    }
+}
+
+impl SmoothingFilter for one_euro_rs::OneEuroFilter<f64> {
+    fn filter(&mut self, x: f64) -> f64 {
+        one_euro_rs::OneEuroFilter::filter(self, x)
+    }
+
+    fn set_parameters(&mut self, settings: &FinalTuningSettings) {
+        self.configuration.cutoff_min = settings.min_cutoff_hz;
+        self.configuration.beta = settings.beta;
+    }
+
+    fn reset(&mut self) {
+        *self = Self::from_configuration(self.configuration.clone());
+    }
+}
+
+/// Limits on one axis of filtered output, i.e. a screen's edges or a robot's workspace.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OutputBounds {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl OutputBounds {
+    // For axis that don't need limiting.
+    pub const UNBOUNDED: Self = Self::new(f64::NEG_INFINITY, f64::INFINITY);
+
+    pub const fn new(min: f64, max: f64) -> Self {
+        Self { min, max }
+    }
+}
+
+/// A runtime one euro filter over D independent axis, all sharing a single set of tuned
+/// parameters. Cloning is a cheap copy, state included.
+#[derive(Debug, Clone)]
+pub struct MultiAxisFilter<const D: usize> {
+    sample_rate: f64,
+    settings: FinalTuningSettings,
+    axes: [OneEuroFilter; D],
+    // Output limits per axis, see with_output_bounds.
+    bounds: Option<[OutputBounds; D]>,
+}
+
+/// The common case - three axis of positional or accelerometer data.
+pub type ThreeAxisFilter = MultiAxisFilter<3>;
+
+/// Everything a MultiAxisFilter needs to carry on exactly where it left off - parameters and
+/// smoothing state - i.e. to hand filtering over to a restarted compositor or another process
+/// without the cursor jumping. Output bounds are configuration rather than state, so they're
+/// not included.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MultiAxisFilterState {
+    pub sample_rate: f64,
+    pub settings: FinalTuningSettings,
+    pub axes: Vec<OneEuroFilter>,
+}
+
+impl<const D: usize> MultiAxisFilter<D> {
+    // Const, so firmware can place filters directly in a static.
+    pub const fn new(sample_rate: f64, settings: &FinalTuningSettings) -> Self {
+        let axis = settings.to_one_euro(sample_rate);
+
+        Self {
+            sample_rate,
+            settings: FinalTuningSettings {
+                min_cutoff_hz: settings.min_cutoff_hz,
+                beta: settings.beta,
+            },
+            axes: [axis; D],
+            bounds: None,
+        }
+    }
+
+    // Each axis with its own tuned parameters, see tuner::tune_per_axis. settings() reports the
+    // first axis, or zeros if there are no axes.
+    pub fn with_axis_settings(sample_rate: f64, settings: &[FinalTuningSettings; D]) -> Self {
+        Self {
+            sample_rate,
+            settings: settings.first().cloned().unwrap_or(FinalTuningSettings {
+                min_cutoff_hz: 0.0,
+                beta: 0.0,
+            }),
+            axes: core::array::from_fn(|i| settings[i].to_one_euro(sample_rate)),
+            bounds: None,
+        }
+    }
+
+    // Keeps the output within bounds on each axis. Output that would land outside is pulled
+    // back to the bound, and so is the filter's own state, so after a spike past an edge the
+    // output stays on the edge rather than gliding back in from beyond it. Raw samples still
+    // drive the derivative, so how fast the cutoff opens isn't affected.
+    pub fn with_output_bounds(mut self, bounds: [OutputBounds; D]) -> Self {
+        self.set_output_bounds(Some(bounds));
+        self
+    }
+
+    // Changes or removes bounds mid stream, i.e. when a window is resized. Current output is
+    // pulled within the new bounds straight away.
+    pub fn set_output_bounds(&mut self, bounds: Option<[OutputBounds; D]>) {
+        self.bounds = bounds;
+        self.clamp_output();
+    }
+
+    pub fn output_bounds(&self) -> Option<&[OutputBounds; D]> {
+        self.bounds.as_ref()
+    }
+
+    // Filters one sample across all axis.
+    pub fn filter(&mut self, sample: [f64; D]) -> [f64; D] {
+        let mut out = [0.0; D];
+        for (i, axis) in self.axes.iter_mut().enumerate() {
+            out[i] = axis.filter(sample[i]);
+        }
+        self.clamp_output().unwrap_or(out)
+    }
+
+    // Filters a sample that arrived dt seconds after the previous one.
+    pub fn filter_with_dt(&mut self, sample: [f64; D], dt: f64) -> [f64; D] {
+        let out = core::array::from_fn(|i| self.axes[i].filter_with_dt(sample[i], dt));
+        self.clamp_output().unwrap_or(out)
+    }
+
+    // Clamps every axis' state to its bounds, returning the clamped output. None without
+    // bounds, or before the first sample.
+    fn clamp_output(&mut self) -> Option<[f64; D]> {
+        let bounds = self.bounds?;
+        let mut out = [0.0; D];
+        for (i, axis) in self.axes.iter_mut().enumerate() {
+            out[i] = axis.clamp_value(bounds[i].min, bounds[i].max)?;
+        }
+        Some(out)
+    }
+
+    // Drops all filter state, so the next sample is passed through as is. Tuned parameters are
+    // kept.
+    pub fn reset(&mut self) {
+        self.axes.iter_mut().for_each(OneEuroFilter::reset);
+    }
+
+    // The filter's smoothed derivative per axis, in units per second. Use this rather than
+    // differentiating the filtered output - it's already smoothed and is exactly what the filter
+    // uses to adapt its cutoff.
+    pub fn velocity(&self) -> [f64; D] {
+        core::array::from_fn(|i| self.axes[i].velocity())
+    }
+
+    // Swaps in new tuned parameters without dropping filter state, so there's no jump in the
+    // output. Records an event unless the bounded-time feature is on, as loggers can block.
+    pub fn set_settings(&mut self, settings: &FinalTuningSettings) {
+        self.apply_settings(settings);
+        if !cfg!(feature = "bounded-time") {
+            events::detail!(
+                "filter parameters applied",
+                min_cutoff_hz = settings.min_cutoff_hz,
+                beta = settings.beta,
+            );
+        }
+    }
+
+    // set_settings without the event, for parameters that change every sample - i.e. during a
+    // crossfade.
+    pub(crate) fn apply_settings(&mut self, settings: &FinalTuningSettings) {
+        for axis in self.axes.iter_mut() {
+            axis.min_cutoff_hz = settings.min_cutoff_hz;
+            axis.beta = settings.beta;
+        }
+        self.settings = settings.clone();
+    }
+
+    // Per axis version of set_settings.
+    pub fn set_axis_settings(&mut self, settings: &[FinalTuningSettings; D]) {
+        for (axis, settings) in self.axes.iter_mut().zip(settings.iter()) {
+            axis.min_cutoff_hz = settings.min_cutoff_hz;
+            axis.beta = settings.beta;
+        }
+        if let Some(first) = settings.first() {
+            self.settings = first.clone();
+        }
+        if !cfg!(feature = "bounded-time") {
+            events::detail!("per axis filter parameters applied", axes = D);
+        }
+    }
+
+    // The parameters each axis is currently running with.
+    pub fn axis_settings(&self) -> [FinalTuningSettings; D] {
+        core::array::from_fn(|i| FinalTuningSettings {
+            min_cutoff_hz: self.axes[i].min_cutoff_hz,
+            beta: self.axes[i].beta,
+        })
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    pub fn settings(&self) -> &FinalTuningSettings {
+        &self.settings
+    }
+
+    pub fn state(&self) -> MultiAxisFilterState {
+        MultiAxisFilterState {
+            sample_rate: self.sample_rate,
+            settings: self.settings.clone(),
+            axes: self.axes.to_vec(),
+        }
+    }
+
+    // None if the state is for a different number of axis.
+    pub fn from_state(state: &MultiAxisFilterState) -> Option<Self> {
+        Some(Self {
+            sample_rate: state.sample_rate,
+            settings: state.settings.clone(),
+            axes: state.axes.as_slice().try_into().ok()?,
+            bounds: None,
+        })
+    }
+}
+
+impl<const D: usize> BoundedWork for MultiAxisFilter<D> {
+    fn work_bound(&self) -> WorkBound {
+        WorkBound::filter(D)
+    }
+}
+
+impl FinalTuningSettings {
+    // A fresh filter running these parameters. Call once per tracked point - or spawn one and
+    // clone it - to run many identically tuned filters from a single calibration.
+    pub fn spawn_filter<const D: usize>(&self, sample_rate: f64) -> MultiAxisFilter<D> {
+        MultiAxisFilter::new(sample_rate, self)
+    }
+}
+
+// glam overloads, so game engines don't have to convert on the hot path.
+#[cfg(feature = "glam")]
+impl ThreeAxisFilter {
+    pub fn filter_vec3(&mut self, sample: glam::Vec3) -> glam::Vec3 {
+        glam::DVec3::from_array(self.filter(sample.as_dvec3().to_array())).as_vec3()
+    }
+
+    pub fn filter_dvec3(&mut self, sample: glam::DVec3) -> glam::DVec3 {
+        glam::DVec3::from_array(self.filter(sample.to_array()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_state_resumes() {
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 0.5,
+            beta: 0.01,
+        };
+        let mut filter = ThreeAxisFilter::new(60.0, &settings);
+        for i in 0..30 {
+            filter.filter([i as f64, 0.5 * i as f64, 1.0]);
+        }
+
+        let state = filter.state();
+        #[cfg(feature = "serde")]
+        let state: MultiAxisFilterState =
+            serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+        let mut resumed = ThreeAxisFilter::from_state(&state).unwrap();
+        assert_eq!(
+            resumed.filter([31.0, 15.5, 1.0]),
+            filter.filter([31.0, 15.5, 1.0])
+        );
+        assert!(MultiAxisFilter::<2>::from_state(&state).is_none());
+    }
+
+    #[test]
+    pub fn test_spawn_filter() {
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 0.5,
+            beta: 0.01,
+        };
+        let mut points: Vec<MultiAxisFilter<2>> =
+            (0..24).map(|_| settings.spawn_filter(60.0)).collect();
+        for (i, point) in points.iter_mut().enumerate() {
+            point.filter([i as f64, 0.0]);
+        }
+        let mut copy = points[3].clone();
+        assert_eq!(copy.settings(), &settings);
+        assert_eq!(copy.filter([5.0, 1.0]), points[3].filter([5.0, 1.0]));
+    }
+
+    #[test]
+    pub fn test_axis_settings() {
+        let settings = [
+            FinalTuningSettings {
+                min_cutoff_hz: 0.5,
+                beta: 0.01,
+            },
+            FinalTuningSettings {
+                min_cutoff_hz: 2.0,
+                beta: 0.0,
+            },
+        ];
+        let mut filter = MultiAxisFilter::<2>::with_axis_settings(60.0, &settings);
+        assert_eq!(filter.axis_settings(), settings);
+        assert_eq!(filter.settings(), &settings[0]);
+
+        // The same as filtering each axis on its own.
+        let mut x = MultiAxisFilter::<1>::new(60.0, &settings[0]);
+        let mut y = MultiAxisFilter::<1>::new(60.0, &settings[1]);
+        for sample in [[0.0, 0.0], [10.0, 10.0], [10.0, 10.0]] {
+            let [fx, fy] = filter.filter(sample);
+            assert_eq!(
+                [fx, fy],
+                [x.filter([sample[0]])[0], y.filter([sample[1]])[0]]
+            );
+        }
+
+        // Nothing to seed from, but nothing to panic over either.
+        let mut empty = MultiAxisFilter::<0>::with_axis_settings(60.0, &[]);
+        assert_eq!(empty.filter([]), [0.0; 0]);
+    }
+
+    #[test]
+    pub fn test_output_bounds() {
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 1.0,
+            beta: 0.0,
+        };
+        let screen = [OutputBounds::new(0.0, 1920.0), OutputBounds::UNBOUNDED];
+        let mut bounded = MultiAxisFilter::<2>::new(60.0, &settings).with_output_bounds(screen);
+        let mut free = MultiAxisFilter::<2>::new(60.0, &settings);
+        for _ in 0..30 {
+            bounded.filter([1900.0, 0.0]);
+            free.filter([1900.0, 0.0]);
+        }
+
+        // A spike well past the edge holds the cursor on it...
+        for _ in 0..5 {
+            let [x, _] = bounded.filter([5000.0, 5000.0]);
+            free.filter([5000.0, 5000.0]);
+            assert_eq!(x, 1920.0);
+        }
+        // ...and once it's gone, smoothing carries on from the edge rather than from beyond it.
+        let [x, y] = bounded.filter([1900.0, 0.0]);
+        let [free_x, free_y] = free.filter([1900.0, 0.0]);
+        assert!(x < 1920.0 && free_x > 1920.0);
+        assert_eq!(y, free_y);
+
+        // Shrinking the bounds pulls the output straight in.
+        bounded.set_output_bounds(Some([
+            OutputBounds::new(0.0, 1280.0),
+            OutputBounds::UNBOUNDED,
+        ]));
+        assert_eq!(bounded.filter([1900.0, 0.0])[0], 1280.0);
+        bounded.set_output_bounds(None);
+        assert!(bounded.filter([1900.0, 0.0])[0] > 1280.0);
+    }
+}
@@ -0,0 +1,204 @@
+//! A Q16.16 fixed point version of the runtime filter, for MCUs without an FPU (i.e. Cortex-M0).
+//!
+//! Calibration and tuning still need floating point, so the intended flow is to tune host side,
+//! quantize the result with QuantizedTuning::from_settings, and ship the raw values to the
+//! device.
+
+use core::ops::{Add, Div, Mul, Sub};
+
+use crate::tuner::FinalTuningSettings;
+
+const FRAC_BITS: u32 = 16;
+
+// 2 * PI in Q16.16.
+const TWO_PI: Q16 = Q16(411_775);
+
+/// A Q16.16 fixed point number. Arithmetic saturates rather than wrapping, so a burst of bad
+/// input pins the output instead of flipping its sign.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Q16(pub i32);
+
+impl Q16 {
+    pub const ZERO: Q16 = Q16(0);
+    pub const ONE: Q16 = Q16(1 << FRAC_BITS);
+
+    pub const fn from_int(value: i16) -> Self {
+        Q16((value as i32) << FRAC_BITS)
+    }
+
+    pub fn from_f64(value: f64) -> Self {
+        let raw = (value * (1 << FRAC_BITS) as f64).round();
+        Q16(raw.clamp(i32::MIN as f64, i32::MAX as f64) as i32)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / (1 << FRAC_BITS) as f64
+    }
+
+    fn saturate(raw: i64) -> Self {
+        Q16(raw.clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+    }
+
+    pub fn abs(self) -> Q16 {
+        Q16(self.0.saturating_abs())
+    }
+}
+
+impl Add for Q16 {
+    type Output = Q16;
+
+    fn add(self, other: Q16) -> Q16 {
+        Q16(self.0.saturating_add(other.0))
+    }
+}
+
+impl Sub for Q16 {
+    type Output = Q16;
+
+    fn sub(self, other: Q16) -> Q16 {
+        Q16(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Mul for Q16 {
+    type Output = Q16;
+
+    fn mul(self, other: Q16) -> Q16 {
+        Q16::saturate((self.0 as i64 * other.0 as i64) >> FRAC_BITS)
+    }
+}
+
+impl Div for Q16 {
+    type Output = Q16;
+
+    // Division by zero saturates towards the sign of the dividend.
+    fn div(self, other: Q16) -> Q16 {
+        if other.0 == 0 {
+            return if self.0 < 0 {
+                Q16(i32::MIN)
+            } else {
+                Q16(i32::MAX)
+            };
+        }
+        Q16::saturate(((self.0 as i64) << FRAC_BITS) / other.0 as i64)
+    }
+}
+
+/// Tuned parameters quantized to Q16.16, ready to ship to a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuantizedTuning {
+    pub sample_rate: Q16,
+    pub min_cutoff_hz: Q16,
+    pub d_cutoff_hz: Q16,
+    pub beta: Q16,
+}
+
+impl QuantizedTuning {
+    pub fn from_settings(settings: &FinalTuningSettings, sample_rate: f64) -> Self {
+        Self {
+            sample_rate: Q16::from_f64(sample_rate),
+            min_cutoff_hz: Q16::from_f64(settings.min_cutoff_hz),
+            d_cutoff_hz: Q16::from_f64(crate::filter::DERIVATIVE_CUTOFF_HZ),
+            beta: Q16::from_f64(settings.beta),
+        }
+    }
+
+    // Smoothing factor for a given cutoff: 2 pi fc / (2 pi fc + rate). Rearranged from the usual
+    // 1 / (1 + tau / te) so only one division is needed.
+    fn alpha(&self, cutoff_hz: Q16) -> Q16 {
+        let num = TWO_PI * cutoff_hz;
+        num / (num + self.sample_rate)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct FixedAxis {
+    x_prev: Q16,
+    x_hat: Q16,
+    dx_hat: Q16,
+    initialized: bool,
+}
+
+impl FixedAxis {
+    fn filter(&mut self, x: Q16, tuning: &QuantizedTuning, alpha_d: Q16) -> Q16 {
+        if !self.initialized {
+            self.initialized = true;
+            self.x_prev = x;
+            self.x_hat = x;
+            return x;
+        }
+
+        let dx = (x - self.x_prev) * tuning.sample_rate;
+        self.dx_hat = self.dx_hat + alpha_d * (dx - self.dx_hat);
+
+        let cutoff = tuning.min_cutoff_hz + tuning.beta * self.dx_hat.abs();
+        let alpha = tuning.alpha(cutoff);
+
+        self.x_hat = self.x_hat + alpha * (x - self.x_hat);
+        self.x_prev = x;
+        self.x_hat
+    }
+}
+
+/// Fixed point one euro filter over D axis. Uses no floating point at all once constructed.
+#[derive(Debug, Clone)]
+pub struct FixedPointFilter<const D: usize> {
+    tuning: QuantizedTuning,
+    // The derivative cutoff never changes, so its alpha is computed once.
+    alpha_d: Q16,
+    axes: [FixedAxis; D],
+}
+
+impl<const D: usize> FixedPointFilter<D> {
+    pub fn new(tuning: QuantizedTuning) -> Self {
+        Self {
+            alpha_d: tuning.alpha(tuning.d_cutoff_hz),
+            tuning,
+            axes: [FixedAxis::default(); D],
+        }
+    }
+
+    pub fn filter(&mut self, sample: [Q16; D]) -> [Q16; D] {
+        let mut out = [Q16::ZERO; D];
+        for (i, axis) in self.axes.iter_mut().enumerate() {
+            out[i] = axis.filter(sample[i], &self.tuning, self.alpha_d);
+        }
+        out
+    }
+
+    pub fn reset(&mut self) {
+        self.axes = [FixedAxis::default(); D];
+    }
+
+    pub fn tuning(&self) -> &QuantizedTuning {
+        &self.tuning
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filter::MultiAxisFilter;
+
+    #[test]
+    pub fn test_tracks_float_filter() {
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 1.0,
+            beta: 0.05,
+        };
+        let mut float = MultiAxisFilter::<1>::new(60.0, &settings);
+        let mut fixed = FixedPointFilter::<1>::new(QuantizedTuning::from_settings(&settings, 60.0));
+
+        for i in 0..120 {
+            let x = (i as f64 * 0.1).sin() * 10.0;
+            let expected = float.filter([x])[0];
+            let actual = fixed.filter([Q16::from_f64(x)])[0].to_f64();
+            assert!(
+                (expected - actual).abs() < 0.01,
+                "{} vs {}",
+                expected,
+                actual
+            );
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{filter::MultiAxisFilter, tuner::FinalTuningSettings, units::Variance};
+
+/// Combines two streams observing the same quantity (i.e. optical and IMU derived position) by
+/// inverse variance weighting, using each stream's calibrated noise variance. The fused stream
+/// is less noisy than either input - tune the filter that follows for fused_variance rather
+/// than either stream's own.
+///
+/// Streams have to agree on units, frame and timing - any fixed offset between them comes
+/// straight through, weighted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InverseVarianceFusion {
+    // Weight given to the first stream, the second gets the rest.
+    weight: f64,
+    fused_variance: Variance,
+}
+
+impl InverseVarianceFusion {
+    pub fn new(first: Variance, second: Variance) -> Self {
+        let total = first.0 + second.0;
+        // Two noiseless streams are as good as each other.
+        let weight = if total > 0.0 { second.0 / total } else { 0.5 };
+        Self {
+            weight,
+            fused_variance: Variance(
+                first.0 * weight * weight + second.0 * (1.0 - weight) * (1.0 - weight),
+            ),
+        }
+    }
+
+    // How much the first stream counts, from 0 to 1.
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    // Noise variance of the fused stream, for tuning.
+    pub fn fused_variance(&self) -> Variance {
+        self.fused_variance
+    }
+
+    pub fn fuse<const D: usize>(&self, first: [f64; D], second: [f64; D]) -> [f64; D] {
+        core::array::from_fn(|i| self.weight * first[i] + (1.0 - self.weight) * second[i])
+    }
+
+    // For streams that drop out now and then - whichever stream has a sample this tick is used
+    // alone. Noise briefly rises to that stream's own when it does.
+    pub fn fuse_available<const D: usize>(
+        &self,
+        first: Option<[f64; D]>,
+        second: Option<[f64; D]>,
+    ) -> Option<[f64; D]> {
+        match (first, second) {
+            (Some(first), Some(second)) => Some(self.fuse(first, second)),
+            (first, second) => first.or(second),
+        }
+    }
+}
+
+/// Fusion feeding straight into a filter.
+pub struct FusedFilter<const D: usize> {
+    fusion: InverseVarianceFusion,
+    filter: MultiAxisFilter<D>,
+}
+
+impl<const D: usize> FusedFilter<D> {
+    // Settings should come from tuning for fusion.fused_variance().
+    pub fn new(
+        fusion: InverseVarianceFusion,
+        sample_rate: f64,
+        settings: &FinalTuningSettings,
+    ) -> Self {
+        Self {
+            fusion,
+            filter: MultiAxisFilter::new(sample_rate, settings),
+        }
+    }
+
+    pub fn filter(&mut self, first: [f64; D], second: [f64; D]) -> [f64; D] {
+        self.filter.filter(self.fusion.fuse(first, second))
+    }
+
+    // None only when neither stream has a sample, in which case the filter isn't advanced.
+    pub fn filter_available(
+        &mut self,
+        first: Option<[f64; D]>,
+        second: Option<[f64; D]>,
+    ) -> Option<[f64; D]> {
+        let fused = self.fusion.fuse_available(first, second)?;
+        Some(self.filter.filter(fused))
+    }
+
+    pub fn fusion(&self) -> &InverseVarianceFusion {
+        &self.fusion
+    }
+
+    pub fn inner(&self) -> &MultiAxisFilter<D> {
+        &self.filter
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+
+    #[test]
+    pub fn test_fusion_reduces_noise() {
+        let fusion = InverseVarianceFusion::new(Variance(1.0), Variance(4.0));
+        assert!((fusion.weight() - 0.8).abs() < 1e-12);
+        assert!((fusion.fused_variance().0 - 0.8).abs() < 1e-12);
+
+        let mut optical = GaussianNoise::new(1.0, 31);
+        let mut imu = GaussianNoise::new(2.0, 37);
+        let n = 20_000;
+        let mut power = 0.0;
+        for _ in 0..n {
+            let [fused] = fusion.fuse([optical.sample()], [imu.sample()]);
+            power += fused * fused;
+        }
+        assert!((power / n as f64 - 0.8).abs() < 0.05);
+
+        assert_eq!(fusion.fuse_available(None, Some([3.0])), Some([3.0]));
+        assert_eq!(fusion.fuse_available::<1>(None, None), None);
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::VecDeque;
+
+use crate::{
+    filter::MultiAxisFilter,
+    source::{SampleSource, Timestamp},
+    units::Seconds,
+};
+
+// A gap this many times the sample period means samples went missing.
+const GAP_FACTOR: f64 = 1.5;
+
+// Gaps with more missing samples than this aren't filled - there's no telling what the device
+// did over that long, so it's better to pick up from the next real sample.
+const DEFAULT_MAX_MISSING: usize = 10;
+
+/// Reconstructs samples lost to dropouts, so estimators and filters see a steady stream rather
+/// than a jump. Missing samples are placed evenly across the gap on a cubic Hermite curve, which
+/// leaves the last real sample heading the way the signal was heading - the slope there comes
+/// from the filter's smoothed derivative when there is one, otherwise from the last two
+/// samples - and reaches the next real sample on the slope a parabola through both would have.
+///
+/// Without gap filling, whatever follows a dropout sees the last sample held and then a jump,
+/// which the filter reads as sudden movement.
+#[derive(Debug, Clone)]
+pub struct GapFiller<const D: usize> {
+    period: f64,
+    max_missing: usize,
+    last: Option<(f64, [f64; D])>,
+    // Slope over the last two real samples, per second.
+    slope: [f64; D],
+}
+
+impl<const D: usize> GapFiller<D> {
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            period: 1.0 / sample_rate,
+            max_missing: DEFAULT_MAX_MISSING,
+            last: None,
+            slope: [0.0; D],
+        }
+    }
+
+    pub fn with_max_missing(mut self, max_missing: usize) -> Self {
+        self.max_missing = max_missing;
+        self
+    }
+
+    // The samples missing before this one, with their timestamps in seconds. Empty unless a
+    // dropout was detected.
+    pub fn fill(&mut self, timestamp_secs: f64, sample: [f64; D]) -> Vec<(f64, [f64; D])> {
+        let slope = self.slope;
+        self.fill_with_slope(timestamp_secs, sample, slope)
+    }
+
+    // fill, with the slope at the last sample given, i.e. from MultiAxisFilter::velocity.
+    pub fn fill_with_slope(
+        &mut self,
+        timestamp_secs: f64,
+        sample: [f64; D],
+        slope: [f64; D],
+    ) -> Vec<(f64, [f64; D])> {
+        let Some((last_secs, last)) = self.last.replace((timestamp_secs, sample)) else {
+            return Vec::new();
+        };
+        let gap = timestamp_secs - last_secs;
+        if gap > 0.0 {
+            self.slope = core::array::from_fn(|i| (sample[i] - last[i]) / gap);
+        }
+        if gap.is_nan() || gap <= GAP_FACTOR * self.period {
+            return Vec::new();
+        }
+
+        let steps = (gap / self.period).round() as usize;
+        if steps - 1 > self.max_missing {
+            return Vec::new();
+        }
+        let end_slope: [f64; D] = core::array::from_fn(|i| 2.0 * self.slope[i] - slope[i]);
+        (1..steps)
+            .map(|step| {
+                let t = step as f64 / steps as f64;
+                let filled = core::array::from_fn(|i| {
+                    hermite(last[i], slope[i] * gap, sample[i], end_slope[i] * gap, t)
+                });
+                (last_secs + t * gap, filled)
+            })
+            .collect()
+    }
+
+    // Starts over, i.e. after the device reconnects.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.slope = [0.0; D];
+    }
+}
+
+// Cubic Hermite between p0 and p1, with tangents scaled to the interval.
+fn hermite(p0: f64, m0: f64, p1: f64, m1: f64, t: f64) -> f64 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (2.0 * t3 - 3.0 * t2 + 1.0) * p0
+        + (t3 - 2.0 * t2 + t) * m0
+        + (-2.0 * t3 + 3.0 * t2) * p1
+        + (t3 - t2) * m1
+}
+
+impl<const D: usize> MultiAxisFilter<D> {
+    // Filters a timestamped sample, filtering any samples the filler reconstructs ahead of it
+    // first. The filter's own derivative sets the slope the gap starts out on.
+    pub fn filter_filling_gaps(
+        &mut self,
+        filler: &mut GapFiller<D>,
+        timestamp_secs: f64,
+        sample: [f64; D],
+    ) -> [f64; D] {
+        let previous = filler.last.map(|(secs, _)| secs);
+        let filled = filler.fill_with_slope(timestamp_secs, sample, self.velocity());
+        let Some(mut previous) = previous else {
+            return self.filter(sample);
+        };
+        for (secs, filled) in filled {
+            self.filter_with_dt(filled, secs - previous);
+            previous = secs;
+        }
+        match timestamp_secs - previous {
+            dt if dt > 0.0 => self.filter_with_dt(sample, dt),
+            _ => self.filter(sample),
+        }
+    }
+}
+
+/// A source with dropouts filled in, see SampleSource::gap_filled.
+pub struct GapFilledSource<S, const D: usize> {
+    source: S,
+    filler: GapFiller<D>,
+    queue: VecDeque<(Timestamp, [f64; D])>,
+}
+
+impl<S, const D: usize> GapFilledSource<S, D> {
+    pub fn new(source: S, filler: GapFiller<D>) -> Self {
+        Self {
+            source,
+            filler,
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl<S: SampleSource<D>, const D: usize> SampleSource<D> for GapFilledSource<S, D> {
+    fn next_sample(&mut self) -> Option<(Timestamp, [f64; D])> {
+        if let Some(queued) = self.queue.pop_front() {
+            return Some(queued);
+        }
+        let (timestamp, sample) = self.source.next_sample()?;
+        let filled = self.filler.fill(timestamp.0, sample);
+        self.queue.extend(
+            filled
+                .into_iter()
+                .map(|(secs, filled)| (Seconds(secs), filled)),
+        );
+        self.queue.push_back((timestamp, sample));
+        self.queue.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    pub fn test_fills_dropouts() {
+        let rate = 100.0;
+        let truth = |secs: f64| [(2.0 * PI * secs).sin(), 0.0];
+        let mut filler = GapFiller::<2>::new(rate);
+
+        assert!(filler.fill(0.0, truth(0.0)).is_empty());
+        assert!(filler.fill(0.01, truth(0.01)).is_empty());
+
+        // Four samples lost, with the true slope known at the start.
+        let slope = [2.0 * PI * (2.0 * PI * 0.01).cos(), 0.0];
+        let filled = filler.fill_with_slope(0.06, truth(0.06), slope);
+        assert_eq!(filled.len(), 4);
+        for (secs, sample) in filled {
+            assert!((sample[0] - truth(secs)[0]).abs() < 1e-3);
+        }
+
+        // Too long to guess at.
+        assert!(filler.fill(1.0, truth(1.0)).is_empty());
+    }
+}
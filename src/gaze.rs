@@ -0,0 +1,153 @@
+use crate::{
+    calibrator::CalibrationStage, filter::MultiAxisFilter, math, tuner::FinalTuningSettings,
+    units::StdDev,
+};
+
+/// Velocity threshold (I-VT) saccade detection on 2D gaze samples.
+#[derive(Debug, Clone)]
+pub struct SaccadeDetector {
+    sample_rate: f64,
+    // Gaze speed, in units per second, above which we call it a saccade. With gaze in degrees of
+    // visual angle 30 deg/s is the usual choice.
+    threshold: f64,
+    previous: Option<[f64; 2]>,
+}
+
+impl SaccadeDetector {
+    pub fn new(sample_rate: f64, threshold: f64) -> Self {
+        Self {
+            sample_rate,
+            threshold,
+            previous: None,
+        }
+    }
+
+    // Returns true if the eye is moving faster than the threshold going into this sample.
+    pub fn update(&mut self, sample: [f64; 2]) -> bool {
+        let saccade = match self.previous {
+            Some([px, py]) => {
+                let distance = math::hypot(sample[0] - px, sample[1] - py);
+                distance * self.sample_rate > self.threshold
+            }
+            None => false,
+        };
+        self.previous = Some(sample);
+        saccade
+    }
+}
+
+/// Calibrates on fixations only. Saccades are passed through unfiltered at runtime, so they'd
+/// only inflate both the noise and amplitude estimates.
+pub struct GazeCalibrator {
+    detector: SaccadeDetector,
+    stage: CalibrationStage,
+}
+
+impl GazeCalibrator {
+    pub fn new(sample_rate: f64, saccade_threshold: f64) -> Self {
+        Self {
+            detector: SaccadeDetector::new(sample_rate, saccade_threshold),
+            // Gaze is two dimensional, see CalibrationStage::with_axes.
+            stage: CalibrationStage::with_axes(2),
+        }
+    }
+
+    // Processes a gaze sample, ignoring it if it's part of a saccade. Returns true once the
+    // current stage has enough data.
+    pub fn process(&mut self, sample: [f64; 2]) -> bool {
+        if self.detector.update(sample) {
+            return false;
+        }
+        self.stage.process(sample[0], sample[1], 0.0)
+    }
+
+    // Moves from noise to amplitude calibration.
+    pub fn next(self) -> Self {
+        Self {
+            stage: self.stage.advance(),
+            ..self
+        }
+    }
+
+    // Per axis noise, once noise calibration has been advanced past.
+    pub fn noise_std_dev(&self) -> Option<StdDev> {
+        match &self.stage {
+            CalibrationStage::Amplitude(amplitude) => Some(amplitude.noise_std_dev()),
+            CalibrationStage::Noise(_) => None,
+        }
+    }
+
+    pub fn into_stage(self) -> CalibrationStage {
+        self.stage
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GazeSample {
+    pub position: [f64; 2],
+    // True if the sample was part of a saccade and passed through unfiltered.
+    pub saccade: bool,
+}
+
+/// Smooths fixations while letting saccades through untouched. The filter is reset on every
+/// saccade, so the next fixation starts fresh instead of dragging the smoothed gaze across the
+/// screen.
+pub struct GazeFilter {
+    detector: SaccadeDetector,
+    filter: MultiAxisFilter<2>,
+}
+
+impl GazeFilter {
+    pub fn new(sample_rate: f64, saccade_threshold: f64, settings: &FinalTuningSettings) -> Self {
+        Self {
+            detector: SaccadeDetector::new(sample_rate, saccade_threshold),
+            filter: MultiAxisFilter::new(sample_rate, settings),
+        }
+    }
+
+    pub fn filter(&mut self, sample: [f64; 2]) -> GazeSample {
+        if self.detector.update(sample) {
+            self.filter.reset();
+            return GazeSample {
+                position: sample,
+                saccade: true,
+            };
+        }
+
+        GazeSample {
+            position: self.filter.filter(sample),
+            saccade: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+
+    #[test]
+    pub fn test_gaze_noise_and_saccades() {
+        // Fixating at 60 hz with a twentieth of a degree of jitter - well under the 30 deg/s
+        // saccade threshold.
+        let mut noise = GaussianNoise::new(0.05, 97);
+        let mut calibrator = GazeCalibrator::new(60.0, 30.0);
+        while !calibrator.process([5.0 + noise.sample(), -2.0 + noise.sample()]) {}
+        let calibrator = calibrator.next();
+        let noise_std_dev = calibrator.noise_std_dev().unwrap().0;
+        assert!((noise_std_dev - 0.05).abs() < 0.005, "{noise_std_dev}");
+
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 1.0,
+            beta: 0.01,
+        };
+        let mut filter = GazeFilter::new(60.0, 30.0, &settings);
+        for _ in 0..30 {
+            assert!(!filter.filter([5.0, -2.0]).saccade);
+        }
+        // A 10 degree jump goes straight through.
+        let jump = filter.filter([15.0, -2.0]);
+        assert!(jump.saccade);
+        assert_eq!(jump.position, [15.0, -2.0]);
+    }
+}
@@ -0,0 +1,291 @@
+//! Smoothing for GPS and other outdoor positioning - around 1 hz, with intervals that vary a lot
+//! from fix to fix. Everything is driven by the fixes' own timestamps, and noise comes from the
+//! receiver's reported accuracy when it has one, or from residuals when it doesn't. Tuning goes
+//! through baro::slow_rate_tuner at the average fix rate.
+
+use crate::{
+    baro::slow_rate_tuner,
+    calibrator::TuningSettings,
+    estimators::RunningStatistics,
+    filter::MultiAxisFilter,
+    math,
+    tuner::FinalTuningSettings,
+    units::{Hertz, Seconds, StdDev},
+};
+
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+// Receivers report accuracy as the radius holding 68% of fixes. For 2D gaussian noise that's
+// this many times the per axis standard deviation.
+const ACCURACY_PER_STD_DEV: f64 = 1.515;
+
+// Noise estimates need at least this many fixes, however steady they look.
+const MIN_FIXES: u64 = 30;
+
+const DEFAULT_THRESHOLD: f64 = 0.2;
+
+// A gap this long (i.e. a tunnel) restarts the filter rather than smoothing across it.
+const DEFAULT_MAX_GAP_SECS: f64 = 10.0;
+
+/// Turns latitude and longitude into meters east and north of an origin, so positions,
+/// noise and precision targets are all in meters. Equirectangular, which is plenty over the few
+/// kilometers a session covers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LocalProjection {
+    origin: [f64; 2],
+    meters_per_degree: [f64; 2],
+}
+
+impl LocalProjection {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        let meters_per_degree = EARTH_RADIUS_M * std::f64::consts::PI / 180.0;
+        Self {
+            origin: [latitude, longitude],
+            meters_per_degree: [
+                meters_per_degree,
+                meters_per_degree * math::cos(latitude.to_radians()),
+            ],
+        }
+    }
+
+    // [east, north] in meters.
+    pub fn to_meters(&self, latitude: f64, longitude: f64) -> [f64; 2] {
+        [
+            (longitude - self.origin[1]) * self.meters_per_degree[1],
+            (latitude - self.origin[0]) * self.meters_per_degree[0],
+        ]
+    }
+
+    // [latitude, longitude] in degrees.
+    pub fn to_degrees(&self, [east, north]: [f64; 2]) -> [f64; 2] {
+        [
+            self.origin[0] + north / self.meters_per_degree[0],
+            self.origin[1] + east / self.meters_per_degree[1],
+        ]
+    }
+}
+
+/// One position fix, in meters (see LocalProjection).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpsFix {
+    pub timestamp_secs: f64,
+    pub position: [f64; 2],
+    // The receiver's horizontal accuracy in meters, if it reports one.
+    pub accuracy_m: Option<f64>,
+}
+
+/// Measures GPS noise and fix rate. Fixes that report accuracy are used for noise directly.
+/// Otherwise noise comes from how far each fix strays from the line through its neighbours,
+/// which steady walking or driving cancels out - so there's no need to stand still, only to
+/// avoid turning much while it runs.
+#[derive(Debug, Clone)]
+pub struct GpsCalibrator {
+    threshold: f64,
+    previous: [Option<(f64, [f64; 2])>; 2],
+    // Per axis noise variance, from each source.
+    accuracy: RunningStatistics,
+    residuals: RunningStatistics,
+    intervals: RunningStatistics,
+}
+
+impl Default for GpsCalibrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GpsCalibrator {
+    pub fn new() -> Self {
+        Self {
+            threshold: DEFAULT_THRESHOLD,
+            previous: [None, None],
+            accuracy: RunningStatistics::new(),
+            residuals: RunningStatistics::new(),
+            intervals: RunningStatistics::new(),
+        }
+    }
+
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    // Returns true once the noise estimate is good enough. Fixes out of order or repeated are
+    // skipped.
+    pub fn process(&mut self, fix: &GpsFix) -> bool {
+        let (secs, position) = (fix.timestamp_secs, fix.position);
+        if let Some(accuracy) = fix.accuracy_m {
+            self.accuracy
+                .update((accuracy / ACCURACY_PER_STD_DEV).powi(2));
+        }
+
+        match self.previous {
+            [Some((last_secs, _)), _] if secs <= last_secs => return self.is_converged(),
+            [Some((t1, x1)), Some((t0, x0))] => {
+                // Weighted so anything moving in a straight line at constant speed cancels,
+                // whatever the intervals.
+                let (h1, h2) = (t1 - t0, secs - t1);
+                let weight = h2 * h2 + (h1 + h2) * (h1 + h2) + h1 * h1;
+                for i in 0..2 {
+                    let residual = h2 * x0[i] - (h1 + h2) * x1[i] + h1 * position[i];
+                    self.residuals.update(residual * residual / weight);
+                }
+                self.intervals.update(h2);
+            }
+            [Some((last_secs, _)), None] => self.intervals.update(secs - last_secs),
+            _ => {}
+        }
+        self.previous = [Some((secs, position)), self.previous[0]];
+        self.is_converged()
+    }
+
+    fn noise(&self) -> &RunningStatistics {
+        if self.accuracy.count() > 0 {
+            &self.accuracy
+        } else {
+            &self.residuals
+        }
+    }
+
+    fn is_converged(&self) -> bool {
+        let noise = self.noise();
+        noise.count() >= MIN_FIXES && (noise.ci95 == 0.0 || noise.converged(self.threshold))
+    }
+
+    // Per axis, in meters.
+    pub fn noise_std_dev(&self) -> Option<StdDev> {
+        let noise = self.noise();
+        (noise.count() > 0).then(|| StdDev(noise.mean().sqrt()))
+    }
+
+    // The average fix rate, which tuning runs at.
+    pub fn sample_rate(&self) -> Option<Hertz> {
+        (self.intervals.count() > 0).then(|| Hertz(1.0 / self.intervals.mean()))
+    }
+
+    // With least_precision in meters, and the fastest the receiver is expected to move - i.e.
+    // 2 m/s walking or 30 m/s driving - in place of an amplitude stage.
+    pub fn tuning_settings(
+        &self,
+        least_precision: f64,
+        worst_lag: Seconds,
+        max_speed_mps: f64,
+    ) -> Option<TuningSettings> {
+        let sample_rate = self.sample_rate()?;
+        Some(TuningSettings {
+            max_target_precision: least_precision / 3.0,
+            max_lag_secs: worst_lag,
+            noise_variance: self.noise_std_dev()?.variance(),
+            max_amplitude: max_speed_mps / sample_rate.0,
+            sample_rate,
+        })
+    }
+
+    pub fn tune(
+        &self,
+        least_precision: f64,
+        worst_lag: Seconds,
+        max_speed_mps: f64,
+    ) -> Option<FinalTuningSettings> {
+        slow_rate_tuner(self.tuning_settings(least_precision, worst_lag, max_speed_mps)?).tune()
+    }
+}
+
+/// Smooths fixes by their timestamps, so irregular intervals are filtered over the time that
+/// actually passed.
+pub struct GpsFilter {
+    filter: MultiAxisFilter<2>,
+    last_secs: Option<f64>,
+    max_gap_secs: f64,
+}
+
+impl GpsFilter {
+    // sample_rate is the average fix rate tuning ran at, see GpsCalibrator::sample_rate.
+    pub fn new(sample_rate: f64, settings: &FinalTuningSettings) -> Self {
+        Self {
+            filter: MultiAxisFilter::new(sample_rate, settings),
+            last_secs: None,
+            max_gap_secs: DEFAULT_MAX_GAP_SECS,
+        }
+    }
+
+    pub fn with_max_gap(mut self, max_gap: Seconds) -> Self {
+        self.max_gap_secs = max_gap.0;
+        self
+    }
+
+    // The smoothed position. Fixes out of order or repeated are filtered at the average rate,
+    // and after a long gap the filter starts over from the fix.
+    pub fn filter(&mut self, fix: &GpsFix) -> [f64; 2] {
+        let previous = self.last_secs.replace(fix.timestamp_secs);
+        match previous.map(|secs| fix.timestamp_secs - secs) {
+            Some(dt) if dt > self.max_gap_secs => {
+                self.filter.reset();
+                self.filter.filter(fix.position)
+            }
+            Some(dt) if dt > 0.0 => self.filter.filter_with_dt(fix.position, dt),
+            _ => self.filter.filter(fix.position),
+        }
+    }
+
+    pub fn settings(&self) -> &FinalTuningSettings {
+        self.filter.settings()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+
+    #[test]
+    pub fn test_gps() {
+        let projection = LocalProjection::new(51.5, -0.12);
+        let [east, north] = projection.to_meters(51.501, -0.119);
+        assert!((north - 111.2).abs() < 0.1 && (east - 69.2).abs() < 0.1);
+        let [latitude, longitude] = projection.to_degrees([east, north]);
+        assert!((latitude - 51.501).abs() < 1e-9 && (longitude + 0.119).abs() < 1e-9);
+
+        // Walking east at 1.4 m/s, fixes every 0.5 to 1.5 seconds with 3 m of noise.
+        let mut noise = GaussianNoise::new(3.0, 67);
+        let mut jitter = GaussianNoise::new(0.25, 71);
+        let mut secs = 0.0;
+        let mut fix = || {
+            secs += (1.0 + jitter.sample()).clamp(0.5, 1.5);
+            GpsFix {
+                timestamp_secs: secs,
+                position: [1.4 * secs + noise.sample(), noise.sample()],
+                accuracy_m: None,
+            }
+        };
+
+        let mut calibrator = GpsCalibrator::new();
+        while !calibrator.process(&fix()) {}
+        let noise_std_dev = calibrator.noise_std_dev().unwrap().0;
+        assert!((noise_std_dev - 3.0).abs() < 0.6, "{noise_std_dev}");
+        let rate = calibrator.sample_rate().unwrap().0;
+        assert!((rate - 1.0).abs() < 0.1);
+
+        let settings = calibrator.tune(5.0, Seconds(5.0), 2.0).unwrap();
+        let mut filter = GpsFilter::new(rate, &settings);
+        let (mut raw, mut smoothed) = (0.0, 0.0);
+        for _ in 0..200 {
+            let fix = fix();
+            let [_, north] = filter.filter(&fix);
+            raw += fix.position[1].powi(2);
+            smoothed += north.powi(2);
+        }
+        assert!(smoothed < raw / 2.0);
+
+        // Accuracy, when reported, is used instead.
+        let mut calibrator = GpsCalibrator::new();
+        for i in 0..MIN_FIXES {
+            calibrator.process(&GpsFix {
+                timestamp_secs: i as f64,
+                position: [0.0; 2],
+                accuracy_m: Some(ACCURACY_PER_STD_DEV * 2.0),
+            });
+        }
+        assert_eq!(calibrator.noise_std_dev(), Some(StdDev(2.0)));
+    }
+}
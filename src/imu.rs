@@ -0,0 +1,391 @@
+use crate::{
+    calibrator::CalibrationStage,
+    decimate::{DecimatingFilter, Decimator},
+    dsp::{DcRemoval, DcRemover},
+    estimators::RunningStatistics,
+    tuner::{FinalTuningSettings, Tuner},
+    units::{Hertz, Seconds},
+};
+
+// Slow enough to ignore hand tremor and motion, fast enough to follow the device being tilted
+// while it's supposed to be idle.
+const DEFAULT_GRAVITY_TIME_CONSTANT_SECS: f64 = 2.0;
+
+// Above how fast the device is turned around by hand, well below the bins noise is measured
+// in.
+const DEFAULT_FIELD_CUTOFF_HZ: f64 = 0.5;
+
+// The rate the tuner's tables are for. Faster gyro streams are decimated down to it.
+const TUNING_RATE: f64 = 60.0;
+
+/// Calibration for raw accelerometer data. The 1 g DC component (and its slow drift as the
+/// device tilts) is estimated and subtracted before anything reaches the noise or amplitude
+/// estimators, so it doesn't bias the noise estimate.
+pub struct AccelerometerCalibrator {
+    gravity: DcRemover<3>,
+    stage: CalibrationStage,
+}
+
+impl AccelerometerCalibrator {
+    pub fn new(sample_rate: f64) -> Self {
+        Self::with_time_constant(sample_rate, DEFAULT_GRAVITY_TIME_CONSTANT_SECS)
+    }
+
+    pub fn with_time_constant(sample_rate: f64, gravity_time_constant_secs: f64) -> Self {
+        Self {
+            gravity: DcRemover::new(
+                sample_rate,
+                DcRemoval::Complementary {
+                    time_constant_secs: gravity_time_constant_secs,
+                },
+            ),
+            stage: CalibrationStage::new().with_sample_rate(Hertz(sample_rate)),
+        }
+    }
+
+    // Processes a raw accelerometer sample. Returns true once the current stage has enough data.
+    pub fn process(&mut self, x: f64, y: f64, z: f64) -> bool {
+        let [x, y, z] = self.gravity.process([x, y, z]);
+        self.stage.process(x, y, z)
+    }
+
+    pub fn next(self) -> Self {
+        Self {
+            stage: self.stage.advance(),
+            ..self
+        }
+    }
+
+    // The current gravity estimate. Subtract this from samples before filtering if you want the
+    // filter to see linear acceleration, same as calibration did.
+    pub fn gravity(&self) -> Option<[f64; 3]> {
+        self.gravity.offset()
+    }
+
+    pub fn tune(self, least_precision: f64, worst_lag: Seconds) -> Option<FinalTuningSettings> {
+        let amplitude = self.stage.into_amplitude()?;
+        Tuner::new(amplitude.tuning_settings(least_precision, worst_lag)).tune()
+    }
+}
+
+/// Calibration for magnetometers. The earth's field is a large DC component that moves slowly
+/// as the device is turned, so it's high passed out of every axis before the noise and
+/// amplitude estimators see it - leaving them the sensor noise and the quick changes the filter
+/// will actually have to follow. Tune and filter the raw field as usual, i.e. for AR compass
+/// heading.
+pub struct MagnetometerCalibrator {
+    field: DcRemover<3>,
+    stage: CalibrationStage,
+}
+
+impl MagnetometerCalibrator {
+    pub fn new(sample_rate: f64) -> Self {
+        Self::with_cutoff(sample_rate, DEFAULT_FIELD_CUTOFF_HZ)
+    }
+
+    pub fn with_cutoff(sample_rate: f64, field_cutoff_hz: f64) -> Self {
+        Self {
+            field: DcRemover::new(
+                sample_rate,
+                DcRemoval::HighPass {
+                    cutoff_hz: field_cutoff_hz,
+                },
+            ),
+            stage: CalibrationStage::new().with_sample_rate(Hertz(sample_rate)),
+        }
+    }
+
+    // Processes a raw magnetometer sample. Returns true once the current stage has enough data.
+    pub fn process(&mut self, x: f64, y: f64, z: f64) -> bool {
+        let [x, y, z] = self.field.process([x, y, z]);
+        self.stage.process(x, y, z)
+    }
+
+    pub fn next(self) -> Self {
+        Self {
+            stage: self.stage.advance(),
+            ..self
+        }
+    }
+
+    // The current field estimate, in the sensor's frame.
+    pub fn field(&self) -> Option<[f64; 3]> {
+        self.field.offset()
+    }
+
+    // The magnitude of the field estimate. Far off the local earth field (25 to 65 uT) points
+    // at hard iron offsets or nearby metal.
+    pub fn field_strength(&self) -> Option<f64> {
+        self.field
+            .offset()
+            .map(|[x, y, z]| (x * x + y * y + z * z).sqrt())
+    }
+
+    pub fn tune(self, least_precision: f64, worst_lag: Seconds) -> Option<FinalTuningSettings> {
+        let amplitude = self.stage.into_amplitude()?;
+        Tuner::new(amplitude.tuning_settings(least_precision, worst_lag)).tune()
+    }
+}
+
+/// What gyro calibration measured while the device was idle, besides what tuning needs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GyroReport {
+    // The zero rate offset per axis, in input units.
+    pub bias: [f64; 3],
+    // White noise density, in input units per root hz.
+    pub noise_density: f64,
+    // How fast the bias wandered, in input units per second - RMS over the three axis, as is
+    // noise_density.
+    pub bias_drift: f64,
+}
+
+/// Calibration for gyro rate streams - high rate, zero mean when idle apart from a slowly
+/// drifting bias. The bias is measured as the idle mean and subtracted, so noise is measured
+/// around it, and noise density and bias drift are measured separately from the native rate
+/// samples, see report. Keeping the device still for longer than noise calibration needs gives
+/// a better drift estimate.
+///
+/// Tuning runs on the stream decimated to 60 hz, and the resulting GyroFilter decimates the
+/// same way, smoothing rates without the bias.
+pub struct GyroCalibrator {
+    sample_rate: f64,
+    // Idle native samples per axis. The means are the bias.
+    idle: [RunningStatistics; 3],
+    decimator: Decimator<3>,
+    stage: CalibrationStage,
+    complete: bool,
+    // Raw samples are averaged over one second blocks, and drift comes from how much
+    // consecutive blocks differ.
+    block: [f64; 3],
+    block_len: usize,
+    last_block: Option<[f64; 3]>,
+    block_changes: RunningStatistics,
+}
+
+impl GyroCalibrator {
+    pub fn new(sample_rate: f64) -> Self {
+        let decimator = Decimator::for_target_rate(sample_rate, TUNING_RATE);
+        Self {
+            sample_rate,
+            idle: [
+                RunningStatistics::new(),
+                RunningStatistics::new(),
+                RunningStatistics::new(),
+            ],
+            stage: CalibrationStage::new().with_sample_rate(Hertz(decimator.internal_rate())),
+            decimator,
+            complete: false,
+            block: [0.0; 3],
+            block_len: 0,
+            last_block: None,
+            block_changes: RunningStatistics::new(),
+        }
+    }
+
+    // Processes a raw rate sample at the native rate. Returns true once the current stage has
+    // enough data.
+    pub fn process(&mut self, x: f64, y: f64, z: f64) -> bool {
+        let sample = [x, y, z];
+        if self.stage.is_noise() {
+            self.update_drift(sample);
+            for (axis, rate) in self.idle.iter_mut().zip(sample) {
+                axis.update(rate);
+            }
+        }
+
+        let bias = self.bias();
+        let unbiased = core::array::from_fn(|i| sample[i] - bias[i]);
+        if let Some([x, y, z]) = self.decimator.push(unbiased) {
+            self.complete = self.stage.process(x, y, z);
+        }
+        self.complete
+    }
+
+    fn update_drift(&mut self, sample: [f64; 3]) {
+        self.block = core::array::from_fn(|i| self.block[i] + sample[i]);
+        self.block_len += 1;
+        if (self.block_len as f64) < self.sample_rate {
+            return;
+        }
+
+        let mean = self.block.map(|sum| sum / self.block_len as f64);
+        if let Some(last) = self.last_block {
+            for i in 0..3 {
+                let change = mean[i] - last[i];
+                self.block_changes.update(change * change);
+            }
+        }
+        self.last_block = Some(mean);
+        self.block = [0.0; 3];
+        self.block_len = 0;
+    }
+
+    pub fn next(self) -> Self {
+        Self {
+            stage: self.stage.advance(),
+            complete: false,
+            ..self
+        }
+    }
+
+    fn bias(&self) -> [f64; 3] {
+        self.idle.each_ref().map(RunningStatistics::mean)
+    }
+
+    // None until a sample has been processed. Drift needs two full seconds of idle samples,
+    // and reads zero until then.
+    pub fn report(&self) -> Option<GyroReport> {
+        let mut variance = 0.0;
+        for axis in &self.idle {
+            variance += axis.population_variance()? / 3.0;
+        }
+        // White noise alone moves a one second average by 2 variance / rate on average,
+        // which isn't drift.
+        let white = 2.0 * variance / self.sample_rate;
+        let drift = match self.block_changes.count() {
+            0 => 0.0,
+            _ => (self.block_changes.mean() - white).max(0.0).sqrt(),
+        };
+
+        Some(GyroReport {
+            bias: self.bias(),
+            noise_density: (variance / (self.sample_rate / 2.0)).sqrt(),
+            bias_drift: drift,
+        })
+    }
+
+    pub fn tune(self, least_precision: f64, worst_lag: Seconds) -> Option<GyroFilter> {
+        let bias = self.bias();
+        let amplitude = self.stage.into_amplitude()?;
+        let settings = Tuner::new(amplitude.tuning_settings(least_precision, worst_lag)).tune()?;
+
+        let mut decimator = self.decimator;
+        decimator.reset();
+        Some(GyroFilter {
+            bias,
+            filter: DecimatingFilter::new(decimator, &settings),
+            settings,
+        })
+    }
+}
+
+/// Smooths gyro rates at the native rate, with the calibrated bias taken off.
+pub struct GyroFilter {
+    bias: [f64; 3],
+    filter: DecimatingFilter<3>,
+    settings: FinalTuningSettings,
+}
+
+impl GyroFilter {
+    pub fn filter(&mut self, rate: [f64; 3]) -> [f64; 3] {
+        self.filter
+            .filter(core::array::from_fn(|i| rate[i] - self.bias[i]))
+    }
+
+    // For when the bias is re-estimated, i.e. each time the device is found to be still.
+    pub fn set_bias(&mut self, bias: [f64; 3]) {
+        self.bias = bias;
+    }
+
+    pub fn bias(&self) -> [f64; 3] {
+        self.bias
+    }
+
+    pub fn settings(&self) -> &FinalTuningSettings {
+        &self.settings
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+
+    #[test]
+    pub fn test_magnetometer_calibration() {
+        let mut noise = GaussianNoise::new(0.3, 59);
+        let mut calibrator = MagnetometerCalibrator::new(60.0);
+        let mut i = 0;
+        // Slowly turning in a 48 uT field.
+        while !calibrator.process(
+            48.0 * (i as f64 * 0.002).cos() + noise.sample(),
+            48.0 * (i as f64 * 0.002).sin() + noise.sample(),
+            noise.sample(),
+        ) {
+            i += 1;
+        }
+        let strength = calibrator.field_strength().unwrap();
+        assert!((strength - 48.0).abs() < 1.0);
+
+        let CalibrationStage::Amplitude(amplitude) = calibrator.next().stage else {
+            panic!("still in the noise stage");
+        };
+        let noise = amplitude.noise_std_dev().0;
+        assert!(noise > 0.15 && noise < 0.45);
+    }
+
+    #[test]
+    pub fn test_accelerometer_sample_rate() {
+        let mut noise = GaussianNoise::new(0.02, 61);
+        let mut calibrator = AccelerometerCalibrator::new(200.0);
+        let mut samples = 0;
+        while !calibrator.process(noise.sample(), noise.sample(), 9.81 + noise.sample()) {
+            samples += 1;
+        }
+        let CalibrationStage::Noise(stage) = &calibrator.stage else {
+            panic!("already in the amplitude stage");
+        };
+        // Samples per axis over the real rate, not 60 hz.
+        let duration = stage.report().duration.0;
+        assert!((duration - (samples + 1) as f64 / 200.0).abs() < 1e-9);
+
+        // And on through to tuning.
+        let CalibrationStage::Amplitude(amplitude) = calibrator.next().stage else {
+            panic!("still in the noise stage");
+        };
+        let settings = amplitude.tuning_settings(0.1, Seconds(0.05));
+        assert_eq!(settings.sample_rate, Hertz(200.0));
+    }
+
+    #[test]
+    pub fn test_gyro_calibration() {
+        let rate = 1000.0;
+        let bias = [0.02, -0.01, 0.005];
+        let mut noise = GaussianNoise::new(0.05, 53);
+        let mut calibrator = GyroCalibrator::new(rate);
+        // Ten seconds idle, well past where noise calibration completes, to see the bias drift.
+        let mut complete = false;
+        for _ in 0..10_000 {
+            complete = calibrator.process(
+                bias[0] + noise.sample(),
+                bias[1] + noise.sample(),
+                bias[2] + noise.sample(),
+            );
+        }
+        assert!(complete);
+
+        let report = calibrator.report().unwrap();
+        // 0.05 of white noise over 500 hz of bandwidth.
+        let density = 0.05 / 500.0_f64.sqrt();
+        assert!((report.noise_density - density).abs() < 0.1 * density);
+        assert!((report.bias[0] - bias[0]).abs() < 0.01);
+        assert!(report.bias_drift < 0.005);
+
+        // Tuned at the rate the stage is fed, after decimating.
+        let mut calibrator = calibrator.next();
+        let CalibrationStage::Amplitude(amplitude) = &calibrator.stage else {
+            panic!("still in the noise stage");
+        };
+        assert_eq!(amplitude.sample_rate(), Hertz(rate / 17.0));
+        for i in 0..5000 {
+            let turning = 2.0 * (i as f64 / rate * 3.0).sin();
+            calibrator.process(bias[0] + turning, bias[1], bias[2]);
+        }
+        let mut filter = calibrator.tune(0.1, Seconds(0.04)).unwrap();
+        let mut out = [0.0; 3];
+        for _ in 0..1000 {
+            out = filter.filter([bias[0] + 1.0, bias[1], bias[2]]);
+        }
+        assert!((out[0] - 1.0).abs() < 0.05);
+    }
+}
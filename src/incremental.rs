@@ -0,0 +1,94 @@
+use std::task::Poll;
+
+use one_euro_rs::OneEuroFilter;
+
+use crate::{
+    filter::SmoothingFilter,
+    tuner::{FinalTuningSettings, GridSearch, Tuner},
+};
+
+/// Runs tune a few candidates at a time, for single threaded game loops that can't block for
+/// a full tune or spawn a thread for it. Call step once a frame with however many candidates fit
+/// in the frame. The costliest candidates simulate a step response, which is still well under a
+/// millisecond.
+///
+/// Finds exactly what tune would. Joint constraints aren't supported, so the search is always
+/// precision then lag.
+pub struct IncrementalTuner<F: SmoothingFilter = OneEuroFilter<f64>> {
+    tuner: Tuner<F>,
+    search: GridSearch,
+    tuned: Option<FinalTuningSettings>,
+}
+
+impl<F: SmoothingFilter> IncrementalTuner<F> {
+    pub fn new(tuner: Tuner<F>) -> Self {
+        Self {
+            search: GridSearch::new(&tuner),
+            tuner,
+            tuned: None,
+        }
+    }
+
+    // Evaluates up to candidates more. Once ready, every later call is too, without evaluating
+    // anything.
+    pub fn step(&mut self, candidates: usize) -> Poll<FinalTuningSettings> {
+        if self.tuned.is_none() {
+            for _ in 0..candidates {
+                if !self.search.advance(&mut self.tuner) {
+                    break;
+                }
+            }
+            if self.search.is_done() {
+                self.tuned = self.search.finish(&mut self.tuner);
+            }
+        }
+        match &self.tuned {
+            Some(tuned) => Poll::Ready(tuned.clone()),
+            None => Poll::Pending,
+        }
+    }
+
+    // How far through the current pass over the candidates, from 0 to 1, i.e. for a progress
+    // bar. Starts over if nothing meets the precision target and it has to be relaxed.
+    pub fn progress(&self) -> f64 {
+        self.search.progress()
+    }
+
+    pub fn into_inner(self) -> Tuner<F> {
+        self.tuner
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        calibrator::TuningSettings,
+        units::{Hertz, Seconds, Variance},
+    };
+
+    #[test]
+    pub fn test_matches_tune() {
+        let settings = || TuningSettings {
+            max_target_precision: 1.0,
+            max_lag_secs: Seconds(0.08),
+            noise_variance: Variance(4.0),
+            max_amplitude: 500.0,
+            sample_rate: Hertz(60.0),
+        };
+
+        let mut incremental = IncrementalTuner::new(Tuner::new(settings()));
+        let mut frames = 0;
+        let tuned = loop {
+            frames += 1;
+            if let Poll::Ready(tuned) = incremental.step(500) {
+                break tuned;
+            }
+            assert!(incremental.progress() < 1.0);
+        };
+        assert!(frames > 1);
+        assert_eq!(incremental.progress(), 1.0);
+        assert_eq!(incremental.step(500), Poll::Ready(tuned.clone()));
+        assert_eq!(Some(tuned), Tuner::new(settings()).tune());
+    }
+}
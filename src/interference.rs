@@ -0,0 +1,220 @@
+use std::f64::consts::PI;
+
+use crate::{dsp::Biquad, math};
+
+// How much a bin has to stand above the median bin to count as interference. White noise only
+// gets there about once in a million bins.
+const DEFAULT_PEAK_RATIO: f64 = 20.0;
+
+// Notch bandwidth is center / q.
+const NOTCH_Q: f64 = 5.0;
+
+const DEFAULT_WINDOW_SECS: f64 = 2.0;
+
+/// Finds narrowband interference - i.e. mains coupling in EMG or IMU rigs - in a window of idle
+/// samples, by looking for spectral peaks that stand well above the rest of the spectrum.
+/// Frequencies are reported where they appear after sampling, so interference above Nyquist
+/// shows up at its alias.
+#[derive(Debug, Clone)]
+pub struct InterferenceDetector<const D: usize> {
+    sample_rate: f64,
+    window: usize,
+    peak_ratio: f64,
+    samples: Vec<[f64; D]>,
+}
+
+impl<const D: usize> InterferenceDetector<D> {
+    pub fn new(sample_rate: f64) -> Self {
+        Self::with_window(sample_rate, DEFAULT_WINDOW_SECS)
+    }
+
+    // A longer window resolves interference more finely, at 1 / window_secs hz.
+    pub fn with_window(sample_rate: f64, window_secs: f64) -> Self {
+        let window = ((window_secs * sample_rate).round() as usize).max(8);
+        Self {
+            sample_rate,
+            window,
+            peak_ratio: DEFAULT_PEAK_RATIO,
+            samples: Vec::with_capacity(window),
+        }
+    }
+
+    pub fn with_peak_ratio(mut self, peak_ratio: f64) -> Self {
+        self.peak_ratio = peak_ratio;
+        self
+    }
+
+    // Collects a sample. Returns the interfering frequencies once the window is full, and None
+    // before that.
+    pub fn update(&mut self, sample: [f64; D]) -> Option<Vec<f64>> {
+        if self.samples.len() < self.window {
+            self.samples.push(sample);
+        }
+        (self.samples.len() == self.window).then(|| self.detect())
+    }
+
+    // A Hann windowed DFT of every bin, summed across axis. Only runs once per window, so the
+    // naive O(n^2) transform is fine.
+    fn detect(&self) -> Vec<f64> {
+        let n = self.window;
+        let mean: [f64; D] = core::array::from_fn(|axis| {
+            self.samples.iter().map(|sample| sample[axis]).sum::<f64>() / n as f64
+        });
+        let hann: Vec<f64> = (0..n)
+            .map(|i| 0.5 - 0.5 * math::cos(2.0 * PI * i as f64 / (n as f64 - 1.0)))
+            .collect();
+
+        // Skip DC and the bin next to it, which the window smears the mean into.
+        let power: Vec<f64> = (2..n / 2)
+            .map(|k| {
+                (0..D)
+                    .map(|axis| {
+                        let (mut re, mut im) = (0.0, 0.0);
+                        for (i, sample) in self.samples.iter().enumerate() {
+                            let x = (sample[axis] - mean[axis]) * hann[i];
+                            let phase = -2.0 * PI * (k * i) as f64 / n as f64;
+                            let (cos, sin) = math::cis(phase);
+                            re += x * cos;
+                            im += x * sin;
+                        }
+                        re * re + im * im
+                    })
+                    .sum()
+            })
+            .collect();
+
+        let mut sorted = power.clone();
+        sorted.sort_by(f64::total_cmp);
+        let median = sorted[sorted.len() / 2];
+
+        let mut found = vec![];
+        for (i, &p) in power.iter().enumerate() {
+            let left = if i > 0 { power[i - 1] } else { 0.0 };
+            let right = power.get(i + 1).copied().unwrap_or(0.0);
+            if p > self.peak_ratio * median && p >= left && p >= right {
+                found.push((i + 2) as f64 * self.sample_rate / n as f64);
+            }
+        }
+        found
+    }
+}
+
+/// A notch per interfering frequency, on every axis.
+#[derive(Debug, Clone)]
+pub struct NotchBank<const D: usize> {
+    frequencies: Vec<f64>,
+    notches: Vec<[Biquad; D]>,
+}
+
+impl<const D: usize> NotchBank<D> {
+    pub fn new(sample_rate: f64, frequencies: &[f64]) -> Self {
+        Self {
+            frequencies: frequencies.to_vec(),
+            notches: frequencies
+                .iter()
+                .map(|&hz| [Biquad::notch(sample_rate, hz, NOTCH_Q); D])
+                .collect(),
+        }
+    }
+
+    pub fn process(&mut self, sample: [f64; D]) -> [f64; D] {
+        let mut out = sample;
+        for notch in self.notches.iter_mut() {
+            for (x, axis) in out.iter_mut().zip(notch.iter_mut()) {
+                *x = axis.process(*x);
+            }
+        }
+        out
+    }
+
+    pub fn frequencies(&self) -> &[f64] {
+        &self.frequencies
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notches.is_empty()
+    }
+
+    pub fn reset(&mut self) {
+        for notch in self.notches.iter_mut() {
+            notch.iter_mut().for_each(Biquad::reset);
+        }
+    }
+}
+
+/// Detection followed by notching - samples are held back until the detector's window is full,
+/// and then everything after goes through a notch for each interfering frequency found.
+#[derive(Debug, Clone)]
+pub enum InterferenceRejector<const D: usize> {
+    Detecting(InterferenceDetector<D>),
+    Notching(NotchBank<D>),
+}
+
+impl<const D: usize> InterferenceRejector<D> {
+    pub fn new(sample_rate: f64) -> Self {
+        InterferenceRejector::Detecting(InterferenceDetector::new(sample_rate))
+    }
+
+    // None while still detecting.
+    pub fn process(&mut self, sample: [f64; D]) -> Option<[f64; D]> {
+        match self {
+            InterferenceRejector::Detecting(detector) => {
+                let frequencies = detector.update(sample)?;
+                *self = InterferenceRejector::Notching(NotchBank::new(
+                    detector.sample_rate,
+                    &frequencies,
+                ));
+                None
+            }
+            InterferenceRejector::Notching(notches) => Some(notches.process(sample)),
+        }
+    }
+
+    // What was found, once detection is done.
+    pub fn detected(&self) -> Option<&[f64]> {
+        match self {
+            InterferenceRejector::Detecting(_) => None,
+            InterferenceRejector::Notching(notches) => Some(notches.frequencies()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+
+    #[test]
+    pub fn test_detects_and_notches_mains() {
+        // 50 hz mains sampled at 120 hz, on top of white noise.
+        let sample_rate = 120.0;
+        let mut noise = GaussianNoise::new(1.0, 11);
+        let mut sample = |i: usize| {
+            let hum = 5.0 * (2.0 * PI * 50.0 * i as f64 / sample_rate).sin();
+            [hum + noise.sample(), noise.sample()]
+        };
+
+        let mut rejector = InterferenceRejector::<2>::new(sample_rate);
+        let mut i = 0;
+        while rejector.detected().is_none() {
+            rejector.process(sample(i));
+            i += 1;
+        }
+        let detected = rejector.detected().unwrap().to_vec();
+        assert_eq!(detected.len(), 1);
+        assert!((detected[0] - 50.0).abs() <= 0.5);
+
+        // Skip the notch's transient, then the hum should be gone.
+        for _ in 0..240 {
+            rejector.process(sample(i));
+            i += 1;
+        }
+        let mut power = 0.0;
+        for _ in 0..1200 {
+            let [x, _] = rejector.process(sample(i)).unwrap();
+            power += x * x;
+            i += 1;
+        }
+        assert!(power / 1200.0 < 1.5);
+    }
+}
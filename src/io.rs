@@ -0,0 +1,297 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{self, BufRead},
+};
+
+/// Where to find a value in a recorded row - either by position, or by header name (CSV) / key
+/// (JSON lines).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Column::Index(i) => write!(f, "#{}", i),
+            Column::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Maps the columns of a recording to a timestamp and D axis.
+#[derive(Debug, Clone)]
+pub struct ColumnMapping<const D: usize> {
+    pub timestamp: Column,
+    pub axes: [Column; D],
+    // Multiplier taking recorded timestamps to seconds, i.e. 1e-3 for milliseconds.
+    pub timestamp_scale: f64,
+}
+
+impl<const D: usize> ColumnMapping<D> {
+    pub fn new(timestamp: Column, axes: [Column; D]) -> Self {
+        Self {
+            timestamp,
+            axes,
+            timestamp_scale: 1.0,
+        }
+    }
+
+    pub fn with_timestamp_scale(mut self, timestamp_scale: f64) -> Self {
+        self.timestamp_scale = timestamp_scale;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub has_header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            has_header: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    // A named column was requested but the recording has no header.
+    NoHeader,
+    MissingColumn { line: usize, column: Column },
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Io(e) => write!(f, "failed to read recording: {}", e),
+            ReadError::NoHeader => write!(f, "named columns require a header row"),
+            ReadError::MissingColumn { line, column } => {
+                write!(f, "line {}: missing column {}", line, column)
+            }
+            ReadError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReadError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+/// A timestamped recording of D axis of device output.
+#[derive(Debug, Clone, Default)]
+pub struct Recording<const D: usize> {
+    // Seconds.
+    pub timestamps: Vec<f64>,
+    pub samples: Vec<[f64; D]>,
+}
+
+impl<const D: usize> Recording<D> {
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    // A single axis as its own trace, i.e. for the eval module.
+    pub fn axis(&self, axis: usize) -> Vec<f64> {
+        self.samples.iter().map(|s| s[axis]).collect()
+    }
+
+    // Estimates the sample rate from the median interval between timestamps. The median keeps
+    // the odd dropped or coalesced sample from skewing it.
+    pub fn sample_rate(&self) -> Option<f64> {
+        let mut intervals: Vec<f64> = self
+            .timestamps
+            .windows(2)
+            .map(|w| w[1] - w[0])
+            .filter(|dt| *dt > 0.0)
+            .collect();
+
+        if intervals.is_empty() {
+            return None;
+        }
+
+        intervals.sort_by(|a, b| a.total_cmp(b));
+        Some(1.0 / intervals[intervals.len() / 2])
+    }
+}
+
+fn parse_field(field: &str, line: usize, column: &Column) -> Result<f64, ReadError> {
+    field.trim().parse().map_err(|_| ReadError::Parse {
+        line,
+        message: format!("column {} is not a number: {:?}", column, field),
+    })
+}
+
+// Reads a delimited text recording. Empty lines are skipped. Line numbers in errors are 1 based
+// and count the header.
+pub fn read_csv<R: BufRead, const D: usize>(
+    reader: R,
+    mapping: &ColumnMapping<D>,
+    options: CsvOptions,
+) -> Result<Recording<D>, ReadError> {
+    let mut lines = reader.lines().enumerate();
+    let mut header: Option<Vec<String>> = None;
+
+    if options.has_header {
+        if let Some((_, line)) = lines.next() {
+            header = Some(
+                line?
+                    .split(options.delimiter)
+                    .map(|s| s.trim().to_string())
+                    .collect(),
+            );
+        }
+    }
+
+    let resolve = |column: &Column| -> Result<usize, ReadError> {
+        match column {
+            Column::Index(i) => Ok(*i),
+            Column::Name(name) => header
+                .as_ref()
+                .ok_or(ReadError::NoHeader)?
+                .iter()
+                .position(|h| h == name)
+                .ok_or(ReadError::MissingColumn {
+                    line: 1,
+                    column: column.clone(),
+                }),
+        }
+    };
+
+    let timestamp = (resolve(&mapping.timestamp)?, &mapping.timestamp);
+    let mut axes = Vec::with_capacity(D);
+    for axis in &mapping.axes {
+        axes.push((resolve(axis)?, axis));
+    }
+
+    let mut recording = Recording::default();
+    for (i, line) in lines {
+        let line = line?;
+        let line_no = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(options.delimiter).collect();
+        let field = |(idx, column): (usize, &Column)| -> Result<f64, ReadError> {
+            let raw = fields.get(idx).ok_or(ReadError::MissingColumn {
+                line: line_no,
+                column: column.clone(),
+            })?;
+            parse_field(raw, line_no, column)
+        };
+
+        recording
+            .timestamps
+            .push(field(timestamp)? * mapping.timestamp_scale);
+
+        let mut sample = [0.0; D];
+        for (value, column) in sample.iter_mut().zip(&axes) {
+            *value = field(*column)?;
+        }
+        recording.samples.push(sample);
+    }
+
+    Ok(recording)
+}
+
+// Reads a JSON lines recording. Each line is either an object (use named columns) or an array
+// (use indexed columns).
+#[cfg(feature = "json")]
+pub fn read_jsonl<R: BufRead, const D: usize>(
+    reader: R,
+    mapping: &ColumnMapping<D>,
+) -> Result<Recording<D>, ReadError> {
+    use serde_json::Value;
+
+    let mut recording = Recording::default();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_no = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let row: Value = serde_json::from_str(&line).map_err(|e| ReadError::Parse {
+            line: line_no,
+            message: e.to_string(),
+        })?;
+
+        let field = |column: &Column| -> Result<f64, ReadError> {
+            let value = match column {
+                Column::Index(idx) => row.get(*idx),
+                Column::Name(name) => row.get(name),
+            }
+            .ok_or(ReadError::MissingColumn {
+                line: line_no,
+                column: column.clone(),
+            })?;
+
+            value.as_f64().ok_or(ReadError::Parse {
+                line: line_no,
+                message: format!("column {} is not a number: {}", column, value),
+            })
+        };
+
+        recording
+            .timestamps
+            .push(field(&mapping.timestamp)? * mapping.timestamp_scale);
+
+        let mut sample = [0.0; D];
+        for (value, column) in sample.iter_mut().zip(&mapping.axes) {
+            *value = field(column)?;
+        }
+        recording.samples.push(sample);
+    }
+
+    Ok(recording)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_read_csv_by_name() {
+        let data = "t_ms,x,y,z\n0,1.0,2.0,3.0\n\n16,1.5,2.5,3.5\n";
+        let mapping = ColumnMapping::new(
+            Column::Name("t_ms".into()),
+            [
+                Column::Name("x".into()),
+                Column::Name("y".into()),
+                Column::Name("z".into()),
+            ],
+        )
+        .with_timestamp_scale(1e-3);
+
+        let recording = read_csv(data.as_bytes(), &mapping, CsvOptions::default()).unwrap();
+
+        assert_eq!(recording.len(), 2);
+        assert_eq!(recording.timestamps, vec![0.0, 0.016]);
+        assert_eq!(recording.samples[1], [1.5, 2.5, 3.5]);
+        assert_eq!(recording.sample_rate(), Some(62.5));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{filter::MultiAxisFilter, tuner::FinalTuningSettings, units::Variance};
+
+const DEFAULT_WINDOW_SECS: f64 = 2.0;
+
+/// How much a filter is smoothing right now, over a rolling window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JitterMetrics {
+    // Standard deviation of sample to sample changes, averaged across axis. Steady motion is
+    // taken out, so this is mostly noise.
+    pub input_jitter: f64,
+    pub output_jitter: f64,
+    // How much of the input jitter the filter removed, i.e. 80 for "jitter reduced by 80%".
+    // Negative if the output is jumpier than the input.
+    pub reduction_percent: f64,
+    // Standard deviation of raw minus filtered. Around the input noise while idle, and grows
+    // with lag while moving.
+    pub residual_std_dev: f64,
+}
+
+impl JitterMetrics {
+    // Input jitter against the noise tuning was done for, as a ratio of standard deviations.
+    // Sample to sample changes of white noise have twice its variance, which is accounted for.
+    // Sitting well above 1 while the device is idle means the noise has grown since tuning and
+    // tuning has gone stale - see recalibrate::BackgroundRecalibrator to act on it.
+    pub fn noise_ratio(&self, tuned_for: Variance) -> f64 {
+        self.input_jitter / (2.0 * tuned_for.0).sqrt()
+    }
+}
+
+// Exponentially weighted mean and variance.
+#[derive(Debug, Clone, Copy, Default)]
+struct RollingVariance {
+    mean: f64,
+    variance: f64,
+}
+
+impl RollingVariance {
+    fn update(&mut self, value: f64, alpha: f64) {
+        let delta = value - self.mean;
+        self.mean += alpha * delta;
+        self.variance = (1.0 - alpha) * (self.variance + alpha * delta * delta);
+    }
+}
+
+/// Rolling jitter metrics from pairs of raw and filtered samples, for dashboards to show
+/// "jitter reduced by X%". Cheap enough to run on every sample - no buffers, just a few
+/// exponentially weighted variances per axis.
+#[derive(Debug, Clone)]
+pub struct JitterMeter<const D: usize> {
+    alpha: f64,
+    warmup: usize,
+    samples: usize,
+    previous: Option<([f64; D], [f64; D])>,
+    input: [RollingVariance; D],
+    output: [RollingVariance; D],
+    residual: [RollingVariance; D],
+}
+
+impl<const D: usize> JitterMeter<D> {
+    pub fn new(sample_rate: f64) -> Self {
+        Self::with_window(sample_rate, DEFAULT_WINDOW_SECS)
+    }
+
+    // Roughly how far back the metrics look. Shorter reacts faster but reads noisier.
+    pub fn with_window(sample_rate: f64, window_secs: f64) -> Self {
+        let window = (window_secs * sample_rate).max(1.0);
+        Self {
+            alpha: 1.0 / window,
+            warmup: window.ceil() as usize,
+            samples: 0,
+            previous: None,
+            input: [RollingVariance::default(); D],
+            output: [RollingVariance::default(); D],
+            residual: [RollingVariance::default(); D],
+        }
+    }
+
+    pub fn update(&mut self, raw: [f64; D], filtered: [f64; D]) {
+        let alpha = self.alpha;
+        for axis in 0..D {
+            self.residual[axis].update(raw[axis] - filtered[axis], alpha);
+        }
+        if let Some((previous_raw, previous_filtered)) = self.previous.replace((raw, filtered)) {
+            for axis in 0..D {
+                self.input[axis].update(raw[axis] - previous_raw[axis], alpha);
+                self.output[axis].update(filtered[axis] - previous_filtered[axis], alpha);
+            }
+        }
+        self.samples += 1;
+    }
+
+    // None until a full window has been seen.
+    pub fn metrics(&self) -> Option<JitterMetrics> {
+        if self.samples < self.warmup || D == 0 {
+            return None;
+        }
+        let average_std_dev = |stats: &[RollingVariance; D]| {
+            (stats.iter().map(|s| s.variance).sum::<f64>() / D as f64).sqrt()
+        };
+        let input_jitter = average_std_dev(&self.input);
+        let output_jitter = average_std_dev(&self.output);
+        let reduction_percent = if input_jitter > 0.0 {
+            100.0 * (1.0 - output_jitter / input_jitter)
+        } else {
+            0.0
+        };
+        Some(JitterMetrics {
+            input_jitter,
+            output_jitter,
+            reduction_percent,
+            residual_std_dev: average_std_dev(&self.residual),
+        })
+    }
+
+    // Starts over, i.e. after the input source changed.
+    pub fn reset(&mut self) {
+        *self = Self {
+            samples: 0,
+            previous: None,
+            input: [RollingVariance::default(); D],
+            output: [RollingVariance::default(); D],
+            residual: [RollingVariance::default(); D],
+            ..*self
+        };
+    }
+}
+
+/// A MultiAxisFilter that keeps a JitterMeter on itself.
+pub struct MeteredFilter<const D: usize> {
+    filter: MultiAxisFilter<D>,
+    meter: JitterMeter<D>,
+}
+
+impl<const D: usize> MeteredFilter<D> {
+    pub fn new(sample_rate: f64, settings: &FinalTuningSettings) -> Self {
+        Self::from_filter(MultiAxisFilter::new(sample_rate, settings))
+    }
+
+    pub fn from_filter(filter: MultiAxisFilter<D>) -> Self {
+        let meter = JitterMeter::new(filter.sample_rate());
+        Self { filter, meter }
+    }
+
+    pub fn filter(&mut self, sample: [f64; D]) -> [f64; D] {
+        let filtered = self.filter.filter(sample);
+        self.meter.update(sample, filtered);
+        filtered
+    }
+
+    pub fn metrics(&self) -> Option<JitterMetrics> {
+        self.meter.metrics()
+    }
+
+    pub fn inner(&self) -> &MultiAxisFilter<D> {
+        &self.filter
+    }
+
+    // For retuning in place. Metrics carry on across the change, so the effect shows up as the
+    // window rolls over.
+    pub fn inner_mut(&mut self) -> &mut MultiAxisFilter<D> {
+        &mut self.filter
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+
+    #[test]
+    pub fn test_reports_reduction() {
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 0.5,
+            beta: 0.01,
+        };
+        let mut filter = MeteredFilter::<2>::new(60.0, &settings);
+        let mut noise = GaussianNoise::new(1.0, 21);
+
+        for _ in 0..60 {
+            filter.filter([noise.sample(), noise.sample()]);
+        }
+        assert!(filter.metrics().is_none());
+
+        for _ in 0..600 {
+            filter.filter([noise.sample(), noise.sample()]);
+        }
+        let metrics = filter.metrics().unwrap();
+        assert!(metrics.reduction_percent > 80.0);
+        assert!((metrics.noise_ratio(Variance(1.0)) - 1.0).abs() < 0.2);
+        assert!((metrics.residual_std_dev - 1.0).abs() < 0.2);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{
+    calibrator::{AmplitudeCalibrator, NoiseCalibrator, StartCalibration},
+    estimators::RunningStatistics,
+    units::{StdDev, Variance},
+};
+
+/// Noise calibration pooled across several devices of the same model (i.e. a pair of
+/// controllers), for one shared noise estimate. Each device keeps its own spectral estimators,
+/// as their samples can't be mixed, but the per bin variances they produce all go into one
+/// average, and it converges on several times the evidence. Every device still has to fill its
+/// own one second window first, so the saving is in the time after that. Amplitude is still
+/// calibrated per device.
+pub struct JointNoiseCalibrator {
+    devices: Vec<NoiseCalibrator>,
+}
+
+impl JointNoiseCalibrator {
+    pub fn new(devices: usize) -> Self {
+        Self::from_calibrators(
+            (0..devices)
+                .map(|_| StartCalibration::new().first_stage())
+                .collect(),
+        )
+    }
+
+    // Pools existing noise calibrations, which should share a threshold - the first one's is
+    // used.
+    pub fn from_calibrators(devices: Vec<NoiseCalibrator>) -> Self {
+        Self { devices }
+    }
+
+    pub fn devices(&self) -> usize {
+        self.devices.len()
+    }
+
+    // Processes a sample from one device. Returns true once the pooled estimate has converged.
+    // Panics if device is out of range.
+    pub fn process_noise(&mut self, device: usize, x: f64, y: f64, z: f64) -> bool {
+        self.devices[device].process_noise(x, y, z);
+        self.converged()
+    }
+
+    pub fn converged(&self) -> bool {
+        let Some(first) = self.devices.first() else {
+            return false;
+        };
+        self.pooled().converged(first.estimator().threshold())
+    }
+
+    // Every device's per bin variances, combined.
+    pub fn pooled(&self) -> RunningStatistics {
+        let mut pooled = RunningStatistics::new();
+        for device in self.devices.iter() {
+            pooled.merge(device.estimator().statistics());
+        }
+        pooled
+    }
+
+    pub fn noise_std_dev(&self) -> StdDev {
+        Variance(self.pooled().mean()).std_dev()
+    }
+
+    // One amplitude calibration per device, in the order devices were numbered, all starting
+    // from the shared noise estimate. Tuning each from the same least precision and lag gives
+    // consistent tuning across devices, differing only by how far each was moved.
+    pub fn next(self) -> Vec<AmplitudeCalibrator> {
+        let noise_std_dev = self.noise_std_dev();
+        self.devices
+            .iter()
+            .map(|_| AmplitudeCalibrator::from_noise(noise_std_dev))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+
+    #[test]
+    pub fn test_pooling_converges_faster() {
+        let run = |devices: usize| {
+            let mut joint = JointNoiseCalibrator::new(devices);
+            let mut noise = GaussianNoise::new(2.0, 23);
+            let mut samples = 0;
+            loop {
+                samples += 1;
+                let mut done = false;
+                for device in 0..devices {
+                    done =
+                        joint.process_noise(device, noise.sample(), noise.sample(), noise.sample());
+                }
+                if done {
+                    return (samples, joint);
+                }
+                assert!(samples < 100_000, "pooled noise never converged");
+            }
+        };
+
+        let (single, _) = run(1);
+        let (paired, joint) = run(2);
+        assert!(paired < single);
+        assert!((joint.noise_std_dev().variance().0 - 4.0).abs() < 2.0);
+
+        let amplitude = joint.next();
+        assert_eq!(amplitude.len(), 2);
+    }
+}
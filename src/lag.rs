@@ -0,0 +1,108 @@
+use circular_buffer::CircularBuffer;
+
+use crate::{eval::lag_samples, math, units::Seconds};
+
+/// Measures the actual lag of a running filter by cross-correlating a window of recent raw
+/// input against the filtered output. Lets applications display the current lag and check it
+/// stays within the tuned max_lag_secs.
+///
+/// WINDOW is the number of samples kept - a second or two of samples works well. Updating is
+/// cheap, measuring is O(WINDOW^2) so do it a few times a second at most.
+pub struct LagMeter<const D: usize, const WINDOW: usize> {
+    sample_rate: f64,
+    // Lag is only defined while the signal is actually moving - below this standard deviation
+    // (in signal units) over the window we report nothing rather than a correlation of noise.
+    min_motion: f64,
+    raw: CircularBuffer<WINDOW, [f64; D]>,
+    filtered: CircularBuffer<WINDOW, [f64; D]>,
+}
+
+impl<const D: usize, const WINDOW: usize> LagMeter<D, WINDOW> {
+    pub fn new(sample_rate: f64, min_motion: f64) -> Self {
+        Self {
+            sample_rate,
+            min_motion,
+            raw: CircularBuffer::new(),
+            filtered: CircularBuffer::new(),
+        }
+    }
+
+    pub fn update(&mut self, raw: [f64; D], filtered: [f64; D]) {
+        self.raw.push_back(raw);
+        self.filtered.push_back(filtered);
+    }
+
+    // The current lag, measured on whichever axis is moving the most. None until the window is
+    // full, or while the signal isn't moving enough to measure.
+    pub fn lag(&self) -> Option<Seconds> {
+        if !self.raw.is_full() {
+            return None;
+        }
+
+        let (axis, std_dev) = (0..D)
+            .map(|axis| (axis, std_dev(self.raw.iter().map(|s| s[axis]))))
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+        if std_dev < self.min_motion {
+            return None;
+        }
+
+        let raw: Vec<f64> = self.raw.iter().map(|s| s[axis]).collect();
+        let filtered: Vec<f64> = self.filtered.iter().map(|s| s[axis]).collect();
+        let samples = lag_samples(&raw, &filtered, self.sample_rate);
+
+        Some(Seconds(samples as f64 / self.sample_rate))
+    }
+
+    // Whether the measured lag is within the given budget, i.e. the tuned max_lag_secs.
+    pub fn within(&self, budget: Seconds) -> Option<bool> {
+        self.lag().map(|lag| lag <= budget)
+    }
+
+    pub fn clear(&mut self) {
+        self.raw.clear();
+        self.filtered.clear();
+    }
+}
+
+fn std_dev(values: impl Iterator<Item = f64> + Clone) -> f64 {
+    let n = values.clone().count() as f64;
+    let mean = values.clone().sum::<f64>() / n;
+    (values.map(|v| math::powi(v - mean, 2)).sum::<f64>() / n).sqrt()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    pub fn test_lag_meter() {
+        // Only the second axis moves, and the "filter" is just a five sample delay.
+        let signal = |i: usize| [1.0, 10.0 * math::sin(2.0 * PI * i as f64 / 60.0)];
+        let mut meter = LagMeter::<2, 120>::new(60.0, 0.5);
+        for i in 5..124 {
+            meter.update(signal(i), signal(i - 5));
+        }
+        assert_eq!(meter.lag(), None);
+
+        meter.update(signal(124), signal(119));
+        assert_eq!(meter.lag(), Some(Seconds(5.0 / 60.0)));
+        assert_eq!(meter.within(Seconds(0.1)), Some(true));
+        assert_eq!(meter.within(Seconds(0.05)), Some(false));
+
+        // Holding still leaves nothing to measure.
+        for _ in 0..120 {
+            meter.update([1.0, 0.0], [1.0, 0.0]);
+        }
+        assert_eq!(meter.lag(), None);
+        assert_eq!(meter.within(Seconds(0.1)), None);
+
+        for i in 5..125 {
+            meter.update(signal(i), signal(i - 5));
+        }
+        assert!(meter.lag().is_some());
+        meter.clear();
+        assert_eq!(meter.lag(), None);
+    }
+}
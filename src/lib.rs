@@ -1,4 +1,66 @@
+pub mod activity;
+pub mod angular;
+pub mod baro;
+pub mod cache;
 pub mod calibrator;
+pub mod clock;
+pub mod constraints;
+pub mod counts;
+pub mod crossfade;
+pub mod decimate;
+pub mod dsp;
+pub mod embedded;
+#[cfg(feature = "build-tables")]
+pub mod embedded_tables;
 pub mod estimators;
+pub mod eval;
+pub mod events;
+pub mod filter;
+#[cfg(feature = "fixed-point")]
+pub mod fixed;
+pub mod fusion;
+pub mod gapfill;
+pub mod gaze;
+pub mod gps;
+pub mod imu;
+pub mod incremental;
+pub mod interference;
+pub mod io;
+pub mod jitter;
+pub mod joint;
+pub mod lag;
+mod math;
+pub mod mocap;
+pub mod one_euro;
+pub mod packed;
+pub mod pointer;
+pub mod pool;
+pub mod profile;
+pub mod profiles;
+pub mod publish;
+pub mod quality;
+pub mod rate;
+pub mod recalibrate;
+pub mod replay;
+pub mod response;
+pub mod sanitize;
+pub mod screen;
+pub mod skeleton;
+pub mod source;
+mod splitmix;
+pub mod stylus;
+pub mod synth;
+#[cfg(feature = "sixty-hz-table")]
 pub mod table;
+pub mod tablegen;
+pub mod trace;
+pub mod transform;
 pub mod tuner;
+pub mod units;
+pub mod upsample;
+pub mod velocity;
+pub mod watchdog;
+pub mod wcet;
+#[cfg(feature = "winit")]
+pub mod winit;
+pub mod wizard;
@@ -0,0 +1,90 @@
+//! Transcendental functions for estimation and tuning. std leaves these to the platform (or
+//! LLVM intrinsics), so they can differ in the last bit between x86, ARM and WASM - enough for
+//! a tune to land on a different grid node. With the `libm` feature they all come from the
+//! pure Rust libm crate instead, and calibration, tuning and the synthetic signals give bit
+//! identical results everywhere, i.e. for golden file tests and replay verification.
+//!
+//! Arithmetic and sqrt are exactly rounded by IEEE 754, so they're left to std either way. The
+//! build time table generator (src/tablegen.rs) runs on the build machine and isn't covered.
+
+macro_rules! forward {
+    ($($name:ident => $libm:ident),* $(,)?) => {
+        $(
+            #[cfg(feature = "libm")]
+            #[inline]
+            pub(crate) fn $name(x: f64) -> f64 {
+                libm::$libm(x)
+            }
+
+            #[cfg(not(feature = "libm"))]
+            #[inline]
+            pub(crate) fn $name(x: f64) -> f64 {
+                f64::$name(x)
+            }
+        )*
+    };
+}
+
+forward!(
+    sin => sin,
+    cos => cos,
+    acos => acos,
+    ln => log,
+    ln_1p => log1p,
+    log10 => log10,
+);
+
+#[cfg(feature = "libm")]
+#[inline]
+pub(crate) fn powf(x: f64, y: f64) -> f64 {
+    libm::pow(x, y)
+}
+
+#[cfg(not(feature = "libm"))]
+#[inline]
+pub(crate) fn powf(x: f64, y: f64) -> f64 {
+    x.powf(y)
+}
+
+// std's powi is an LLVM intrinsic whose rounding isn't pinned down, so it goes through pow too.
+#[cfg(feature = "libm")]
+#[inline]
+pub(crate) fn powi(x: f64, n: i32) -> f64 {
+    libm::pow(x, n as f64)
+}
+
+#[cfg(not(feature = "libm"))]
+#[inline]
+pub(crate) fn powi(x: f64, n: i32) -> f64 {
+    x.powi(n)
+}
+
+#[cfg(feature = "libm")]
+#[inline]
+pub(crate) fn atan2(y: f64, x: f64) -> f64 {
+    libm::atan2(y, x)
+}
+
+#[cfg(not(feature = "libm"))]
+#[inline]
+pub(crate) fn atan2(y: f64, x: f64) -> f64 {
+    y.atan2(x)
+}
+
+#[cfg(feature = "libm")]
+#[inline]
+pub(crate) fn hypot(x: f64, y: f64) -> f64 {
+    libm::hypot(x, y)
+}
+
+#[cfg(not(feature = "libm"))]
+#[inline]
+pub(crate) fn hypot(x: f64, y: f64) -> f64 {
+    x.hypot(y)
+}
+
+// e^(i theta), as (re, im).
+#[inline]
+pub(crate) fn cis(theta: f64) -> (f64, f64) {
+    (cos(theta), sin(theta))
+}
@@ -0,0 +1,170 @@
+use crate::{filter::ThreeAxisFilter, skeleton::Point3, tuner::FinalTuningSettings};
+
+/// What to output for a marker while it's occluded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OcclusionPolicy {
+    // Hold the last filtered position.
+    Freeze,
+    // Keep moving at the last filtered velocity for up to max_secs, then freeze.
+    Extrapolate { max_secs: f64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarkerOutput {
+    // None until the marker has been seen at least once.
+    pub position: Option<Point3>,
+    // True if the position was frozen or extrapolated rather than filtered.
+    pub occluded: bool,
+}
+
+struct MarkerState {
+    filter: ThreeAxisFilter,
+    last: Option<Point3>,
+    // Per sample, from the last two filtered positions.
+    velocity: Point3,
+    occluded_samples: usize,
+}
+
+/// Filters a set of optical mocap markers, handling occlusion per marker. Occluded samples are
+/// flagged by the caller (None) rather than smoothed through, and a reacquired marker starts a
+/// fresh filter so it doesn't glide in from wherever it was last seen.
+pub struct MarkerSetFilter {
+    sample_rate: f64,
+    policy: OcclusionPolicy,
+    markers: Vec<MarkerState>,
+}
+
+impl MarkerSetFilter {
+    pub fn new(
+        marker_count: usize,
+        sample_rate: f64,
+        settings: &FinalTuningSettings,
+        policy: OcclusionPolicy,
+    ) -> Self {
+        let markers = (0..marker_count)
+            .map(|_| MarkerState {
+                filter: ThreeAxisFilter::new(sample_rate, settings),
+                last: None,
+                velocity: [0.0; 3],
+                occluded_samples: 0,
+            })
+            .collect();
+
+        Self {
+            sample_rate,
+            policy,
+            markers,
+        }
+    }
+
+    // Filters one frame. The frame should have one entry per marker, None where the marker is
+    // occluded - any extra entries are ignored.
+    pub fn filter(&mut self, frame: &[Option<Point3>]) -> Vec<MarkerOutput> {
+        let sample_rate = self.sample_rate;
+        let policy = self.policy;
+
+        self.markers
+            .iter_mut()
+            .zip(frame)
+            .map(|(marker, sample)| match sample {
+                Some(point) => marker.observe(*point),
+                None => marker.occlude(policy, sample_rate),
+            })
+            .collect()
+    }
+}
+
+impl MarkerState {
+    fn observe(&mut self, point: Point3) -> MarkerOutput {
+        if self.occluded_samples > 0 {
+            self.filter.reset();
+            self.last = None;
+            self.occluded_samples = 0;
+        }
+
+        let filtered = self.filter.filter(point);
+        self.velocity = match self.last {
+            Some(last) => core::array::from_fn(|i| filtered[i] - last[i]),
+            None => [0.0; 3],
+        };
+        self.last = Some(filtered);
+
+        MarkerOutput {
+            position: Some(filtered),
+            occluded: false,
+        }
+    }
+
+    fn occlude(&mut self, policy: OcclusionPolicy, sample_rate: f64) -> MarkerOutput {
+        self.occluded_samples += 1;
+
+        let position = self.last.map(|last| match policy {
+            OcclusionPolicy::Freeze => last,
+            OcclusionPolicy::Extrapolate { max_secs } => {
+                let steps = (self.occluded_samples as f64).min(max_secs * sample_rate);
+                core::array::from_fn(|i| last[i] + self.velocity[i] * steps)
+            }
+        });
+
+        MarkerOutput {
+            position,
+            occluded: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_occlusion() {
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 1.0,
+            beta: 0.01,
+        };
+        // A tenth of a second of extrapolation at 60 hz is six samples.
+        let extrapolate = OcclusionPolicy::Extrapolate { max_secs: 0.1 };
+        let mut extrapolating = MarkerSetFilter::new(2, 60.0, &settings, extrapolate);
+        let mut freezing = MarkerSetFilter::new(2, 60.0, &settings, OcclusionPolicy::Freeze);
+
+        // The first marker moves along a ramp and the second is never seen. The third entry is past
+        // the marker count, so ignored.
+        let mut outputs = vec![];
+        for i in 0..60 {
+            let frame = [Some([i as f64, 0.0, 0.0]), None, Some([0.0; 3])];
+            let out = extrapolating.filter(&frame);
+            assert_eq!(out.len(), 2);
+            assert_eq!(out[1].position, None);
+            assert!(out[1].occluded);
+            assert_eq!(out, freezing.filter(&frame));
+            outputs.push(out[0].position.unwrap());
+        }
+        let last = outputs[59];
+        let velocity = outputs[59][0] - outputs[58][0];
+        assert!(velocity > 0.0);
+
+        for samples in 1..10 {
+            let frame = [None, None];
+            assert_eq!(
+                freezing.filter(&frame)[0],
+                MarkerOutput {
+                    position: Some(last),
+                    occluded: true,
+                }
+            );
+            let out = extrapolating.filter(&frame)[0];
+            let steps = samples.min(6) as f64;
+            assert!(out.occluded);
+            assert_eq!(out.position, Some([last[0] + velocity * steps, 0.0, 0.0]));
+        }
+
+        // Reacquired markers start over where they're seen, rather than gliding in from where
+        // they were lost.
+        for filter in [&mut extrapolating, &mut freezing] {
+            let out = filter.filter(&[Some([100.0, 0.0, 0.0]), None])[0];
+            assert!(!out.occluded);
+            assert!((out.position.unwrap()[0] - 100.0).abs() < 1e-9, "{out:?}");
+        }
+    }
+}
@@ -0,0 +1,414 @@
+use std::f64::consts::PI;
+
+use crate::{
+    filter::DERIVATIVE_CUTOFF_HZ,
+    math,
+    tuner::FinalTuningSettings,
+    wcet::{BoundedWork, WorkBound},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct LowPass {
+    prev_hat: f64,
+    initialized: bool,
+}
+
+impl LowPass {
+    const fn new() -> Self {
+        Self {
+            prev_hat: 0.0,
+            initialized: false,
+        }
+    }
+
+    fn filter(&mut self, x: f64, alpha: f64) -> f64 {
+        if !self.initialized {
+            self.initialized = true;
+            self.prev_hat = x;
+        }
+        self.prev_hat = alpha * x + (1.0 - alpha) * self.prev_hat;
+        self.prev_hat
+    }
+}
+
+/// Our own single axis one euro filter. Behaves exactly like the one_euro_rs filter the tuner
+/// simulates with, but exposes its internal state - most importantly the smoothed derivative,
+/// which one_euro_rs keeps private.
+///
+/// Serializes with its state, so filtering can carry on exactly where it left off.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OneEuroFilter {
+    pub sample_rate: f64,
+    pub min_cutoff_hz: f64,
+    pub d_cutoff_hz: f64,
+    pub beta: f64,
+
+    x: LowPass,
+    dx: LowPass,
+    x_prev: Option<f64>,
+}
+
+impl OneEuroFilter {
+    pub const fn new(sample_rate: f64, min_cutoff_hz: f64, d_cutoff_hz: f64, beta: f64) -> Self {
+        Self {
+            sample_rate,
+            min_cutoff_hz,
+            d_cutoff_hz,
+            beta,
+            x: LowPass::new(),
+            dx: LowPass::new(),
+            x_prev: None,
+        }
+    }
+
+    fn alpha(te: f64, cutoff_hz: f64) -> f64 {
+        let tau = 1.0 / (2.0 * PI * cutoff_hz);
+        1.0 / (1.0 + tau / te)
+    }
+
+    fn step(&mut self, x: f64, dx: f64, te: f64) -> f64 {
+        self.x_prev = Some(x);
+
+        let edx = self.dx.filter(dx, Self::alpha(te, self.d_cutoff_hz));
+        let cutoff = self.min_cutoff_hz + self.beta * edx.abs();
+        self.x.filter(x, Self::alpha(te, cutoff))
+    }
+
+    pub fn filter(&mut self, x: f64) -> f64 {
+        let dx = match self.x_prev {
+            Some(prev) => (x - prev) * self.sample_rate,
+            None => 0.0,
+        };
+        self.step(x, dx, 1.0 / self.sample_rate)
+    }
+
+    // Filters a sample that arrived dt seconds after the previous one, for sources without a
+    // steady sample rate (or whose clock drifts, see clock::ClockSync). sample_rate is ignored.
+    pub fn filter_with_dt(&mut self, x: f64, dt: f64) -> f64 {
+        let dx = match self.x_prev {
+            Some(prev) if dt > 0.0 => (x - prev) / dt,
+            _ => 0.0,
+        };
+        self.step(x, dx, dt)
+    }
+
+    // The smoothed derivative of the input, in units per second. This is what drives the
+    // adaptive cutoff, so it's consistent with how the filter itself sees the motion.
+    pub fn velocity(&self) -> f64 {
+        self.dx.prev_hat
+    }
+
+    // The last filtered value, if anything has been filtered yet.
+    pub fn value(&self) -> Option<f64> {
+        self.x.initialized.then_some(self.x.prev_hat)
+    }
+
+    // Pulls the filtered value back within [min, max], so the next sample is smoothed from
+    // there rather than from outside. The derivative is of the raw input and is left alone.
+    pub fn clamp_value(&mut self, min: f64, max: f64) -> Option<f64> {
+        let value = self.value()?.max(min).min(max);
+        self.x.prev_hat = value;
+        Some(value)
+    }
+
+    // Drops all state so the next sample is passed through as is.
+    pub fn reset(&mut self) {
+        self.x = LowPass::default();
+        self.dx = LowPass::default();
+        self.x_prev = None;
+    }
+}
+
+impl BoundedWork for OneEuroFilter {
+    fn work_bound(&self) -> WorkBound {
+        WorkBound::filter(1)
+    }
+}
+
+// Tuning results only carry min_cutoff and beta - the rest has to match what the tuner
+// simulated with, so build filters through these rather than copying fields across by hand.
+impl FinalTuningSettings {
+    pub const fn to_one_euro(&self, sample_rate: f64) -> OneEuroFilter {
+        OneEuroFilter::new(
+            sample_rate,
+            self.min_cutoff_hz,
+            DERIVATIVE_CUTOFF_HZ,
+            self.beta,
+        )
+    }
+
+    pub fn to_one_euro_rs(&self, sample_rate: f64) -> one_euro_rs::OneEuroFilter<f64> {
+        one_euro_rs::OneEuroFilter::new(
+            sample_rate,
+            self.min_cutoff_hz,
+            DERIVATIVE_CUTOFF_HZ,
+            self.beta,
+        )
+    }
+
+    // For settings tuned in radians, see angular::AngularCalibrator.
+    pub fn to_quaternion_filter(&self, sample_rate: f64) -> QuaternionOneEuroFilter {
+        QuaternionOneEuroFilter::new(
+            sample_rate,
+            self.min_cutoff_hz,
+            DERIVATIVE_CUTOFF_HZ,
+            self.beta,
+        )
+    }
+}
+
+// Unit quaternions are stored as [x, y, z, w], the same order nalgebra and glam keep them in.
+fn quat_dot(a: [f64; 4], b: [f64; 4]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn quat_normalize(q: [f64; 4]) -> [f64; 4] {
+    let norm = quat_dot(q, q).sqrt();
+    q.map(|c| c / norm)
+}
+
+// The rotation angle between two orientations, in radians.
+fn quat_angle(a: [f64; 4], b: [f64; 4]) -> f64 {
+    2.0 * math::acos(quat_dot(a, b).abs().min(1.0))
+}
+
+// Spherical interpolation along the shortest path from a to b.
+fn quat_slerp(a: [f64; 4], b: [f64; 4], t: f64) -> [f64; 4] {
+    let dot = quat_dot(a, b);
+    let (b, dot) = if dot < 0.0 {
+        (b.map(|c| -c), -dot)
+    } else {
+        (b, dot)
+    };
+
+    // Nearly identical orientations - sin(theta) is too small to divide by, and lerp is exact
+    // enough anyway.
+    if dot > 0.9995 {
+        return quat_normalize(core::array::from_fn(|i| a[i] + t * (b[i] - a[i])));
+    }
+
+    let theta = math::acos(dot);
+    let wa = math::sin((1.0 - t) * theta) / math::sin(theta);
+    let wb = math::sin(t * theta) / math::sin(theta);
+    core::array::from_fn(|i| wa * a[i] + wb * b[i])
+}
+
+/// A one euro filter for orientations. The low pass is a slerp towards each new sample, and
+/// the adaptive cutoff is driven by angular speed rather than a per axis derivative, so the
+/// output is always a valid rotation.
+#[derive(Debug, Clone)]
+pub struct QuaternionOneEuroFilter {
+    sample_rate: f64,
+    min_cutoff_hz: f64,
+    d_cutoff_hz: f64,
+    beta: f64,
+
+    q_hat: Option<[f64; 4]>,
+    dx: LowPass,
+    q_prev: Option<[f64; 4]>,
+}
+
+impl QuaternionOneEuroFilter {
+    pub fn new(sample_rate: f64, min_cutoff_hz: f64, d_cutoff_hz: f64, beta: f64) -> Self {
+        Self {
+            sample_rate,
+            min_cutoff_hz,
+            d_cutoff_hz,
+            beta,
+            q_hat: None,
+            dx: LowPass::default(),
+            q_prev: None,
+        }
+    }
+
+    fn alpha(&self, cutoff_hz: f64) -> f64 {
+        let te = 1.0 / self.sample_rate;
+        let tau = 1.0 / (2.0 * PI * cutoff_hz);
+        1.0 / (1.0 + tau / te)
+    }
+
+    // Filters a unit quaternion in [x, y, z, w] order.
+    pub fn filter(&mut self, q: [f64; 4]) -> [f64; 4] {
+        let q = quat_normalize(q);
+        let dx = match self.q_prev {
+            Some(prev) => quat_angle(prev, q) * self.sample_rate,
+            None => 0.0,
+        };
+        self.q_prev = Some(q);
+
+        let edx = self.dx.filter(dx, self.alpha(self.d_cutoff_hz));
+        let cutoff = self.min_cutoff_hz + self.beta * edx;
+        let q_hat = match self.q_hat {
+            Some(prev) => quat_slerp(prev, q, self.alpha(cutoff)),
+            None => q,
+        };
+        self.q_hat = Some(q_hat);
+        q_hat
+    }
+
+    #[cfg(feature = "glam")]
+    pub fn filter_quat(&mut self, q: glam::Quat) -> glam::Quat {
+        glam::DQuat::from_array(self.filter(q.as_dquat().to_array())).as_quat()
+    }
+
+    #[cfg(feature = "glam")]
+    pub fn filter_dquat(&mut self, q: glam::DQuat) -> glam::DQuat {
+        glam::DQuat::from_array(self.filter(q.to_array()))
+    }
+
+    // Smoothed angular speed, in radians per second.
+    pub fn angular_speed(&self) -> f64 {
+        self.dx.prev_hat
+    }
+
+    pub fn reset(&mut self) {
+        self.q_hat = None;
+        self.dx = LowPass::default();
+        self.q_prev = None;
+    }
+}
+
+/// A position and orientation. The rotation is a unit quaternion in [x, y, z, w] order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pose {
+    pub translation: [f64; 3],
+    pub rotation: [f64; 4],
+}
+
+/// Filters full 6DoF poses, i.e. from a head or controller tracker. Translation and rotation
+/// are in different units so they get tuned separately.
+#[derive(Debug, Clone)]
+pub struct PoseFilter {
+    translation: [OneEuroFilter; 3],
+    rotation: QuaternionOneEuroFilter,
+}
+
+impl PoseFilter {
+    pub fn new(
+        sample_rate: f64,
+        translation: &FinalTuningSettings,
+        rotation: &FinalTuningSettings,
+    ) -> Self {
+        Self {
+            translation: core::array::from_fn(|_| {
+                OneEuroFilter::new(
+                    sample_rate,
+                    translation.min_cutoff_hz,
+                    DERIVATIVE_CUTOFF_HZ,
+                    translation.beta,
+                )
+            }),
+            rotation: QuaternionOneEuroFilter::new(
+                sample_rate,
+                rotation.min_cutoff_hz,
+                DERIVATIVE_CUTOFF_HZ,
+                rotation.beta,
+            ),
+        }
+    }
+
+    pub fn filter(&mut self, pose: Pose) -> Pose {
+        let mut translation = [0.0; 3];
+        for (i, axis) in self.translation.iter_mut().enumerate() {
+            translation[i] = axis.filter(pose.translation[i]);
+        }
+
+        Pose {
+            translation,
+            rotation: self.rotation.filter(pose.rotation),
+        }
+    }
+
+    // Filters an isometry directly, for robotics code that works in nalgebra transforms.
+    #[cfg(feature = "nalgebra")]
+    pub fn filter_isometry(
+        &mut self,
+        isometry: nalgebra::Isometry3<f64>,
+    ) -> nalgebra::Isometry3<f64> {
+        self.filter(isometry.into()).into()
+    }
+
+    #[cfg(feature = "glam")]
+    pub fn filter_glam(
+        &mut self,
+        translation: glam::Vec3,
+        rotation: glam::Quat,
+    ) -> (glam::Vec3, glam::Quat) {
+        let pose = self.filter(Pose {
+            translation: translation.as_dvec3().to_array(),
+            rotation: rotation.as_dquat().to_array(),
+        });
+        (
+            glam::DVec3::from_array(pose.translation).as_vec3(),
+            glam::DQuat::from_array(pose.rotation).as_quat(),
+        )
+    }
+
+    pub fn reset(&mut self) {
+        self.translation.iter_mut().for_each(OneEuroFilter::reset);
+        self.rotation.reset();
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl From<nalgebra::Isometry3<f64>> for Pose {
+    fn from(isometry: nalgebra::Isometry3<f64>) -> Self {
+        let t = isometry.translation.vector;
+        let q = isometry.rotation.coords;
+        Self {
+            translation: [t.x, t.y, t.z],
+            rotation: [q.x, q.y, q.z, q.w],
+        }
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl From<Pose> for nalgebra::Isometry3<f64> {
+    fn from(pose: Pose) -> Self {
+        let [x, y, z] = pose.translation;
+        let [i, j, k, w] = pose.rotation;
+        nalgebra::Isometry3::from_parts(
+            nalgebra::Translation3::new(x, y, z),
+            nalgebra::UnitQuaternion::new_normalize(nalgebra::Quaternion::new(w, i, j, k)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_matches_one_euro_rs() {
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 0.5,
+            beta: 0.2,
+        };
+        let mut ours = settings.to_one_euro(60.0);
+        let mut theirs = settings.to_one_euro_rs(60.0);
+
+        for i in 0..200 {
+            let x = (i as f64 * 0.07).sin() * 5.0 + if i > 100 { 20.0 } else { 0.0 };
+            assert_eq!(ours.filter(x), theirs.filter(x));
+        }
+    }
+
+    #[test]
+    pub fn test_quaternion_filter() {
+        let mut filter = QuaternionOneEuroFilter::new(60.0, 1.0, 1.0, 0.1);
+        let half = std::f64::consts::FRAC_PI_4;
+        let target = [0.0, 0.0, half.sin(), half.cos()];
+
+        filter.filter([0.0, 0.0, 0.0, 1.0]);
+        let mut out = [0.0; 4];
+        for _ in 0..300 {
+            // Same rotation with the opposite sign, which must not send the filter the long way
+            // around.
+            out = filter.filter(target.map(|c| -c));
+            assert!((quat_dot(out, out) - 1.0).abs() < 1e-9);
+        }
+        assert!(quat_angle(out, target) < 1e-3);
+    }
+}
@@ -0,0 +1,190 @@
+use std::{error::Error, fmt, sync::Arc};
+
+use crate::{
+    tablegen::{self, PACKED_HEADER_BYTES, PACKED_MAGIC},
+    tuner::{Grid, Nodes},
+};
+
+/// Why bytes couldn't be read as a packed table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackedGridError {
+    // Doesn't start with tablegen::PACKED_MAGIC - not a packed table, or another version of the
+    // format.
+    BadMagic,
+    // Fewer bytes than the header says there are nodes.
+    Truncated { expected: usize, actual: usize },
+}
+
+impl fmt::Display for PackedGridError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackedGridError::BadMagic => write!(f, "not a packed precision table"),
+            PackedGridError::Truncated { expected, actual } => write!(
+                f,
+                "packed precision table is {} bytes, expected {}",
+                actual, expected
+            ),
+        }
+    }
+}
+
+impl Error for PackedGridError {}
+
+/// The header of a packed table, see tablegen::PACKED_MAGIC for the layout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PackedHeader {
+    // Nodes along each axis, as [jitter, cutoff, beta].
+    pub dimensions: [usize; 3],
+    // What the table was generated for, or 0 if unknown.
+    pub sample_rate: f64,
+}
+
+impl PackedHeader {
+    // Checks the magic, and that there are as many nodes as the header says.
+    pub fn parse(bytes: &[u8]) -> Result<Self, PackedGridError> {
+        let truncated = |expected| PackedGridError::Truncated {
+            expected,
+            actual: bytes.len(),
+        };
+        let header = bytes
+            .get(..PACKED_HEADER_BYTES)
+            .ok_or(truncated(PACKED_HEADER_BYTES))?;
+        if header[..8] != PACKED_MAGIC {
+            return Err(PackedGridError::BadMagic);
+        }
+
+        let u32_at = |offset: usize| {
+            let mut le = [0; 4];
+            le.copy_from_slice(&header[offset..offset + 4]);
+            u32::from_le_bytes(le) as usize
+        };
+        let mut le = [0; 8];
+        le.copy_from_slice(&header[24..32]);
+        let parsed = Self {
+            dimensions: [u32_at(8), u32_at(12), u32_at(16)],
+            sample_rate: f64::from_le_bytes(le),
+        };
+
+        let expected = parsed.packed_len();
+        if bytes.len() < expected {
+            return Err(truncated(expected));
+        }
+        Ok(parsed)
+    }
+
+    // Header and nodes, in bytes.
+    pub fn packed_len(&self) -> usize {
+        // Saturating, as headers are untrusted until checked against the length.
+        self.dimensions
+            .iter()
+            .fold(8, |len: usize, &nodes| len.saturating_mul(nodes))
+            .saturating_add(PACKED_HEADER_BYTES)
+    }
+}
+
+impl Grid {
+    // A grid reading its nodes straight out of a packed table, i.e. from include_bytes! or a
+    // memory mapped file - nothing is parsed or copied up front. Nodes are read with
+    // from_le_bytes, so the bytes don't need to be aligned.
+    pub fn from_packed(
+        bytes: impl AsRef<[u8]> + Send + Sync + 'static,
+    ) -> Result<Self, PackedGridError> {
+        let header = PackedHeader::parse(bytes.as_ref())?;
+        Ok(Grid::with_nodes(Nodes::Packed {
+            bytes: Arc::new(bytes),
+            dimensions: header.dimensions,
+        }))
+    }
+
+    // The grid as a packed table. None for ragged tables, which the format can't hold.
+    pub fn to_packed(&self, sample_rate: f64) -> Option<Vec<u8>> {
+        let dimensions = self.dimensions();
+        let header = PackedHeader {
+            dimensions,
+            sample_rate,
+        };
+        let mut out = Vec::with_capacity(header.packed_len());
+        tablegen::write_packed_header(&mut out, dimensions, sample_rate);
+
+        let mut nodes = 0;
+        for cell in self.cells() {
+            out.extend_from_slice(&cell.precision.to_le_bytes());
+            nodes += 1;
+        }
+        (nodes == dimensions.iter().product::<usize>()).then_some(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_packed_round_trip() {
+        let nested = Grid::new(vec![
+            vec![vec![0.1, 0.2], vec![0.3, 0.4], vec![0.5, 0.6]],
+            vec![vec![0.7, 0.8], vec![0.9, 1.0], vec![1.1, 1.2]],
+        ]);
+        let bytes = nested.to_packed(120.0).unwrap();
+        let header = PackedHeader::parse(&bytes).unwrap();
+        assert_eq!(header.dimensions, [2, 3, 2]);
+        assert_eq!(header.sample_rate, 120.0);
+        assert_eq!(bytes.len(), header.packed_len());
+
+        let packed = Grid::from_packed(bytes.clone()).unwrap();
+        assert_eq!(packed.max_difference(&nested), Some(0.0));
+        assert_eq!(packed.cell(1, 2, 1).unwrap().precision, 1.2);
+        assert!(packed.cell(2, 0, 0).is_none());
+        assert_eq!(
+            packed.try_precision(0.5, 0.06, 0.0),
+            nested.try_precision(0.5, 0.06, 0.0)
+        );
+
+        // Ragged tables can't be packed.
+        assert!(Grid::new(vec![vec![vec![0.1, 0.2], vec![0.3]]])
+            .to_packed(60.0)
+            .is_none());
+
+        assert_eq!(
+            Grid::from_packed(bytes[..40].to_vec()).err(),
+            Some(PackedGridError::Truncated {
+                expected: bytes.len(),
+                actual: 40
+            })
+        );
+        let mut bad = bytes;
+        bad[0] = b'X';
+        assert_eq!(
+            Grid::from_packed(bad).err(),
+            Some(PackedGridError::BadMagic)
+        );
+    }
+
+    #[test]
+    pub fn test_hand_packed() {
+        // Written out byte by byte rather than by to_packed: one jitter, two cutoffs and two
+        // betas at 60 hz, then 0.5, 2.0, -1.0 and 0.25 - all little endian.
+        let mut bytes = b"PPGRID01".to_vec();
+        bytes.extend_from_slice(&[1, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0x4E, 0x40]);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0xE0, 0x3F]);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0x40]);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0xF0, 0xBF]);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0xD0, 0x3F]);
+
+        let header = PackedHeader::parse(&bytes).unwrap();
+        assert_eq!(header.dimensions, [1, 2, 2]);
+        assert_eq!(header.sample_rate, 60.0);
+
+        // Beta is innermost.
+        let grid = Grid::from_packed(bytes).unwrap();
+        assert_eq!(grid.dimensions(), [1, 2, 2]);
+        assert_eq!(grid.cell(0, 0, 0).unwrap().precision, 0.5);
+        assert_eq!(grid.cell(0, 0, 1).unwrap().precision, 2.0);
+        assert_eq!(grid.cell(0, 1, 0).unwrap().precision, -1.0);
+        let cell = grid.cell(0, 1, 1).unwrap();
+        assert_eq!(cell.precision, 0.25);
+        assert_eq!(cell.index, [0, 1, 1]);
+        assert!(grid.cell(1, 0, 0).is_none());
+    }
+}
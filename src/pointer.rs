@@ -0,0 +1,167 @@
+use crate::{
+    calibrator::{least_precision_for_target_size, CalibrationStage},
+    filter::MultiAxisFilter,
+    tuner::FinalTuningSettings,
+    units::{DeviceScale, Seconds, StdDev},
+};
+
+// Lag budget for pointers, matching the calibrator's default.
+const POINTER_MAX_LAG: Seconds = Seconds(0.080);
+
+/// Relates raw device counts to on-screen pixels. Getting this wrong is the most common way to
+/// end up with nonsense tuning, as noise gets measured in counts while targets are in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointerScale {
+    // The device resolution, i.e. mouse DPI.
+    pub counts_per_inch: f64,
+    // The display resolution.
+    pub pixels_per_inch: f64,
+}
+
+impl PointerScale {
+    pub fn pixels_per_count(&self) -> f64 {
+        self.pixels_per_inch / self.counts_per_inch
+    }
+}
+
+// Counts are the device unit, for precision targets in pixels or millimeters.
+impl From<PointerScale> for DeviceScale {
+    fn from(scale: PointerScale) -> Self {
+        DeviceScale::default()
+            .with_pixels_per_unit(scale.pixels_per_count())
+            .with_millimeters_per_unit(25.4 / scale.counts_per_inch)
+    }
+}
+
+/// Two axis calibration for pointers, done entirely in pixels. Feed it either relative counts
+/// (mice) or absolute positions (tablets, touchpads) - not both.
+pub struct PointerCalibrator {
+    scale: PointerScale,
+    position: [f64; 2],
+    stage: CalibrationStage,
+}
+
+impl PointerCalibrator {
+    pub fn new(scale: PointerScale) -> Self {
+        Self {
+            scale,
+            position: [0.0; 2],
+            stage: CalibrationStage::with_axes(2),
+        }
+    }
+
+    // Accumulates relative counts into a pixel position and processes it. During the noise stage
+    // this returns true once noise calibration has completed.
+    pub fn process_counts(&mut self, dx: f64, dy: f64) -> bool {
+        let pixels_per_count = self.scale.pixels_per_count();
+        self.position[0] += dx * pixels_per_count;
+        self.position[1] += dy * pixels_per_count;
+        self.process_position(self.position[0], self.position[1])
+    }
+
+    // Processes an absolute position in pixels. During the noise stage this returns true once
+    // noise calibration has completed.
+    pub fn process_position(&mut self, x: f64, y: f64) -> bool {
+        // A pointer has no third axis, and the stage only estimates noise from two.
+        self.stage.process(x, y, 0.0)
+    }
+
+    // Moves from noise to amplitude calibration. Call once process_* returns true, then have the
+    // user move the pointer as fast as they normally would.
+    pub fn next(self) -> Self {
+        Self {
+            stage: self.stage.advance(),
+            ..self
+        }
+    }
+
+    // Per axis noise in pixels, once noise calibration has been advanced past.
+    pub fn noise_std_dev(&self) -> Option<StdDev> {
+        match &self.stage {
+            CalibrationStage::Amplitude(amplitude) => Some(amplitude.noise_std_dev()),
+            CalibrationStage::Noise(_) => None,
+        }
+    }
+
+    // Tunes for the smallest on-screen target (in pixels) the pointer needs to hit. Returns None
+    // if called before amplitude calibration.
+    pub fn finish(self, min_target_px: f64) -> Option<PointerSmoother> {
+        let CalibrationStage::Amplitude(amplitude) = self.stage else {
+            return None;
+        };
+
+        let least_precision = least_precision_for_target_size(min_target_px);
+        let mut tuner = amplitude.tuner(least_precision, POINTER_MAX_LAG);
+        let settings = tuner.tune()?;
+
+        Some(PointerSmoother::new(
+            self.scale,
+            tuner.settings.sample_rate.0,
+            &settings,
+        ))
+    }
+}
+
+/// Smooths pointer motion in pixel space.
+pub struct PointerSmoother {
+    scale: PointerScale,
+    position: [f64; 2],
+    filter: MultiAxisFilter<2>,
+}
+
+impl PointerSmoother {
+    pub fn new(scale: PointerScale, sample_rate: f64, settings: &FinalTuningSettings) -> Self {
+        Self {
+            scale,
+            position: [0.0; 2],
+            filter: MultiAxisFilter::new(sample_rate, settings),
+        }
+    }
+
+    // Applies relative counts and returns the smoothed pointer position in pixels.
+    pub fn move_by_counts(&mut self, dx: f64, dy: f64) -> [f64; 2] {
+        let pixels_per_count = self.scale.pixels_per_count();
+        self.position[0] += dx * pixels_per_count;
+        self.position[1] += dy * pixels_per_count;
+        self.filter.filter(self.position)
+    }
+
+    // Smooths an absolute position in pixels.
+    pub fn move_to(&mut self, x: f64, y: f64) -> [f64; 2] {
+        self.position = [x, y];
+        self.filter.filter(self.position)
+    }
+
+    // Jumps to a position without smoothing - i.e. when the pointer is warped by the OS.
+    pub fn warp_to(&mut self, x: f64, y: f64) {
+        self.position = [x, y];
+        self.filter.reset();
+        self.filter.filter(self.position);
+    }
+
+    pub fn settings(&self) -> &FinalTuningSettings {
+        self.filter.settings()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+
+    #[test]
+    pub fn test_pointer_noise() {
+        // A 1600 DPI mouse on a 96 DPI display, jittering by 2 pixels.
+        let scale = PointerScale {
+            counts_per_inch: 1600.0,
+            pixels_per_inch: 96.0,
+        };
+        let mut noise = GaussianNoise::new(2.0, 89);
+        let mut calibrator = PointerCalibrator::new(scale);
+        while !calibrator.process_position(500.0 + noise.sample(), 300.0 + noise.sample()) {}
+        let calibrator = calibrator.next();
+
+        let noise_std_dev = calibrator.noise_std_dev().unwrap().0;
+        assert!((noise_std_dev - 2.0).abs() < 0.2, "{noise_std_dev}");
+    }
+}
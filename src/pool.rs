@@ -0,0 +1,122 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    hash::Hash,
+};
+
+use crate::{filter::MultiAxisFilter, tuner::FinalTuningSettings};
+
+/// Manages many identically tuned filters keyed by a contact or track ID - i.e. one filter per
+/// finger on a touchscreen, or per marker in a mocap rig. All filters share one calibration
+/// result.
+///
+/// Filters are allocated on touch down and recycled on touch up, so steady state operation with
+/// contacts coming and going doesn't allocate.
+pub struct FilterPool<K, const D: usize> {
+    sample_rate: f64,
+    settings: FinalTuningSettings,
+    active: HashMap<K, MultiAxisFilter<D>>,
+    // Filters released by touch up, ready to be handed to the next contact.
+    free: Vec<MultiAxisFilter<D>>,
+}
+
+impl<K: Eq + Hash, const D: usize> FilterPool<K, D> {
+    pub fn new(sample_rate: f64, settings: FinalTuningSettings) -> Self {
+        Self {
+            sample_rate,
+            settings,
+            active: HashMap::new(),
+            free: Vec::new(),
+        }
+    }
+
+    // Starts tracking a contact. If the contact is already active its filter state is reset, as
+    // a repeated touch down means we missed the touch up.
+    pub fn touch_down(&mut self, id: K) -> &mut MultiAxisFilter<D> {
+        let filter = match self.active.entry(id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            // Only a new contact takes a filter from the free list.
+            Entry::Vacant(entry) => entry.insert(match self.free.pop() {
+                Some(filter) => filter,
+                None => MultiAxisFilter::new(self.sample_rate, &self.settings),
+            }),
+        };
+        filter.reset();
+        filter
+    }
+
+    // Stops tracking a contact and recycles its filter. Returns false if the contact wasn't
+    // active.
+    pub fn touch_up(&mut self, id: &K) -> bool {
+        match self.active.remove(id) {
+            Some(filter) => {
+                self.free.push(filter);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Filters a sample for the given contact, implicitly touching down if we haven't seen it yet.
+    pub fn filter(&mut self, id: K, sample: [f64; D]) -> [f64; D] {
+        if let Some(filter) = self.active.get_mut(&id) {
+            return filter.filter(sample);
+        }
+        self.touch_down(id).filter(sample)
+    }
+
+    pub fn get_mut(&mut self, id: &K) -> Option<&mut MultiAxisFilter<D>> {
+        self.active.get_mut(id)
+    }
+
+    pub fn is_active(&self, id: &K) -> bool {
+        self.active.contains_key(id)
+    }
+
+    pub fn active_count(&self) -> usize {
+        self.active.len()
+    }
+
+    // Releases every active contact, i.e. when the input device is lost.
+    pub fn clear(&mut self) {
+        self.free
+            .extend(self.active.drain().map(|(_, filter)| filter));
+    }
+
+    pub fn settings(&self) -> &FinalTuningSettings {
+        &self.settings
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_contact_lifecycle() {
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 1.0,
+            beta: 0.01,
+        };
+        let mut pool: FilterPool<u32, 2> = FilterPool::new(60.0, settings);
+
+        pool.filter(1, [10.0, 10.0]);
+        pool.filter(2, [20.0, 20.0]);
+        assert_eq!(pool.active_count(), 2);
+
+        assert!(pool.touch_up(&1));
+        assert!(!pool.touch_up(&1));
+        assert_eq!(pool.active_count(), 1);
+
+        // A recycled filter must not carry state over from its previous contact.
+        let out = pool.filter(3, [100.0, 100.0]);
+        assert_eq!(out, [100.0, 100.0]);
+
+        // A repeated touch down resets the contact's own filter, leaving the free list alone.
+        pool.touch_up(&2);
+        assert_eq!(pool.free.len(), 1);
+        pool.filter(3, [0.0, 0.0]);
+        assert_eq!(pool.touch_down(3).filter([50.0, 50.0]), [50.0, 50.0]);
+        assert_eq!(pool.free.len(), 1);
+        assert_eq!(pool.active_count(), 1);
+    }
+}
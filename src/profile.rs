@@ -0,0 +1,347 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    calibrator::{CalibrationStage, TuningSettings},
+    screen::ScreenScale,
+    tuner::FinalTuningSettings,
+    units::Hertz,
+};
+
+/// A tuning result along with enough about the device and calibration to manage it from a
+/// config file - i.e. across a fleet of devices.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CalibrationProfile {
+    pub device_name: String,
+    pub sample_rate: f64,
+    // When calibration happened, as seconds since the unix epoch.
+    pub calibrated_at_unix_secs: u64,
+    pub noise_std_dev: f64,
+    pub max_amplitude: f64,
+    // Version of this crate that produced the profile.
+    pub crate_version: String,
+    pub tuning: FinalTuningSettings,
+    // For screen space devices, the scale noise and amplitude were measured at - they're in
+    // logical pixels, so only hold for the same scale. See CalibrationProfile::with_screen.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub screen: Option<ScreenScale>,
+}
+
+/// Something about an imported profile that doesn't match the device it's being applied to.
+/// The profile is still usable, but the tuning may not hold.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProfileWarning {
+    SampleRateMismatch { profile: f64, device: f64 },
+    // Device units land on a different number of logical pixels than at calibration - i.e. a
+    // different monitor, resolution or OS scaling.
+    ScreenScaleMismatch { profile: f64, device: f64 },
+}
+
+impl CalibrationProfile {
+    pub fn new(
+        device_name: impl Into<String>,
+        settings: &TuningSettings,
+        tuning: FinalTuningSettings,
+    ) -> Self {
+        let calibrated_at_unix_secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        Self {
+            device_name: device_name.into(),
+            sample_rate: settings.sample_rate.0,
+            calibrated_at_unix_secs,
+            noise_std_dev: settings.noise_variance.std_dev().0,
+            max_amplitude: settings.max_amplitude,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            tuning,
+            screen: None,
+        }
+    }
+
+    // Checks the profile against the device it's about to be applied to.
+    pub fn check(&self, device_sample_rate: f64) -> Vec<ProfileWarning> {
+        let mut warnings = vec![];
+
+        if (self.sample_rate - device_sample_rate).abs() > f64::EPSILON {
+            warnings.push(ProfileWarning::SampleRateMismatch {
+                profile: self.sample_rate,
+                device: device_sample_rate,
+            });
+        }
+
+        warnings
+    }
+
+    // check, plus whether the screen scale still matches. Profiles without a screen scale
+    // aren't checked against it.
+    pub fn check_screen(
+        &self,
+        device_sample_rate: f64,
+        screen: &ScreenScale,
+    ) -> Vec<ProfileWarning> {
+        let mut warnings = self.check(device_sample_rate);
+
+        if let Some(profile) = &self.screen {
+            let (profile, device) = (profile.to_logical(1.0), screen.to_logical(1.0));
+            if (profile - device).abs() > f64::EPSILON * profile.abs().max(1.0) {
+                warnings.push(ProfileWarning::ScreenScaleMismatch { profile, device });
+            }
+        }
+
+        warnings
+    }
+}
+
+/// What to do with a device that just connected - see ProfileStore::connect.
+pub enum Reconnect<'a> {
+    // Seen before, so its profile can be applied straight away.
+    Known {
+        profile: &'a CalibrationProfile,
+        warnings: Vec<ProfileWarning>,
+    },
+    // Not seen before. Calibrate, then ProfileStore::insert the result.
+    Calibrate(CalibrationStage),
+}
+
+/// Calibration profiles for many devices, keyed by whatever identifies a device to the
+/// application - i.e. a serial number or MAC address. With the serde feature, the whole store can
+/// be saved to and opened from a JSON file.
+#[derive(Debug, Default)]
+pub struct ProfileStore {
+    profiles: HashMap<String, CalibrationProfile>,
+    #[cfg(feature = "serde")]
+    path: Option<std::path::PathBuf>,
+}
+
+impl ProfileStore {
+    // An empty, in memory store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Looks the device up, handing back a fresh calibration if it isn't known.
+    pub fn connect(&self, device_id: &str, device_sample_rate: f64) -> Reconnect<'_> {
+        match self.profiles.get(device_id) {
+            Some(profile) => Reconnect::Known {
+                profile,
+                warnings: profile.check(device_sample_rate),
+            },
+            None => Reconnect::Calibrate(
+                CalibrationStage::new().with_sample_rate(Hertz(device_sample_rate)),
+            ),
+        }
+    }
+
+    pub fn get(&self, device_id: &str) -> Option<&CalibrationProfile> {
+        self.profiles.get(device_id)
+    }
+
+    // Stores a device's profile, returning the one it replaced.
+    pub fn insert(
+        &mut self,
+        device_id: impl Into<String>,
+        profile: CalibrationProfile,
+    ) -> Option<CalibrationProfile> {
+        self.profiles.insert(device_id.into(), profile)
+    }
+
+    // Forgets a device, so it's calibrated again on its next connect.
+    pub fn remove(&mut self, device_id: &str) -> Option<CalibrationProfile> {
+        self.profiles.remove(device_id)
+    }
+
+    pub fn device_ids(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.profiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+}
+
+#[cfg(feature = "serde")]
+mod export {
+    use std::{
+        collections::HashMap,
+        error::Error,
+        fmt, fs, io,
+        path::{Path, PathBuf},
+    };
+
+    use super::{CalibrationProfile, ProfileStore, ProfileWarning};
+
+    #[derive(Debug)]
+    pub enum ProfileError {
+        Io(io::Error),
+        Json(serde_json::Error),
+        TomlSerialize(toml::ser::Error),
+        TomlDeserialize(toml::de::Error),
+    }
+
+    impl fmt::Display for ProfileError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ProfileError::Io(e) => write!(f, "failed to access profile store: {}", e),
+                ProfileError::Json(e) => write!(f, "invalid JSON profile: {}", e),
+                ProfileError::TomlSerialize(e) => write!(f, "failed to write TOML profile: {}", e),
+                ProfileError::TomlDeserialize(e) => write!(f, "invalid TOML profile: {}", e),
+            }
+        }
+    }
+
+    impl Error for ProfileError {}
+
+    /// A profile read back from a config file, along with anything that doesn't line up with
+    /// the device it's being loaded for.
+    #[derive(Debug, Clone)]
+    pub struct ImportedProfile {
+        pub profile: CalibrationProfile,
+        pub warnings: Vec<ProfileWarning>,
+    }
+
+    impl CalibrationProfile {
+        pub fn to_json(&self) -> Result<String, ProfileError> {
+            serde_json::to_string_pretty(self).map_err(ProfileError::Json)
+        }
+
+        pub fn to_toml(&self) -> Result<String, ProfileError> {
+            toml::to_string_pretty(self).map_err(ProfileError::TomlSerialize)
+        }
+
+        pub fn from_json(
+            json: &str,
+            device_sample_rate: f64,
+        ) -> Result<ImportedProfile, ProfileError> {
+            let profile: CalibrationProfile =
+                serde_json::from_str(json).map_err(ProfileError::Json)?;
+            Ok(ImportedProfile {
+                warnings: profile.check(device_sample_rate),
+                profile,
+            })
+        }
+
+        pub fn from_toml(
+            toml: &str,
+            device_sample_rate: f64,
+        ) -> Result<ImportedProfile, ProfileError> {
+            let profile: CalibrationProfile =
+                toml::from_str(toml).map_err(ProfileError::TomlDeserialize)?;
+            Ok(ImportedProfile {
+                warnings: profile.check(device_sample_rate),
+                profile,
+            })
+        }
+    }
+
+    impl ProfileStore {
+        // Opens a store backed by a JSON file, which is created on the first save if it doesn't
+        // exist yet.
+        pub fn open(path: impl Into<PathBuf>) -> Result<Self, ProfileError> {
+            let path = path.into();
+            let profiles = match fs::read_to_string(&path) {
+                Ok(json) => serde_json::from_str(&json).map_err(ProfileError::Json)?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+                Err(e) => return Err(ProfileError::Io(e)),
+            };
+            Ok(Self {
+                profiles,
+                path: Some(path),
+            })
+        }
+
+        // Writes every profile back to the file the store was opened from. Does nothing for an
+        // in memory store.
+        pub fn save(&self) -> Result<(), ProfileError> {
+            match &self.path {
+                Some(path) => self.save_to(path),
+                None => Ok(()),
+            }
+        }
+
+        // Writes every profile to the given file. Written to a temporary file first and moved
+        // into place, so a crash mid save can't lose the existing profiles.
+        pub fn save_to(&self, path: &Path) -> Result<(), ProfileError> {
+            let json = serde_json::to_string_pretty(&self.profiles).map_err(ProfileError::Json)?;
+            let tmp = path.with_extension("tmp");
+            fs::write(&tmp, json).map_err(ProfileError::Io)?;
+            fs::rename(&tmp, path).map_err(ProfileError::Io)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+pub use export::{ImportedProfile, ProfileError};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::units::Seconds;
+
+    #[test]
+    pub fn test_connect_unknown_device() {
+        let store = ProfileStore::new();
+        let mut stage = match store.connect("AA:BB", 120.0) {
+            Reconnect::Calibrate(stage) => stage,
+            Reconnect::Known { .. } => panic!("empty store knew the device"),
+        };
+
+        for _ in 0..240 {
+            stage.process(0.0, 0.0, 0.0);
+        }
+        match &stage {
+            CalibrationStage::Noise(noise) => {
+                assert_eq!(noise.report().duration, Seconds(2.0));
+            }
+            CalibrationStage::Amplitude(_) => panic!("calibration started in the amplitude stage"),
+        }
+
+        let amplitude = stage.advance().into_amplitude().unwrap();
+        let settings = amplitude.tuning_settings(1.0, Seconds(0.08));
+        assert_eq!(settings.sample_rate, Hertz(120.0));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn test_store_round_trip() {
+        use crate::units::Variance;
+
+        let settings = TuningSettings {
+            max_target_precision: 0.3,
+            max_lag_secs: Seconds(0.08),
+            noise_variance: Variance(4.0),
+            max_amplitude: 500.0,
+            sample_rate: Hertz(60.0),
+        };
+        let tuning = FinalTuningSettings {
+            min_cutoff_hz: 0.3,
+            beta: 0.01,
+        };
+        let path = std::env::temp_dir().join(format!("pitch-pipe-{}.json", std::process::id()));
+
+        let mut store = ProfileStore::open(&path).unwrap();
+        assert!(matches!(
+            store.connect("AA:BB", 60.0),
+            Reconnect::Calibrate(_)
+        ));
+        store.insert("AA:BB", CalibrationProfile::new("pen", &settings, tuning));
+        store.save().unwrap();
+
+        let reopened = ProfileStore::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        match reopened.connect("AA:BB", 120.0) {
+            Reconnect::Known { profile, warnings } => {
+                assert_eq!(profile, store.get("AA:BB").unwrap());
+                assert_eq!(warnings.len(), 1);
+            }
+            Reconnect::Calibrate(_) => panic!("stored profile not found"),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use crate::{
+    calibrator::{least_precision_for_target_size, TuningSettings},
+    filter::MultiAxisFilter,
+    publish::SettingsCell,
+    tuner::FinalTuningSettings,
+    units::{Hertz, Seconds, StdDev},
+};
+
+/// Common device classes, each with a starting point to filter with before calibration has
+/// run. See DeviceClass::profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceClass {
+    // Headset position from optical (inside out or outside in) tracking, in meters.
+    OpticalHmd,
+    // Tracked hand controller position, in meters.
+    Controller,
+    // Pointer position in pixels.
+    Mouse,
+    // Touchscreen contact position in pixels.
+    Touch,
+    // Gaze direction in degrees of visual angle.
+    Gaze,
+}
+
+impl DeviceClass {
+    pub const ALL: [DeviceClass; 5] = [
+        DeviceClass::OpticalHmd,
+        DeviceClass::Controller,
+        DeviceClass::Mouse,
+        DeviceClass::Touch,
+        DeviceClass::Gaze,
+    ];
+
+    // Parameters in the range the one euro filter is usually run with for this kind of device,
+    // alongside typical noise, speed and targets for it. Mouse is the one euro paper's own
+    // example. The others are conservative starting points in the same spirit - min cutoff low
+    // enough to hide idle jitter at the typical noise, and beta high enough to follow the
+    // typical top speed.
+    pub fn profile(self) -> DefaultProfile {
+        let (sample_rate, min_cutoff_hz, beta, noise, max_speed, least_precision, max_lag) =
+            match self {
+                // 0.2 mm of jitter, heads turning at up to a meter per second, and lag kept
+                // well inside the motion to photon budget.
+                DeviceClass::OpticalHmd => (90.0, 1.5, 10.0, 0.0002, 1.0, 0.002, 0.02),
+                // Hands move faster and get tracked less cleanly than heads.
+                DeviceClass::Controller => (90.0, 1.0, 5.0, 0.0005, 3.0, 0.005, 0.03),
+                DeviceClass::Mouse => (125.0, 1.0, 0.007, 0.5, 3000.0, 3.0, 0.08),
+                // Fingers are big - targets are around 44 pixels rather than 14.
+                DeviceClass::Touch => (
+                    120.0,
+                    1.0,
+                    0.01,
+                    1.5,
+                    4000.0,
+                    least_precision_for_target_size(44.0),
+                    0.05,
+                ),
+                // Saccades run to hundreds of degrees per second, and eye trackers are rarely
+                // better than a third of a degree.
+                DeviceClass::Gaze => (120.0, 0.5, 0.05, 0.3, 500.0, 1.0, 0.05),
+            };
+
+        DefaultProfile {
+            class: self,
+            sample_rate,
+            settings: FinalTuningSettings {
+                min_cutoff_hz,
+                beta,
+            },
+            noise_std_dev: StdDev(noise),
+            max_speed,
+            least_precision,
+            max_lag: Seconds(max_lag),
+        }
+    }
+}
+
+/// Default parameters for a device class, to filter with straight away while calibration runs
+/// in the background - i.e. seed a SettingsCell with them and publish calibrated parameters
+/// once they're ready, or hand tuning_settings to a BackgroundRecalibrator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DefaultProfile {
+    pub class: DeviceClass,
+    // The rate the parameters and the figures below are for.
+    pub sample_rate: f64,
+    pub settings: FinalTuningSettings,
+    pub noise_std_dev: StdDev,
+    // In units per second.
+    pub max_speed: f64,
+    pub least_precision: f64,
+    pub max_lag: Seconds,
+}
+
+impl DefaultProfile {
+    // Stands in for calibration, at the profile's sample rate.
+    pub fn tuning_settings(&self) -> TuningSettings {
+        TuningSettings {
+            max_target_precision: self.least_precision / 3.0,
+            max_lag_secs: self.max_lag,
+            noise_variance: self.noise_std_dev.variance(),
+            max_amplitude: self.max_speed / self.sample_rate,
+            sample_rate: Hertz(self.sample_rate),
+        }
+    }
+
+    pub fn cell(&self) -> Arc<SettingsCell> {
+        SettingsCell::new(&self.settings)
+    }
+
+    pub fn spawn_filter<const D: usize>(&self) -> MultiAxisFilter<D> {
+        self.settings.spawn_filter(self.sample_rate)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{estimators::RunningStatistics, synth::GaussianNoise};
+
+    #[test]
+    pub fn test_profiles_smooth_typical_noise() {
+        for class in DeviceClass::ALL {
+            let profile = class.profile();
+            let noise_std_dev = profile.noise_std_dev.0;
+            let mut noise = GaussianNoise::new(noise_std_dev, 79);
+            let mut filter = profile.spawn_filter::<1>();
+            let mut jitter = RunningStatistics::new();
+            for i in 0..(20.0 * profile.sample_rate) as usize {
+                let x = filter.filter([noise.sample()])[0];
+                if i as f64 > 5.0 * profile.sample_rate {
+                    jitter.update(x);
+                }
+            }
+            let std_dev = jitter.population_variance().unwrap().sqrt();
+            assert!(std_dev < noise_std_dev / 2.0, "{class:?}");
+            assert_eq!(profile.cell().load(), profile.settings);
+        }
+    }
+}
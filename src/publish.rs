@@ -0,0 +1,257 @@
+use std::sync::{
+    atomic::{fence, AtomicU64, Ordering},
+    Arc,
+};
+
+use crate::{
+    crossfade::Crossfade,
+    events,
+    filter::MultiAxisFilter,
+    tuner::FinalTuningSettings,
+    units::Seconds,
+    wcet::{BoundedWork, WorkBound},
+};
+
+/// Shares tuned parameters between a tuning thread and a real time filtering thread. Publishing
+/// may spin against other publishers, but reading never blocks, spins or allocates - a reader
+/// that catches a publish in progress just keeps its current parameters and picks up the new
+/// ones on the next sample.
+///
+/// Internally a sequence lock over the two parameters, stored as raw bits.
+#[derive(Debug)]
+pub struct SettingsCell {
+    // Odd while a publish is in progress.
+    seq: AtomicU64,
+    min_cutoff_hz: AtomicU64,
+    beta: AtomicU64,
+}
+
+impl SettingsCell {
+    pub fn new(settings: &FinalTuningSettings) -> Arc<Self> {
+        Arc::new(Self {
+            seq: AtomicU64::new(0),
+            min_cutoff_hz: AtomicU64::new(settings.min_cutoff_hz.to_bits()),
+            beta: AtomicU64::new(settings.beta.to_bits()),
+        })
+    }
+
+    pub fn publish(&self, settings: &FinalTuningSettings) {
+        let mut seq = self.seq.load(Ordering::Relaxed);
+        loop {
+            if seq % 2 == 1 {
+                std::hint::spin_loop();
+                seq = self.seq.load(Ordering::Relaxed);
+                continue;
+            }
+            match self
+                .seq
+                .compare_exchange_weak(seq, seq + 1, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(current) => seq = current,
+            }
+        }
+        fence(Ordering::Release);
+
+        self.min_cutoff_hz
+            .store(settings.min_cutoff_hz.to_bits(), Ordering::Relaxed);
+        self.beta.store(settings.beta.to_bits(), Ordering::Relaxed);
+        self.seq.store(seq + 2, Ordering::Release);
+        events::milestone!(
+            "parameters published",
+            min_cutoff_hz = settings.min_cutoff_hz,
+            beta = settings.beta,
+        );
+    }
+
+    // A single wait free attempt at reading consistent settings. None if a publish was in
+    // progress, otherwise the settings along with the sequence number they were published at.
+    fn try_load(&self) -> Option<(u64, FinalTuningSettings)> {
+        let before = self.seq.load(Ordering::Acquire);
+        if before % 2 == 1 {
+            return None;
+        }
+
+        let min_cutoff_hz = f64::from_bits(self.min_cutoff_hz.load(Ordering::Relaxed));
+        let beta = f64::from_bits(self.beta.load(Ordering::Relaxed));
+        fence(Ordering::Acquire);
+
+        (self.seq.load(Ordering::Relaxed) == before).then_some((
+            before,
+            FinalTuningSettings {
+                min_cutoff_hz,
+                beta,
+            },
+        ))
+    }
+
+    // The settings last published. Spins while a publish is in progress, so keep it off real
+    // time threads - they should use a reader.
+    pub fn load(&self) -> FinalTuningSettings {
+        loop {
+            if let Some((_, settings)) = self.try_load() {
+                return settings;
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    pub fn reader(self: &Arc<Self>) -> SettingsReader {
+        SettingsReader {
+            cell: self.clone(),
+            seen: None,
+        }
+    }
+}
+
+/// The real time side of a SettingsCell.
+#[derive(Debug)]
+pub struct SettingsReader {
+    cell: Arc<SettingsCell>,
+    seen: Option<u64>,
+}
+
+impl SettingsReader {
+    // Returns settings if anything was published since the last successful poll. Wait free.
+    pub fn poll(&mut self) -> Option<FinalTuningSettings> {
+        let (seq, settings) = self.cell.try_load()?;
+        if self.seen == Some(seq) {
+            return None;
+        }
+        self.seen = Some(seq);
+        Some(settings)
+    }
+}
+
+/// A filter that picks up parameters published to a SettingsCell at the next sample, without
+/// resetting its state - all at once, or faded in over a while with with_crossfade.
+pub struct LiveFilter<const D: usize> {
+    filter: MultiAxisFilter<D>,
+    reader: SettingsReader,
+    crossfade: Seconds,
+    fading: Option<Crossfade>,
+}
+
+impl<const D: usize> LiveFilter<D> {
+    pub fn new(sample_rate: f64, cell: &Arc<SettingsCell>) -> Self {
+        let mut reader = cell.reader();
+        // Another handle might be mid publish - spinning is fine here, as construction isn't on
+        // the real time path.
+        let settings = loop {
+            if let Some(settings) = reader.poll() {
+                break settings;
+            }
+            std::hint::spin_loop();
+        };
+
+        Self {
+            filter: MultiAxisFilter::new(sample_rate, &settings),
+            reader,
+            crossfade: Seconds(0.0),
+            fading: None,
+        }
+    }
+
+    // Fades newly published parameters in over this long, so a mid session retune isn't felt as
+    // a sudden change. Anything published mid fade fades on from wherever the fade had got to.
+    pub fn with_crossfade(mut self, duration: Seconds) -> Self {
+        self.crossfade = duration;
+        self
+    }
+
+    pub fn filter(&mut self, sample: [f64; D]) -> [f64; D] {
+        if let Some(settings) = self.reader.poll() {
+            if self.crossfade.0 > 0.0 {
+                self.fading = Some(Crossfade::new(
+                    self.filter.settings(),
+                    &settings,
+                    self.crossfade,
+                ));
+            } else {
+                self.fading = None;
+                self.filter.set_settings(&settings);
+            }
+        }
+        if let Some(fade) = self.fading.as_mut() {
+            let settings = fade.advance(1.0 / self.filter.sample_rate());
+            if fade.is_done() {
+                self.fading = None;
+                self.filter.set_settings(&settings);
+            } else {
+                self.filter.apply_settings(&settings);
+            }
+        }
+        self.filter.filter(sample)
+    }
+
+    // Whether published parameters are still being faded in.
+    pub fn is_fading(&self) -> bool {
+        self.fading.is_some()
+    }
+
+    pub fn inner(&self) -> &MultiAxisFilter<D> {
+        &self.filter
+    }
+}
+
+// Polling and fading are a handful of loads and arithmetic on top of filtering.
+impl<const D: usize> BoundedWork for LiveFilter<D> {
+    fn work_bound(&self) -> WorkBound {
+        self.filter.work_bound()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_live_filter() {
+        let initial = FinalTuningSettings {
+            min_cutoff_hz: 1.0,
+            beta: 0.01,
+        };
+        let tuned = FinalTuningSettings {
+            min_cutoff_hz: 2.5,
+            beta: 0.3,
+        };
+
+        let cell = SettingsCell::new(&initial);
+        let mut filter = LiveFilter::<3>::new(60.0, &cell);
+        // Partway through a step, so a filter that lost its state would jump straight to it.
+        filter.filter([0.0; 3]);
+        let mut before = [0.0; 3];
+        for _ in 0..3 {
+            before = filter.filter([1.0, 2.0, 3.0]);
+        }
+        assert_eq!(filter.inner().settings(), &initial);
+
+        let (publisher, published) = (cell.clone(), tuned.clone());
+        std::thread::spawn(move || publisher.publish(&published))
+            .join()
+            .unwrap();
+
+        let out = filter.filter([1.0, 2.0, 3.0]);
+        assert_eq!(filter.inner().settings(), &tuned);
+        // State survived the swap: the output carries on up the step from where it was.
+        for (axis, step) in [1.0, 2.0, 3.0].into_iter().enumerate() {
+            assert!(
+                out[axis] > before[axis] && out[axis] < step,
+                "{out:?} vs {before:?}"
+            );
+        }
+
+        // A tenth of a second at 60 hz is six samples, give or take rounding.
+        let mut filter = LiveFilter::<3>::new(60.0, &cell).with_crossfade(Seconds(0.1));
+        cell.publish(&initial);
+        filter.filter([0.0; 3]);
+        assert!(filter.is_fading());
+        let cutoff = filter.inner().settings().min_cutoff_hz;
+        assert!(cutoff < tuned.min_cutoff_hz && cutoff > initial.min_cutoff_hz);
+        for _ in 0..6 {
+            filter.filter([0.0; 3]);
+        }
+        assert!(!filter.is_fading());
+        assert_eq!(filter.inner().settings(), &initial);
+    }
+}
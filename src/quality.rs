@@ -0,0 +1,216 @@
+use crate::{estimators::RunningStatistics, math, units::StdDev};
+
+// Noise at this multiple of the tuning target or worse scores zero, and at half of it or better
+// scores full marks. Scored on a log scale in between.
+const WORST_NOISE_RATIO: f64 = 4.0;
+const BEST_NOISE_RATIO: f64 = 0.5;
+
+// Fraction of samples missing that scores zero.
+const WORST_DROPOUT_RATE: f64 = 0.1;
+
+// Standard deviation of dt as a fraction of the expected dt that scores zero.
+const WORST_TIMING_JITTER: f64 = 0.5;
+
+// Motion amplitude over noise, in dB, scored from zero to full marks.
+const WORST_HEADROOM_DB: f64 = 20.0;
+const BEST_HEADROOM_DB: f64 = 60.0;
+
+// A gap this many times the expected dt counts the samples that should have been in it as
+// dropped.
+const GAP_FACTOR: f64 = 1.5;
+
+/// One of the things that go into a QualityScore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityComponent {
+    Noise,
+    Dropouts,
+    TimingJitter,
+    AmplitudeHeadroom,
+}
+
+impl QualityComponent {
+    // Something to show users next to a poor score.
+    pub fn advice(&self) -> &'static str {
+        match self {
+            QualityComponent::Noise => "the tracker is too noisy for the precision asked of it",
+            QualityComponent::Dropouts => "samples are going missing - check the connection",
+            QualityComponent::TimingJitter => "samples are arriving unevenly",
+            QualityComponent::AmplitudeHeadroom => {
+                "movement barely stands out from the noise - move further during calibration"
+            }
+        }
+    }
+}
+
+/// A 0 - 100 rating of the input signal, with the breakdown behind it. The overall score is the
+/// weakest component, as any one of them is enough to ruin filtering. Components that weren't
+/// measured (i.e. timing without timestamps) are None and left out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityScore {
+    pub score: f64,
+    pub noise: Option<f64>,
+    pub dropouts: Option<f64>,
+    pub timing_jitter: Option<f64>,
+    pub amplitude_headroom: Option<f64>,
+}
+
+impl QualityScore {
+    pub(crate) fn new(
+        noise: Option<f64>,
+        dropouts: Option<f64>,
+        timing_jitter: Option<f64>,
+        amplitude_headroom: Option<f64>,
+    ) -> Self {
+        let mut score = Self {
+            score: 100.0,
+            noise,
+            dropouts,
+            timing_jitter,
+            amplitude_headroom,
+        };
+        if let Some((_, weakest)) = score.weakest_with_score() {
+            score.score = weakest;
+        }
+        score
+    }
+
+    // The component dragging the score down, if anything was measured.
+    pub fn weakest(&self) -> Option<QualityComponent> {
+        self.weakest_with_score().map(|(component, _)| component)
+    }
+
+    fn weakest_with_score(&self) -> Option<(QualityComponent, f64)> {
+        [
+            (QualityComponent::Noise, self.noise),
+            (QualityComponent::Dropouts, self.dropouts),
+            (QualityComponent::TimingJitter, self.timing_jitter),
+            (QualityComponent::AmplitudeHeadroom, self.amplitude_headroom),
+        ]
+        .into_iter()
+        .filter_map(|(component, score)| Some((component, score?)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    // Noise standard deviation against the filter's precision target.
+    pub(crate) fn noise_score(noise_std_dev: StdDev, target_precision: f64) -> f64 {
+        let ratio = noise_std_dev.0 / target_precision;
+        let span = math::ln(WORST_NOISE_RATIO / BEST_NOISE_RATIO);
+        to_score(1.0 - math::ln(ratio / BEST_NOISE_RATIO) / span)
+    }
+
+    pub(crate) fn headroom_score(max_amplitude: f64, noise_std_dev: StdDev) -> f64 {
+        let headroom_db = 20.0 * math::log10(max_amplitude / noise_std_dev.0);
+        to_score((headroom_db - WORST_HEADROOM_DB) / (BEST_HEADROOM_DB - WORST_HEADROOM_DB))
+    }
+}
+
+// From a 0 - 1 fraction, clamped.
+fn to_score(fraction: f64) -> f64 {
+    if fraction.is_nan() {
+        return 0.0;
+    }
+    100.0 * fraction.clamp(0.0, 1.0)
+}
+
+/// Keeps track of dropouts and timing while calibrating, for the parts of the quality score
+/// that the calibrator can't see from sample values alone. Feed it alongside the calibrator.
+#[derive(Debug, Clone)]
+pub struct SignalMonitor {
+    expected_dt: f64,
+    expected: u64,
+    dropped: u64,
+    last_secs: Option<f64>,
+    // dt over expected dt.
+    dt: RunningStatistics,
+}
+
+impl SignalMonitor {
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            expected_dt: 1.0 / sample_rate,
+            expected: 0,
+            dropped: 0,
+            last_secs: None,
+            dt: RunningStatistics::new(),
+        }
+    }
+
+    // A sample that arrived. Non finite values count as dropped.
+    pub fn record(&mut self, sample: &[f64]) {
+        self.expected += 1;
+        if sample.iter().any(|value| !value.is_finite()) {
+            self.dropped += 1;
+        }
+    }
+
+    // A sample with its timestamp. Gaps well past the expected dt count the samples that should
+    // have been there as dropped.
+    pub fn record_at(&mut self, sample: &[f64], timestamp_secs: f64) {
+        if let Some(last) = self.last_secs.replace(timestamp_secs) {
+            let ratio = (timestamp_secs - last) / self.expected_dt;
+            if ratio > GAP_FACTOR {
+                let missing = ratio.round() as u64 - 1;
+                self.expected += missing;
+                self.dropped += missing;
+            } else {
+                self.dt.update(ratio);
+            }
+        }
+        self.record(sample);
+    }
+
+    // The device said a sample was lost, i.e. a sequence number was skipped.
+    pub fn record_dropout(&mut self) {
+        self.expected += 1;
+        self.dropped += 1;
+    }
+
+    pub fn dropout_rate(&self) -> Option<f64> {
+        (self.expected > 0).then(|| self.dropped as f64 / self.expected as f64)
+    }
+
+    // Standard deviation of dt, as a fraction of the expected dt. Gaps counted as dropouts are
+    // left out. None without timestamps.
+    pub fn timing_jitter(&self) -> Option<f64> {
+        self.dt.sample_variance().map(f64::sqrt)
+    }
+
+    pub(crate) fn dropout_score(&self) -> Option<f64> {
+        self.dropout_rate()
+            .map(|rate| to_score(1.0 - rate / WORST_DROPOUT_RATE))
+    }
+
+    pub(crate) fn timing_score(&self) -> Option<f64> {
+        self.timing_jitter()
+            .map(|jitter| to_score(1.0 - jitter / WORST_TIMING_JITTER))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_quality_score() {
+        let mut monitor = SignalMonitor::new(60.0);
+        for i in 0..600 {
+            // Every 20th sample goes missing.
+            if i % 20 != 0 {
+                monitor.record_at(&[0.0, 0.0, 0.0], i as f64 / 60.0);
+            }
+        }
+        assert!((monitor.dropout_rate().unwrap() - 0.05).abs() < 0.01);
+        assert!(monitor.timing_jitter().unwrap() < 1e-6);
+
+        let score = QualityScore::new(
+            Some(QualityScore::noise_score(StdDev(0.5), 1.0)),
+            monitor.dropout_score(),
+            monitor.timing_score(),
+            Some(QualityScore::headroom_score(100.0, StdDev(0.5))),
+        );
+        assert_eq!(score.noise, Some(100.0));
+        assert!(score.timing_jitter.unwrap() > 99.9);
+        assert_eq!(score.weakest(), Some(QualityComponent::Dropouts));
+        assert!((score.score - 50.0).abs() < 10.0);
+    }
+}
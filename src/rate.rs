@@ -0,0 +1,88 @@
+//! Moving tuned parameters between sample rates, i.e. calibrating at 60 hz and filtering the
+//! same device later at 120 hz.
+//!
+//! Cutoffs are in hz, but the filter is discrete, so the same cutoff decays at a slightly
+//! different rate per second at a different sample rate. Beta is worse - it multiplies the
+//! smoothed derivative, and the derivative of noise grows with the sample rate, so the same beta
+//! opens the filter up further on noise at a higher rate than it was tuned for.
+
+use std::f64::consts::PI;
+
+use crate::{filter::DERIVATIVE_CUTOFF_HZ, math, tuner::FinalTuningSettings, units::Hertz};
+
+fn alpha(sample_rate: f64, cutoff_hz: f64) -> f64 {
+    let tau = 1.0 / (2.0 * PI * cutoff_hz);
+    1.0 / (1.0 + tau * sample_rate)
+}
+
+fn cutoff_for_alpha(sample_rate: f64, alpha: f64) -> f64 {
+    let tau = (1.0 / alpha - 1.0) / sample_rate;
+    1.0 / (2.0 * PI * tau)
+}
+
+// Standard deviation of the smoothed derivative of unit white noise - what beta multiplies when
+// the device is at rest. Differencing makes the derivative an MA(1) process, and through the
+// derivative low pass that works out to alpha * rate * sqrt(2 / (2 - alpha)).
+fn derivative_noise(sample_rate: f64) -> f64 {
+    let alpha_d = alpha(sample_rate, DERIVATIVE_CUTOFF_HZ);
+    alpha_d * sample_rate * (2.0 / (2.0 - alpha_d)).sqrt()
+}
+
+// Translates parameters tuned at one sample rate for use at another.
+//
+// min_cutoff_hz is adjusted so the filter at rest decays by the same amount per second, and beta
+// is scaled so noise opens the filter up by the same amount - which is what the precision
+// target was tuned against. Response to real motion changes slightly, as the motion's derivative
+// doesn't scale with the rate the way noise's does.
+pub fn translate(settings: &FinalTuningSettings, from: Hertz, to: Hertz) -> FinalTuningSettings {
+    let decay_per_sec = math::powf(1.0 - alpha(from.0, settings.min_cutoff_hz), from.0);
+    let alpha_to = 1.0 - math::powf(decay_per_sec, 1.0 / to.0);
+
+    FinalTuningSettings {
+        min_cutoff_hz: cutoff_for_alpha(to.0, alpha_to),
+        beta: settings.beta * derivative_noise(from.0) / derivative_noise(to.0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{filter::ThreeAxisFilter, synth::GaussianNoise};
+
+    fn noise_precision(settings: &FinalTuningSettings, rate: f64) -> f64 {
+        let mut filter = ThreeAxisFilter::new(rate, settings);
+        let mut noise = GaussianNoise::new(1.0, 3);
+        let samples = (120.0 * rate) as usize;
+        let mut sum_squares = 0.0;
+        for _ in 0..samples {
+            let [x, _, _] = filter.filter([noise.sample(), 0.0, 0.0]);
+            sum_squares += x * x;
+        }
+        (sum_squares / samples as f64).sqrt()
+    }
+
+    #[test]
+    pub fn test_translate() {
+        let tuned = FinalTuningSettings {
+            min_cutoff_hz: 1.2,
+            beta: 0.05,
+        };
+        let same = translate(&tuned, Hertz(60.0), Hertz(60.0));
+        assert!((same.min_cutoff_hz - tuned.min_cutoff_hz).abs() < 1e-9);
+        assert!((same.beta - tuned.beta).abs() < 1e-12);
+
+        // Filtering at 120 hz averages twice the samples, so noise should drop by about root 2
+        // with translated parameters - untranslated, beta lets through more noise than that.
+        let translated = translate(&tuned, Hertz(60.0), Hertz(120.0));
+        let at_60 = noise_precision(&tuned, 60.0);
+        let expected = at_60 / 2f64.sqrt();
+        let at_120 = noise_precision(&translated, 120.0);
+        let untranslated = noise_precision(&tuned, 120.0);
+
+        assert!(
+            (at_120 / expected - 1.0).abs() < 0.1,
+            "{at_120} vs {expected}"
+        );
+        assert!(untranslated > at_120);
+    }
+}
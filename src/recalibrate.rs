@@ -0,0 +1,239 @@
+use std::{
+    sync::Arc,
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    calibrator::TuningSettings, estimators::RunningStatistics, publish::SettingsCell, tuner::Tuner,
+    units::Variance,
+};
+
+// A sample to sample change past this many (differenced) noise standard deviations on any axis
+// is treated as the user moving. Generous, so that noise a few times worse than calibrated still
+// counts as idle and can be picked up.
+const IDLE_DEVIATIONS: f64 = 8.0;
+
+// How long input has to stay idle before it's used, so the tail end of a movement isn't.
+const IDLE_SETTLE_SECS: f64 = 0.5;
+
+// Same convergence threshold as calibration's noise stage.
+const CONVERGENCE_THRESHOLD: f64 = 0.1;
+
+// How far the running noise estimate can drift from the calibrated one, as a ratio of standard
+// deviations, before retuning.
+const DEFAULT_DIVERGENCE: f64 = 1.25;
+
+// How much better retuned parameters have to be before they replace the current ones.
+const DEFAULT_MARGIN: f64 = 0.1;
+
+/// Keeps tuning current during normal use. Idle stretches of input are picked out as they
+/// happen and their noise is estimated from sample to sample differences - much cheaper than
+/// calibration's PSD estimate, and fine for telling whether noise has changed. Once that estimate
+/// converges somewhere too far from the calibrated noise, tune_fast runs on a background thread
+/// and publishes to the SettingsCell, so any LiveFilter reading from it switches over at its
+/// next sample.
+///
+/// Retuned parameters are only published if they're meaningfully better than the current ones
+/// for the new noise (see with_margin), so small drifts over a long session don't keep changing
+/// how the device feels.
+///
+/// Opt in by feeding raw samples to process alongside filtering them.
+pub struct BackgroundRecalibrator {
+    settings: TuningSettings,
+    cell: Arc<SettingsCell>,
+    divergence: f64,
+    margin: f64,
+    previous: Option<[f64; 3]>,
+    idle_samples: usize,
+    settle_samples: usize,
+    // Half the variance of first differences, which is the variance of white noise.
+    stats: RunningStatistics,
+    retune: Option<JoinHandle<TuningSettings>>,
+}
+
+impl BackgroundRecalibrator {
+    // Settings are what the cell's current parameters were tuned from.
+    pub fn new(settings: TuningSettings, cell: &Arc<SettingsCell>) -> Self {
+        let settle_samples = (IDLE_SETTLE_SECS * settings.sample_rate.0).ceil() as usize;
+        Self {
+            settings,
+            cell: cell.clone(),
+            divergence: DEFAULT_DIVERGENCE,
+            margin: DEFAULT_MARGIN,
+            previous: None,
+            idle_samples: 0,
+            settle_samples,
+            stats: RunningStatistics::new(),
+            retune: None,
+        }
+    }
+
+    // The standard deviation ratio (i.e. 1.25 for 25% either way) past which a retune is
+    // triggered.
+    pub fn with_divergence(mut self, divergence: f64) -> Self {
+        self.divergence = divergence;
+        self
+    }
+
+    // How much better, as a fraction, retuned parameters have to be than the current ones to be
+    // published - see Tuner::improvement. Zero publishes every retune.
+    pub fn with_margin(mut self, margin: f64) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    // Feeds one raw sample. Returns true if this sample triggered a retune.
+    pub fn process(&mut self, x: f64, y: f64, z: f64) -> bool {
+        self.collect_retune();
+
+        let sample = [x, y, z];
+        let Some(previous) = self.previous.replace(sample) else {
+            return false;
+        };
+
+        let limit = IDLE_DEVIATIONS * (2.0 * self.settings.noise_variance.0).sqrt();
+        let diffs = [x - previous[0], y - previous[1], z - previous[2]];
+        if diffs.iter().any(|diff| diff.abs() > limit) {
+            self.idle_samples = 0;
+            return false;
+        }
+
+        self.idle_samples += 1;
+        if self.idle_samples <= self.settle_samples {
+            return false;
+        }
+
+        for diff in diffs {
+            self.stats.update(diff * diff / 2.0);
+        }
+        if self.retune.is_some() || !self.stats.converged(CONVERGENCE_THRESHOLD) {
+            return false;
+        }
+
+        let estimate = Variance(self.stats.mean);
+        self.stats = RunningStatistics::new();
+        if !self.diverged(estimate) {
+            return false;
+        }
+
+        let mut settings = self.settings.clone();
+        settings.noise_variance = estimate;
+        let cell = self.cell.clone();
+        let margin = self.margin;
+        self.retune = Some(thread::spawn(move || {
+            let mut tuner = Tuner::new(settings.clone());
+            if let Some(tuned) = tuner.tune_fast() {
+                if tuner.improvement(&cell.load(), &tuned) > margin {
+                    cell.publish(&tuned);
+                }
+            }
+            settings
+        }));
+        true
+    }
+
+    // Whether a retune is still running in the background.
+    pub fn is_retuning(&self) -> bool {
+        self.retune
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
+
+    // The noise last retuned for. Later divergence is measured against it even if the retune
+    // wasn't enough of an improvement to publish, so it isn't retried over and over.
+    pub fn noise_variance(&self) -> Variance {
+        self.settings.noise_variance
+    }
+
+    fn diverged(&self, estimate: Variance) -> bool {
+        let ratio = (estimate.0 / self.settings.noise_variance.0).sqrt();
+        ratio > self.divergence || ratio < 1.0 / self.divergence
+    }
+
+    // Picks up settings from a finished retune, so later divergence is measured against them.
+    fn collect_retune(&mut self) {
+        if !self.retune.as_ref().is_some_and(JoinHandle::is_finished) {
+            return;
+        }
+        if let Some(Ok(settings)) = self.retune.take().map(JoinHandle::join) {
+            self.settings = settings;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        synth::GaussianNoise,
+        units::{Hertz, Seconds},
+    };
+
+    #[test]
+    pub fn test_retunes_on_noise_change() {
+        let settings = TuningSettings {
+            max_target_precision: 0.3,
+            max_lag_secs: Seconds(0.08),
+            noise_variance: Variance(1.0),
+            max_amplitude: 500.0,
+            sample_rate: Hertz(60.0),
+        };
+        let tuned = Tuner::new(settings.clone()).tune_fast().unwrap();
+        let cell = SettingsCell::new(&tuned);
+        let mut reader = cell.reader();
+        reader.poll();
+
+        let mut recalibrator = BackgroundRecalibrator::new(settings, &cell);
+
+        // Same noise as calibrated - nothing to do.
+        let mut noise = GaussianNoise::new(1.0, 3);
+        for _ in 0..2000 {
+            assert!(!recalibrator.process(noise.sample(), noise.sample(), noise.sample()));
+        }
+
+        // The sensor got twice as noisy. The first estimate can straddle the change, so give it
+        // time to settle on the new noise.
+        let mut noise = GaussianNoise::new(2.0, 5);
+        let mut triggered = false;
+        for _ in 0..10000 {
+            triggered |= recalibrator.process(noise.sample(), noise.sample(), noise.sample());
+            while recalibrator.is_retuning() {
+                thread::yield_now();
+            }
+        }
+        assert!(triggered);
+        assert!((recalibrator.noise_variance().0 - 4.0).abs() < 1.0);
+        assert_ne!(reader.poll().unwrap(), tuned);
+    }
+
+    #[test]
+    pub fn test_margin_holds_parameters() {
+        let settings = TuningSettings {
+            max_target_precision: 0.3,
+            max_lag_secs: Seconds(0.08),
+            noise_variance: Variance(1.0),
+            max_amplitude: 500.0,
+            sample_rate: Hertz(60.0),
+        };
+        let tuned = Tuner::new(settings.clone()).tune_fast().unwrap();
+        let cell = SettingsCell::new(&tuned);
+        let mut reader = cell.reader();
+        reader.poll();
+
+        // Noise a little over the divergence, which would retune but not by enough to matter.
+        let mut recalibrator = BackgroundRecalibrator::new(settings, &cell)
+            .with_divergence(1.1)
+            .with_margin(10.0);
+        let mut noise = GaussianNoise::new(1.3, 7);
+        let mut triggered = false;
+        for _ in 0..10000 {
+            triggered |= recalibrator.process(noise.sample(), noise.sample(), noise.sample());
+            while recalibrator.is_retuning() {
+                thread::yield_now();
+            }
+        }
+        assert!(triggered);
+        assert!(recalibrator.noise_variance().0 > 1.2);
+        assert_eq!(reader.poll(), None);
+    }
+}
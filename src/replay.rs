@@ -0,0 +1,163 @@
+use crate::{
+    calibrator::CalibrationStage,
+    tuner::{FinalTuningSettings, Tuner},
+    units::Seconds,
+};
+
+/// One step of a calibration session, in the order it happened.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CalibrationEvent {
+    Sample([f64; 3]),
+    // Moved from noise to amplitude calibration.
+    Advance,
+}
+
+/// Everything fed into a calibration, enough to reproduce its tuning result exactly. Attach
+/// one of these to a "calibration chose weird parameters" bug report.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CalibrationSession {
+    pub events: Vec<CalibrationEvent>,
+    pub least_precision: f64,
+    pub worst_lag: Seconds,
+}
+
+/// Wraps calibration, recording every sample on the way through.
+pub struct RecordingCalibrator {
+    stage: CalibrationStage,
+    events: Vec<CalibrationEvent>,
+}
+
+impl RecordingCalibrator {
+    pub fn new() -> Self {
+        Self {
+            stage: CalibrationStage::new(),
+            events: Vec::new(),
+        }
+    }
+
+    // Processes a sample in the current stage. Returns true once the stage has enough data.
+    pub fn process(&mut self, x: f64, y: f64, z: f64) -> bool {
+        self.events.push(CalibrationEvent::Sample([x, y, z]));
+        self.stage.process(x, y, z)
+    }
+
+    pub fn next(mut self) -> Self {
+        self.events.push(CalibrationEvent::Advance);
+        Self {
+            stage: self.stage.advance(),
+            events: self.events,
+        }
+    }
+
+    // Tunes, returning the result along with the recorded session. Tuning fails (None) if
+    // called before the amplitude stage, but the session is still returned.
+    pub fn tune(
+        self,
+        least_precision: f64,
+        worst_lag: Seconds,
+    ) -> (Option<FinalTuningSettings>, CalibrationSession) {
+        let session = CalibrationSession {
+            events: self.events,
+            least_precision,
+            worst_lag,
+        };
+
+        let result = tune_stage(self.stage, least_precision, worst_lag);
+        (result, session)
+    }
+}
+
+impl Default for RecordingCalibrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn tune_stage(
+    stage: CalibrationStage,
+    least_precision: f64,
+    worst_lag: Seconds,
+) -> Option<FinalTuningSettings> {
+    let amplitude = stage.into_amplitude()?;
+    Tuner::new(amplitude.tuning_settings(least_precision, worst_lag)).tune()
+}
+
+// Runs a recorded session through a fresh calibration, producing the same result the original
+// calibration did.
+pub fn replay(session: &CalibrationSession) -> Option<FinalTuningSettings> {
+    let mut stage = CalibrationStage::new();
+
+    for event in &session.events {
+        match *event {
+            CalibrationEvent::Sample([x, y, z]) => {
+                stage.process(x, y, z);
+            }
+            CalibrationEvent::Advance => stage = stage.advance(),
+        }
+    }
+
+    tune_stage(stage, session.least_precision, session.worst_lag)
+}
+
+#[cfg(feature = "serde")]
+impl CalibrationSession {
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+
+    fn record() -> (Option<FinalTuningSettings>, CalibrationSession) {
+        let mut noise = GaussianNoise::new(0.5, 61);
+        let mut calibrator = RecordingCalibrator::new();
+        while !calibrator.process(noise.sample(), noise.sample(), noise.sample()) {}
+
+        let mut calibrator = calibrator.next();
+        for i in 0..240 {
+            let position = if i % 2 == 0 { 0.0 } else { 10.0 };
+            calibrator.process(position + noise.sample(), noise.sample(), noise.sample());
+        }
+        calibrator.tune(10.0, Seconds(0.08))
+    }
+
+    #[test]
+    pub fn test_replay() {
+        let (recorded, session) = record();
+        assert!(recorded.is_some());
+        assert_eq!(session.least_precision, 10.0);
+        assert_eq!(session.worst_lag, Seconds(0.08));
+
+        // Replaying reproduces the recorded result exactly, however many times it's run.
+        assert_eq!(replay(&session), recorded);
+        assert_eq!(replay(&session), replay(&session));
+
+        #[cfg(feature = "serde")]
+        {
+            let json = session.to_json().unwrap();
+            let restored = CalibrationSession::from_json(&json).unwrap();
+            assert_eq!(restored, session);
+            assert_eq!(replay(&restored), recorded);
+        }
+
+        // Tuning without reaching the amplitude stage fails, but still gives back the session.
+        let mut calibrator = RecordingCalibrator::new();
+        calibrator.process(1.0, 2.0, 3.0);
+        let (result, session) = calibrator.tune(10.0, Seconds(0.08));
+        assert_eq!(result, None);
+        assert_eq!(
+            session.events,
+            vec![CalibrationEvent::Sample([1.0, 2.0, 3.0])]
+        );
+        assert_eq!(replay(&session), None);
+    }
+}
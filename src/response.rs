@@ -0,0 +1,306 @@
+use std::f64::consts::PI;
+
+use num::Complex;
+use one_euro_rs::OneEuroFilter;
+
+use crate::{math, tuner::FinalTuningSettings};
+
+// Long enough for any usable set of parameters to have settled.
+const SIMULATION_SECS: f64 = 5.0;
+
+// With the no-panic feature, a step that still hasn't settled after this many samples - only
+// possible with non finite settings - is given up on as infinitely slow, rather than looping
+// forever.
+const MAX_STEP_SAMPLES: u64 = 1_000_000;
+
+/// The raw output of a simulated step, see simulate_step_response.
+#[derive(Debug, Clone)]
+pub struct StepTrace {
+    pub amplitude: f64,
+    pub sample_rate: f64,
+    // Filter output starting at the first sample of the step.
+    pub trace: Vec<f64>,
+}
+
+impl StepTrace {
+    // Summarizes the trace, treating output within tolerance of the amplitude as arrived.
+    pub fn analyze(&self, tolerance: f64) -> StepResponse {
+        StepResponse::from_trace(&self.trace, self.amplitude, self.sample_rate, tolerance)
+    }
+}
+
+// Feeds a one euro filter with the given parameters a step from zero to amplitude, the same way
+// the tuner does when measuring lag, and returns the output. Handy for probing parameter
+// combinations without going through calibration.
+pub fn simulate_step_response(
+    min_cutoff_hz: f64,
+    beta: f64,
+    d_cutoff_hz: f64,
+    sample_rate: f64,
+    amplitude: f64,
+) -> StepTrace {
+    let mut filter = OneEuroFilter::new(sample_rate, min_cutoff_hz, d_cutoff_hz, beta);
+
+    // Warm at zero
+    for _ in 0..2 {
+        filter.filter(0.0);
+    }
+
+    let samples = (SIMULATION_SECS * sample_rate) as usize;
+    let trace = (0..samples).map(|_| filter.filter(amplitude)).collect();
+
+    StepTrace {
+        amplitude,
+        sample_rate,
+        trace,
+    }
+}
+
+/// Summary of how a filter responded to a step from zero to some amplitude.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepResponse {
+    // Time until the output first came within the tolerance of the step amplitude.
+    pub lag_secs: f64,
+    // How far the output went past the step amplitude, as a percentage of the amplitude.
+    pub overshoot_percent: f64,
+    // Time after which the output stays within the tolerance for good.
+    pub settling_time_secs: f64,
+    // Number of times the output crossed the step amplitude. A well behaved one euro filter
+    // approaches from one side, so this is zero unless something is ringing.
+    pub ring_count: usize,
+}
+
+impl StepResponse {
+    // Analyzes the output of a filter that was fed a step from zero to amplitude at the first
+    // sample of the trace. Time is counted the same way as the tuner's lag - the first sample
+    // counts as one sample period.
+    pub fn from_trace(trace: &[f64], amplitude: f64, sample_rate: f64, tolerance: f64) -> Self {
+        let to_secs = |samples: usize| samples as f64 / sample_rate;
+        let within = |y: f64| (y - amplitude).abs() < tolerance;
+
+        let lag_samples = trace
+            .iter()
+            .position(|&y| within(y))
+            .map_or(trace.len(), |i| i + 1);
+
+        let settling_samples = trace
+            .iter()
+            .rposition(|&y| !within(y))
+            .map_or(0, |i| (i + 2).min(trace.len()));
+
+        // Overshoot is measured in the direction of the step, so negative steps work too.
+        let direction = amplitude.signum();
+        let peak_past = trace
+            .iter()
+            .map(|&y| (y - amplitude) * direction)
+            .fold(0.0, f64::max);
+        let overshoot_percent = if amplitude != 0.0 {
+            peak_past / amplitude.abs() * 100.0
+        } else {
+            0.0
+        };
+
+        let ring_count = trace
+            .windows(2)
+            .filter(|w| (w[0] - amplitude).signum() * (w[1] - amplitude).signum() < 0.0)
+            .count();
+
+        Self {
+            lag_secs: to_secs(lag_samples),
+            overshoot_percent,
+            settling_time_secs: to_secs(settling_samples),
+            ring_count,
+        }
+    }
+}
+
+/// A single point of a frequency (Bode) response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrequencyPoint {
+    pub frequency_hz: f64,
+    // Linear gain - 1.0 passes the frequency through untouched.
+    pub magnitude: f64,
+    pub magnitude_db: f64,
+    // Negative phase means the output trails the input.
+    pub phase_rad: f64,
+}
+
+// The cutoff a one euro filter settles on while the signal moves at a steady speed (units per
+// second). At rest this is just min_cutoff_hz.
+pub fn effective_cutoff_hz(settings: &FinalTuningSettings, speed: f64) -> f64 {
+    settings.min_cutoff_hz + settings.beta * speed.abs()
+}
+
+// Computes the magnitude and phase response of a one euro filter at a fixed speed operating
+// point. Once the speed is fixed the one euro filter is an ordinary first order low pass, so this
+// is exact for steady motion and a good guide otherwise.
+pub fn frequency_response(
+    settings: &FinalTuningSettings,
+    sample_rate: f64,
+    speed: f64,
+    frequencies_hz: &[f64],
+) -> Vec<FrequencyPoint> {
+    let cutoff = effective_cutoff_hz(settings, speed);
+    let te = 1.0 / sample_rate;
+    let tau = 1.0 / (2.0 * PI * cutoff);
+    let alpha = 1.0 / (1.0 + tau / te);
+
+    frequencies_hz
+        .iter()
+        .map(|&frequency_hz| {
+            let omega = 2.0 * PI * frequency_hz / sample_rate;
+            // H(z) = alpha / (1 - (1 - alpha) z^-1)
+            let (re, im) = math::cis(-omega);
+            let z_inv = Complex::new(re, im);
+            let h = Complex::new(alpha, 0.0) / (Complex::new(1.0, 0.0) - z_inv * (1.0 - alpha));
+
+            FrequencyPoint {
+                frequency_hz,
+                magnitude: math::hypot(h.re, h.im),
+                magnitude_db: 20.0 * math::log10(math::hypot(h.re, h.im)),
+                phase_rad: math::atan2(h.im, h.re),
+            }
+        })
+        .collect()
+}
+
+// Once the derivative burst from a step has decayed to this fraction of min_cutoff_hz, the
+// filter is treated as a plain first order low pass.
+const BURST_NEGLIGIBLE: f64 = 1e-3;
+
+fn alpha(sample_rate: f64, cutoff_hz: f64) -> f64 {
+    let tau = 1.0 / (2.0 * PI * cutoff_hz);
+    1.0 / (1.0 + tau * sample_rate)
+}
+
+// Time for a one euro filter resting at zero to get within target_precision of a step to
+// amplitude, without simulating the filter.
+//
+// A step produces a single derivative sample, which the derivative low pass turns into a
+// geometrically decaying burst - so the cutoff at every sample is known up front, and the error
+// shrinks by (1 - alpha) each sample. The burst is walked until it's negligible, after which the
+// remaining samples come from a log. Matches Tuner::lag_s to within a sample.
+pub fn step_lag_secs(
+    settings: &FinalTuningSettings,
+    d_cutoff_hz: f64,
+    sample_rate: f64,
+    amplitude: f64,
+    target_precision: f64,
+) -> f64 {
+    let alpha_d = alpha(sample_rate, d_cutoff_hz);
+    let mut edx = alpha_d * amplitude.abs() * sample_rate;
+    let mut error = amplitude.abs();
+    let mut samples = 0;
+
+    loop {
+        if cfg!(feature = "no-panic") && samples >= MAX_STEP_SAMPLES {
+            return f64::INFINITY;
+        }
+        samples += 1;
+        let burst = settings.beta * edx;
+        error *= 1.0 - alpha(sample_rate, settings.min_cutoff_hz + burst);
+        edx *= 1.0 - alpha_d;
+
+        if error < target_precision {
+            return samples as f64 / sample_rate;
+        }
+        if burst < BURST_NEGLIGIBLE * settings.min_cutoff_hz {
+            break;
+        }
+    }
+
+    let decay = 1.0 - alpha(sample_rate, settings.min_cutoff_hz);
+    let remaining = (math::ln(target_precision / error) / math::ln(decay)).floor() + 1.0;
+    (samples as f64 + remaining) / sample_rate
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_ringing_trace() {
+        let trace = [0.5, 0.92, 1.2, 0.95, 1.05, 1.0, 1.0];
+        let response = StepResponse::from_trace(&trace, 1.0, 10.0, 0.1);
+
+        assert_eq!(response.lag_secs, 0.2);
+        assert!((response.overshoot_percent - 20.0).abs() < 1e-9);
+        assert_eq!(response.settling_time_secs, 0.4);
+        assert_eq!(response.ring_count, 3);
+    }
+
+    #[test]
+    pub fn test_step_lag_model() {
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 0.8,
+            beta: 0.004,
+        };
+        let model = step_lag_secs(&settings, 1.0, 60.0, 100.0, 1.0);
+        let trace = simulate_step_response(settings.min_cutoff_hz, settings.beta, 1.0, 60.0, 100.0);
+        let simulated = trace.analyze(1.0).lag_secs;
+
+        assert!(
+            (model - simulated).abs() <= 1.0 / 60.0,
+            "{model} vs {simulated}"
+        );
+    }
+
+    #[test]
+    pub fn test_simulate_step_response() {
+        let trace = simulate_step_response(1.0, 0.0, 1.0, 60.0, 10.0);
+        assert_eq!(trace.trace.len(), 300);
+        assert_eq!((trace.amplitude, trace.sample_rate), (10.0, 60.0));
+
+        // With beta at zero it's a plain low pass: the first sample moves alpha of the way, and
+        // from there it closes in from below without passing the step.
+        assert!(
+            (trace.trace[0] - 10.0 * alpha(60.0, 1.0)).abs() < 1e-9,
+            "{}",
+            trace.trace[0]
+        );
+        assert!(trace.trace.windows(2).all(|w| w[0] < w[1] && w[1] <= 10.0));
+        assert!((trace.trace[299] - 10.0).abs() < 1e-6);
+
+        // Negative steps mirror positive ones.
+        let negative = simulate_step_response(1.0, 0.0, 1.0, 60.0, -10.0);
+        assert!(negative
+            .trace
+            .iter()
+            .zip(&trace.trace)
+            .all(|(n, p)| *n == -*p));
+        assert_eq!(
+            negative.analyze(0.1),
+            StepResponse::from_trace(&trace.trace, 10.0, 60.0, 0.1)
+        );
+    }
+
+    #[test]
+    pub fn test_frequency_response() {
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 1.0,
+            beta: 0.1,
+        };
+        let frequencies = [0.0, 1.0, 30.0];
+        let at_rest = frequency_response(&settings, 60.0, 0.0, &frequencies);
+        assert_eq!(at_rest.len(), 3);
+
+        // DC passes untouched, the cutoff is roughly 3 db down (a little more once discretized) and
+        // Nyquist gets alpha / (2 - alpha).
+        assert!((at_rest[0].magnitude - 1.0).abs() < 1e-12);
+        assert!(at_rest[0].phase_rad.abs() < 1e-12);
+        assert!(
+            (at_rest[1].magnitude_db + 3.0).abs() < 0.5,
+            "{:?}",
+            at_rest[1]
+        );
+        assert!(at_rest[1].phase_rad < 0.0);
+        let a = alpha(60.0, 1.0);
+        assert!((at_rest[2].magnitude - a / (2.0 - a)).abs() < 1e-12);
+
+        // Moving opens the filter up, passing more of the same frequency with less delay.
+        assert_eq!(effective_cutoff_hz(&settings, -20.0), 3.0);
+        let moving = frequency_response(&settings, 60.0, 20.0, &frequencies);
+        assert!(moving[1].magnitude > at_rest[1].magnitude);
+        assert!(moving[1].phase_rad > at_rest[1].phase_rad);
+    }
+}
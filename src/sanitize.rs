@@ -0,0 +1,156 @@
+use std::{error::Error, fmt};
+
+use crate::{calibrator::CalibrationStage, filter::MultiAxisFilter};
+
+/// What to do with a sample that's out of range or not a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SanitizeAction {
+    // Pull out of range values back to the nearest bound. Non finite values can't be clamped,
+    // so the sample is dropped instead.
+    Clamp,
+    // Skip the whole sample, as if it never arrived.
+    Drop,
+    // Hand back a SanitizeError, for callers that want to know.
+    Error,
+}
+
+/// A valid range for incoming samples and what to do outside it, shared by everything in a
+/// pipeline - calibration and filtering - so corrupt spikes from flaky drivers are treated the
+/// same everywhere. Non finite values are always invalid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SanitizePolicy {
+    pub min: f64,
+    pub max: f64,
+    pub action: SanitizeAction,
+}
+
+impl Default for SanitizePolicy {
+    // Only drops NaN and infinities.
+    fn default() -> Self {
+        Self::new(f64::NEG_INFINITY, f64::INFINITY, SanitizeAction::Drop)
+    }
+}
+
+/// A sample that failed a SanitizePolicy with SanitizeAction::Error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SanitizeError {
+    pub axis: usize,
+    pub value: f64,
+}
+
+impl fmt::Display for SanitizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "axis {}: invalid sample value {}", self.axis, self.value)
+    }
+}
+
+impl Error for SanitizeError {}
+
+impl SanitizePolicy {
+    pub fn new(min: f64, max: f64, action: SanitizeAction) -> Self {
+        Self { min, max, action }
+    }
+
+    // Every value within range, i.e. a sensor's full scale.
+    pub fn clamp(min: f64, max: f64) -> Self {
+        Self::new(min, max, SanitizeAction::Clamp)
+    }
+
+    pub fn drop(min: f64, max: f64) -> Self {
+        Self::new(min, max, SanitizeAction::Drop)
+    }
+
+    pub fn error(min: f64, max: f64) -> Self {
+        Self::new(min, max, SanitizeAction::Error)
+    }
+
+    // The sample to use, or None if it should be skipped.
+    pub fn apply<const D: usize>(
+        &self,
+        sample: [f64; D],
+    ) -> Result<Option<[f64; D]>, SanitizeError> {
+        let Some((axis, &value)) = sample
+            .iter()
+            .enumerate()
+            .find(|(_, value)| !self.is_valid(**value))
+        else {
+            return Ok(Some(sample));
+        };
+
+        match self.action {
+            SanitizeAction::Error => Err(SanitizeError { axis, value }),
+            SanitizeAction::Drop => Ok(None),
+            SanitizeAction::Clamp => {
+                if sample.iter().any(|value| value.is_nan()) {
+                    return Ok(None);
+                }
+                // Infinities only survive clamping when the range is unbounded on that side.
+                let clamped = sample.map(|value| value.clamp(self.min, self.max));
+                Ok(clamped
+                    .iter()
+                    .all(|value| value.is_finite())
+                    .then_some(clamped))
+            }
+        }
+    }
+
+    fn is_valid(&self, value: f64) -> bool {
+        value.is_finite() && value >= self.min && value <= self.max
+    }
+}
+
+impl CalibrationStage {
+    // process, with the sample run through a policy first. Skipped samples don't count towards
+    // calibration, and return false.
+    pub fn process_sanitized(
+        &mut self,
+        policy: &SanitizePolicy,
+        x: f64,
+        y: f64,
+        z: f64,
+    ) -> Result<bool, SanitizeError> {
+        Ok(match policy.apply([x, y, z])? {
+            Some([x, y, z]) => self.process(x, y, z),
+            None => false,
+        })
+    }
+}
+
+impl<const D: usize> MultiAxisFilter<D> {
+    // filter, with the sample run through a policy first. Skipped samples leave the filter
+    // untouched and return None - use filter_with_dt on the next one if timing matters.
+    pub fn filter_sanitized(
+        &mut self,
+        policy: &SanitizePolicy,
+        sample: [f64; D],
+    ) -> Result<Option<[f64; D]>, SanitizeError> {
+        Ok(policy.apply(sample)?.map(|sample| self.filter(sample)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_sanitize_actions() {
+        let spike = [0.5, 1e9, 0.0];
+
+        let clamp = SanitizePolicy::clamp(-10.0, 10.0);
+        assert_eq!(clamp.apply(spike), Ok(Some([0.5, 10.0, 0.0])));
+        assert_eq!(clamp.apply([f64::NAN, 0.0, 0.0]), Ok(None));
+
+        assert_eq!(SanitizePolicy::drop(-10.0, 10.0).apply(spike), Ok(None));
+        assert_eq!(
+            SanitizePolicy::error(-10.0, 10.0).apply(spike),
+            Err(SanitizeError {
+                axis: 1,
+                value: 1e9
+            })
+        );
+
+        let policy = SanitizePolicy::default();
+        assert_eq!(policy.apply(spike), Ok(Some(spike)));
+        assert_eq!(policy.apply([f64::INFINITY]), Ok(None));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{
+    calibrator::{CalibrationStage, TuningSettings},
+    profile::CalibrationProfile,
+    units::{DeviceScale, PrecisionTarget, Seconds},
+};
+
+/// The monitor a screen space device (eye tracker, light gun, touch panel) points at. Sizes
+/// are in physical pixels, alongside the OS scale factor - physical pixels per logical pixel,
+/// i.e. 2.0 on most high DPI laptops.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MonitorGeometry {
+    pub width_px: f64,
+    pub height_px: f64,
+    pub width_mm: f64,
+    pub height_mm: f64,
+    pub scale_factor: f64,
+}
+
+impl MonitorGeometry {
+    pub fn new(width_px: f64, height_px: f64, width_mm: f64, height_mm: f64) -> Self {
+        Self {
+            width_px,
+            height_px,
+            width_mm,
+            height_mm,
+            scale_factor: 1.0,
+        }
+    }
+
+    pub fn with_scale_factor(mut self, scale_factor: f64) -> Self {
+        self.scale_factor = scale_factor;
+        self
+    }
+
+    // Logical pixels per millimeter, averaged over both axis as panels are rarely exactly
+    // square.
+    pub fn logical_pixels_per_mm(&self) -> f64 {
+        (self.width_px / self.width_mm + self.height_px / self.height_mm)
+            / (2.0 * self.scale_factor)
+    }
+
+    // Physical dots per inch.
+    pub fn dpi(&self) -> f64 {
+        self.logical_pixels_per_mm() * self.scale_factor * 25.4
+    }
+}
+
+/// How a screen space device's units land on the monitor. Calibration runs in logical pixels -
+/// the units UI layouts and target sizes are in - so noise, amplitude and precision targets all
+/// agree no matter what the device reports in or how the OS scales the display.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScreenScale {
+    // Physical pixels per device unit.
+    pub pixels_per_unit: f64,
+    pub monitor: MonitorGeometry,
+}
+
+impl ScreenScale {
+    pub fn new(pixels_per_unit: f64, monitor: MonitorGeometry) -> Self {
+        Self {
+            pixels_per_unit,
+            monitor,
+        }
+    }
+
+    // For devices reporting 0 to 1 across the width of the screen, as most eye trackers do.
+    pub fn normalized(monitor: MonitorGeometry) -> Self {
+        Self::new(monitor.width_px, monitor)
+    }
+
+    // Device units to logical pixels.
+    pub fn to_logical(&self, units: f64) -> f64 {
+        units * self.pixels_per_unit / self.monitor.scale_factor
+    }
+
+    // Converts precision targets to logical pixels, the unit calibration runs in.
+    pub fn device_scale(&self) -> DeviceScale {
+        DeviceScale::default()
+            .with_pixels_per_unit(1.0)
+            .with_millimeters_per_unit(1.0 / self.monitor.logical_pixels_per_mm())
+    }
+}
+
+/// Two axis calibration for screen space devices, done in logical pixels. Feed it positions in
+/// device units.
+pub struct ScreenCalibrator {
+    scale: ScreenScale,
+    stage: CalibrationStage,
+}
+
+impl ScreenCalibrator {
+    pub fn new(scale: ScreenScale) -> Self {
+        Self {
+            scale,
+            // Screen space is two dimensional, see CalibrationStage::with_axes.
+            stage: CalibrationStage::with_axes(2),
+        }
+    }
+
+    // During the noise stage this returns true once noise calibration has completed.
+    pub fn process(&mut self, x: f64, y: f64) -> bool {
+        self.stage
+            .process(self.scale.to_logical(x), self.scale.to_logical(y), 0.0)
+    }
+
+    // Moves from noise to amplitude calibration, see CalibrationStage::advance.
+    pub fn next(self) -> Self {
+        Self {
+            stage: self.stage.advance(),
+            ..self
+        }
+    }
+
+    // Tuning settings in logical pixels, with the target in pixels or millimeters. None if
+    // called before amplitude calibration, or for a target in degrees.
+    pub fn tuning_settings(
+        self,
+        target: PrecisionTarget,
+        worst_lag: Seconds,
+    ) -> Option<TuningSettings> {
+        self.stage.into_amplitude()?.tuning_settings_for_target(
+            target,
+            &self.scale.device_scale(),
+            worst_lag,
+        )
+    }
+
+    pub fn scale(&self) -> &ScreenScale {
+        &self.scale
+    }
+}
+
+impl CalibrationProfile {
+    // Records the screen scale calibration ran at, so it can be checked on load.
+    pub fn with_screen(mut self, scale: ScreenScale) -> Self {
+        self.screen = Some(scale);
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{synth::GaussianNoise, tuner::FinalTuningSettings};
+
+    #[test]
+    pub fn test_screen_calibration() {
+        // A 27" 4k panel at 200% scaling, and an eye tracker reporting 0 to 1.
+        let monitor = MonitorGeometry::new(3840.0, 2160.0, 597.0, 336.0).with_scale_factor(2.0);
+        assert!((monitor.dpi() - 163.3).abs() < 0.5);
+        let scale = ScreenScale::normalized(monitor);
+        assert_eq!(scale.to_logical(0.5), 960.0);
+
+        let mut noise = GaussianNoise::new(0.001, 43);
+        let mut calibrator = ScreenCalibrator::new(scale);
+        while !calibrator.process(0.5 + noise.sample(), 0.5 + noise.sample()) {}
+        // Another twenty seconds, so the estimate is tight enough to check.
+        for _ in 0..1200 {
+            calibrator.process(0.5 + noise.sample(), 0.5 + noise.sample());
+        }
+        let mut calibrator = calibrator.next();
+        for i in 0..600 {
+            calibrator.process((i % 60) as f64 / 60.0, 0.5);
+        }
+
+        let settings = calibrator
+            .tuning_settings(PrecisionTarget::Millimeters(1.0), Seconds(0.08))
+            .unwrap();
+        // 1.92 logical pixels of noise.
+        let noise_px = settings.noise_variance.std_dev().0;
+        assert!((noise_px - 1.92).abs() < 0.15, "{noise_px}");
+        assert!(settings.max_amplitude > 1800.0);
+        // A millimeter is about 3.2 logical pixels on this panel.
+        assert!((settings.max_target_precision - 3.2 / 3.0).abs() < 0.05);
+
+        let tuning = FinalTuningSettings {
+            min_cutoff_hz: 1.0,
+            beta: 0.01,
+        };
+        let profile = CalibrationProfile::new("tracker", &settings, tuning).with_screen(scale);
+        assert!(profile.check_screen(60.0, &scale).is_empty());
+        let unscaled = ScreenScale::normalized(monitor.with_scale_factor(1.0));
+        assert_eq!(profile.check_screen(60.0, &unscaled).len(), 1);
+    }
+}
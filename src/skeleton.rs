@@ -0,0 +1,147 @@
+use crate::{
+    calibrator::{AmplitudeCalibrator, NoiseCalibrator, StartCalibration},
+    filter::ThreeAxisFilter,
+    tuner::FinalTuningSettings,
+    units::Seconds,
+};
+
+pub type Point3 = [f64; 3];
+
+/// Calibrates a whole skeleton (i.e. the 21 joints of a hand) at once. Every joint comes from the
+/// same tracker, so noise is estimated once on a reference joint and shared, while amplitude is
+/// estimated per joint - fingertips move a lot further and faster than the wrist.
+pub struct SkeletonNoiseCalibrator {
+    joint_count: usize,
+    reference_joint: usize,
+    noise: Box<NoiseCalibrator>,
+}
+
+pub struct SkeletonAmplitudeCalibrator {
+    joints: Vec<AmplitudeCalibrator>,
+}
+
+impl SkeletonNoiseCalibrator {
+    // The reference joint should be one that stays put while the user holds still - the wrist
+    // or pelvis usually.
+    pub fn new(joint_count: usize, reference_joint: usize) -> Self {
+        Self {
+            joint_count,
+            reference_joint,
+            noise: Box::new(StartCalibration::new().first_stage()),
+        }
+    }
+
+    // Processes one frame of joints. Returns true when noise calibration has completed.
+    pub fn process_noise(&mut self, joints: &[Point3]) -> bool {
+        match joints.get(self.reference_joint) {
+            Some(&[x, y, z]) => self.noise.process_noise(x, y, z),
+            None => false,
+        }
+    }
+
+    pub fn next(self) -> SkeletonAmplitudeCalibrator {
+        let shared = self.noise.next();
+        let joints = (0..self.joint_count).map(|_| shared.fork()).collect();
+
+        SkeletonAmplitudeCalibrator { joints }
+    }
+}
+
+impl SkeletonAmplitudeCalibrator {
+    pub fn process_amplitude(&mut self, joints: &[Point3]) {
+        for (calibrator, &[x, y, z]) in self.joints.iter_mut().zip(joints) {
+            calibrator.process_amplitude(x, y, z);
+        }
+    }
+
+    // Tunes every joint, returning None if any joint couldn't be tuned.
+    pub fn tune(self, least_precision: f64, worst_lag: Seconds) -> Option<SkeletonFilter> {
+        let mut sample_rate = 0.0;
+        let mut settings = Vec::with_capacity(self.joints.len());
+
+        for joint in self.joints {
+            let mut tuner = joint.tuner(least_precision, worst_lag);
+            sample_rate = tuner.settings.sample_rate.0;
+            settings.push(tuner.tune()?);
+        }
+
+        Some(SkeletonFilter::new(sample_rate, &settings))
+    }
+}
+
+/// A bank of three axis filters, one per joint, each with its own tuning.
+pub struct SkeletonFilter {
+    joints: Vec<ThreeAxisFilter>,
+}
+
+impl SkeletonFilter {
+    pub fn new(sample_rate: f64, settings: &[FinalTuningSettings]) -> Self {
+        Self {
+            joints: settings
+                .iter()
+                .map(|settings| ThreeAxisFilter::new(sample_rate, settings))
+                .collect(),
+        }
+    }
+
+    // Filters one frame of joints. The frame should have one point per joint - any extra points
+    // are ignored.
+    pub fn filter(&mut self, joints: &[Point3]) -> Vec<Point3> {
+        self.joints
+            .iter_mut()
+            .zip(joints)
+            .map(|(filter, &point)| filter.filter(point))
+            .collect()
+    }
+
+    // Resets every joint, i.e. when tracking of the skeleton is lost.
+    pub fn reset(&mut self) {
+        self.joints.iter_mut().for_each(ThreeAxisFilter::reset);
+    }
+
+    pub fn joint_count(&self) -> usize {
+        self.joints.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+
+    #[test]
+    pub fn test_skeleton() {
+        let mut noise = GaussianNoise::new(0.5, 41);
+        let mut calibrator = SkeletonNoiseCalibrator::new(3, 0);
+        // A frame that lost the reference joint can't count.
+        assert!(!calibrator.process_noise(&[]));
+        let mut frame = || [0; 3].map(|_| [noise.sample(), noise.sample(), noise.sample()]);
+        while !calibrator.process_noise(&frame()) {}
+
+        // The fingertip (joint 2) moves twenty times as far per frame as the wrist (joint 0).
+        let mut calibrator = calibrator.next();
+        for i in 0..120 {
+            let position = if i % 2 == 0 { 0.0 } else { 1.0 };
+            calibrator.process_amplitude(&[
+                [position; 3],
+                [position * 5.0; 3],
+                [position * 20.0; 3],
+            ]);
+        }
+        let mut filter = calibrator.tune(10.0, Seconds(0.08)).unwrap();
+        assert_eq!(filter.joint_count(), 3);
+
+        // The same step through every joint comes out differently, as each joint has its own
+        // tuning. Extra points in a frame are ignored, and a short frame filters only its joints.
+        let rest = filter.filter(&[[0.0; 3]; 4]);
+        assert_eq!(rest.len(), 3);
+        let step = filter.filter(&[[10.0; 3]; 3]);
+        assert_ne!(step[0], step[2]);
+        assert_eq!(filter.filter(&[[10.0; 3]; 2]).len(), 2);
+
+        // After a reset, each joint starts over as if new.
+        filter.reset();
+        assert_eq!(filter.filter(&[[0.0; 3]; 3]), rest);
+        assert_eq!(filter.filter(&[[10.0; 3]; 3]), step);
+    }
+}
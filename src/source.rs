@@ -0,0 +1,203 @@
+use std::sync::mpsc::Receiver;
+
+use crate::{
+    calibrator::CalibrationStage,
+    filter::MultiAxisFilter,
+    gapfill::{GapFilledSource, GapFiller},
+    io::Recording,
+    units::Seconds,
+};
+
+/// When a sample was taken, in seconds from whatever origin the device uses. Only differences
+/// between timestamps matter.
+pub type Timestamp = Seconds;
+
+/// Anything that produces timestamped D axis samples - a device driver, a channel fed by one, a
+/// recording. The standard way for devices to plug into calibration and filtering, see
+/// CalibrationStage::drain and SampleSource::filtered.
+pub trait SampleSource<const D: usize> {
+    // The next sample, or None if there isn't one right now. Sources fed in real time may have
+    // more later - draining just stops at None, and can be picked up again next frame.
+    fn next_sample(&mut self) -> Option<(Timestamp, [f64; D])>;
+
+    // Filters samples as they're pulled, yielding the filtered sample with its timestamp.
+    fn filtered(self, filter: MultiAxisFilter<D>) -> FilteredSource<Self, D>
+    where
+        Self: Sized,
+    {
+        FilteredSource {
+            source: self,
+            filter,
+            last: None,
+        }
+    }
+
+    // Fills in samples lost to dropouts, see GapFiller.
+    fn gap_filled(self, filler: GapFiller<D>) -> GapFilledSource<Self, D>
+    where
+        Self: Sized,
+    {
+        GapFilledSource::new(self, filler)
+    }
+}
+
+// Non blocking - an empty or disconnected channel is just out of samples for now.
+impl<const D: usize> SampleSource<D> for Receiver<(Timestamp, [f64; D])> {
+    fn next_sample(&mut self) -> Option<(Timestamp, [f64; D])> {
+        self.try_recv().ok()
+    }
+}
+
+/// Any iterator of timestamped samples.
+pub struct IterSource<I>(pub I);
+
+impl<I: Iterator<Item = (Timestamp, [f64; D])>, const D: usize> SampleSource<D> for IterSource<I> {
+    fn next_sample(&mut self) -> Option<(Timestamp, [f64; D])> {
+        self.0.next()
+    }
+}
+
+/// An iterator of untimestamped samples at a fixed rate, timestamped from zero.
+pub struct FixedRateSource<I> {
+    samples: I,
+    period: f64,
+    index: u64,
+}
+
+impl<I> FixedRateSource<I> {
+    pub fn new(samples: I, sample_rate: f64) -> Self {
+        Self {
+            samples,
+            period: 1.0 / sample_rate,
+            index: 0,
+        }
+    }
+}
+
+impl<I: Iterator<Item = [f64; D]>, const D: usize> SampleSource<D> for FixedRateSource<I> {
+    fn next_sample(&mut self) -> Option<(Timestamp, [f64; D])> {
+        let sample = self.samples.next()?;
+        let timestamp = Seconds(self.index as f64 * self.period);
+        self.index += 1;
+        Some((timestamp, sample))
+    }
+}
+
+/// A closure polled for each sample, i.e. wrapping a driver's read call.
+pub struct CallbackSource<F>(pub F);
+
+impl<F: FnMut() -> Option<(Timestamp, [f64; D])>, const D: usize> SampleSource<D>
+    for CallbackSource<F>
+{
+    fn next_sample(&mut self) -> Option<(Timestamp, [f64; D])> {
+        (self.0)()
+    }
+}
+
+/// A source run through a filter, see SampleSource::filtered. Timestamps drive the filter's dt,
+/// so dropped or late samples are smoothed over the time that actually passed.
+pub struct FilteredSource<S, const D: usize> {
+    source: S,
+    filter: MultiAxisFilter<D>,
+    last: Option<f64>,
+}
+
+impl<S, const D: usize> FilteredSource<S, D> {
+    pub fn filter(&self) -> &MultiAxisFilter<D> {
+        &self.filter
+    }
+
+    // For retuning in place.
+    pub fn filter_mut(&mut self) -> &mut MultiAxisFilter<D> {
+        &mut self.filter
+    }
+
+    pub fn into_inner(self) -> (S, MultiAxisFilter<D>) {
+        (self.source, self.filter)
+    }
+}
+
+impl<S: SampleSource<D>, const D: usize> SampleSource<D> for FilteredSource<S, D> {
+    fn next_sample(&mut self) -> Option<(Timestamp, [f64; D])> {
+        let (timestamp, sample) = self.source.next_sample()?;
+        // Out of order or repeated timestamps fall back to the nominal rate.
+        let filtered = match self.last.replace(timestamp.0) {
+            Some(last) if timestamp.0 > last => {
+                self.filter.filter_with_dt(sample, timestamp.0 - last)
+            }
+            _ => self.filter.filter(sample),
+        };
+        Some((timestamp, filtered))
+    }
+}
+
+impl CalibrationStage {
+    // Processes samples until the source runs dry, or the noise stage completes - so the rest
+    // can go to the amplitude stage once advanced. Returns what process returned for the last
+    // sample, or false if there wasn't one.
+    pub fn drain<S: SampleSource<3>>(&mut self, source: &mut S) -> bool {
+        let mut complete = false;
+        while let Some((_, [x, y, z])) = source.next_sample() {
+            complete = self.process(x, y, z);
+            if complete && self.is_noise() {
+                break;
+            }
+        }
+        complete
+    }
+}
+
+impl<const D: usize> Recording<D> {
+    pub fn source(&self) -> impl SampleSource<D> + '_ {
+        IterSource(
+            self.timestamps
+                .iter()
+                .zip(self.samples.iter())
+                .map(|(&timestamp, &sample)| (Seconds(timestamp), sample)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{synth::GaussianNoise, tuner::FinalTuningSettings};
+    use std::sync::mpsc;
+
+    #[test]
+    pub fn test_sources() {
+        let mut noise = GaussianNoise::new(0.5, 41);
+        let samples: Vec<[f64; 3]> = (0..20_000)
+            .map(|_| [noise.sample(), noise.sample(), noise.sample()])
+            .collect();
+
+        let mut stage = CalibrationStage::new();
+        let mut source = FixedRateSource::new(samples.into_iter(), 60.0);
+        assert!(stage.drain(&mut source));
+        let stage = stage.advance();
+        assert!(!stage.is_noise());
+        assert!(source.next_sample().is_some());
+
+        let (sender, receiver) = mpsc::channel();
+        sender.send((Seconds(0.0), [1.0, 2.0])).unwrap();
+        sender.send((Seconds(0.1), [3.0, 4.0])).unwrap();
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 1.0,
+            beta: 0.0,
+        };
+        let mut filtered = receiver.filtered(MultiAxisFilter::new(60.0, &settings));
+        assert_eq!(filtered.next_sample(), Some((Seconds(0.0), [1.0, 2.0])));
+        // A tenth of a second at 1 hz moves well over the 1/60th a fixed rate step would.
+        let (_, [x, _]) = filtered.next_sample().unwrap();
+        assert!(x > 1.5 && x < 3.0);
+        assert_eq!(filtered.next_sample(), None);
+
+        let mut count = 0;
+        let mut callback = CallbackSource(|| {
+            count += 1;
+            (count <= 3).then_some((Seconds(count as f64), [0.0]))
+        });
+        while callback.next_sample().is_some() {}
+        assert_eq!(count, 4);
+    }
+}
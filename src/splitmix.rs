@@ -0,0 +1,29 @@
+//! The uniform random source behind synth::GaussianNoise and the table generator, kept in one
+//! place so generated tables and the synthetic signals they're checked against can't drift
+//! apart. build.rs includes this file by path alongside src/tablegen.rs, so it can't use
+//! anything else from the crate.
+
+/// splitmix64. We don't need cryptographic quality here, and it's tiny.
+#[derive(Debug, Clone)]
+pub(crate) struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub(crate) const fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in (0, 1] - never zero so it's safe to take the log of.
+    pub(crate) fn next_unit(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+}
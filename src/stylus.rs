@@ -0,0 +1,187 @@
+use crate::{
+    calibrator::CalibrationStage, filter::MultiAxisFilter, tuner::FinalTuningSettings,
+    units::Seconds,
+};
+
+// Pressure is reported normalized to 0..1, but noise that small falls off the bottom of the
+// precision table. Working in percent puts it in the same range as positional noise in pixels.
+const PRESSURE_SCALE: f64 = 100.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StylusSample {
+    // Pixels.
+    pub x: f64,
+    pub y: f64,
+    // Normalized 0..1.
+    pub pressure: f64,
+    // Degrees.
+    pub tilt_x: f64,
+    pub tilt_y: f64,
+}
+
+/// Least precision for each kind of stylus axis, each in its own units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StylusPrecision {
+    pub position_px: f64,
+    // Normalized 0..1, like the samples.
+    pub pressure: f64,
+    pub tilt_degrees: f64,
+}
+
+impl Default for StylusPrecision {
+    fn default() -> Self {
+        Self {
+            position_px: 3.0,
+            pressure: 0.01,
+            tilt_degrees: 1.0,
+        }
+    }
+}
+
+/// Calibrates position, pressure and tilt separately. Pressure noise comes from a completely
+/// different sensor than position, so sharing one calibration across them makes no sense.
+///
+/// For the noise stage the pen should be resting on the surface with steady pressure.
+pub struct StylusCalibrator {
+    position: CalibrationStage,
+    pressure: CalibrationStage,
+    tilt: CalibrationStage,
+}
+
+impl StylusCalibrator {
+    // Each group only estimates noise from the axis it has, see CalibrationStage::with_axes.
+    pub fn new() -> Self {
+        Self {
+            position: CalibrationStage::with_axes(2),
+            pressure: CalibrationStage::with_axes(1),
+            tilt: CalibrationStage::with_axes(2),
+        }
+    }
+
+    // Returns true once every axis group has enough data for the current stage.
+    pub fn process(&mut self, sample: &StylusSample) -> bool {
+        let position = self.position.process(sample.x, sample.y, 0.0);
+        let pressure = self
+            .pressure
+            .process(sample.pressure * PRESSURE_SCALE, 0.0, 0.0);
+        let tilt = self.tilt.process(sample.tilt_x, sample.tilt_y, 0.0);
+
+        position && pressure && tilt
+    }
+
+    pub fn next(self) -> Self {
+        Self {
+            position: self.position.advance(),
+            pressure: self.pressure.advance(),
+            tilt: self.tilt.advance(),
+        }
+    }
+
+    // Tunes each axis group against its own precision target. Returns None if called before the
+    // amplitude stage or if any group can't be tuned.
+    pub fn tune(self, precision: StylusPrecision, worst_lag: Seconds) -> Option<StylusFilter> {
+        let mut position = self
+            .position
+            .into_amplitude()?
+            .tuner(precision.position_px, worst_lag);
+        let mut pressure = self
+            .pressure
+            .into_amplitude()?
+            .tuner(precision.pressure * PRESSURE_SCALE, worst_lag);
+        let mut tilt = self
+            .tilt
+            .into_amplitude()?
+            .tuner(precision.tilt_degrees, worst_lag);
+
+        Some(StylusFilter::new(
+            position.settings.sample_rate.0,
+            &position.tune()?,
+            &pressure.tune()?,
+            &tilt.tune()?,
+        ))
+    }
+}
+
+impl Default for StylusCalibrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Filters a five axis pen stream with separate tuning for position, pressure and tilt.
+pub struct StylusFilter {
+    position: MultiAxisFilter<2>,
+    pressure: MultiAxisFilter<1>,
+    tilt: MultiAxisFilter<2>,
+}
+
+impl StylusFilter {
+    pub fn new(
+        sample_rate: f64,
+        position: &FinalTuningSettings,
+        pressure: &FinalTuningSettings,
+        tilt: &FinalTuningSettings,
+    ) -> Self {
+        Self {
+            position: MultiAxisFilter::new(sample_rate, position),
+            pressure: MultiAxisFilter::new(sample_rate, pressure),
+            tilt: MultiAxisFilter::new(sample_rate, tilt),
+        }
+    }
+
+    pub fn filter(&mut self, sample: &StylusSample) -> StylusSample {
+        let [x, y] = self.position.filter([sample.x, sample.y]);
+        let [pressure] = self.pressure.filter([sample.pressure * PRESSURE_SCALE]);
+        let [tilt_x, tilt_y] = self.tilt.filter([sample.tilt_x, sample.tilt_y]);
+
+        StylusSample {
+            x,
+            y,
+            pressure: (pressure / PRESSURE_SCALE).clamp(0.0, 1.0),
+            tilt_x,
+            tilt_y,
+        }
+    }
+
+    // Resets all axis, i.e. when the pen leaves proximity.
+    pub fn reset(&mut self) {
+        self.position.reset();
+        self.pressure.reset();
+        self.tilt.reset();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::GaussianNoise;
+
+    #[test]
+    pub fn test_stylus_noise_per_group() {
+        // Half a pixel of position jitter, 0.2% pressure jitter and a tenth of a degree of tilt.
+        let mut noise = GaussianNoise::new(1.0, 101);
+        let mut sample = || StylusSample {
+            x: 400.0 + 0.5 * noise.sample(),
+            y: 300.0 + 0.5 * noise.sample(),
+            pressure: 0.5 + 0.002 * noise.sample(),
+            tilt_x: 20.0 + 0.1 * noise.sample(),
+            tilt_y: -10.0 + 0.1 * noise.sample(),
+        };
+        let mut calibrator = StylusCalibrator::new();
+        while !calibrator.process(&sample()) {}
+        // Another ten seconds, to tighten every group's estimate up.
+        for _ in 0..600 {
+            calibrator.process(&sample());
+        }
+        let calibrator = calibrator.next();
+
+        let noise_std_dev =
+            |stage: CalibrationStage| stage.into_amplitude().unwrap().noise_std_dev().0;
+        let position = noise_std_dev(calibrator.position);
+        let pressure = noise_std_dev(calibrator.pressure) / PRESSURE_SCALE;
+        let tilt = noise_std_dev(calibrator.tilt);
+        assert!((position - 0.5).abs() < 0.075, "{position}");
+        assert!((pressure - 0.002).abs() < 0.0003, "{pressure}");
+        assert!((tilt - 0.1).abs() < 0.015, "{tilt}");
+    }
+}
@@ -0,0 +1,294 @@
+//! Generates precision tables like the hard coded 60 hz one, for any sample rate.
+//!
+//! Each node is the standard deviation of white noise after going through a one euro filter with
+//! that node's parameters, measured by simulation. Node spacing matches what Grid::precision
+//! looks up, so generated tables drop straight in with Grid::new.
+//!
+//! This file is also compiled into build.rs (for the build-tables feature), so it can't use
+//! anything else from the crate - hence its own small filter. The noise comes from
+//! src/splitmix.rs, which build.rs includes alongside it.
+
+use std::f64::consts::PI;
+
+use crate::splitmix::SplitMix64;
+
+pub const JITTER_LEVELS: usize = 16;
+pub const CUTOFF_LEVELS: usize = 199;
+pub const BETA_LEVELS: usize = 47;
+
+/// A full precision table, indexed [jitter][cutoff][beta].
+pub type Table = [[[f64; BETA_LEVELS]; CUTOFF_LEVELS]; JITTER_LEVELS];
+
+// The tuner's derivative cutoff, see filter::DERIVATIVE_CUTOFF_HZ.
+const D_CUTOFF_HZ: f64 = 1.0;
+
+// The least each node is warmed up and measured for. Nodes also run for at least as many of the
+// min cutoff's time constants as below, so high rates and low cutoffs - where these counts are
+// a fraction of one - still settle, and average over more than a little of the filter's memory.
+const WARMUP_SAMPLES: usize = 120;
+pub const DEFAULT_MEASURED_SAMPLES: usize = 1200;
+const WARMUP_TIME_CONSTANTS: f64 = 5.0;
+const MEASURED_TIME_CONSTANTS: f64 = 20.0;
+// So a cutoff of (or near) zero can't run forever.
+const MAX_SCALED_SAMPLES: f64 = 2_000_000.0;
+// Noise seed for generate and node_precision. Every node sees the same noise, which keeps the
+// table smooth across nodes - a different seed moves every node together.
+pub const DEFAULT_SEED: u64 = 0x7AB1_E5EED;
+
+// Jitter goes up in steps of 1/3, starting at 1/3.
+pub fn jitter_at(index: usize) -> f64 {
+    (index + 1) as f64 / 3.0
+}
+
+// The inverse of the lookup in Grid::precision.
+pub fn cutoff_at(index: usize) -> f64 {
+    (index as f64 + 0.05) * 0.05
+}
+
+// The start of each decade on the beta axis. Literals rather than powers of ten, so the axis is
+// exactly the same wherever it's computed.
+const BETA_DECADES: [f64; 6] = [1e-5, 1e-4, 1e-3, 1e-2, 1e-1, 1.0];
+
+// 0, then 9 per decade from 1e-5 up to 1.0 - 0.1 is index 37, 1.0 is index 46.
+pub fn beta_at(index: usize) -> f64 {
+    beta_from_index(index as f64)
+}
+
+// The beta axis is logarithmic across decades and linear within them: index 0 is beta 0, and
+// index 1 + 9d + (k - 1) is k * 10^(d - 5) for decade d from 0 to 5 and k from 1 to 9. In
+// between, beta runs linearly from one node to the next - including from 0 up to 1e-5. Betas
+// past 1.0 clamp to the last index.
+//
+// Continuous across decade boundaries, so a beta that rounds into the neighbouring decade
+// still lands on the same index.
+pub fn beta_index(beta: f64) -> f64 {
+    if beta.is_nan() || beta <= 0.0 {
+        return 0.0;
+    }
+    if beta < BETA_DECADES[0] {
+        return beta / BETA_DECADES[0];
+    }
+    if beta >= 1.0 {
+        return (BETA_LEVELS - 1) as f64;
+    }
+    let decade = BETA_DECADES
+        .iter()
+        .rposition(|&start| beta >= start)
+        .unwrap_or(0);
+    let mantissa = beta / BETA_DECADES[decade];
+    1.0 + 9.0 * decade as f64 + (mantissa - 1.0)
+}
+
+// The inverse of beta_index, for fractional indices too.
+pub fn beta_from_index(index: f64) -> f64 {
+    let index = index.clamp(0.0, (BETA_LEVELS - 1) as f64);
+    if index < 1.0 {
+        return index * BETA_DECADES[0];
+    }
+    let decade = (((index - 1.0) / 9.0).floor() as usize).min(BETA_DECADES.len() - 1);
+    let mantissa = index - 9.0 * decade as f64;
+    mantissa * BETA_DECADES[decade]
+}
+
+// The same generator as synth::GaussianNoise through Box-Muller, keeping only the cosine half.
+struct Noise(SplitMix64);
+
+impl Noise {
+    fn sample(&mut self) -> f64 {
+        (-2.0 * self.0.next_unit().ln()).sqrt() * (2.0 * PI * self.0.next_unit()).cos()
+    }
+}
+
+fn alpha(sample_rate: f64, cutoff_hz: f64) -> f64 {
+    1.0 / (1.0 + sample_rate / (2.0 * PI * cutoff_hz))
+}
+
+// Simulates a single node.
+pub fn node_precision(
+    sample_rate: f64,
+    jitter: f64,
+    cutoff_hz: f64,
+    beta: f64,
+    measured_samples: usize,
+) -> f64 {
+    node_precision_seeded(
+        sample_rate,
+        jitter,
+        cutoff_hz,
+        beta,
+        measured_samples,
+        DEFAULT_SEED,
+    )
+}
+
+// node_precision with its own noise seed. The same seed always gives the same result, on any
+// machine.
+pub fn node_precision_seeded(
+    sample_rate: f64,
+    jitter: f64,
+    cutoff_hz: f64,
+    beta: f64,
+    measured_samples: usize,
+    seed: u64,
+) -> f64 {
+    let mut noise = Noise(SplitMix64::new(seed));
+    let alpha_d = alpha(sample_rate, D_CUTOFF_HZ);
+    let time_constant_samples = sample_rate / (2.0 * PI * cutoff_hz);
+    let scaled = |time_constants: f64| {
+        (time_constants * time_constant_samples)
+            .min(MAX_SCALED_SAMPLES)
+            .ceil() as usize
+    };
+    let warmup_samples = WARMUP_SAMPLES.max(scaled(WARMUP_TIME_CONSTANTS));
+    let measured_samples = measured_samples.max(scaled(MEASURED_TIME_CONSTANTS));
+    // Start at rest on the true (zero) signal. Starting on the first noisy sample instead leaves
+    // an offset that low cutoffs never recover from within the simulation.
+    let (mut x_hat, mut dx_hat, mut x_prev) = (0.0, 0.0, 0.0);
+    let mut sum_squares = 0.0;
+
+    for i in 0..warmup_samples + measured_samples {
+        let x = jitter * noise.sample();
+        let dx = (x - x_prev) * sample_rate;
+        x_prev = x;
+
+        dx_hat = alpha_d * dx + (1.0 - alpha_d) * dx_hat;
+        let a = alpha(sample_rate, cutoff_hz + beta * dx_hat.abs());
+        x_hat = a * x + (1.0 - a) * x_hat;
+
+        if i >= warmup_samples {
+            sum_squares += x_hat * x_hat;
+        }
+    }
+
+    (sum_squares / measured_samples as f64).sqrt()
+}
+
+// Generates a full table. This is ~150k simulations - seconds in release at 60 hz, much longer
+// in debug or at high rates, where the low cutoffs run for more samples.
+pub fn generate(sample_rate: f64, measured_samples: usize) -> Box<Table> {
+    generate_seeded(sample_rate, measured_samples, DEFAULT_SEED)
+}
+
+// generate with its own noise seed, i.e. to check how much a table moves with the noise it was
+// measured on.
+pub fn generate_seeded(sample_rate: f64, measured_samples: usize, seed: u64) -> Box<Table> {
+    let mut table = Box::new([[[0.0; BETA_LEVELS]; CUTOFF_LEVELS]; JITTER_LEVELS]);
+    for (j, jitter_slice) in table.iter_mut().enumerate() {
+        for (c, cutoff_slice) in jitter_slice.iter_mut().enumerate() {
+            for (b, node) in cutoff_slice.iter_mut().enumerate() {
+                *node = node_precision_seeded(
+                    sample_rate,
+                    jitter_at(j),
+                    cutoff_at(c),
+                    beta_at(b),
+                    measured_samples,
+                    seed,
+                );
+            }
+        }
+    }
+    table
+}
+
+pub fn to_vec(table: &Table) -> Vec<Vec<Vec<f64>>> {
+    table
+        .iter()
+        .map(|jitter| jitter.iter().map(|cutoff| cutoff.to_vec()).collect())
+        .collect()
+}
+
+// Packed tables, for include_bytes! or loading at runtime without parsing - see
+// Grid::from_packed. A 32 byte header, then every node as an f64, jitter outermost and beta
+// innermost. The header is the magic, the node count along jitter, cutoff and beta as u32s, 4
+// reserved zero bytes, then the sample rate the table was generated for as an f64 (0 if
+// unknown). Everything is little endian and on its own alignment, so from 8 byte aligned memory
+// the nodes can also be cast in place.
+pub const PACKED_MAGIC: [u8; 8] = *b"PPGRID01";
+pub const PACKED_HEADER_BYTES: usize = 32;
+
+pub fn write_packed_header(out: &mut Vec<u8>, dimensions: [usize; 3], sample_rate: f64) {
+    out.extend_from_slice(&PACKED_MAGIC);
+    for len in dimensions {
+        out.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+}
+
+pub fn to_packed(table: &Table, sample_rate: f64) -> Vec<u8> {
+    let mut out = Vec::with_capacity(PACKED_HEADER_BYTES + core::mem::size_of::<Table>());
+    write_packed_header(
+        &mut out,
+        [JITTER_LEVELS, CUTOFF_LEVELS, BETA_LEVELS],
+        sample_rate,
+    );
+    for node in table.iter().flatten().flatten() {
+        out.extend_from_slice(&node.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_node_matches_simulation() {
+        // With beta at zero the filter is a plain low pass, whose output std dev on white noise
+        // is jitter * sqrt(alpha / (2 - alpha)).
+        let a = alpha(60.0, cutoff_at(20));
+        let expected = (a / (2.0 - a)).sqrt();
+        let simulated = node_precision(60.0, 1.0, cutoff_at(20), beta_at(0), 20_000);
+
+        assert!(
+            (simulated / expected - 1.0).abs() < 0.05,
+            "{simulated} vs {expected}"
+        );
+    }
+
+    #[test]
+    pub fn test_low_cutoff_nodes_settle() {
+        // At 1000 hz a 0.05 hz cutoff's time constant is ~3000 samples, so the fixed counts alone
+        // would measure the filter while it's still rising from rest.
+        let a = alpha(1000.0, cutoff_at(1));
+        let expected = (a / (2.0 - a)).sqrt();
+        let simulated = node_precision(1000.0, 1.0, cutoff_at(1), beta_at(0), 1200);
+
+        assert!(
+            (simulated / expected - 1.0).abs() < 0.15,
+            "{simulated} vs {expected}"
+        );
+    }
+
+    #[test]
+    pub fn test_seeded_nodes() {
+        let node = |seed| node_precision_seeded(60.0, 1.0, cutoff_at(20), beta_at(30), 600, seed);
+        assert_eq!(node(DEFAULT_SEED).to_bits(), node(DEFAULT_SEED).to_bits());
+        assert_eq!(
+            node(DEFAULT_SEED),
+            node_precision(60.0, 1.0, cutoff_at(20), beta_at(30), 600)
+        );
+        assert_ne!(node(1), node(2));
+    }
+
+    #[test]
+    pub fn test_beta_axis() {
+        assert_eq!(beta_at(0), 0.0);
+        assert_eq!(beta_at(1), 1e-5);
+        assert_eq!(beta_at(37), 0.1);
+        assert_eq!(beta_at(41), 0.5);
+        assert_eq!(beta_at(46), 1.0);
+
+        for index in 0..BETA_LEVELS {
+            let beta = beta_at(index);
+            assert!((beta_index(beta) - index as f64).abs() < 1e-9, "{index}");
+        }
+        for index in [0.5, 3.25, 36.9, 45.5] {
+            assert!((beta_index(beta_from_index(index)) - index).abs() < 1e-9);
+        }
+
+        assert_eq!(beta_index(0.0), 0.0);
+        assert_eq!(beta_index(5.0), 46.0);
+        assert!((beta_index(5e-6) - 0.5).abs() < 1e-12);
+    }
+}
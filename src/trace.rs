@@ -0,0 +1,146 @@
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+/// Why the tuner did or didn't take a candidate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CandidateOutcome {
+    // Predicted precision was worse than the target, so lag was never simulated.
+    ExceedsPrecision,
+    // Within the target, but didn't beat the best so far.
+    NoImprovement,
+    // Became the best so far. Later candidates may still replace it.
+    NewBest,
+}
+
+impl CandidateOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CandidateOutcome::ExceedsPrecision => "exceeds_precision",
+            CandidateOutcome::NoImprovement => "no_improvement",
+            CandidateOutcome::NewBest => "new_best",
+        }
+    }
+}
+
+/// One candidate evaluated by Tuner::tune.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceEntry {
+    // The precision target in force, which is relaxed each time a full pass finds nothing.
+    pub target_precision: f64,
+    pub min_cutoff_hz: f64,
+    pub beta: f64,
+    pub predicted_precision: f64,
+    // None when the candidate was rejected before simulating.
+    pub lag_s: Option<f64>,
+    pub outcome: CandidateOutcome,
+}
+
+/// Every candidate a tune evaluated, in search order. Enable with Tuner::with_trace.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SearchTrace {
+    pub entries: Vec<TraceEntry>,
+}
+
+impl SearchTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, entry: TraceEntry) {
+        self.entries.push(entry);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Writes a header row followed by one row per candidate. Lag is left empty when it wasn't
+    // simulated.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "target_precision,min_cutoff_hz,beta,predicted_precision,lag_s,outcome"
+        )?;
+        for entry in self.entries.iter() {
+            let lag_s = entry.lag_s.map(|lag| lag.to_string()).unwrap_or_default();
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                entry.target_precision,
+                entry.min_cutoff_hz,
+                entry.beta,
+                entry.predicted_precision,
+                lag_s,
+                entry.outcome.as_str()
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut out = Vec::new();
+        self.write_csv(&mut out)
+            .expect("writing to a Vec can't fail");
+        String::from_utf8(out).expect("csv is always utf8")
+    }
+
+    // A JSON array of candidate objects, with the same fields as the CSV. Doesn't need the serde
+    // feature.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        use serde_json::{json, Value};
+
+        let entries: Vec<Value> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                json!({
+                    "target_precision": entry.target_precision,
+                    "min_cutoff_hz": entry.min_cutoff_hz,
+                    "beta": entry.beta,
+                    "predicted_precision": entry.predicted_precision,
+                    "lag_s": entry.lag_s,
+                    "outcome": entry.outcome.as_str(),
+                })
+            })
+            .collect();
+        Value::Array(entries).to_string()
+    }
+}
+
+/// A summary of how a tune went, from Tuner::tune_with_diagnostics. Cheap enough to always
+/// collect, unlike a SearchTrace.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TuneDiagnostics {
+    pub candidates_evaluated: usize,
+    pub elapsed: Duration,
+    // How many times nothing met the precision target, and it was relaxed by a third.
+    pub relaxations: usize,
+    // The precision target the result was found at, after relaxing.
+    pub target_precision: f64,
+    // The result's lag to reach target_precision. None when nothing was found.
+    pub lag_s: Option<f64>,
+    // Whether that lag is within max_lag_secs.
+    pub lag_satisfied: bool,
+    // The search was cut short by Tuner::tune_with_budget, so a better result may exist.
+    pub budget_limited: bool,
+}
+
+impl TuneDiagnostics {
+    // The result missed what was asked of it - either the precision target had to be relaxed or
+    // nothing was fast enough - so the device probably needs recalibrating, or the targets
+    // loosening.
+    pub fn is_degenerate(&self) -> bool {
+        self.relaxations > 0 || !self.lag_satisfied
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{
+    calibrator::CalibrationStage,
+    filter::ThreeAxisFilter,
+    tuner::{FinalTuningSettings, Tuner},
+    units::Seconds,
+};
+
+/// One of the application frame's axis, as reported by the sensor, possibly flipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignedAxis {
+    PlusX,
+    MinusX,
+    PlusY,
+    MinusY,
+    PlusZ,
+    MinusZ,
+}
+
+impl SignedAxis {
+    fn row(self) -> [f64; 3] {
+        match self {
+            SignedAxis::PlusX => [1.0, 0.0, 0.0],
+            SignedAxis::MinusX => [-1.0, 0.0, 0.0],
+            SignedAxis::PlusY => [0.0, 1.0, 0.0],
+            SignedAxis::MinusY => [0.0, -1.0, 0.0],
+            SignedAxis::PlusZ => [0.0, 0.0, 1.0],
+            SignedAxis::MinusZ => [0.0, 0.0, -1.0],
+        }
+    }
+}
+
+/// Maps samples from a device's sensor frame into the application frame: out = matrix * in +
+/// offset. Covers axis swaps and flips, arbitrary rotations and a fixed offset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AxisTransform {
+    // Row major.
+    matrix: [[f64; 3]; 3],
+    offset: [f64; 3],
+}
+
+impl Default for AxisTransform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl AxisTransform {
+    pub fn identity() -> Self {
+        Self::rotation([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]])
+    }
+
+    // Each entry says where the application's x, y and z come from in the sensor frame, i.e.
+    // [PlusY, MinusX, PlusZ] turns a sensor rotated 90 degrees about z back upright.
+    pub fn remap(axes: [SignedAxis; 3]) -> Self {
+        Self::rotation(axes.map(SignedAxis::row))
+    }
+
+    // A row major rotation matrix. Nothing checks this is actually a rotation - a scaling matrix
+    // works too, but then calibration results are in the scaled units.
+    pub fn rotation(matrix: [[f64; 3]; 3]) -> Self {
+        Self {
+            matrix,
+            offset: [0.0; 3],
+        }
+    }
+
+    // Added after rotating, so it's in application frame units.
+    pub fn with_offset(self, offset: [f64; 3]) -> Self {
+        Self { offset, ..self }
+    }
+
+    // A transform applying self, then next.
+    pub fn then(&self, next: &AxisTransform) -> Self {
+        let matrix = core::array::from_fn(|row| {
+            core::array::from_fn(|col| {
+                (0..3)
+                    .map(|k| next.matrix[row][k] * self.matrix[k][col])
+                    .sum()
+            })
+        });
+        Self {
+            matrix,
+            offset: next.apply(self.offset),
+        }
+    }
+
+    pub fn apply(&self, sample: [f64; 3]) -> [f64; 3] {
+        core::array::from_fn(|row| {
+            (0..3).map(|k| self.matrix[row][k] * sample[k]).sum::<f64>() + self.offset[row]
+        })
+    }
+}
+
+/// Calibration on samples in the sensor frame, transformed into the application frame first.
+pub struct TransformedCalibrator {
+    transform: AxisTransform,
+    stage: CalibrationStage,
+}
+
+impl TransformedCalibrator {
+    pub fn new(transform: AxisTransform) -> Self {
+        Self {
+            transform,
+            stage: CalibrationStage::new(),
+        }
+    }
+
+    // Returns true once the current stage has enough data.
+    pub fn process(&mut self, sample: [f64; 3]) -> bool {
+        let [x, y, z] = self.transform.apply(sample);
+        self.stage.process(x, y, z)
+    }
+
+    pub fn next(self) -> Self {
+        Self {
+            stage: self.stage.advance(),
+            ..self
+        }
+    }
+
+    pub fn tune(self, least_precision: f64, worst_lag: Seconds) -> Option<FinalTuningSettings> {
+        let amplitude = self.stage.into_amplitude()?;
+        Tuner::new(amplitude.tuning_settings(least_precision, worst_lag)).tune()
+    }
+
+    // A filter for the same device, taking samples in the sensor frame.
+    pub fn filter(&self, sample_rate: f64, settings: &FinalTuningSettings) -> TransformedFilter {
+        TransformedFilter::new(self.transform, sample_rate, settings)
+    }
+}
+
+/// A three axis filter taking samples in the sensor frame and returning filtered samples in the
+/// application frame.
+pub struct TransformedFilter {
+    transform: AxisTransform,
+    filter: ThreeAxisFilter,
+}
+
+impl TransformedFilter {
+    pub fn new(transform: AxisTransform, sample_rate: f64, settings: &FinalTuningSettings) -> Self {
+        Self {
+            transform,
+            filter: ThreeAxisFilter::new(sample_rate, settings),
+        }
+    }
+
+    pub fn filter(&mut self, sample: [f64; 3]) -> [f64; 3] {
+        self.filter.filter(self.transform.apply(sample))
+    }
+
+    pub fn reset(&mut self) {
+        self.filter.reset();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_axis_transform() {
+        let upright =
+            AxisTransform::remap([SignedAxis::PlusY, SignedAxis::MinusX, SignedAxis::PlusZ])
+                .with_offset([1.0, 0.0, 0.0]);
+        assert_eq!(upright.apply([2.0, 3.0, 4.0]), [4.0, -2.0, 4.0]);
+
+        // Flipping z twice is a no-op, offsets included.
+        let flip = AxisTransform::remap([SignedAxis::PlusX, SignedAxis::PlusY, SignedAxis::MinusZ])
+            .with_offset([0.0, 0.0, 1.0]);
+        assert_eq!(flip.then(&flip).apply([2.0, 3.0, 4.0]), [2.0, 3.0, 4.0]);
+        assert_eq!(
+            upright.then(&flip).apply([2.0, 3.0, 4.0]),
+            flip.apply(upright.apply([2.0, 3.0, 4.0]))
+        );
+    }
+}
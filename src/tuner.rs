@@ -1,21 +1,331 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
 use one_euro_rs::OneEuroFilter;
 
-use crate::calibrator::TuningSettings;
+use crate::{
+    calibrator::TuningSettings,
+    constraints::{CandidateScore, Constraint, JointConstraints},
+    events,
+    filter::{SmoothingFilter, DERIVATIVE_CUTOFF_HZ},
+    math,
+    response::{step_lag_secs, StepResponse},
+    tablegen,
+    trace::{CandidateOutcome, SearchTrace, TraceEntry, TuneDiagnostics},
+};
+
+#[cfg(feature = "sixty-hz-table")]
+use crate::table::sixty_hz_grid;
+#[cfg(not(feature = "sixty-hz-table"))]
+use crate::velocity::{NoiseColor, VelocityPrecision};
+
+// How long step_response keeps watching the output after it first reaches the target precision.
+const SETTLE_WINDOW_SECS: f64 = 1.0;
+
+// A step that hasn't settled after this long never will in any way that matters to us.
+const MAX_STEP_SECS: f64 = 10.0;
 
-use crate::table::sixty_hz;
+// How often a budgeted tune checks the clock while simulating a single candidate, in samples.
+const DEADLINE_CHECK_SAMPLES: u64 = 64;
+
+/// Predicts precision - the standard deviation of filtered white noise - for a one euro filter
+/// with the given parameters, given the noise standard deviation (jitter) of the input.
+pub trait PrecisionModel {
+    fn precision(&self, jitter: f64, cutoff_hz: f64, beta: f64) -> f64;
+
+    // Does any setup a model leaves until first use, so it isn't charged to a budgeted tune.
+    fn prepare(&self) {}
+}
+
+impl PrecisionModel for Grid {
+    fn precision(&self, jitter: f64, cutoff_hz: f64, beta: f64) -> f64 {
+        Grid::precision(self, jitter, cutoff_hz, beta)
+    }
+}
 
 pub struct Grid {
-    table: Vec<Vec<Vec<f64>>>,
+    pub(crate) nodes: Nodes,
+    interpolation: Interpolation,
+}
+
+// Where a Grid's nodes live.
+pub(crate) enum Nodes {
+    Nested(Vec<Vec<Vec<f64>>>),
+    // Read in place from a packed table, see Grid::from_packed.
+    Packed {
+        bytes: Arc<dyn AsRef<[u8]> + Send + Sync>,
+        dimensions: [usize; 3],
+    },
+}
+
+/// How Grid::precision reads between nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    // The closest node. Cheapest, and steps at every cell boundary.
+    Nearest,
+    // Linear along each axis from the 8 surrounding nodes.
+    #[default]
+    Trilinear,
+    // Catmull-Rom along each axis from the 64 surrounding nodes. The precision surface is
+    // smooth, so this tracks it much more closely inside cells than trilinear does, with a
+    // continuous slope across boundaries - at around 8x the lookups.
+    Tricubic,
+}
+
+/// One of Grid's three axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridAxis {
+    Jitter,
+    Cutoff,
+    Beta,
+}
+
+/// A single node of a Grid, with its position on each axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridCell {
+    // [jitter, cutoff, beta].
+    pub index: [usize; 3],
+    pub jitter: f64,
+    pub cutoff_hz: f64,
+    pub beta: f64,
+    pub precision: f64,
 }
 
 impl Grid {
     pub fn new(table: Vec<Vec<Vec<f64>>>) -> Self {
-        Self { table }
+        Self::with_nodes(Nodes::Nested(table))
+    }
+
+    pub(crate) fn with_nodes(nodes: Nodes) -> Self {
+        Self {
+            nodes,
+            interpolation: Interpolation::default(),
+        }
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    // Nodes along each axis, as [jitter, cutoff, beta]. Taken from the first row of each, so
+    // ragged tables report their first row.
+    pub fn dimensions(&self) -> [usize; 3] {
+        match &self.nodes {
+            Nodes::Nested(table) => {
+                let cutoffs = table.first().map_or(0, Vec::len);
+                let betas = table
+                    .first()
+                    .and_then(|cutoffs| cutoffs.first())
+                    .map_or(0, Vec::len);
+                [table.len(), cutoffs, betas]
+            }
+            Nodes::Packed { dimensions, .. } => *dimensions,
+        }
+    }
+
+    // The number of beta nodes for each jitter and cutoff. Only ragged tables differ from
+    // dimensions.
+    fn shape(&self) -> Vec<Vec<usize>> {
+        match &self.nodes {
+            Nodes::Nested(table) => table
+                .iter()
+                .map(|cutoffs| cutoffs.iter().map(Vec::len).collect())
+                .collect(),
+            Nodes::Packed {
+                dimensions: [jitters, cutoffs, betas],
+                ..
+            } => vec![vec![*betas; *cutoffs]; *jitters],
+        }
+    }
+
+    // The input value at each node along an axis, i.e. jitter standard deviation for
+    // GridAxis::Jitter.
+    pub fn axis(&self, axis: GridAxis) -> Vec<f64> {
+        let [jitters, cutoffs, betas] = self.dimensions();
+        match axis {
+            GridAxis::Jitter => (0..jitters).map(tablegen::jitter_at).collect(),
+            GridAxis::Cutoff => (0..cutoffs).map(tablegen::cutoff_at).collect(),
+            GridAxis::Beta => (0..betas).map(tablegen::beta_at).collect(),
+        }
+    }
+
+    pub fn cell(&self, jitter: usize, cutoff: usize, beta: usize) -> Option<GridCell> {
+        let precision = self.node(jitter, cutoff, beta)?;
+        Some(GridCell {
+            index: [jitter, cutoff, beta],
+            jitter: tablegen::jitter_at(jitter),
+            cutoff_hz: tablegen::cutoff_at(cutoff),
+            beta: tablegen::beta_at(beta),
+            precision,
+        })
+    }
+
+    // The nodes as [jitter][cutoff][beta], i.e. to edit a packed table.
+    pub fn to_nested(&self) -> Vec<Vec<Vec<f64>>> {
+        self.shape()
+            .into_iter()
+            .enumerate()
+            .map(|(j, cutoffs)| {
+                cutoffs
+                    .into_iter()
+                    .enumerate()
+                    .map(|(c, betas)| (0..betas).filter_map(|b| self.node(j, c, b)).collect())
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Every node, jitter outermost and beta innermost.
+    pub fn cells(&self) -> impl Iterator<Item = GridCell> + '_ {
+        self.shape()
+            .into_iter()
+            .enumerate()
+            .flat_map(move |(j, cutoffs)| {
+                cutoffs
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(move |(c, betas)| (0..betas).filter_map(move |b| self.cell(j, c, b)))
+            })
+    }
+
+    // Filtered noise should never drop as jitter, cutoff or beta go up. Returns every node that
+    // comes in more than tolerance below its predecessor along an axis, along with the axis.
+    // Simulated tables carry some measurement noise, so a small tolerance is expected.
+    pub fn monotonicity_violations(&self, tolerance: f64) -> Vec<(GridCell, GridAxis)> {
+        let mut violations = vec![];
+        for cell in self.cells() {
+            let [j, c, b] = cell.index;
+            let predecessors = [
+                (
+                    GridAxis::Jitter,
+                    j.checked_sub(1).and_then(|j| self.cell(j, c, b)),
+                ),
+                (
+                    GridAxis::Cutoff,
+                    c.checked_sub(1).and_then(|c| self.cell(j, c, b)),
+                ),
+                (
+                    GridAxis::Beta,
+                    b.checked_sub(1).and_then(|b| self.cell(j, c, b)),
+                ),
+            ];
+            for (axis, previous) in predecessors {
+                if previous.is_some_and(|previous| cell.precision < previous.precision - tolerance)
+                {
+                    violations.push((cell, axis));
+                }
+            }
+        }
+        violations
+    }
+
+    // The largest difference between matching nodes, i.e. an embedded table against a freshly
+    // generated one. None if the tables differ in shape.
+    pub fn max_difference(&self, other: &Grid) -> Option<f64> {
+        if self.shape() != other.shape() {
+            return None;
+        }
+        Some(
+            self.cells()
+                .zip(other.cells())
+                .map(|(ours, theirs)| (ours.precision - theirs.precision).abs())
+                .fold(0.0, f64::max),
+        )
+    }
+
+    // Panics if the lookup falls outside the table, i.e. trilinear lookups past the highest
+    // cutoff. With the no-panic feature it's infinitely imprecise instead, so tuning never picks
+    // it.
+    pub fn precision(&self, jitter: f64, cutoff_hz: f64, beta: f64) -> f64 {
+        match self.try_precision(jitter, cutoff_hz, beta) {
+            Some(precision) => precision,
+            #[cfg(feature = "no-panic")]
+            None => f64::INFINITY,
+            #[cfg(not(feature = "no-panic"))]
+            None => panic!("precision lookup outside the table"),
+        }
+    }
+
+    // precision, or None if the lookup falls outside the table or the table is ragged.
+    pub fn try_precision(&self, jitter: f64, cutoff_hz: f64, beta: f64) -> Option<f64> {
+        match self.interpolation {
+            Interpolation::Trilinear => self.trilinear(jitter, cutoff_hz, beta),
+            Interpolation::Nearest => {
+                let [j, c, b] = self.fractional_index(jitter, cutoff_hz, beta);
+                self.node(j.round() as usize, c.round() as usize, b.round() as usize)
+            }
+            Interpolation::Tricubic => self.tricubic(jitter, cutoff_hz, beta),
+        }
+    }
+
+    fn node(&self, jitter: usize, cutoff: usize, beta: usize) -> Option<f64> {
+        match &self.nodes {
+            Nodes::Nested(table) => table.get(jitter)?.get(cutoff)?.get(beta).copied(),
+            Nodes::Packed { bytes, dimensions } => {
+                let [_, cutoffs, betas] = *dimensions;
+                if jitter >= dimensions[0] || cutoff >= cutoffs || beta >= betas {
+                    return None;
+                }
+                let offset = tablegen::PACKED_HEADER_BYTES
+                    + 8 * ((jitter * cutoffs + cutoff) * betas + beta);
+                let bytes: &[u8] = (**bytes).as_ref();
+                let node = bytes.get(offset..offset + 8)?;
+                Some(f64::from_le_bytes(node.try_into().ok()?))
+            }
+        }
+    }
+
+    // Where a lookup falls in node units along each axis, clamped to the table.
+    fn fractional_index(&self, jitter: f64, cutoff_hz: f64, beta: f64) -> [f64; 3] {
+        let [jitters, cutoffs, betas] = self.dimensions();
+        let clamp = |index: f64, len: usize| index.clamp(0.0, len.saturating_sub(1) as f64);
+        [
+            clamp(3.0 * jitter - 1.0, jitters),
+            clamp(cutoff_hz / 0.05 - 0.05, cutoffs),
+            clamp(Self::get_beta_index(beta)[0], betas),
+        ]
+    }
+
+    fn tricubic(&self, jitter: f64, cutoff_hz: f64, beta: f64) -> Option<f64> {
+        let [j, c, b] = self.fractional_index(jitter, cutoff_hz, beta);
+        let [jitters, cutoffs, betas] = self.dimensions();
+        // The four nodes around a position, repeating the edge node past either end.
+        let around = |index: f64, len: usize| -> ([usize; 4], f64) {
+            let base = index.floor() as isize;
+            let nodes = core::array::from_fn(|k| {
+                (base - 1 + k as isize).clamp(0, len as isize - 1) as usize
+            });
+            (nodes, index - base as f64)
+        };
+        let (j_nodes, jt) = around(j, jitters);
+        let (c_nodes, ct) = around(c, cutoffs);
+        let (b_nodes, bt) = around(b, betas);
+
+        let mut along_jitter = [0.0; 4];
+        for (out, j) in along_jitter.iter_mut().zip(j_nodes) {
+            let mut along_cutoff = [0.0; 4];
+            for (out, c) in along_cutoff.iter_mut().zip(c_nodes) {
+                let mut along_beta = [0.0; 4];
+                for (out, b) in along_beta.iter_mut().zip(b_nodes) {
+                    *out = self.node(j, c, b)?;
+                }
+                *out = catmull_rom(along_beta, bt);
+            }
+            *out = catmull_rom(along_cutoff, ct);
+        }
+        Some(catmull_rom(along_jitter, jt).max(0.0))
     }
 
     // I don't really understand what's going on here, so this was copied verbatum from the js repo
     // created by the researchers.
-    pub fn precision(&self, jitter: f64, cutoff_hz: f64, beta: f64) -> f64 {
+    fn trilinear(&self, jitter: f64, cutoff_hz: f64, beta: f64) -> Option<f64> {
         // Jitter level goes up in steps of 1/3 start at 1/3.
         let mut j_idx = 3.0 * jitter - 1.0;
         j_idx = j_idx.min(15.0);
@@ -59,14 +369,14 @@ impl Grid {
         let b_idx_lo = b_idx_lo as usize;
         let b_idx_hi = b_idx_hi as usize;
 
-        let c000 = self.table[j_idx_lo][fc_idx_lo][b_idx_lo];
-        let c100 = self.table[j_idx_hi][fc_idx_lo][b_idx_lo];
-        let c010 = self.table[j_idx_lo][fc_idx_hi][b_idx_lo];
-        let c110 = self.table[j_idx_hi][fc_idx_hi][b_idx_lo];
-        let c001 = self.table[j_idx_lo][fc_idx_lo][b_idx_hi];
-        let c101 = self.table[j_idx_hi][fc_idx_lo][b_idx_hi];
-        let c011 = self.table[j_idx_lo][fc_idx_hi][b_idx_hi];
-        let c111 = self.table[j_idx_hi][fc_idx_hi][b_idx_hi];
+        let c000 = self.node(j_idx_lo, fc_idx_lo, b_idx_lo)?;
+        let c100 = self.node(j_idx_hi, fc_idx_lo, b_idx_lo)?;
+        let c010 = self.node(j_idx_lo, fc_idx_hi, b_idx_lo)?;
+        let c110 = self.node(j_idx_hi, fc_idx_hi, b_idx_lo)?;
+        let c001 = self.node(j_idx_lo, fc_idx_lo, b_idx_hi)?;
+        let c101 = self.node(j_idx_hi, fc_idx_lo, b_idx_hi)?;
+        let c011 = self.node(j_idx_lo, fc_idx_hi, b_idx_hi)?;
+        let c111 = self.node(j_idx_hi, fc_idx_hi, b_idx_hi)?;
 
         let c00 = c000 * (1.0 - xd) + c100 * xd;
         let c01 = c001 * (1.0 - xd) + c101 * xd;
@@ -76,44 +386,395 @@ impl Grid {
         let c0 = c00 * (1.0 - yd) + c10 * yd;
         let c1 = c01 * (1.0 - yd) + c11 * yd;
 
-        c0 * (1.0 - zd) + c1 * zd
+        Some(c0 * (1.0 - zd) + c1 * zd)
     }
 
+    // The fractional beta index along with the nodes either side of it, see
+    // tablegen::beta_index for the mapping.
     pub fn get_beta_index(beta: f64) -> [f64; 3] {
-        let mut b_idx: f64 = 46.0;
-        let mut beta = beta;
-        while beta < 1.0 && b_idx > 0.0 {
-            beta *= 10.00000001;
-            b_idx -= 9.0;
+        let index = tablegen::beta_index(beta);
+        [index, index.floor(), index.ceil()]
+    }
+}
+
+// Interpolates between the middle two of four evenly spaced points, t from 0 to 1.
+fn catmull_rom(p: [f64; 4], t: f64) -> f64 {
+    0.5 * (2.0 * p[1]
+        + (p[2] - p[0]) * t
+        + (2.0 * p[0] - 5.0 * p[1] + 4.0 * p[2] - p[3]) * t * t
+        + (3.0 * p[1] - p[0] - 3.0 * p[2] + p[3]) * t * t * t)
+}
+
+// Generic over the filter being tuned, see SmoothingFilter. Defaults to the one euro filter the
+// precision table was generated for.
+pub struct Tuner<F: SmoothingFilter = OneEuroFilter<f64>> {
+    pub(crate) filter: F,
+    pub(crate) settings: TuningSettings,
+    pub(crate) current_filtered_val: f64,
+    pub(crate) grid: Box<dyn PrecisionModel + Send + Sync>,
+    pub(crate) precision_metric: PrecisionMetric,
+    // Only recorded when asked for, as a full tune evaluates tens of thousands of candidates.
+    pub(crate) trace: Option<SearchTrace>,
+    // Replaces the precision-then-lag policy when set, see with_joint_constraints.
+    pub(crate) constraints: Option<JointConstraints>,
+    pub(crate) beta_search: BetaSearch,
+    pub(crate) cutoff_search: CutoffSearch,
+    // Filled in by whichever search is running.
+    pub(crate) diagnostics: TuneDiagnostics,
+    // Searches stop early past this, see tune_with_budget.
+    pub(crate) deadline: Option<Instant>,
+}
+
+/// The range of beta tune searches, and how finely. Beta is stepped down from max, a decade at a
+/// time - each decade split into steps_per_decade even steps - until it passes min. Beta = 0, a
+/// plain low pass at min_cutoff_hz, is tried last when zero is set - for devices dominated by
+/// static noise, where speeding up for movement doesn't pay for the noise it lets through.
+///
+/// The precision table only covers beta from 1e-5 to 1.0 and is clamped outside that, so going
+/// past either end only makes sense with a precision model that covers it, i.e.
+/// velocity::VelocityPrecision.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BetaSearch {
+    pub min: f64,
+    pub max: f64,
+    pub steps_per_decade: usize,
+    pub zero: bool,
+}
+
+impl Default for BetaSearch {
+    // 0.975 down to 1e-5, in steps of 0.025 then 0.0025 and so on, then 0.
+    fn default() -> Self {
+        Self {
+            min: 1e-5,
+            max: 1.0,
+            steps_per_decade: 36,
+            zero: true,
         }
+    }
+}
 
-        if b_idx < 0.0 {
-            return [0.0, 0.0, 0.0];
+impl BetaSearch {
+    pub fn new(min: f64, max: f64) -> Self {
+        Self {
+            min,
+            max,
+            ..Default::default()
         }
+    }
+
+    pub fn with_steps_per_decade(mut self, steps_per_decade: usize) -> Self {
+        self.steps_per_decade = steps_per_decade;
+        self
+    }
 
-        b_idx = (b_idx - 1.0).max(0.0);
-        beta += b_idx;
-        let b_idx_lo = beta.floor();
-        let b_idx_hi = beta.ceil();
+    // Leaves beta = 0 out, for when the filter has to stay adaptive.
+    pub fn without_zero(mut self) -> Self {
+        self.zero = false;
+        self
+    }
 
-        [beta, b_idx_lo, b_idx_hi]
+    // Every beta tune tries, largest first. Max itself is only tried when the range is too
+    // narrow to hold a single step, so there's always at least one value.
+    pub fn values(&self) -> Vec<f64> {
+        let steps = self.steps_per_decade.max(1);
+        // Anything smaller would never get there.
+        let min = self.min.max(f64::MIN_POSITIVE);
+        let mut values = Vec::new();
+        let mut top = self.max;
+        'decades: while top > min && top.is_finite() {
+            for k in 1..=steps {
+                // Rounded so decades meet exactly, rather than a rounding error apart.
+                let fraction = 1.0 - 0.9 * k as f64 / steps as f64;
+                let beta = top * (fraction * 1e6).round() / 1e6;
+                if beta < min * (1.0 - 1e-9) {
+                    break 'decades;
+                }
+                values.push(beta);
+            }
+            top /= 10.0;
+        }
+        if values.is_empty() {
+            values.push(self.max);
+        }
+        if self.zero {
+            values.push(0.0);
+        }
+        values
+    }
+
+    fn contains(&self, beta: f64) -> bool {
+        if beta == 0.0 {
+            return self.zero;
+        }
+        beta >= self.min * (1.0 - 1e-9) && beta <= self.max * (1.0 + 1e-9)
     }
 }
 
-pub struct Tuner {
-    pub(crate) filter: OneEuroFilter<f64>,
-    pub(crate) settings: TuningSettings,
-    pub(crate) current_filtered_val: f64,
-    pub(crate) grid: Grid,
+/// The min cutoffs tune's grid search tries - every step from min_hz up to max_hz, steps_per_hz
+/// steps to the hertz. The default suits the 60 hz table. Much slower devices want a range
+/// scaled down to their bandwidth, along with a precision model that covers it, see
+/// baro::BarometerCalibrator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CutoffSearch {
+    pub min_hz: f64,
+    pub max_hz: f64,
+    pub steps_per_hz: f64,
+}
+
+impl Default for CutoffSearch {
+    // 0.10 to 3.99 hz, in hundredths.
+    fn default() -> Self {
+        Self {
+            min_hz: 0.1,
+            max_hz: 3.99,
+            steps_per_hz: 100.0,
+        }
+    }
+}
+
+impl CutoffSearch {
+    pub fn new(min_hz: f64, max_hz: f64, steps_per_hz: f64) -> Self {
+        Self {
+            min_hz,
+            max_hz,
+            steps_per_hz,
+        }
+    }
+
+    // Every cutoff tune tries, smallest first. Always at least one.
+    pub fn values(&self) -> Vec<f64> {
+        let first = (self.min_hz * self.steps_per_hz).round().max(1.0) as usize;
+        let last = ((self.max_hz * self.steps_per_hz).round() as usize).max(first);
+        (first..=last)
+            .map(|step| step as f64 / self.steps_per_hz)
+            .collect()
+    }
+
+    fn contains(&self, cutoff_hz: f64) -> bool {
+        cutoff_hz >= self.min_hz * (1.0 - 1e-9) && cutoff_hz <= self.max_hz * (1.0 + 1e-9)
+    }
+}
+
+/// How the precision target is interpreted.
+///
+/// The table stores the standard deviation of the filtered noise, which is an average wobble.
+/// For text selection and aiming the worst case wobble matters more, so the target can instead be
+/// expressed as a percentile of the absolute error.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum PrecisionMetric {
+    #[default]
+    StdDev,
+    // The fraction (i.e. 0.95) of filtered samples that must be within the target precision.
+    Percentile(f64),
+}
+
+impl PrecisionMetric {
+    // Converts a standard deviation from the table into this metric. Filtered white noise is
+    // still Gaussian, so the percentile of the absolute error is a fixed multiple of the standard
+    // deviation.
+    pub fn from_std_dev(&self, std_dev: f64) -> f64 {
+        match *self {
+            PrecisionMetric::StdDev => std_dev,
+            PrecisionMetric::Percentile(p) => std_dev * inverse_normal_cdf((1.0 + p) / 2.0),
+        }
+    }
+}
+
+// Acklam's rational approximation of the standard normal quantile function. Relative error is
+// around 1e-9, far tighter than the table itself.
+fn inverse_normal_cdf(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239e0,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838e0,
+        -2.549732539343734e0,
+        4.374664141464968e0,
+        2.938163982698783e0,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996e0,
+        3.754408661907416e0,
+    ];
+    const P_LOW: f64 = 0.02425;
+
+    let p = p.clamp(f64::EPSILON, 1.0 - f64::EPSILON);
+
+    if p < P_LOW {
+        let q = (-2.0 * math::ln(p)).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    } else if p <= 1.0 - P_LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -inverse_normal_cdf(1.0 - p)
+    }
+}
+
+#[cfg(feature = "sixty-hz-table")]
+fn default_precision_model(_: &TuningSettings) -> Box<dyn PrecisionModel + Send + Sync> {
+    Box::new(sixty_hz_grid())
+}
+
+// Without the table, precision is simulated for the settings' own noise and rate - on first use,
+// so tuners handed another model straight away never pay for it.
+#[cfg(not(feature = "sixty-hz-table"))]
+fn default_precision_model(settings: &TuningSettings) -> Box<dyn PrecisionModel + Send + Sync> {
+    Box::new(SimulatedPrecision {
+        noise_std_dev: settings.noise_variance.std_dev().0,
+        sample_rate: settings.sample_rate.0,
+        model: std::sync::OnceLock::new(),
+    })
+}
+
+#[cfg(not(feature = "sixty-hz-table"))]
+struct SimulatedPrecision {
+    noise_std_dev: f64,
+    sample_rate: f64,
+    model: std::sync::OnceLock<VelocityPrecision>,
+}
+
+#[cfg(not(feature = "sixty-hz-table"))]
+impl PrecisionModel for SimulatedPrecision {
+    fn precision(&self, jitter: f64, cutoff_hz: f64, beta: f64) -> f64 {
+        self.model().precision(jitter, cutoff_hz, beta)
+    }
+
+    fn prepare(&self) {
+        self.model();
+    }
+}
+
+#[cfg(not(feature = "sixty-hz-table"))]
+impl SimulatedPrecision {
+    fn model(&self) -> &VelocityPrecision {
+        self.model.get_or_init(|| {
+            VelocityPrecision::new(self.noise_std_dev, self.sample_rate, NoiseColor::White)
+        })
+    }
 }
 
 impl Tuner {
     pub fn new(settings: TuningSettings) -> Self {
+        let filter = OneEuroFilter::new(settings.sample_rate.0, 1.0, 1.0, 1.0);
+        Self::with_filter(settings, filter)
+    }
+}
+
+impl<F: SmoothingFilter> Tuner<F> {
+    // Tunes an alternative smoother. Precision still comes from the one euro table, so either
+    // the smoother needs to respond to noise the same way or a matching model should be swapped
+    // in with with_precision_model.
+    pub fn with_filter(settings: TuningSettings, filter: F) -> Self {
+        let grid = default_precision_model(&settings);
         Self {
-            filter: OneEuroFilter::new(60.0, 1.0, 1.0, 1.0),
+            filter,
             settings,
             current_filtered_val: 0.0,
-            grid: Grid::new(sixty_hz()),
+            grid,
+            precision_metric: PrecisionMetric::default(),
+            trace: None,
+            constraints: None,
+            beta_search: BetaSearch::default(),
+            cutoff_search: CutoffSearch::default(),
+            diagnostics: TuneDiagnostics::default(),
+            deadline: None,
+        }
+    }
+
+    pub fn with_grid(self, grid: Grid) -> Self {
+        self.with_precision_model(grid)
+    }
+
+    // Swaps the precision table for any other model, i.e. velocity::VelocityPrecision.
+    pub fn with_precision_model(
+        mut self,
+        model: impl PrecisionModel + Send + Sync + 'static,
+    ) -> Self {
+        self.grid = Box::new(model);
+        self
+    }
+
+    pub fn with_precision_metric(mut self, precision_metric: PrecisionMetric) -> Self {
+        self.precision_metric = precision_metric;
+        self
+    }
+
+    // Has tune weigh precision, lag and ringing together in the given priority, rather than
+    // meeting precision first and then lag. Slower, as every candidate's step response is
+    // simulated in full.
+    pub fn with_joint_constraints(mut self, constraints: JointConstraints) -> Self {
+        self.constraints = Some(constraints);
+        self
+    }
+
+    // For devices that need more beta than 1.0, or much less than 1e-5, or a finer search.
+    pub fn with_beta_search(mut self, beta_search: BetaSearch) -> Self {
+        self.beta_search = beta_search;
+        self
+    }
+
+    // For devices far slower than 60 hz, where the default cutoffs are mostly above Nyquist.
+    pub fn with_cutoff_search(mut self, cutoff_search: CutoffSearch) -> Self {
+        self.cutoff_search = cutoff_search;
+        self
+    }
+
+    // See TuningSettings::with_preference.
+    pub fn with_preference(mut self, preference: f64) -> Self {
+        self.settings = self.settings.with_preference(preference);
+        self
+    }
+
+    // Records every candidate tune evaluates, see take_trace.
+    pub fn with_trace(mut self) -> Self {
+        self.trace = Some(SearchTrace::new());
+        self
+    }
+
+    // The candidates recorded so far, leaving an empty trace to record the next tune into. None
+    // if tracing wasn't enabled.
+    pub fn take_trace(&mut self) -> Option<SearchTrace> {
+        self.trace.as_mut().map(std::mem::take)
+    }
+
+    fn record(
+        &mut self,
+        target_precision: f64,
+        candidate: &FinalTuningSettings,
+        predicted_precision: f64,
+        lag_s: Option<f64>,
+        outcome: CandidateOutcome,
+    ) {
+        self.diagnostics.candidates_evaluated += 1;
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(TraceEntry {
+                target_precision,
+                min_cutoff_hz: candidate.min_cutoff_hz,
+                beta: candidate.beta,
+                predicted_precision,
+                lag_s,
+                outcome,
+            });
         }
     }
 
@@ -126,6 +787,9 @@ impl Tuner {
     // it's not correctly supported in the parent library.
     pub fn lag_s(&mut self, target_precision: f64) -> f64 {
         let mut cnt = 0;
+        // Only with no-panic - a step that never settles (i.e. a non finite amplitude) is
+        // infinitely slow, rather than hanging.
+        let max_cnt = (MAX_STEP_SECS * self.settings.sample_rate.0) as u64;
 
         // Warm at zero
         for _ in 0..2 {
@@ -133,6 +797,13 @@ impl Tuner {
         }
 
         loop {
+            if cfg!(feature = "no-panic") && cnt >= max_cnt {
+                return f64::INFINITY;
+            }
+            // Cut short by a budgeted tune, see tune_with_budget.
+            if cnt.is_multiple_of(DEADLINE_CHECK_SAMPLES) && self.past_deadline() {
+                return f64::INFINITY;
+            }
             self.current_filtered_val = self.filter.filter(self.settings.max_amplitude);
 
             cnt += 1;
@@ -140,88 +811,593 @@ impl Tuner {
             let delta = (self.current_filtered_val - self.settings.max_amplitude).abs();
 
             if delta < target_precision {
-                return cnt as f64 / self.settings.sample_rate;
+                return cnt as f64 / self.settings.sample_rate.0;
             }
         }
     }
 
+    // Like lag_s, but keeps simulating past the point the output reaches the target precision so
+    // overshoot, settling and ringing can be measured as well.
+    pub fn step_response(&mut self, target_precision: f64) -> StepResponse {
+        let sample_rate = self.settings.sample_rate.0;
+        let amplitude = self.settings.max_amplitude;
+        let max_samples = (MAX_STEP_SECS * sample_rate) as usize;
+        let settle_samples = (SETTLE_WINDOW_SECS * sample_rate) as usize;
+
+        // Warm at zero
+        for _ in 0..2 {
+            self.current_filtered_val = self.filter.filter(0.0);
+        }
+
+        let mut trace = Vec::new();
+        let mut reached_at = None;
+        while trace.len() < max_samples {
+            if (trace.len() as u64).is_multiple_of(DEADLINE_CHECK_SAMPLES) && self.past_deadline() {
+                break;
+            }
+            self.current_filtered_val = self.filter.filter(amplitude);
+            trace.push(self.current_filtered_val);
+
+            let delta = (self.current_filtered_val - amplitude).abs();
+            if reached_at.is_none() && delta < target_precision {
+                reached_at = Some(trace.len());
+            }
+
+            if reached_at.is_some_and(|reached| trace.len() >= reached + settle_samples) {
+                break;
+            }
+        }
+
+        StepResponse::from_trace(&trace, amplitude, sample_rate, target_precision)
+    }
+
     pub fn tune(&mut self) -> Option<FinalTuningSettings> {
-        let noise_stddev = self.settings.noise_variance.sqrt();
-        let mut best_precision = f64::MAX;
-        let mut best_lag_s = f64::MAX;
-        let mut best_min_cutoff_hz = None;
-        let mut best_beta = 1.1;
+        self.tune_with_diagnostics().0
+    }
 
-        let mut target_precision = self.settings.max_target_precision;
+    // tune, along with how it went - i.e. to spot tunings that had to give up on the targets.
+    pub fn tune_with_diagnostics(&mut self) -> (Option<FinalTuningSettings>, TuneDiagnostics) {
+        announce_start(&self.settings, false);
+        let started = self.begin_diagnostics();
+        let tuned = match self.constraints {
+            Some(constraints) => self.tune_joint(constraints),
+            None => self.tune_grid(),
+        };
+        announce_finish(&tuned);
+        (tuned, self.finish_diagnostics(started))
+    }
+
+    // tune, stopping once the budget runs out with the best found so far - so calibration takes a
+    // bounded time, i.e. behind a loading screen. If nothing has met the precision target by
+    // then, the most precise candidate seen is returned. Diagnostics show whether it ran out.
+    // The clock is checked while each candidate is simulated too, so a tune overruns by at most
+    // a few dozen filter steps. The budget starts after the precision model has been prepared,
+    // which without the 60 hz table means simulating it.
+    pub fn tune_with_budget(
+        &mut self,
+        budget: Duration,
+    ) -> (Option<FinalTuningSettings>, TuneDiagnostics) {
+        self.grid.prepare();
+        self.deadline = Some(Instant::now() + budget);
+        let tuned = self.tune_with_diagnostics();
+        self.deadline = None;
+        tuned
+    }
+
+    fn past_deadline(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    fn out_of_time(&mut self) -> bool {
+        let out_of_time = self.past_deadline();
+        self.diagnostics.budget_limited |= out_of_time;
+        out_of_time
+    }
 
-        while best_precision == f64::MAX {
-            for min_hz in (10..400).map(|x| x as f64 / 100.0) {
-                self.filter.configuration.cutoff_min = min_hz;
+    // How much better candidate is than current for these settings, as a fraction - i.e. 0.2 for
+    // 20% better, negative if it's worse. Judged the way tuning judges: by precision if current
+    // misses the precision target, otherwise by lag. Lag comes from response::step_lag_secs, as
+    // in tune_fast.
+    pub fn improvement(
+        &self,
+        current: &FinalTuningSettings,
+        candidate: &FinalTuningSettings,
+    ) -> f64 {
+        let settings = &self.settings;
+        let noise_stddev = settings.noise_variance.std_dev().0;
+        let precision = |tuning: &FinalTuningSettings| {
+            self.precision_metric.from_std_dev(self.grid.precision(
+                noise_stddev,
+                tuning.min_cutoff_hz,
+                tuning.beta,
+            ))
+        };
+        let lag_s = |tuning: &FinalTuningSettings| {
+            step_lag_secs(
+                tuning,
+                DERIVATIVE_CUTOFF_HZ,
+                settings.sample_rate.0,
+                settings.max_amplitude,
+                settings.max_target_precision,
+            )
+        };
+
+        let current_precision = precision(current);
+        if current_precision > settings.max_target_precision {
+            (current_precision - precision(candidate)) / current_precision
+        } else {
+            let current_lag_s = lag_s(current);
+            (current_lag_s - lag_s(candidate)) / current_lag_s
+        }
+    }
+
+    fn begin_diagnostics(&mut self) -> Instant {
+        self.diagnostics = TuneDiagnostics {
+            target_precision: self.settings.max_target_precision,
+            ..Default::default()
+        };
+        Instant::now()
+    }
+
+    fn finish_diagnostics(&mut self, started: Instant) -> TuneDiagnostics {
+        let max_lag_secs = self.settings.max_lag_secs.0;
+        let diagnostics = &mut self.diagnostics;
+        diagnostics.elapsed = started.elapsed();
+        diagnostics.lag_satisfied = diagnostics.lag_s.is_some_and(|lag_s| lag_s <= max_lag_secs);
+        diagnostics.clone()
+    }
+
+    fn tune_grid(&mut self) -> Option<FinalTuningSettings> {
+        let mut search = GridSearch::new(self);
+        while !self.out_of_time() && search.advance(self) {}
+        search.finish(self)
+    }
+
+    // Same search as tune, but every candidate is scored against all three constraints at once.
+    // A best candidate always exists, so there's no relaxing of the precision target.
+    fn tune_joint(&mut self, constraints: JointConstraints) -> Option<FinalTuningSettings> {
+        let noise_stddev = self.settings.noise_variance.std_dev().0;
+        let target_precision = self.settings.max_target_precision;
+        let max_lag_secs = self.settings.max_lag_secs.0;
+        let precision_first = constraints.priority[0] == Constraint::Precision;
+
+        let mut best: Option<(CandidateScore, FinalTuningSettings, f64)> = None;
+        let mut best_precision_met = false;
+        let cutoffs = self.cutoff_search.values();
+        let betas = self.beta_search.values();
+
+        'search: for &min_hz in cutoffs.iter() {
+            for &beta in betas.iter() {
+                if self.out_of_time() {
+                    break 'search;
+                }
+
+                let precision = self.precision_metric.from_std_dev(self.grid.precision(
+                    noise_stddev,
+                    min_hz,
+                    beta,
+                ));
+                let candidate = FinalTuningSettings {
+                    min_cutoff_hz: min_hz,
+                    beta,
+                };
+
+                // Nothing else can make up for missing the top priority, so don't bother
+                // simulating.
+                if precision_first && best_precision_met && precision > target_precision {
+                    self.record(
+                        target_precision,
+                        &candidate,
+                        precision,
+                        None,
+                        CandidateOutcome::ExceedsPrecision,
+                    );
+                    continue;
+                }
+
+                // Reset too, so the measurement doesn't depend on the previous candidate.
+                self.filter.set_parameters(&candidate);
+                self.filter.reset();
+                let response = self.step_response(target_precision);
+                // Possibly cut short, so not to be trusted.
+                if self.out_of_time() {
+                    break 'search;
+                }
+                let score = constraints.score(precision, target_precision, &response, max_lag_secs);
+
+                if best.as_ref().is_some_and(|(best, _, _)| !score.beats(best)) {
+                    self.record(
+                        target_precision,
+                        &candidate,
+                        precision,
+                        Some(response.lag_secs),
+                        CandidateOutcome::NoImprovement,
+                    );
+                    continue;
+                }
+
+                self.record(
+                    target_precision,
+                    &candidate,
+                    precision,
+                    Some(response.lag_secs),
+                    CandidateOutcome::NewBest,
+                );
+                best_precision_met = precision <= target_precision;
+                best = Some((score, candidate, response.lag_secs));
+            }
+        }
 
-                let mut beta = 1.0;
-                for scale in 1..=5 {
-                    let step = 10f64.powi(-scale) / 4.0;
+        self.diagnostics.lag_s = best.as_ref().map(|(_, _, lag_s)| *lag_s);
+        best.map(|(_, settings, _)| settings)
+    }
 
-                    for _ in 0..36 {
-                        beta -= step;
-                        beta = (beta * 1e6).round() / 1e6;
+    // Whether a candidate beats the best so far. Once something within the lag budget has been
+    // found, only more precise candidates that are also within budget win. Until then, anything
+    // with less lag does.
+    fn improves(&self, best_precision: f64, best_lag_s: f64, precision: f64, lag_s: f64) -> bool {
+        let max_lag_secs = self.settings.max_lag_secs.0;
+        if best_lag_s <= max_lag_secs {
+            !(lag_s >= max_lag_secs || precision > best_precision)
+        } else {
+            lag_s <= best_lag_s
+        }
+    }
+}
 
-                        let precision = self.grid.precision(noise_stddev, min_hz, beta);
+// With the no-panic feature, a search that still hasn't met the precision target after relaxing
+// it this many times - by 333, far past any real target - gives up rather than carrying on
+// forever, i.e. against a precision model that only ever returns infinity.
+const MAX_RELAXATIONS: usize = 1000;
 
-                        if precision > target_precision {
-                            continue;
-                        }
+fn gives_up(relaxations: usize) -> bool {
+    cfg!(feature = "no-panic") && relaxations >= MAX_RELAXATIONS
+}
 
-                        self.filter.configuration.beta = beta;
+/// Where tune's precision-then-lag search is up to, so it can be run a candidate at a time. See
+/// incremental::IncrementalTuner.
+pub(crate) struct GridSearch {
+    noise_stddev: f64,
+    cutoffs: Vec<f64>,
+    betas: Vec<f64>,
+    target_precision: f64,
+    // Next candidate, as [cutoff, beta] offsets into the search.
+    next: [usize; 2],
+    done: bool,
+    best_precision: f64,
+    best_lag_s: f64,
+    best: Option<FinalTuningSettings>,
+    // The most precise candidate that missed the target, in case time runs out before anything
+    // meets it.
+    closest: Option<(f64, FinalTuningSettings)>,
+}
 
-                        let lag_s = self.lag_s(target_precision);
+impl GridSearch {
+    pub(crate) fn new<F: SmoothingFilter>(tuner: &Tuner<F>) -> Self {
+        Self {
+            noise_stddev: tuner.settings.noise_variance.std_dev().0,
+            cutoffs: tuner.cutoff_search.values(),
+            betas: tuner.beta_search.values(),
+            target_precision: tuner.settings.max_target_precision,
+            next: [0, 0],
+            done: false,
+            best_precision: f64::MAX,
+            best_lag_s: f64::MAX,
+            best: None,
+            closest: None,
+        }
+    }
 
-                        let accept = if best_lag_s <= self.settings.max_lag_secs {
-                            !(lag_s >= self.settings.max_lag_secs || precision > best_precision)
-                        } else {
-                            lag_s <= best_lag_s
-                        };
+    pub(crate) fn is_done(&self) -> bool {
+        self.done
+    }
+
+    // Evaluates the next candidate. False once the search is over.
+    pub(crate) fn advance<F: SmoothingFilter>(&mut self, tuner: &mut Tuner<F>) -> bool {
+        if self.done {
+            return false;
+        }
+
+        let [cutoff, beta] = self.next;
+        let candidate = FinalTuningSettings {
+            min_cutoff_hz: self.cutoffs[cutoff],
+            beta: self.betas[beta],
+        };
+        self.evaluate(tuner, candidate);
+
+        self.next = if beta + 1 < self.betas.len() {
+            [cutoff, beta + 1]
+        } else if cutoff + 1 < self.cutoffs.len() {
+            [cutoff + 1, 0]
+        } else {
+            if self.best.is_some() || gives_up(tuner.diagnostics.relaxations) {
+                self.done = true;
+            } else {
+                // Adjust target precision and try again if no configuration is good enough
+                self.target_precision += 1.0 / 3.0;
+                tuner.diagnostics.relaxations += 1;
+            }
+            [0, 0]
+        };
+        !self.done
+    }
+
+    // How far through the current pass the search is, from 0 to 1.
+    pub(crate) fn progress(&self) -> f64 {
+        if self.done {
+            return 1.0;
+        }
+        let [cutoff, beta] = self.next;
+        (cutoff * self.betas.len() + beta) as f64 / (self.cutoffs.len() * self.betas.len()) as f64
+    }
 
-                        if !accept {
-                            continue;
-                        }
+    fn evaluate<F: SmoothingFilter>(
+        &mut self,
+        tuner: &mut Tuner<F>,
+        candidate: FinalTuningSettings,
+    ) {
+        let target_precision = self.target_precision;
+        let precision = tuner.precision_metric.from_std_dev(tuner.grid.precision(
+            self.noise_stddev,
+            candidate.min_cutoff_hz,
+            candidate.beta,
+        ));
 
-                        best_precision = precision;
-                        best_lag_s = lag_s;
-                        best_beta = beta;
-                        best_min_cutoff_hz = Some(min_hz);
+        if precision > target_precision {
+            tuner.record(
+                target_precision,
+                &candidate,
+                precision,
+                None,
+                CandidateOutcome::ExceedsPrecision,
+            );
+            if self
+                .closest
+                .as_ref()
+                .is_none_or(|(closest, _)| precision < *closest)
+            {
+                self.closest = Some((precision, candidate));
+            }
+            return;
+        }
+
+        tuner.filter.set_parameters(&candidate);
+
+        let lag_s = tuner.lag_s(target_precision);
+        // Cut short, the candidate's lag is unknown.
+        if lag_s.is_infinite() && tuner.out_of_time() {
+            return;
+        }
+
+        if !tuner.improves(self.best_precision, self.best_lag_s, precision, lag_s) {
+            tuner.record(
+                target_precision,
+                &candidate,
+                precision,
+                Some(lag_s),
+                CandidateOutcome::NoImprovement,
+            );
+            return;
+        }
+
+        tuner.record(
+            target_precision,
+            &candidate,
+            precision,
+            Some(lag_s),
+            CandidateOutcome::NewBest,
+        );
+
+        self.best_precision = precision;
+        self.best_lag_s = lag_s;
+        self.best = Some(candidate);
+    }
+
+    // The best candidate so far, falling back to the closest miss if time ran out.
+    pub(crate) fn finish<F: SmoothingFilter>(
+        &self,
+        tuner: &mut Tuner<F>,
+    ) -> Option<FinalTuningSettings> {
+        tuner.diagnostics.target_precision = self.target_precision;
+        tuner.diagnostics.lag_s = self.best.as_ref().map(|_| self.best_lag_s);
+        let closest = self
+            .closest
+            .as_ref()
+            .filter(|_| tuner.diagnostics.budget_limited);
+        self.best
+            .clone()
+            .or(closest.map(|(_, candidate)| candidate.clone()))
+    }
+}
+
+impl Tuner {
+    // A much cheaper tune for applications that retune at runtime. Only the nodes of the
+    // precision table that fall within the cutoff and beta searches are searched (0.1 hz cutoff
+    // steps, and the table's own 46 beta values) and lag comes from response::step_lag_secs
+    // rather than simulating a filter, which brings it down to a few milliseconds. Results are
+    // usually within a grid step of tune().
+    pub fn tune_fast(&mut self) -> Option<FinalTuningSettings> {
+        self.tune_fast_with_diagnostics().0
+    }
+
+    pub fn tune_fast_with_diagnostics(&mut self) -> (Option<FinalTuningSettings>, TuneDiagnostics) {
+        announce_start(&self.settings, true);
+        let started = self.begin_diagnostics();
+        let noise_stddev = self.settings.noise_variance.std_dev().0;
+        let sample_rate = self.settings.sample_rate.0;
+        let mut best_precision = f64::MAX;
+        let mut best_lag_s = f64::MAX;
+        let mut best = None;
+
+        // 1.0, then 0.9, 0.8 ... 0.1, 0.09 ... down to 1e-5 and then 0, same as the table's beta
+        // axis.
+        let betas: Vec<f64> = std::iter::once(1.0)
+            .chain((1..=5).flat_map(|decade| {
+                (1..=9)
+                    .rev()
+                    .map(move |k| k as f64 * math::powi(10.0, -decade))
+            }))
+            .chain(std::iter::once(0.0))
+            .filter(|&beta| self.beta_search.contains(beta))
+            .collect();
+        // A range between two nodes falls back to the full search's own values.
+        let betas = if betas.is_empty() {
+            self.beta_search.values()
+        } else {
+            betas
+        };
+        let cutoff_nodes = (self.cutoff_search.max_hz * 10.0).floor().max(0.0) as usize;
+        let cutoffs: Vec<f64> = (1..=cutoff_nodes)
+            .map(|x| x as f64 / 10.0)
+            .filter(|&cutoff_hz| self.cutoff_search.contains(cutoff_hz))
+            .collect();
+        let cutoffs = if cutoffs.is_empty() {
+            self.cutoff_search.values()
+        } else {
+            cutoffs
+        };
+
+        let mut target_precision = self.settings.max_target_precision;
+
+        loop {
+            for &min_cutoff_hz in cutoffs.iter() {
+                for &beta in betas.iter() {
+                    self.diagnostics.candidates_evaluated += 1;
+                    let precision = self.precision_metric.from_std_dev(self.grid.precision(
+                        noise_stddev,
+                        min_cutoff_hz,
+                        beta,
+                    ));
+
+                    if precision > target_precision {
+                        continue;
+                    }
+
+                    let candidate = FinalTuningSettings {
+                        min_cutoff_hz,
+                        beta,
+                    };
+                    let lag_s = step_lag_secs(
+                        &candidate,
+                        DERIVATIVE_CUTOFF_HZ,
+                        sample_rate,
+                        self.settings.max_amplitude,
+                        target_precision,
+                    );
+
+                    if !self.improves(best_precision, best_lag_s, precision, lag_s) {
+                        continue;
                     }
+
+                    best_precision = precision;
+                    best_lag_s = lag_s;
+                    best = Some(candidate);
                 }
             }
+            if best.is_some() || gives_up(self.diagnostics.relaxations) {
+                break;
+            }
             // Adjust target precision and try again if no configuration is good enough
             target_precision += 1.0 / 3.0;
+            self.diagnostics.relaxations += 1;
         }
 
-        best_min_cutoff_hz.map(|min_cutoff_hz| FinalTuningSettings {
-            min_cutoff_hz,
-            beta: best_beta,
-        })
+        self.diagnostics.target_precision = target_precision;
+        self.diagnostics.lag_s = Some(best_lag_s);
+        announce_finish(&best);
+        (best, self.finish_diagnostics(started))
+    }
+}
+
+fn announce_start(settings: &TuningSettings, fast: bool) {
+    events::milestone!(
+        "tuning started",
+        fast = fast,
+        noise_variance = settings.noise_variance.0,
+        max_target_precision = settings.max_target_precision,
+        max_lag_s = settings.max_lag_secs.0,
+        max_amplitude = settings.max_amplitude,
+    );
+}
+
+fn announce_finish(tuned: &Option<FinalTuningSettings>) {
+    match tuned {
+        Some(tuned) => {
+            events::milestone!(
+                "tuning finished",
+                min_cutoff_hz = tuned.min_cutoff_hz,
+                beta = tuned.beta,
+            );
+        }
+        None => {
+            events::milestone!("tuning found nothing");
+        }
+    }
+}
+
+// Tunes each axis to its own settings, see AmplitudeCalibrator::tuning_settings_per_axis. Axis
+// with identical settings are only tuned once. The result feeds
+// MultiAxisFilter::with_axis_settings.
+pub fn tune_per_axis<const D: usize>(
+    settings: [TuningSettings; D],
+) -> Option<[FinalTuningSettings; D]> {
+    let mut tuned: Vec<(TuningSettings, FinalTuningSettings)> = Vec::with_capacity(D);
+    let mut out = Vec::with_capacity(D);
+    for axis_settings in settings {
+        let axis_tuned = match tuned.iter().find(|(done, _)| *done == axis_settings) {
+            Some((_, done)) => done.clone(),
+            None => {
+                let axis_tuned = Tuner::new(axis_settings.clone()).tune()?;
+                tuned.push((axis_settings, axis_tuned.clone()));
+                axis_tuned
+            }
+        };
+        out.push(axis_tuned);
     }
+    out.try_into().ok()
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FinalTuningSettings {
     pub min_cutoff_hz: f64,
     pub beta: f64,
 }
 
+impl FinalTuningSettings {
+    // Whether the cutoff rises with speed. False for beta = 0, which is a plain low pass.
+    pub fn is_adaptive(&self) -> bool {
+        self.beta > 0.0
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    #[cfg(feature = "sixty-hz-table")]
+    use crate::table::sixty_hz;
+    use crate::units::{Hertz, Seconds, Variance};
+
+    // A noisy, fast moving device at 60 hz, with a typical precision and lag budget.
+    fn settings() -> TuningSettings {
+        TuningSettings {
+            max_target_precision: 1.0,
+            max_lag_secs: Seconds(0.08),
+            noise_variance: Variance(4.0),
+            max_amplitude: 500.0,
+            sample_rate: Hertz(60.0),
+        }
+    }
 
     #[test]
     pub fn test_tuning() {
         let settings = TuningSettings {
             max_target_precision: 1.0,
-            max_lag_secs: 0.08,
-            noise_variance: 2.5522531939863018e-9,
+            max_lag_secs: Seconds(0.08),
+            noise_variance: Variance(2.5522531939863018e-9),
             max_amplitude: 0.6117461919784546,
-            sample_rate: 60.0,
+            sample_rate: Hertz(60.0),
         };
 
         let mut tuner = Tuner::new(settings);
@@ -230,4 +1406,373 @@ mod test {
 
         print!("{:?}", final_settings);
     }
+
+    #[test]
+    pub fn test_percentile_metric() {
+        let metric = PrecisionMetric::Percentile(0.95);
+        assert!((metric.from_std_dev(1.0) - 1.959964).abs() < 1e-6);
+    }
+
+    #[test]
+    pub fn test_tune_fast() {
+        let full = Tuner::new(settings()).tune().unwrap();
+        let fast = Tuner::new(settings()).tune_fast().unwrap();
+
+        assert!((full.min_cutoff_hz - fast.min_cutoff_hz).abs() <= 0.2);
+        // Both meet the precision target, and the fast one's lag is within a couple of samples.
+        let tuner = Tuner::new(settings());
+        for tuned in [&full, &fast] {
+            assert!(tuner.grid.precision(2.0, tuned.min_cutoff_hz, tuned.beta) <= 1.0);
+        }
+        let lag = |tuned: &FinalTuningSettings| {
+            let mut tuner = Tuner::new(settings());
+            tuner.filter.set_parameters(tuned);
+            tuner.lag_s(1.0)
+        };
+        assert!(lag(&full) <= lag(&fast));
+        assert!(lag(&fast) - lag(&full) <= 2.0 / 60.0 + 1e-9);
+    }
+
+    #[test]
+    pub fn test_tune_other_filter() {
+        // Our own filter behaves exactly like one_euro_rs, so it must tune the same.
+        let native = crate::one_euro::OneEuroFilter::new(60.0, 1.0, 1.0, 1.0);
+        assert_eq!(
+            Tuner::with_filter(settings(), native).tune(),
+            Tuner::new(settings()).tune()
+        );
+
+        // Simulated at the settings' own rate, not 60 hz.
+        let fast = TuningSettings {
+            sample_rate: Hertz(240.0),
+            ..settings()
+        };
+        let native = crate::one_euro::OneEuroFilter::new(240.0, 1.0, 1.0, 1.0);
+        assert_eq!(
+            Tuner::with_filter(fast.clone(), native).lag_s(1.0),
+            Tuner::new(fast).lag_s(1.0)
+        );
+    }
+
+    #[test]
+    pub fn test_trace() {
+        let mut tuner = Tuner::new(settings()).with_trace();
+        let tuned = tuner.tune().unwrap();
+        let trace = tuner.take_trace().unwrap();
+
+        let last_best = trace
+            .entries
+            .iter()
+            .rev()
+            .find(|entry| entry.outcome == CandidateOutcome::NewBest)
+            .unwrap();
+        assert_eq!(last_best.min_cutoff_hz, tuned.min_cutoff_hz);
+        assert_eq!(last_best.beta, tuned.beta);
+
+        let csv = trace.to_csv();
+        assert_eq!(csv.lines().count(), trace.len() + 1);
+    }
+
+    #[test]
+    pub fn test_tune_per_axis() {
+        let depth_settings = TuningSettings {
+            max_target_precision: 3.0,
+            ..settings()
+        };
+
+        let [x, y, depth] =
+            tune_per_axis([settings(), settings(), depth_settings.clone()]).unwrap();
+        assert_eq!(x, y);
+        assert_eq!(Tuner::new(depth_settings).tune().unwrap(), depth);
+        assert_ne!(x, depth);
+
+        // Sharing a precision target isn't enough to share a tuning.
+        let noisy = TuningSettings {
+            noise_variance: Variance(16.0),
+            ..settings()
+        };
+        let [x, noisy_tuned] = tune_per_axis([settings(), noisy.clone()]).unwrap();
+        assert_eq!(Tuner::new(noisy).tune().unwrap(), noisy_tuned);
+        assert_ne!(x, noisy_tuned);
+    }
+
+    #[test]
+    pub fn test_joint_constraints() {
+        let settings = || TuningSettings {
+            max_lag_secs: Seconds(0.04),
+            ..settings()
+        };
+
+        let plain = Tuner::new(settings()).tune().unwrap();
+        let precision_first = Tuner::new(settings())
+            .with_joint_constraints(JointConstraints::default())
+            .tune()
+            .unwrap();
+        // Precision first still meets the precision target, within the overshoot limit.
+        let tuner = Tuner::new(settings());
+        for tuned in [&plain, &precision_first] {
+            assert!(tuner.grid.precision(2.0, tuned.min_cutoff_hz, tuned.beta) <= 1.0);
+        }
+        let mut tuner = Tuner::new(settings());
+        tuner.filter.set_parameters(&precision_first);
+        let response = tuner.step_response(1.0);
+        assert!(response.overshoot_percent <= 1.0);
+
+        // Lag first gives up precision to get within the lag budget.
+        let lag_first = Tuner::new(settings())
+            .with_joint_constraints(JointConstraints {
+                priority: [Constraint::Lag, Constraint::Ringing, Constraint::Precision],
+                ..Default::default()
+            })
+            .tune()
+            .unwrap();
+        let lag = |tuned: &FinalTuningSettings| {
+            let mut tuner = Tuner::new(settings());
+            tuner.filter.set_parameters(tuned);
+            tuner.lag_s(1.0)
+        };
+        assert!(lag(&lag_first) <= 0.04);
+        assert!(lag(&lag_first) <= lag(&precision_first));
+    }
+
+    #[test]
+    pub fn test_preference() {
+        let settings = || TuningSettings {
+            max_target_precision: 0.3,
+            ..settings()
+        };
+
+        let neutral = settings().with_preference(0.5);
+        assert_eq!(neutral.max_target_precision, 0.3);
+        assert_eq!(neutral.max_lag_secs, Seconds(0.08));
+
+        let lag = |preference: f64| {
+            let mut tuner = Tuner::new(settings()).with_preference(preference);
+            let tuned = tuner.tune_fast().unwrap();
+            tuner.filter.set_parameters(&tuned);
+            tuner.lag_s(0.3)
+        };
+        assert!(lag(0.0) < lag(1.0));
+    }
+
+    #[test]
+    pub fn test_beta_search() {
+        let betas = BetaSearch::default().without_zero().values();
+        assert_eq!(betas.len(), 180);
+        assert_eq!(betas[0], 0.975);
+        assert!((betas[179] - 1e-5).abs() < 1e-12);
+        assert_eq!(BetaSearch::default().values().last(), Some(&0.0));
+        assert!(betas.windows(2).all(|pair| pair[0] > pair[1]));
+
+        let wide = BetaSearch::new(0.5, 10.0)
+            .with_steps_per_decade(9)
+            .without_zero()
+            .values();
+        assert_eq!(wide[0], 9.0);
+        assert!(wide.iter().all(|&beta| (0.5..10.0).contains(&beta)));
+
+        let tuned = Tuner::new(settings())
+            .with_beta_search(BetaSearch::new(1e-3, 0.05))
+            .tune_fast()
+            .unwrap();
+        assert!((1e-3..=0.05).contains(&tuned.beta));
+    }
+
+    #[test]
+    pub fn test_cutoff_search() {
+        let search = CutoffSearch::new(2.05, 2.95, 100.0);
+        assert!(!search.contains(Tuner::new(settings()).tune_fast().unwrap().min_cutoff_hz));
+
+        // Every search keeps to the configured cutoffs, not just tune.
+        let fast = Tuner::new(settings())
+            .with_cutoff_search(search)
+            .tune_fast()
+            .unwrap();
+        assert!(search.contains(fast.min_cutoff_hz));
+        let joint = Tuner::new(settings())
+            .with_cutoff_search(search)
+            .with_joint_constraints(JointConstraints::default())
+            .tune()
+            .unwrap();
+        assert!(search.contains(joint.min_cutoff_hz));
+
+        // Between two table nodes, tune_fast falls back to the search's own steps.
+        let between = CutoffSearch::new(2.01, 2.09, 100.0);
+        let fast = Tuner::new(settings())
+            .with_cutoff_search(between)
+            .tune_fast()
+            .unwrap();
+        assert!(between.contains(fast.min_cutoff_hz));
+    }
+
+    #[test]
+    pub fn test_diagnostics() {
+        let settings = |max_target_precision, max_lag_secs| TuningSettings {
+            max_target_precision,
+            max_lag_secs: Seconds(max_lag_secs),
+            ..settings()
+        };
+
+        let (tuned, diagnostics) = Tuner::new(settings(1.0, 0.08)).tune_fast_with_diagnostics();
+        assert!(tuned.is_some());
+        assert_eq!(diagnostics.relaxations, 0);
+        assert!(diagnostics.candidates_evaluated > 0);
+        assert!(diagnostics.lag_satisfied);
+        assert!(!diagnostics.is_degenerate());
+
+        // Nothing gets that precise, or that fast.
+        let (_, diagnostics) = Tuner::new(settings(0.01, 0.001)).tune_fast_with_diagnostics();
+        assert!(diagnostics.relaxations > 0);
+        assert!(diagnostics.target_precision > 0.01);
+        assert!(!diagnostics.lag_satisfied);
+        assert!(diagnostics.is_degenerate());
+    }
+
+    #[test]
+    pub fn test_budget() {
+        let (tuned, diagnostics) = Tuner::new(settings()).tune_with_budget(Duration::ZERO);
+        assert_eq!(tuned, None);
+        assert!(diagnostics.budget_limited);
+
+        let (tuned, diagnostics) =
+            Tuner::new(settings()).tune_with_budget(Duration::from_millis(20));
+        assert!(tuned.is_some());
+        assert!(diagnostics.budget_limited);
+        assert!(diagnostics.elapsed < Duration::from_millis(500));
+
+        // A candidate whose step never settles is cut short too, rather than simulated forever.
+        struct Stuck;
+        impl SmoothingFilter for Stuck {
+            fn filter(&mut self, _: f64) -> f64 {
+                0.0
+            }
+            fn set_parameters(&mut self, _: &FinalTuningSettings) {}
+            fn reset(&mut self) {}
+        }
+        for constraints in [None, Some(JointConstraints::default())] {
+            let mut tuner = Tuner::with_filter(settings(), Stuck);
+            tuner.constraints = constraints;
+            let (_, diagnostics) = tuner.tune_with_budget(Duration::from_millis(20));
+            assert!(diagnostics.budget_limited);
+            assert!(diagnostics.elapsed < Duration::from_millis(500));
+        }
+
+        let (tuned, diagnostics) =
+            Tuner::new(settings()).tune_with_budget(Duration::from_secs(600));
+        assert!(!diagnostics.budget_limited);
+        assert_eq!(tuned, Tuner::new(settings()).tune());
+    }
+
+    #[cfg(feature = "sixty-hz-table")]
+    #[test]
+    pub fn test_out_of_table() {
+        let grid = Grid::new(sixty_hz());
+        assert!(grid.try_precision(1.0, 1.0, 0.1).is_some());
+        assert_eq!(grid.try_precision(1.0, 50.0, 0.1), None);
+        assert_eq!(
+            Grid::new(vec![vec![vec![1.0]]]).try_precision(1.0, 1.0, 0.1),
+            None
+        );
+    }
+
+    #[cfg(all(feature = "no-panic", feature = "sixty-hz-table"))]
+    #[test]
+    pub fn test_no_panic() {
+        struct Hopeless;
+        impl PrecisionModel for Hopeless {
+            fn precision(&self, _: f64, _: f64, _: f64) -> f64 {
+                f64::INFINITY
+            }
+        }
+
+        let settings = TuningSettings {
+            max_amplitude: f64::NAN,
+            ..settings()
+        };
+        assert_eq!(
+            Grid::new(sixty_hz()).precision(1.0, 50.0, 0.1),
+            f64::INFINITY
+        );
+        assert_eq!(Tuner::new(settings.clone()).lag_s(1.0), f64::INFINITY);
+        assert_eq!(
+            Tuner::new(settings)
+                .with_precision_model(Hopeless)
+                .tune_fast(),
+            None
+        );
+    }
+
+    #[test]
+    pub fn test_zero_beta() {
+        // Movement barely larger than the noise, and plenty of lag to spare.
+        let settings = || TuningSettings {
+            max_lag_secs: Seconds(0.5),
+            noise_variance: Variance(1.0),
+            max_amplitude: 3.0,
+            ..settings()
+        };
+        let tuned = Tuner::new(settings()).tune_fast().unwrap();
+        assert_eq!(tuned.beta, 0.0);
+        assert!(!tuned.is_adaptive());
+
+        let adaptive = Tuner::new(settings())
+            .with_beta_search(BetaSearch::default().without_zero())
+            .tune_fast()
+            .unwrap();
+        assert!(adaptive.is_adaptive());
+    }
+
+    #[cfg(feature = "sixty-hz-table")]
+    #[test]
+    pub fn test_grid_introspection() {
+        let grid = Grid::new(sixty_hz());
+        let dimensions = grid.dimensions();
+        assert_eq!(dimensions, [16, 199, 47]);
+        assert_eq!(grid.cells().count(), dimensions.iter().product::<usize>());
+
+        let betas = grid.axis(GridAxis::Beta);
+        assert_eq!(betas[0], 0.0);
+        assert!((betas[46] - 1.0).abs() < 1e-12);
+
+        // Nodes land exactly where the lookup does.
+        let cell = grid.cell(5, 40, 37).unwrap();
+        assert!(
+            (grid.precision(cell.jitter, cell.cutoff_hz, cell.beta) - cell.precision).abs() < 1e-9
+        );
+
+        assert_eq!(grid.max_difference(&Grid::new(sixty_hz())), Some(0.0));
+        assert!(grid.monotonicity_violations(0.01).is_empty());
+    }
+
+    #[cfg(feature = "sixty-hz-table")]
+    #[test]
+    pub fn test_interpolation() {
+        let cell = Grid::new(sixty_hz()).cell(5, 40, 37).unwrap();
+        for interpolation in [
+            Interpolation::Nearest,
+            Interpolation::Trilinear,
+            Interpolation::Tricubic,
+        ] {
+            let grid = Grid::new(sixty_hz()).with_interpolation(interpolation);
+            // Every method passes through the nodes.
+            let at_node = grid.precision(cell.jitter, cell.cutoff_hz, cell.beta);
+            assert!((at_node - cell.precision).abs() < 1e-9, "{interpolation:?}");
+
+            // And stays between neighbours in between.
+            let next = grid.cell(5, 41, 37).unwrap();
+            let between = grid.precision(
+                cell.jitter,
+                (cell.cutoff_hz + next.cutoff_hz) / 2.0,
+                cell.beta,
+            );
+            let (lo, hi) = (
+                cell.precision.min(next.precision),
+                cell.precision.max(next.precision),
+            );
+            assert!(
+                between >= lo - 1e-3 && between <= hi + 1e-3,
+                "{interpolation:?}"
+            );
+        }
+    }
 }
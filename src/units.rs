@@ -0,0 +1,134 @@
+//! Newtypes for the quantities that are easy to mix up. A variance and a standard deviation are
+//! both just f64s, and feeding one where the other is expected silently produces nonsense
+//! tuning rather than an error.
+
+/// A frequency, i.e. a sample rate or cutoff.
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Hertz(pub f64);
+
+/// A duration, i.e. a lag budget.
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Seconds(pub f64);
+
+/// A noise variance, in squared signal units.
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Variance(pub f64);
+
+/// A noise standard deviation, in signal units.
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct StdDev(pub f64);
+
+impl Hertz {
+    // The time between samples at this rate.
+    pub fn period(self) -> Seconds {
+        Seconds(1.0 / self.0)
+    }
+
+    // How many samples at this rate fit in the given duration.
+    pub fn samples_in(self, duration: Seconds) -> f64 {
+        self.0 * duration.0
+    }
+}
+
+impl Seconds {
+    pub fn from_millis(millis: f64) -> Self {
+        Seconds(millis / 1000.0)
+    }
+
+    pub fn as_millis(self) -> f64 {
+        self.0 * 1000.0
+    }
+}
+
+impl Variance {
+    pub fn std_dev(self) -> StdDev {
+        StdDev(self.0.sqrt())
+    }
+}
+
+impl StdDev {
+    pub fn variance(self) -> Variance {
+        Variance(self.0 * self.0)
+    }
+}
+
+/// How big one device unit is in the units precision targets are usually thought of in. Device
+/// units are whatever samples arrive in - counts, or what a CountScale converts them to. Leave
+/// out any conversion that doesn't apply to the device.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DeviceScale {
+    pub pixels_per_unit: Option<f64>,
+    pub millimeters_per_unit: Option<f64>,
+    pub degrees_per_unit: Option<f64>,
+}
+
+impl DeviceScale {
+    pub fn with_pixels_per_unit(mut self, pixels: f64) -> Self {
+        self.pixels_per_unit = Some(pixels);
+        self
+    }
+
+    pub fn with_millimeters_per_unit(mut self, millimeters: f64) -> Self {
+        self.millimeters_per_unit = Some(millimeters);
+        self
+    }
+
+    pub fn with_degrees_per_unit(mut self, degrees: f64) -> Self {
+        self.degrees_per_unit = Some(degrees);
+        self
+    }
+}
+
+/// A least precision (the jitter that can be tolerated) in whichever unit is natural for the
+/// application, converted to device units for tuning with to_units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrecisionTarget {
+    // Already in device units.
+    Units(f64),
+    Pixels(f64),
+    Millimeters(f64),
+    Degrees(f64),
+}
+
+impl PrecisionTarget {
+    // None if the scale has no conversion for the target's unit - better than guessing, which
+    // tunes for nonsense.
+    pub fn to_units(self, scale: &DeviceScale) -> Option<f64> {
+        match self {
+            PrecisionTarget::Units(units) => Some(units),
+            PrecisionTarget::Pixels(pixels) => Some(pixels / scale.pixels_per_unit?),
+            PrecisionTarget::Millimeters(millimeters) => {
+                Some(millimeters / scale.millimeters_per_unit?)
+            }
+            PrecisionTarget::Degrees(degrees) => Some(degrees / scale.degrees_per_unit?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_precision_target() {
+        // A 1600 dpi mouse on a 100 ppi display.
+        let scale = DeviceScale::default()
+            .with_pixels_per_unit(100.0 / 1600.0)
+            .with_millimeters_per_unit(25.4 / 1600.0);
+        assert_eq!(PrecisionTarget::Pixels(2.0).to_units(&scale), Some(32.0));
+        assert_eq!(
+            PrecisionTarget::Millimeters(25.4).to_units(&scale),
+            Some(1600.0)
+        );
+        assert_eq!(PrecisionTarget::Units(3.0).to_units(&scale), Some(3.0));
+        assert_eq!(PrecisionTarget::Degrees(1.0).to_units(&scale), None);
+    }
+}
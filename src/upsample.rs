@@ -0,0 +1,127 @@
+use crate::{filter::MultiAxisFilter, tuner::FinalTuningSettings};
+
+/// How output is produced between input samples.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UpsampleMode {
+    // Repeat the latest filtered value.
+    Hold,
+    // Blend between the previous and latest filtered values. Smoothest, but adds one input period
+    // of latency.
+    Interpolate,
+    // Project the latest filtered value forward along the filter's velocity. No added latency,
+    // but overshoots slightly when motion stops.
+    #[default]
+    Extrapolate,
+}
+
+/// Produces filtered output at a higher rate than the input - i.e. a 60 Hz tracker driving a
+/// 120 Hz render loop. Input is pushed as it arrives and output is queried with the time since
+/// the latest input, as often as needed.
+pub struct Upsampler<const D: usize> {
+    filter: MultiAxisFilter<D>,
+    mode: UpsampleMode,
+    input_period: f64,
+    previous: Option<[f64; D]>,
+    latest: Option<[f64; D]>,
+}
+
+impl<const D: usize> Upsampler<D> {
+    pub fn new(input_rate: f64, settings: &FinalTuningSettings, mode: UpsampleMode) -> Self {
+        Self {
+            filter: MultiAxisFilter::new(input_rate, settings),
+            mode,
+            input_period: 1.0 / input_rate,
+            previous: None,
+            latest: None,
+        }
+    }
+
+    // Filters a new input sample and returns the filtered value.
+    pub fn push(&mut self, sample: [f64; D]) -> [f64; D] {
+        let filtered = self.filter.filter(sample);
+        self.previous = self.latest.or(Some(filtered));
+        self.latest = Some(filtered);
+        filtered
+    }
+
+    // The output elapsed_secs after the latest input. Time is capped at one input period, so a
+    // late input holds the output rather than running away. None until the first input.
+    pub fn value_at(&self, elapsed_secs: f64) -> Option<[f64; D]> {
+        let latest = self.latest?;
+        let fraction = (elapsed_secs / self.input_period).clamp(0.0, 1.0);
+
+        let value = match self.mode {
+            UpsampleMode::Hold => latest,
+            UpsampleMode::Interpolate => {
+                // Always set along with latest.
+                let previous = self.previous.unwrap_or(latest);
+                core::array::from_fn(|i| previous[i] + (latest[i] - previous[i]) * fraction)
+            }
+            UpsampleMode::Extrapolate => {
+                let velocity = self.filter.velocity();
+                let dt = fraction * self.input_period;
+                core::array::from_fn(|i| latest[i] + velocity[i] * dt)
+            }
+        };
+
+        Some(value)
+    }
+
+    pub fn reset(&mut self) {
+        self.filter.reset();
+        self.previous = None;
+        self.latest = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_upsample_modes() {
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 1.0,
+            beta: 0.01,
+        };
+        let period = 1.0 / 60.0;
+        let upsampler = |mode| Upsampler::<1>::new(60.0, &settings, mode);
+        let (mut hold, mut interpolate, mut extrapolate) = (
+            upsampler(UpsampleMode::Hold),
+            upsampler(UpsampleMode::Interpolate),
+            upsampler(UpsampleMode::Extrapolate),
+        );
+        assert_eq!(hold.value_at(0.0), None);
+
+        // With only the first input of the ramp below, there is nothing to blend from yet.
+        let first = interpolate.push([0.0]);
+        assert_eq!(interpolate.value_at(period / 2.0), Some(first));
+
+        // Up a ramp, all three agree on the latest input, then part ways after it.
+        let mut outputs = vec![];
+        for i in 0..30 {
+            let sample = [i as f64];
+            outputs.push(hold.push(sample));
+            extrapolate.push(sample);
+            if i > 0 {
+                interpolate.push(sample);
+            }
+        }
+        let (previous, latest) = (outputs[28][0], outputs[29][0]);
+        for upsampler in [&hold, &extrapolate] {
+            assert_eq!(upsampler.value_at(0.0), Some([latest]));
+        }
+
+        assert_eq!(hold.value_at(period / 2.0), Some([latest]));
+        let halfway = interpolate.value_at(period / 2.0).unwrap()[0];
+        assert!((halfway - (previous + latest) / 2.0).abs() < 1e-9);
+        assert!(extrapolate.value_at(period / 2.0).unwrap()[0] > latest);
+
+        // Late input holds rather than running on.
+        assert_eq!(interpolate.value_at(10.0), Some([latest]));
+        assert_eq!(extrapolate.value_at(10.0), extrapolate.value_at(period));
+
+        extrapolate.reset();
+        assert_eq!(extrapolate.value_at(0.0), None);
+    }
+}
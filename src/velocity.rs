@@ -0,0 +1,186 @@
+//! Tuning for filters applied to velocity streams - scroll deltas, gyro rates, mouse counts -
+//! rather than positions.
+//!
+//! The precision table assumes white noise on top of the signal. Deltas computed by differencing
+//! a noisy position don't have white noise: differencing pushes the noise towards Nyquist (and
+//! doubles its variance), where a low pass knocks it down much harder, so the table badly
+//! overstates how much smoothing is needed. VelocityPrecision builds the equivalent of one
+//! jitter slice of the table by simulation, for whichever noise the stream actually has.
+
+use crate::{
+    calibrator::TuningSettings,
+    filter::DERIVATIVE_CUTOFF_HZ,
+    one_euro::OneEuroFilter,
+    synth::GaussianNoise,
+    tablegen::beta_at,
+    tuner::{FinalTuningSettings, Grid, PrecisionModel, Tuner},
+};
+
+// Cutoff nodes cover what the tuner searches by default, 0.1 to 4.0 hz - see with_cutoff_step.
+const CUTOFF_STEP_HZ: f64 = 0.1;
+const CUTOFF_NODES: usize = 40;
+// Same beta nodes as the precision table - 0, then 9 per decade from 1e-5 up to 1.0.
+const BETA_NODES: usize = 47;
+
+// Per simulation. The first second lets the filter settle and isn't measured.
+const WARMUP_SECS: f64 = 1.0;
+const MEASURE_SECS: f64 = 60.0;
+// Default noise seed, see VelocityPrecision::with_seed.
+pub const DEFAULT_SEED: u64 = 0xD1FF;
+
+/// How noise shows up in a velocity stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseColor {
+    // Independent noise on every sample, i.e. a gyro's rate noise.
+    White,
+    // Deltas of a noisy position, i.e. mouse counts or scroll deltas - each sample's noise is the
+    // difference of two white noise samples. The std dev given is that of the position noise.
+    Differenced,
+}
+
+/// Simulated precision for one noise level and color. Building it runs a couple thousand short
+/// simulations, so build once per calibration and reuse it.
+#[derive(Debug, Clone)]
+pub struct VelocityPrecision {
+    noise_std_dev: f64,
+    cutoff_step_hz: f64,
+    // [cutoff node][beta node]
+    table: Vec<[f64; BETA_NODES]>,
+}
+
+impl VelocityPrecision {
+    pub fn new(noise_std_dev: f64, sample_rate: f64, color: NoiseColor) -> Self {
+        Self::with_seed(noise_std_dev, sample_rate, color, DEFAULT_SEED)
+    }
+
+    // Simulates with the given noise seed. The same seed gives the same table, and so the same
+    // tuning, on any machine - with the libm feature, bit for bit.
+    pub fn with_seed(noise_std_dev: f64, sample_rate: f64, color: NoiseColor, seed: u64) -> Self {
+        Self::build(noise_std_dev, sample_rate, color, seed, CUTOFF_STEP_HZ)
+    }
+
+    // Cutoff nodes from cutoff_step_hz to 40 times that, rather than 0.1 to 4.0 hz - for slow
+    // devices, tuned with a matching tuner::CutoffSearch.
+    pub fn with_cutoff_step(
+        noise_std_dev: f64,
+        sample_rate: f64,
+        color: NoiseColor,
+        cutoff_step_hz: f64,
+    ) -> Self {
+        Self::build(
+            noise_std_dev,
+            sample_rate,
+            color,
+            DEFAULT_SEED,
+            cutoff_step_hz,
+        )
+    }
+
+    fn build(
+        noise_std_dev: f64,
+        sample_rate: f64,
+        color: NoiseColor,
+        seed: u64,
+        cutoff_step_hz: f64,
+    ) -> Self {
+        let warmup = (WARMUP_SECS * sample_rate) as usize;
+        let measured = (MEASURE_SECS * sample_rate) as usize;
+
+        let table = (0..CUTOFF_NODES)
+            .map(|c| {
+                let cutoff = (c + 1) as f64 * cutoff_step_hz;
+                core::array::from_fn(|b| {
+                    let mut filter =
+                        OneEuroFilter::new(sample_rate, cutoff, DERIVATIVE_CUTOFF_HZ, beta_at(b));
+                    // Same noise for every node, so the table is smooth across nodes.
+                    let mut noise = GaussianNoise::new(noise_std_dev, seed);
+                    let mut previous = noise.sample();
+                    // Start at rest on the true (zero) signal.
+                    filter.filter(0.0);
+                    let mut sum_squares = 0.0;
+
+                    for i in 0..warmup + measured {
+                        let current = noise.sample();
+                        let sample = match color {
+                            NoiseColor::White => current,
+                            NoiseColor::Differenced => current - previous,
+                        };
+                        previous = current;
+
+                        let out = filter.filter(sample);
+                        if i >= warmup {
+                            sum_squares += out * out;
+                        }
+                    }
+                    (sum_squares / measured as f64).sqrt()
+                })
+            })
+            .collect();
+
+        Self {
+            noise_std_dev,
+            cutoff_step_hz,
+            table,
+        }
+    }
+
+    pub fn noise_std_dev(&self) -> f64 {
+        self.noise_std_dev
+    }
+}
+
+impl PrecisionModel for VelocityPrecision {
+    // Bilinear over cutoff and beta. The table was built for one noise level, so other jitter
+    // values are scaled linearly from it - close enough for small differences only.
+    fn precision(&self, jitter: f64, cutoff_hz: f64, beta: f64) -> f64 {
+        let c = (cutoff_hz / self.cutoff_step_hz - 1.0).clamp(0.0, (CUTOFF_NODES - 1) as f64);
+        let [b, _, _] = Grid::get_beta_index(beta);
+        let b = b.clamp(0.0, (BETA_NODES - 1) as f64);
+
+        let (c_lo, b_lo) = (c.floor() as usize, b.floor() as usize);
+        let (c_hi, b_hi) = (c.ceil() as usize, b.ceil() as usize);
+        let (cd, bd) = (c - c_lo as f64, b - b_lo as f64);
+
+        let lo = self.table[c_lo][b_lo] * (1.0 - bd) + self.table[c_lo][b_hi] * bd;
+        let hi = self.table[c_hi][b_lo] * (1.0 - bd) + self.table[c_hi][b_hi] * bd;
+        let precision = lo * (1.0 - cd) + hi * cd;
+
+        precision * jitter / self.noise_std_dev
+    }
+}
+
+// A tuner for a velocity stream with the given noise color, in place of the position table.
+// Simulates at settings.sample_rate, like the model.
+pub fn velocity_tuner(settings: TuningSettings, color: NoiseColor) -> Tuner {
+    let sample_rate = settings.sample_rate.0;
+    let model = VelocityPrecision::new(settings.noise_variance.std_dev().0, sample_rate, color);
+    let filter = FinalTuningSettings {
+        min_cutoff_hz: 1.0,
+        beta: 1.0,
+    }
+    .to_one_euro_rs(sample_rate);
+    Tuner::with_filter(settings, filter).with_precision_model(model)
+}
+
+// Compares against the 60 hz table.
+#[cfg(all(test, feature = "sixty-hz-table"))]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_velocity_precision() {
+        let white = VelocityPrecision::new(1.0, 60.0, NoiseColor::White);
+        let differenced = VelocityPrecision::new(1.0, 60.0, NoiseColor::Differenced);
+
+        // White noise should agree with the table, give or take simulation noise.
+        let table = Grid::new(crate::table::sixty_hz()).precision(1.0, 1.0, 0.01);
+        let simulated = white.precision(1.0, 1.0, 0.01);
+        assert!(
+            (simulated / table - 1.0).abs() < 0.25,
+            "{simulated} vs {table}"
+        );
+
+        // Differenced noise has twice the variance, but it's almost all filtered out.
+        assert!(differenced.precision(1.0, 1.0, 0.01) < simulated);
+    }
+}
@@ -0,0 +1,226 @@
+use std::fmt;
+
+use crate::{
+    events,
+    filter::MultiAxisFilter,
+    wcet::{BoundedWork, WorkBound},
+};
+
+/// What tripped the watchdog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DivergenceKind {
+    // NaN or infinity in the filter's output or smoothed derivative.
+    NonFinite,
+    // Output further from the raw sample than the max deviation.
+    Deviation,
+    // Smoothed derivative faster than the max velocity.
+    Velocity,
+}
+
+impl DivergenceKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DivergenceKind::NonFinite => "non finite",
+            DivergenceKind::Deviation => "deviation",
+            DivergenceKind::Velocity => "velocity",
+        }
+    }
+}
+
+/// One reset, as reported by WatchedFilter::take_divergence.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Divergence {
+    pub kind: DivergenceKind,
+    // The first axis found diverged, and its offending output, deviation or velocity.
+    pub axis: usize,
+    pub value: f64,
+    // Counting every sample the watched filter has been given, from 0.
+    pub sample: u64,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "filter diverged ({}) on axis {} at sample {}: {}",
+            self.kind.as_str(),
+            self.axis,
+            self.sample,
+            self.value
+        )
+    }
+}
+
+/// Wraps a filter so it heals itself when its state goes bad - i.e. after a burst of garbage
+/// from a flaky driver leaves NaN in the filter, or a huge spike leaves it chasing nothing.
+/// After each sample the output and smoothed derivative are checked, and on divergence the
+/// filter is reset to the raw sample, so a kiosk or VR session that's been running for days
+/// doesn't need restarting to recover.
+///
+/// Non finite state is always caught. Deviation and velocity limits are off until set, as what
+/// counts as runaway depends on the units.
+#[derive(Debug, Clone)]
+pub struct WatchedFilter<const D: usize> {
+    filter: MultiAxisFilter<D>,
+    max_deviation: Option<f64>,
+    max_velocity: Option<f64>,
+    // The last output that passed the checks.
+    last_output: Option<[f64; D]>,
+    samples: u64,
+    resets: u64,
+    divergence: Option<Divergence>,
+}
+
+impl<const D: usize> WatchedFilter<D> {
+    pub fn new(filter: MultiAxisFilter<D>) -> Self {
+        Self {
+            filter,
+            max_deviation: None,
+            max_velocity: None,
+            last_output: None,
+            samples: 0,
+            resets: 0,
+            divergence: None,
+        }
+    }
+
+    // Resets whenever an axis' output is further than this from its raw sample. Set it well
+    // above the lag the filter is tuned for at top speed, or fast motion will trip it.
+    pub fn with_max_deviation(mut self, max_deviation: f64) -> Self {
+        self.max_deviation = Some(max_deviation);
+        self
+    }
+
+    // Resets whenever an axis' smoothed derivative is faster than this, in units per second -
+    // i.e. a few times the fastest the device can really move.
+    pub fn with_max_velocity(mut self, max_velocity: f64) -> Self {
+        self.max_velocity = Some(max_velocity);
+        self
+    }
+
+    // Filters a sample, resetting first if that left the filter diverged. After a reset a
+    // finite sample is passed through as is. A non finite one leaves the filter empty and
+    // returns the last good output instead, or the sample itself if there's never been one.
+    pub fn filter(&mut self, sample: [f64; D]) -> [f64; D] {
+        let output = self.filter.filter(sample);
+        let index = self.samples;
+        self.samples += 1;
+
+        let Some(divergence) = self.check(sample, output, index) else {
+            self.last_output = Some(output);
+            return output;
+        };
+
+        self.filter.reset();
+        self.resets += 1;
+        self.divergence = Some(divergence);
+        // Loggers can block, so under bounded-time it's left to take_divergence's callers.
+        if !cfg!(feature = "bounded-time") {
+            events::milestone!(
+                "filter diverged, reset",
+                kind = divergence.kind.as_str(),
+                axis = divergence.axis,
+                value = divergence.value,
+                sample = divergence.sample,
+            );
+        }
+
+        if sample.iter().all(|value| value.is_finite()) {
+            let output = self.filter.filter(sample);
+            self.last_output = Some(output);
+            output
+        } else {
+            self.last_output.unwrap_or(sample)
+        }
+    }
+
+    fn check(&self, sample: [f64; D], output: [f64; D], index: u64) -> Option<Divergence> {
+        let velocity = self.filter.velocity();
+        (0..D).find_map(|axis| {
+            let diverged = |kind, value| {
+                Some(Divergence {
+                    kind,
+                    axis,
+                    value,
+                    sample: index,
+                })
+            };
+            let deviation = (output[axis] - sample[axis]).abs();
+            if !output[axis].is_finite() || !velocity[axis].is_finite() {
+                diverged(DivergenceKind::NonFinite, output[axis])
+            } else if self.max_deviation.is_some_and(|max| deviation > max) {
+                diverged(DivergenceKind::Deviation, deviation)
+            } else if self
+                .max_velocity
+                .is_some_and(|max| velocity[axis].abs() > max)
+            {
+                diverged(DivergenceKind::Velocity, velocity[axis])
+            } else {
+                None
+            }
+        })
+    }
+
+    // The latest reset, if there's been one since the last call.
+    pub fn take_divergence(&mut self) -> Option<Divergence> {
+        self.divergence.take()
+    }
+
+    // Resets so far.
+    pub fn resets(&self) -> u64 {
+        self.resets
+    }
+
+    pub fn inner(&self) -> &MultiAxisFilter<D> {
+        &self.filter
+    }
+
+    pub fn inner_mut(&mut self) -> &mut MultiAxisFilter<D> {
+        &mut self.filter
+    }
+}
+
+// Filtering a second time after a reset.
+impl<const D: usize> BoundedWork for WatchedFilter<D> {
+    fn work_bound(&self) -> WorkBound {
+        WorkBound::filter(2 * D)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tuner::FinalTuningSettings;
+
+    #[test]
+    pub fn test_watchdog_resets() {
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 1.0,
+            beta: 0.01,
+        };
+        let mut filter =
+            WatchedFilter::new(MultiAxisFilter::<2>::new(60.0, &settings)).with_max_velocity(1e4);
+        for _ in 0..10 {
+            filter.filter([1.0, 2.0]);
+        }
+        assert_eq!(filter.take_divergence(), None);
+
+        // Garbage leaves NaN behind, which is reset away and the last good output held.
+        let held = filter.filter([f64::INFINITY, 2.0]);
+        assert!((held[0] - 1.0).abs() < 1e-9);
+        let divergence = filter.take_divergence().unwrap();
+        assert_eq!(divergence.kind, DivergenceKind::NonFinite);
+        assert_eq!((divergence.axis, divergence.sample), (0, 10));
+        assert_eq!(filter.filter([3.0, 4.0]), [3.0, 4.0]);
+
+        // A finite spike fast enough to trip the velocity limit resets to the spike itself.
+        assert_eq!(filter.filter([3.0, 1e6]), [3.0, 1e6]);
+        let divergence = filter.take_divergence().unwrap();
+        assert_eq!(
+            (divergence.kind, divergence.axis),
+            (DivergenceKind::Velocity, 1)
+        );
+        assert_eq!(filter.resets(), 2);
+        assert_eq!(filter.take_divergence(), None);
+    }
+}
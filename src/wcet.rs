@@ -0,0 +1,168 @@
+//! Worst case execution time for the per sample calls, so filtering and noise estimation can run
+//! inside real time audio or haptics callbacks. Every per sample call reports a WorkBound - how
+//! many of each kind of fixed cost step it can take at most - and WorkCosts turns that into time
+//! on the machine it's measured on.
+//!
+//! Filters and estimators never allocate or loop on their input, but two things can make a
+//! single call far slower than the rest, and the `bounded-time` feature rules both out:
+//!
+//! - NoiseEstimator::new_const defers an O(N) setup to the first update. With the feature that
+//!   update is skipped instead - call prepare (or SingleAxisNoiseEstimator::prepare) up front,
+//!   off the real time path.
+//! - MultiAxisFilter (and so LiveFilter) records an event through the log crate when it picks up
+//!   new parameters, as does WatchedFilter when it resets, and loggers may allocate or lock.
+//!   With the feature neither records the event - check WatchedFilter::take_divergence off the
+//!   real time path instead.
+//!
+//! Calibrators aren't covered - interference detection runs a DFT over its whole window once it
+//! fills, and tuning simulates thousands of runs. Feed them from a queue on another thread.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    dsp::{DcRemoval, DcRemover},
+    estimators::{NoiseEstimator, RunningStatistics},
+    one_euro::OneEuroFilter,
+};
+
+// Samples per batch when measuring, and how many batches the slowest is taken from.
+const MEASURE_STEPS: u32 = 1000;
+const MEASURE_BATCHES: u32 = 20;
+
+/// The most work one call can do, in steps that each take a fixed time.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WorkBound {
+    // Single axis one euro filter steps.
+    pub filter_steps: usize,
+    // Single axis noise bin updates, each with its share of the running statistics.
+    pub bin_updates: usize,
+    // Biquad sections, i.e. detrending ahead of the noise bins.
+    pub biquad_steps: usize,
+}
+
+impl WorkBound {
+    pub const fn filter(filter_steps: usize) -> Self {
+        Self {
+            filter_steps,
+            bin_updates: 0,
+            biquad_steps: 0,
+        }
+    }
+
+    pub const fn bins(bin_updates: usize) -> Self {
+        Self {
+            filter_steps: 0,
+            bin_updates,
+            biquad_steps: 0,
+        }
+    }
+
+    // Both calls made one after the other.
+    pub const fn then(self, other: WorkBound) -> Self {
+        Self {
+            filter_steps: self.filter_steps + other.filter_steps,
+            bin_updates: self.bin_updates + other.bin_updates,
+            biquad_steps: self.biquad_steps + other.biquad_steps,
+        }
+    }
+
+    pub fn time(&self, costs: &WorkCosts) -> Duration {
+        costs.filter_step * self.filter_steps as u32
+            + costs.bin_update * self.bin_updates as u32
+            + costs.biquad_step * self.biquad_steps as u32
+    }
+}
+
+/// Anything with a per sample call that runs in bounded time.
+pub trait BoundedWork {
+    // The most work the per sample call (filter or update) does, whatever the input.
+    fn work_bound(&self) -> WorkBound;
+}
+
+/// What each step of a WorkBound takes on this machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkCosts {
+    pub filter_step: Duration,
+    pub bin_update: Duration,
+    pub biquad_step: Duration,
+}
+
+impl WorkCosts {
+    // Times each step over a few batches and keeps the slowest batch's average. Takes a few
+    // milliseconds - run it at startup, not on the real time thread, and leave headroom for
+    // whatever else shares the callback.
+    pub fn measure() -> Self {
+        let mut filter = OneEuroFilter::new(60.0, 1.0, 1.0, 0.01);
+        let mut bin = NoiseEstimator::<60>::new(0);
+        let mut stats = RunningStatistics::new();
+        let mut detrend = DcRemover::<1>::new(60.0, DcRemoval::HighPass { cutoff_hz: 0.5 });
+
+        let mut x = 0.0;
+        Self {
+            filter_step: slowest(|| {
+                x = std::hint::black_box(filter.filter(x + 1.0));
+            }),
+            bin_update: slowest(|| {
+                bin.update(std::hint::black_box(1.0));
+                stats.update(bin.variance().unwrap_or(1.0));
+            }),
+            biquad_step: slowest(|| {
+                std::hint::black_box(detrend.process([1.0]));
+            }),
+        }
+    }
+}
+
+fn slowest(mut step: impl FnMut()) -> Duration {
+    (0..MEASURE_BATCHES)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..MEASURE_STEPS {
+                step();
+            }
+            start.elapsed() / MEASURE_STEPS
+        })
+        .max()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        estimators::SixtyHzThreeAxisNoiseEstimator, filter::MultiAxisFilter,
+        tuner::FinalTuningSettings,
+    };
+
+    #[test]
+    pub fn test_work_bounds() {
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 1.0,
+            beta: 0.01,
+        };
+        assert_eq!(
+            MultiAxisFilter::<3>::new(60.0, &settings).work_bound(),
+            WorkBound::filter(3)
+        );
+        assert_eq!(
+            SixtyHzThreeAxisNoiseEstimator::quick().work_bound(),
+            WorkBound::bins(15)
+        );
+        let detrended = SixtyHzThreeAxisNoiseEstimator::new(0.1).with_detrending(0.5);
+        assert_eq!(detrended.work_bound().biquad_steps, 3);
+
+        let costs = WorkCosts::measure();
+        let bound = detrended.work_bound().then(WorkBound::filter(3));
+        assert!(bound.time(&costs) >= costs.bin_update * 60);
+
+        // Unprepared bins are skipped rather than set up mid callback.
+        #[cfg(feature = "bounded-time")]
+        {
+            let mut bin = NoiseEstimator::<60>::new_const(0);
+            bin.update(1.0);
+            assert!(!bin.is_ready() && bin.variance().is_none());
+            bin.prepare();
+            assert!(bin.is_ready());
+        }
+    }
+}
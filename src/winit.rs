@@ -0,0 +1,172 @@
+use std::time::Instant;
+
+use ::winit::event::{DeviceEvent, WindowEvent};
+
+use crate::{filter::MultiAxisFilter, pointer::PointerScale, tuner::FinalTuningSettings};
+
+// Events arriving within this long of the first in a burst are taken as coalesced - delivered
+// together, but sampled spread out over the time since the previous burst.
+const COALESCE_SECS: f64 = 0.001;
+
+/// Smooths winit pointer input in window pixels. Feed it every window and device event along
+/// with when it arrived - winit doesn't timestamp events - and read the smoothed position back
+/// once a frame.
+///
+/// Operating systems often deliver several high rate pointer events at once. Taking their
+/// arrival times at face value would filter them with a near zero dt, so a burst is held until
+/// it ends and then spread evenly over the time since the previous one.
+///
+/// Use either cursor positions (CursorMoved) or raw motion (MouseMotion) - not both.
+pub struct WinitPointer {
+    filter: MultiAxisFilter<2>,
+    scale: Option<PointerScale>,
+    // Raw position, in pixels. Motion deltas are accumulated into it.
+    position: [f64; 2],
+    burst: Vec<[f64; 2]>,
+    burst_at: Option<Instant>,
+    // When the last burst to be filtered arrived.
+    filtered_at: Option<Instant>,
+    smoothed: Option<[f64; 2]>,
+}
+
+impl WinitPointer {
+    // sample_rate is the device's nominal rate, only used for the very first burst.
+    pub fn new(sample_rate: f64, settings: &FinalTuningSettings) -> Self {
+        Self::from_filter(MultiAxisFilter::new(sample_rate, settings))
+    }
+
+    pub fn from_filter(filter: MultiAxisFilter<2>) -> Self {
+        Self {
+            filter,
+            scale: None,
+            position: [0.0; 2],
+            burst: Vec::new(),
+            burst_at: None,
+            filtered_at: None,
+            smoothed: None,
+        }
+    }
+
+    // Converts MouseMotion's device counts to pixels. Without it they're taken as pixels already.
+    pub fn with_scale(mut self, scale: PointerScale) -> Self {
+        self.scale = Some(scale);
+        self
+    }
+
+    // Returns whether the event was pointer motion.
+    pub fn window_event(&mut self, event: &WindowEvent, at: Instant) -> bool {
+        let WindowEvent::CursorMoved { position, .. } = event else {
+            return false;
+        };
+        self.push([position.x, position.y], at);
+        true
+    }
+
+    // Returns whether the event was pointer motion.
+    pub fn device_event(&mut self, event: &DeviceEvent, at: Instant) -> bool {
+        let DeviceEvent::MouseMotion { delta: (dx, dy) } = event else {
+            return false;
+        };
+        let pixels_per_count = self.scale.map_or(1.0, |scale| scale.pixels_per_count());
+        let position = [
+            self.position[0] + dx * pixels_per_count,
+            self.position[1] + dy * pixels_per_count,
+        ];
+        self.push(position, at);
+        true
+    }
+
+    fn push(&mut self, position: [f64; 2], at: Instant) {
+        if self
+            .burst_at
+            .is_some_and(|burst_at| at.duration_since(burst_at).as_secs_f64() > COALESCE_SECS)
+        {
+            self.flush();
+        }
+        self.burst_at.get_or_insert(at);
+        self.burst.push(position);
+        self.position = position;
+    }
+
+    // Filters whatever has arrived and returns the smoothed position. Call once a frame, before
+    // reading position. None until the first event.
+    pub fn flush(&mut self) -> Option<[f64; 2]> {
+        if let Some(burst_at) = self.burst_at.take() {
+            let samples = self.burst.len() as f64;
+            let period = 1.0 / self.filter.sample_rate();
+            let dt = match self.filtered_at.replace(burst_at) {
+                Some(filtered_at) => burst_at.duration_since(filtered_at).as_secs_f64() / samples,
+                None => period,
+            };
+            for sample in self.burst.drain(..) {
+                self.smoothed = Some(if dt > 0.0 {
+                    self.filter.filter_with_dt(sample, dt)
+                } else {
+                    self.filter.filter(sample)
+                });
+            }
+        }
+        self.smoothed
+    }
+
+    // The smoothed position as of the last flush.
+    pub fn position(&self) -> Option<[f64; 2]> {
+        self.smoothed
+    }
+
+    // Jumps to a position without smoothing - i.e. when the cursor is warped.
+    pub fn warp_to(&mut self, x: f64, y: f64) {
+        self.burst.clear();
+        self.burst_at = None;
+        self.filtered_at = None;
+        self.position = [x, y];
+        self.filter.reset();
+        self.smoothed = Some(self.filter.filter(self.position));
+    }
+
+    pub fn filter(&self) -> &MultiAxisFilter<2> {
+        &self.filter
+    }
+
+    // For retuning in place.
+    pub fn filter_mut(&mut self) -> &mut MultiAxisFilter<2> {
+        &mut self.filter
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_coalesced_bursts() {
+        let settings = FinalTuningSettings {
+            min_cutoff_hz: 1.0,
+            beta: 0.0,
+        };
+        let motion = |dx: f64| DeviceEvent::MouseMotion { delta: (dx, 0.0) };
+        let start = Instant::now();
+
+        let mut pointer = WinitPointer::new(1000.0, &settings);
+        assert!(pointer.device_event(&motion(0.0), start));
+        assert_eq!(pointer.flush(), Some([0.0, 0.0]));
+
+        // Eight 1 khz samples delivered at once, 8ms later, should filter the same as eight
+        // delivered a millisecond apart.
+        let at = start + Duration::from_millis(8);
+        for _ in 0..8 {
+            pointer.device_event(&motion(1.0), at);
+        }
+        let [x, _] = pointer.flush().unwrap();
+
+        let mut spaced = MultiAxisFilter::<2>::new(1000.0, &settings);
+        spaced.filter([0.0, 0.0]);
+        let mut expected = [0.0; 2];
+        for i in 1..=8 {
+            expected = spaced.filter_with_dt([i as f64, 0.0], 0.001);
+        }
+        assert!((x - expected[0]).abs() < 1e-9);
+        assert!(!pointer.device_event(&DeviceEvent::Added, at));
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{
+    calibrator::{AmplitudeCalibrator, CalibrationStage},
+    units::Seconds,
+};
+
+// The calibrator's noise stage runs off the 60 hz table, so motion time is counted at 60 hz too.
+const SAMPLE_HZ: f64 = 60.0;
+
+const DEFAULT_MOTION_SECS: f64 = 10.0;
+
+/// Where the user is in a guided calibration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WizardStep {
+    HoldStill,
+    MoveAround,
+    Done,
+}
+
+impl WizardStep {
+    // Stable identifier for looking up a translated instruction.
+    pub fn key(&self) -> &'static str {
+        match self {
+            WizardStep::HoldStill => "calibration.hold_still",
+            WizardStep::MoveAround => "calibration.move_around",
+            WizardStep::Done => "calibration.done",
+        }
+    }
+
+    // Default English instruction, for callers without their own translations.
+    pub fn instruction(&self) -> &'static str {
+        match self {
+            WizardStep::HoldStill => {
+                "Set the device down or hold it as still as you can until this step completes."
+            }
+            WizardStep::MoveAround => {
+                "Move the device around as quickly as you would during normal use."
+            }
+            WizardStep::Done => "Calibration complete.",
+        }
+    }
+}
+
+/// A snapshot of wizard state for display. Percentages run from 0 to 100 - the overall
+/// percentage splits evenly between the two steps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WizardStatus {
+    pub step: WizardStep,
+    pub step_percent: f64,
+    pub overall_percent: f64,
+}
+
+/// A guided flow over CalibrationStage. Feed it samples and present whatever status comes back,
+/// nothing is printed - so CLIs and GUIs walk the user through the same steps. Advances from
+/// holding still to moving around by itself once noise estimation completes.
+pub struct CalibrationWizard {
+    // Only None mid-advance.
+    stage: Option<CalibrationStage>,
+    motion_samples: usize,
+    motion_target: usize,
+}
+
+impl Default for CalibrationWizard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CalibrationWizard {
+    pub fn new() -> Self {
+        Self::with_motion_duration(Seconds(DEFAULT_MOTION_SECS))
+    }
+
+    // How long the user is asked to move around for.
+    pub fn with_motion_duration(duration: Seconds) -> Self {
+        Self {
+            stage: Some(CalibrationStage::new()),
+            motion_samples: 0,
+            motion_target: ((duration.0 * SAMPLE_HZ).ceil() as usize).max(1),
+        }
+    }
+
+    pub fn process(&mut self, x: f64, y: f64, z: f64) -> WizardStatus {
+        if self.step() == WizardStep::Done {
+            return self.status();
+        }
+
+        let stage = self
+            .stage
+            .as_mut()
+            .expect("stage is only taken while advancing");
+        let complete = stage.process(x, y, z);
+        if stage.is_noise() {
+            if complete {
+                self.stage = self.stage.take().map(CalibrationStage::advance);
+            }
+        } else {
+            self.motion_samples += 1;
+        }
+
+        self.status()
+    }
+
+    pub fn step(&self) -> WizardStep {
+        match &self.stage {
+            Some(CalibrationStage::Noise(_)) => WizardStep::HoldStill,
+            _ if self.motion_samples < self.motion_target => WizardStep::MoveAround,
+            _ => WizardStep::Done,
+        }
+    }
+
+    pub fn status(&self) -> WizardStatus {
+        let step = self.step();
+        let step_fraction = match (&self.stage, step) {
+            (_, WizardStep::Done) => 1.0,
+            (Some(CalibrationStage::Noise(noise)), _) => noise.progress(),
+            _ => self.motion_samples as f64 / self.motion_target as f64,
+        };
+        let overall_fraction = match step {
+            WizardStep::HoldStill => step_fraction / 2.0,
+            WizardStep::MoveAround => 0.5 + step_fraction / 2.0,
+            WizardStep::Done => 1.0,
+        };
+
+        WizardStatus {
+            step,
+            step_percent: step_fraction * 100.0,
+            overall_percent: overall_fraction * 100.0,
+        }
+    }
+
+    // The finished calibration, ready for tuning - None until the wizard reaches Done.
+    pub fn finish(self) -> Option<AmplitudeCalibrator> {
+        if self.step() != WizardStep::Done {
+            return None;
+        }
+        self.stage?.into_amplitude()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_wizard_flow() {
+        let mut wizard = CalibrationWizard::with_motion_duration(Seconds(1.0));
+        assert_eq!(wizard.step(), WizardStep::HoldStill);
+
+        // Deterministic pseudo noise, so the noise stage converges.
+        let mut seed = 7u64;
+        let mut noise = move || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+        };
+
+        let mut last = 0.0;
+        let mut samples = 0;
+        while wizard.step() == WizardStep::HoldStill {
+            let status = wizard.process(noise(), noise(), noise());
+            assert!(status.overall_percent >= 0.0 && status.overall_percent <= 100.0);
+            last = status.overall_percent;
+            samples += 1;
+            assert!(samples < 100_000, "noise stage never completed");
+        }
+        assert!(last >= 50.0);
+
+        for i in 0..60 {
+            let status = wizard.process(i as f64, 0.0, 0.0);
+            assert!(status.overall_percent >= last);
+            last = status.overall_percent;
+        }
+        assert_eq!(wizard.step(), WizardStep::Done);
+        assert_eq!(wizard.status().overall_percent, 100.0);
+        assert!(wizard.finish().is_some());
+    }
+}